@@ -0,0 +1,370 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![forbid(unsafe_code)]
+//! Universal accumulator in a class group of unknown order.
+//!
+//! The accumulated value is `g^(x_1 * ... * x_n)` for the prime members
+//! `x_i`. A membership witness for `x` is `g^(u / x)`, where `u` is the
+//! product of all members. A non-membership witness is `(a, g^b)` with
+//! `a * u + b * x = 1`, following Li, Li and Xue. Since nobody knows the
+//! group order, the setup is just a discriminant and needs no trusted
+//! party. The flip side is that deleting requires either the deleted
+//! member's witness or a recomputation from the remaining members.
+//!
+//! Members must be odd primes. Map data to members with
+//! `hash_to_prime::hash_to_prime`; a 128-bit prime divides the class number
+//! only with negligible probability. The tests use small discriminants and
+//! primes above their class number for the same reason.
+use super::gmp::mpz::{Mpz, ProbabPrimeResult};
+use super::gmp_classgroup::GmpClassGroup;
+use super::ClassGroup;
+use std::collections::HashSet;
+use std::fmt;
+
+const PRIMALITY_REPS: i32 = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccumulatorError {
+    NotPrime,
+    AlreadyMember,
+    NotMember,
+    BadWitness,
+}
+
+impl fmt::Display for AccumulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AccumulatorError::NotPrime => "accumulator element is not prime",
+            AccumulatorError::AlreadyMember => "element is already accumulated",
+            AccumulatorError::NotMember => "element is not accumulated",
+            AccumulatorError::BadWitness => "accumulator witness does not verify",
+        })
+    }
+}
+
+impl std::error::Error for AccumulatorError {}
+
+/// Witness that `x` is not accumulated: `value^a * b^x == g`, `0 <= a < x`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonMembershipWitness {
+    pub a: Mpz,
+    pub b: GmpClassGroup,
+}
+
+/// The accumulator manager's state. Verifiers only need `generator` and
+/// `value`.
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    generator: GmpClassGroup,
+    value: GmpClassGroup,
+    members: HashSet<Mpz>,
+}
+
+/// `base^exponent` for an exponent of either sign.
+fn pow_signed(base: &GmpClassGroup, exponent: &Mpz) -> GmpClassGroup {
+    let mut result = base.clone();
+    if *exponent < Mpz::zero() {
+        result.inverse();
+        result.pow(-exponent);
+    } else {
+        result.pow(exponent.clone());
+    }
+    result
+}
+
+fn check_prime(x: &Mpz) -> Result<(), AccumulatorError> {
+    if *x <= Mpz::from(2u64) || x.probab_prime(PRIMALITY_REPS) == ProbabPrimeResult::NotPrime {
+        Err(AccumulatorError::NotPrime)
+    } else {
+        Ok(())
+    }
+}
+
+fn product<'a, I: IntoIterator<Item = &'a Mpz>>(xs: I) -> Mpz {
+    xs.into_iter().fold(Mpz::one(), |acc, x| acc * x)
+}
+
+impl Accumulator {
+    /// Empty accumulator in the class group of `discriminant`, which must be
+    /// negative, prime and 1 mod 4.
+    pub fn new(discriminant: Mpz) -> Self {
+        let generator = GmpClassGroup::generator_for_discriminant(discriminant);
+        Self {
+            value: generator.clone(),
+            generator,
+            members: HashSet::new(),
+        }
+    }
+
+    pub fn generator(&self) -> &GmpClassGroup {
+        &self.generator
+    }
+
+    pub fn value(&self) -> &GmpClassGroup {
+        &self.value
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, x: &Mpz) -> bool {
+        self.members.contains(x)
+    }
+
+    pub fn add(&mut self, x: Mpz) -> Result<(), AccumulatorError> {
+        self.add_batch(&[x]).map(|_| ())
+    }
+
+    /// Add all of `xs` with one exponentiation. Returns their product, which
+    /// holders of existing witnesses need for `update_membership_on_add` and
+    /// `NonMembershipWitness::update_on_add`. Nothing is added on error.
+    pub fn add_batch(&mut self, xs: &[Mpz]) -> Result<Mpz, AccumulatorError> {
+        let mut fresh = HashSet::with_capacity(xs.len());
+        for x in xs {
+            check_prime(x)?;
+            if self.members.contains(x) || !fresh.insert(x) {
+                return Err(AccumulatorError::AlreadyMember);
+            }
+        }
+        let added = product(xs);
+        self.value.pow(added.clone());
+        self.members.extend(xs.iter().cloned());
+        Ok(added)
+    }
+
+    /// Remove `x` using its membership witness: the new value is the witness.
+    pub fn delete_with_witness(
+        &mut self,
+        x: &Mpz,
+        witness: &GmpClassGroup,
+    ) -> Result<(), AccumulatorError> {
+        if !self.members.contains(x) {
+            return Err(AccumulatorError::NotMember);
+        }
+        if !verify_membership(&self.value, x, witness) {
+            return Err(AccumulatorError::BadWitness);
+        }
+        self.members.remove(x);
+        self.value = witness.clone();
+        Ok(())
+    }
+
+    /// Remove all of `xs`, recomputing the value from the remaining members.
+    /// Returns the product of the removed elements for witness updates.
+    /// Nothing is removed on error.
+    pub fn delete_batch(&mut self, xs: &[Mpz]) -> Result<Mpz, AccumulatorError> {
+        let removed: HashSet<&Mpz> = xs.iter().collect();
+        if removed.len() != xs.len() || !xs.iter().all(|x| self.members.contains(x)) {
+            return Err(AccumulatorError::NotMember);
+        }
+        for x in xs {
+            self.members.remove(x);
+        }
+        self.value = self.generator.clone();
+        self.value.pow(product(&self.members));
+        Ok(product(xs))
+    }
+
+    /// `g^(u / x)` for a member `x`.
+    pub fn membership_witness(&self, x: &Mpz) -> Result<GmpClassGroup, AccumulatorError> {
+        if !self.members.contains(x) {
+            return Err(AccumulatorError::NotMember);
+        }
+        let mut witness = self.generator.clone();
+        witness.pow(product(self.members.iter().filter(|y| *y != x)));
+        Ok(witness)
+    }
+
+    pub fn non_membership_witness(
+        &self,
+        x: &Mpz,
+    ) -> Result<NonMembershipWitness, AccumulatorError> {
+        check_prime(x)?;
+        if self.members.contains(x) {
+            return Err(AccumulatorError::AlreadyMember);
+        }
+        let u = product(&self.members);
+        // s * x + t * u = 1; shift t into [0, x) and compensate in s.
+        let (gcd, s, t) = x.gcdext(&u);
+        debug_assert!(gcd == Mpz::one());
+        let a = t.mod_floor(x);
+        let k = (&t - &a).div_floor(x);
+        let b = s + &k * &u;
+        Ok(NonMembershipWitness {
+            b: pow_signed(&self.generator, &b),
+            a,
+        })
+    }
+}
+
+pub fn verify_membership(value: &GmpClassGroup, x: &Mpz, witness: &GmpClassGroup) -> bool {
+    let mut check = witness.clone();
+    check.pow(x.clone());
+    check == *value
+}
+
+pub fn verify_non_membership(
+    generator: &GmpClassGroup,
+    value: &GmpClassGroup,
+    x: &Mpz,
+    witness: &NonMembershipWitness,
+) -> bool {
+    if witness.a < Mpz::zero()
+        || witness.a >= *x
+        || !GmpClassGroup::share_discriminant(&[generator, value, &witness.b])
+    {
+        return false;
+    }
+    GmpClassGroup::multi_pow(
+        &[value.clone(), witness.b.clone()],
+        &[witness.a.clone(), x.clone()],
+    ) == *generator
+}
+
+/// Membership witness after elements with product `added` joined.
+pub fn update_membership_on_add(witness: &GmpClassGroup, added: &Mpz) -> GmpClassGroup {
+    let mut updated = witness.clone();
+    updated.pow(added.clone());
+    updated
+}
+
+/// Membership witness for `x` after elements with product `deleted` left
+/// and the value became `new_value`.
+pub fn update_membership_on_delete(
+    x: &Mpz,
+    witness: &GmpClassGroup,
+    deleted: &Mpz,
+    new_value: &GmpClassGroup,
+) -> Result<GmpClassGroup, AccumulatorError> {
+    // s * x + t * deleted = 1 gives (witness^t * new_value^s)^x == new_value.
+    let (gcd, s, t) = x.gcdext(deleted);
+    if gcd != Mpz::one() {
+        return Err(AccumulatorError::NotMember);
+    }
+    Ok(pow_signed(witness, &t) * &pow_signed(new_value, &s))
+}
+
+impl NonMembershipWitness {
+    /// Witness for `x` after elements with product `added` joined an
+    /// accumulator whose value was `old_value`.
+    pub fn update_on_add(
+        &self,
+        x: &Mpz,
+        old_value: &GmpClassGroup,
+        added: &Mpz,
+    ) -> Result<Self, AccumulatorError> {
+        // a' = a / added mod x, so a' * added = a + k * x and b' = b - k * u.
+        let a = (&self.a * &added.invert(x).ok_or(AccumulatorError::AlreadyMember)?).mod_floor(x);
+        let k = (&a * added - &self.a).div_floor(x);
+        Ok(Self {
+            b: self.b.clone() * &pow_signed(old_value, &-k),
+            a,
+        })
+    }
+
+    /// Witness for `x` after elements with product `deleted` left and the
+    /// value became `new_value`.
+    pub fn update_on_delete(&self, x: &Mpz, new_value: &GmpClassGroup, deleted: &Mpz) -> Self {
+        // a * deleted = a' + k * x, so b' = b + k * (u / deleted).
+        let scaled = &self.a * deleted;
+        let a = scaled.mod_floor(x);
+        let k = (&scaled - &a).div_floor(x);
+        Self {
+            b: self.b.clone() * &pow_signed(new_value, &k),
+            a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn primes(count: usize) -> Vec<Mpz> {
+        let mut p = Mpz::from(1u64 << 40);
+        (0..count)
+            .map(|_| {
+                p = p.nextprime();
+                p.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accumulator_test() {
+        let p = primes(5);
+        let mut acc = Accumulator::new((-0xdead_beefi64).into());
+        acc.add_batch(&p[..3]).unwrap();
+        assert_eq!(acc.add(p[0].clone()), Err(AccumulatorError::AlreadyMember));
+        assert_eq!(acc.add(Mpz::from(15u64)), Err(AccumulatorError::NotPrime));
+
+        let mut w = acc.membership_witness(&p[0]).unwrap();
+        assert!(verify_membership(acc.value(), &p[0], &w));
+        assert!(!verify_membership(acc.value(), &p[3], &w));
+        let mut nw = acc.non_membership_witness(&p[3]).unwrap();
+        assert!(verify_non_membership(
+            acc.generator(),
+            acc.value(),
+            &p[3],
+            &nw
+        ));
+        let foreign = NonMembershipWitness {
+            b: GmpClassGroup::generator_for_discriminant((-0xdead_bee7i64).into()),
+            a: nw.a.clone(),
+        };
+        assert!(!verify_non_membership(
+            acc.generator(),
+            acc.value(),
+            &p[3],
+            &foreign
+        ));
+
+        let old_value = acc.value().clone();
+        let added = acc.add_batch(&p[4..]).unwrap();
+        w = update_membership_on_add(&w, &added);
+        assert!(verify_membership(acc.value(), &p[0], &w));
+        nw = nw.update_on_add(&p[3], &old_value, &added).unwrap();
+        assert!(verify_non_membership(
+            acc.generator(),
+            acc.value(),
+            &p[3],
+            &nw
+        ));
+
+        let deleted = acc.delete_batch(&p[1..3]).unwrap();
+        w = update_membership_on_delete(&p[0], &w, &deleted, acc.value()).unwrap();
+        assert!(verify_membership(acc.value(), &p[0], &w));
+        nw = nw.update_on_delete(&p[3], acc.value(), &deleted);
+        assert!(verify_non_membership(
+            acc.generator(),
+            acc.value(),
+            &p[3],
+            &nw
+        ));
+
+        let w4 = acc.membership_witness(&p[4]).unwrap();
+        acc.delete_with_witness(&p[4], &w4).unwrap();
+        assert_eq!(acc.len(), 1);
+        assert!(verify_membership(
+            acc.value(),
+            &p[0],
+            &acc.membership_witness(&p[0]).unwrap()
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![forbid(unsafe_code)]
+//! Cooperative cancellation for long-running group operations.
+//!
+//! A `Deadline` combines an optional point in time with a shared
+//! cancellation flag. Loops that may run for a long time (repeated squaring,
+//! exponentiation with large exponents, batches of proofs) poll it between
+//! iterations and stop with `Cancelled` once it has expired, leaving the
+//! element they were working on unspecified.
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Shared flag that can be raised from any thread to abandon work.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+/// A wall-clock limit and/or a cancellation token.
+/// `Deadline::none()` never expires.
+#[derive(Clone, Debug, Default)]
+pub struct Deadline {
+    at: Option<Instant>,
+    token: Option<CancellationToken>,
+}
+
+/// Returned when an operation stopped because its deadline expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation cancelled or past its deadline")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Deadline {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn at(at: Instant) -> Self {
+        Self {
+            at: Some(at),
+            token: None,
+        }
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Self::at(Instant::now() + timeout)
+    }
+
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    pub fn is_expired(&self) -> bool {
+        if let Some(token) = &self.token {
+            if token.is_cancelled() {
+                return true;
+            }
+        }
+        match self.at {
+            Some(at) => Instant::now() >= at,
+            None => false,
+        }
+    }
+
+    /// `Err(Cancelled)` once the deadline has expired.
+    #[inline]
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_expired() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
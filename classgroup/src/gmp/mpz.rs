@@ -138,6 +138,11 @@ pub struct Mpz {
 }
 
 const HEX_RADIX: u8 = 16;
+
+/// Largest integer `Deserialize` accepts. Every value in the class groups
+/// used here is at most a few thousand bits; callers that know the field
+/// should check its own bound on top.
+pub const MAX_SERDE_BITS: usize = 16384;
 impl Serialize for Mpz {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -166,7 +171,7 @@ impl<'de> Visitor<'de> for MpzVisitor {
     }
 
     fn visit_str<E: de::Error>(self, s: &str) -> Result<Mpz, E> {
-        Ok(Mpz::from_str_radix(s, HEX_RADIX).expect("Failed in serde"))
+        Mpz::from_canonical_hex(s, MAX_SERDE_BITS).map_err(E::custom)
     }
 }
 
@@ -223,6 +228,53 @@ impl Mpz {
         }
     }
 
+    /// Overwrite the limb buffer with zeros and set `self` to 0. GMP does not
+    /// scrub memory it frees or leaves behind on reallocation, so only the
+    /// current buffer is covered.
+    pub fn zeroize(&mut self) {
+        let limbs = self.mpz._mp_alloc.max(0) as usize;
+        let d = self.mpz._mp_d as *mut mp_limb_t;
+        if !d.is_null() {
+            for i in 0..limbs {
+                unsafe { std::ptr::write_volatile(d.add(i), 0) };
+            }
+        }
+        self.mpz._mp_size = 0;
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn limb_region(&self) -> (*const c_void, usize) {
+        let limbs = self.mpz._mp_alloc.max(0) as usize;
+        (
+            self.mpz._mp_d as *const c_void,
+            limbs * size_of::<mp_limb_t>(),
+        )
+    }
+
+    /// Keep the limb buffer out of swap with `mlock`. Returns whether that
+    /// succeeded; it stays locked only while `self` is not reallocated.
+    #[cfg(unix)]
+    pub fn mlock_limbs(&self) -> bool {
+        let (ptr, len) = self.limb_region();
+        len == 0 || unsafe { libc::mlock(ptr, len) } == 0
+    }
+
+    #[cfg(not(unix))]
+    pub fn mlock_limbs(&self) -> bool {
+        false
+    }
+
+    #[cfg(unix)]
+    pub fn munlock_limbs(&self) {
+        let (ptr, len) = self.limb_region();
+        if len != 0 {
+            unsafe { libc::munlock(ptr, len) };
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn munlock_limbs(&self) {}
+
     #[inline]
     pub fn size_in_base(&self, base: u8) -> usize {
         unsafe { __gmpz_sizeinbase(&self.mpz, base as c_int) as usize }
@@ -267,6 +319,42 @@ impl Mpz {
         }
     }
 
+    /// Parse the hex form `Serialize` writes, and nothing else: lowercase
+    /// digits, an optional leading `-`, no leading zeros, no `-0`, and at
+    /// most `max_bits` bits. The length is checked before GMP allocates.
+    pub fn from_canonical_hex(s: &str, max_bits: usize) -> Result<Mpz, MpzDecodeError> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if digits.is_empty() {
+            return Err(MpzDecodeError::Empty);
+        }
+        if !digits
+            .bytes()
+            .all(|d| matches!(d, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            return Err(MpzDecodeError::InvalidDigit);
+        }
+        if digits == "0" {
+            return if negative {
+                Err(MpzDecodeError::NonCanonical)
+            } else {
+                Ok(Mpz::zero())
+            };
+        }
+        if digits.starts_with('0') {
+            return Err(MpzDecodeError::NonCanonical);
+        }
+        // Exact bit length from the leading digit, without parsing.
+        let lead = (digits.as_bytes()[0] as char).to_digit(16).unwrap_or(0);
+        let bits = (digits.len() - 1) * 4 + (32 - lead.leading_zeros()) as usize;
+        if bits > max_bits {
+            return Err(MpzDecodeError::TooLong { bits, max_bits });
+        }
+        Mpz::from_str_radix(s, 16).map_err(|_| MpzDecodeError::InvalidDigit)
+    }
+
     #[inline]
     pub fn set(&mut self, other: &Mpz) {
         unsafe { __gmpz_set(&mut self.mpz, &other.mpz) }
@@ -553,6 +641,34 @@ impl Error for ParseMpzError {
     }
 }
 
+/// Why `Mpz::from_canonical_hex` refused its input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MpzDecodeError {
+    Empty,
+    InvalidDigit,
+    /// Leading zeros or `-0`: the value has another, shorter encoding.
+    NonCanonical,
+    TooLong {
+        bits: usize,
+        max_bits: usize,
+    },
+}
+
+impl fmt::Display for MpzDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MpzDecodeError::Empty => write!(f, "empty integer"),
+            MpzDecodeError::InvalidDigit => write!(f, "invalid hex digit in integer"),
+            MpzDecodeError::NonCanonical => write!(f, "non-canonical integer encoding"),
+            MpzDecodeError::TooLong { bits, max_bits } => {
+                write!(f, "integer of {} bits exceeds {} bits", bits, max_bits)
+            }
+        }
+    }
+}
+
+impl Error for MpzDecodeError {}
+
 impl Clone for Mpz {
     fn clone(&self) -> Mpz {
         unsafe {
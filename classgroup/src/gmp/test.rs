@@ -594,4 +594,47 @@ mod mpz {
         let zero = Mpz::from(-51213);
         assert_eq!(format!("{}", zero), "-51213");
     }
+
+    #[test]
+    fn test_zeroize() {
+        let mut x = Mpz::from_str_radix("123456789abcdef0123456789abcdef", 16).unwrap();
+        x.mlock_limbs();
+        x.munlock_limbs();
+        x.zeroize();
+        assert_eq!(x, Mpz::zero());
+        x += 7u64;
+        assert_eq!(x, Mpz::from(7u64));
+    }
+
+    #[test]
+    fn test_canonical_hex() {
+        use super::super::mpz::MpzDecodeError;
+        let x = Mpz::from_str_radix("-1f3", 16).unwrap();
+        assert_eq!(Mpz::from_canonical_hex(&x.to_str_radix(16), 9), Ok(x));
+        assert_eq!(Mpz::from_canonical_hex("0", 0), Ok(Mpz::zero()));
+        assert_eq!(
+            Mpz::from_canonical_hex("1f3", 8),
+            Err(MpzDecodeError::TooLong {
+                bits: 9,
+                max_bits: 8
+            })
+        );
+        assert_eq!(
+            Mpz::from_canonical_hex("01f3", 64),
+            Err(MpzDecodeError::NonCanonical)
+        );
+        assert_eq!(
+            Mpz::from_canonical_hex("-0", 64),
+            Err(MpzDecodeError::NonCanonical)
+        );
+        assert_eq!(
+            Mpz::from_canonical_hex("1F3", 64),
+            Err(MpzDecodeError::InvalidDigit)
+        );
+        assert_eq!(
+            Mpz::from_canonical_hex(" 1f3", 64),
+            Err(MpzDecodeError::InvalidDigit)
+        );
+        assert_eq!(Mpz::from_canonical_hex("-", 64), Err(MpzDecodeError::Empty));
+    }
 }
@@ -0,0 +1,263 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Fixed-width limb export of forms, for circuits and
+//! arithmetization-friendly hashes.
+//!
+//! A reduced form of discriminant `D` has `0 < a <= sqrt(|D| / 3)` and
+//! `|b| <= a`, so both fit in `(bit_length(D) + 1) / 2` bits and the limb
+//! count for a layout only depends on the discriminant. `c` is left out:
+//! it follows from `a`, `b` and `D`.
+use super::GmpClassGroup;
+use crate::gmp::mpz::Mpz;
+use crate::gmp::sign::Sign;
+use std::{error::Error, fmt};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// How an integer is cut into limbs. `limb_order` orders the limbs
+/// (`Little`: least significant limb first), `byte_order` the bytes inside
+/// each limb, padded to `ceil(limb_bits / 8)` bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LimbLayout {
+    pub limb_bits: usize,
+    pub count: usize,
+    pub limb_order: Endian,
+    pub byte_order: Endian,
+}
+
+/// The magnitude does not fit in `capacity` bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LimbOverflow {
+    pub bits: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for LimbOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "integer of {} bits exceeds {} limb bits",
+            self.bits, self.capacity
+        )
+    }
+}
+
+impl Error for LimbOverflow {}
+
+impl LimbLayout {
+    pub fn new(limb_bits: usize, count: usize) -> Self {
+        assert!(limb_bits > 0, "limbs must hold at least one bit");
+        LimbLayout {
+            limb_bits,
+            count,
+            limb_order: Endian::Little,
+            byte_order: Endian::Little,
+        }
+    }
+
+    /// Enough `limb_bits`-wide limbs for `a` and `|b|` of any reduced form
+    /// of `discriminant`.
+    pub fn for_discriminant(discriminant: &Mpz, limb_bits: usize) -> Self {
+        let bits = (discriminant.bit_length() + 1) / 2;
+        Self::new(limb_bits, (bits + limb_bits - 1) / limb_bits)
+    }
+
+    pub fn with_limb_order(self, limb_order: Endian) -> Self {
+        LimbLayout { limb_order, ..self }
+    }
+
+    pub fn with_byte_order(self, byte_order: Endian) -> Self {
+        LimbLayout { byte_order, ..self }
+    }
+
+    pub fn limb_bytes(&self) -> usize {
+        (self.limb_bits + 7) / 8
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.limb_bits * self.count
+    }
+
+    /// Stream the limbs of `|n|` in `limb_order`.
+    pub fn limbs(&self, n: &Mpz) -> Result<Limbs, LimbOverflow> {
+        let bits = if n.is_zero() { 0 } else { n.bit_length() };
+        if bits > self.capacity() {
+            return Err(LimbOverflow {
+                bits,
+                capacity: self.capacity(),
+            });
+        }
+        let mut mask = Mpz::zero();
+        mask.setbit(self.limb_bits);
+        Ok(Limbs {
+            magnitude: n.abs(),
+            layout: *self,
+            mask,
+            next: 0,
+        })
+    }
+}
+
+/// Iterator over the limbs of one integer; each item is one padded limb.
+pub struct Limbs {
+    magnitude: Mpz,
+    layout: LimbLayout,
+    mask: Mpz,
+    next: usize,
+}
+
+impl Iterator for Limbs {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.next == self.layout.count {
+            return None;
+        }
+        let pos = match self.layout.limb_order {
+            Endian::Little => self.next,
+            Endian::Big => self.layout.count - 1 - self.next,
+        };
+        self.next += 1;
+        let limb = (&self.magnitude >> (pos * self.layout.limb_bits)).modulus(&self.mask);
+        let digits = if limb.is_zero() {
+            Vec::new()
+        } else {
+            Vec::<u8>::from(&limb)
+        };
+        let mut bytes = vec![0u8; self.layout.limb_bytes() - digits.len()];
+        bytes.extend_from_slice(&digits);
+        if self.layout.byte_order == Endian::Little {
+            bytes.reverse();
+        }
+        Some(bytes)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.layout.count - self.next;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Limbs {}
+
+/// `|n|` as `count` little-endian 64-bit words.
+pub fn to_u64_limbs(n: &Mpz, count: usize) -> Result<Vec<u64>, LimbOverflow> {
+    let limbs = LimbLayout::new(64, count).limbs(n)?;
+    Ok(limbs
+        .map(|limb| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&limb);
+            u64::from_le_bytes(word)
+        })
+        .collect())
+}
+
+/// `a` and `b` of a form, cut into limbs; `b` is stored as sign and
+/// magnitude.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormLimbs {
+    pub a: Vec<Vec<u8>>,
+    pub b_negative: bool,
+    pub b: Vec<Vec<u8>>,
+}
+
+impl FormLimbs {
+    pub fn export(form: &GmpClassGroup, layout: &LimbLayout) -> Result<Self, LimbOverflow> {
+        Ok(FormLimbs {
+            a: layout.limbs(&form.a)?.collect(),
+            b_negative: form.b.sign() == Sign::Negative,
+            b: layout.limbs(&form.b)?.collect(),
+        })
+    }
+
+    /// All limbs back to back: `a`, then a 0/1 sign byte, then `b`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.a.concat();
+        out.push(self.b_negative as u8);
+        out.extend(self.b.concat());
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ClassGroup;
+
+    #[test]
+    fn limb_layouts() {
+        let n = Mpz::from_str_radix("0102030405060708090a", 16).unwrap();
+        let layout = LimbLayout::new(32, 3);
+        let limbs: Vec<_> = layout.limbs(&n).unwrap().collect();
+        assert_eq!(
+            limbs,
+            [vec![10, 9, 8, 7], vec![6, 5, 4, 3], vec![2, 1, 0, 0]]
+        );
+
+        let big = layout
+            .with_limb_order(Endian::Big)
+            .with_byte_order(Endian::Big);
+        let limbs: Vec<_> = big.limbs(&n).unwrap().collect();
+        assert_eq!(
+            limbs,
+            [vec![0, 0, 1, 2], vec![3, 4, 5, 6], vec![7, 8, 9, 10]]
+        );
+
+        assert_eq!(
+            to_u64_limbs(&n, 2).unwrap(),
+            [0x0304_0506_0708_090a, 0x0102]
+        );
+        assert_eq!(
+            LimbLayout::new(32, 2).limbs(&n).err(),
+            Some(LimbOverflow {
+                bits: 73,
+                capacity: 64
+            })
+        );
+
+        // Odd widths, as for a field element.
+        let layout = LimbLayout::new(12, 7);
+        let limbs: Vec<_> = layout.limbs(&n).unwrap().collect();
+        assert_eq!(limbs[0], [0x0a, 0x09]);
+        assert_eq!(limbs[6], [0x01, 0x00]);
+    }
+
+    #[test]
+    fn form_limbs_fit_discriminant() {
+        let disc: Mpz = (-0xdead_beefi64).into();
+        let mut form = GmpClassGroup::generator_for_discriminant(disc.clone());
+        form.pow(Mpz::from(0x1234_5678u64));
+        let layout = LimbLayout::for_discriminant(&disc, 8);
+        let limbs = FormLimbs::export(&form, &layout).unwrap();
+        assert_eq!(layout.count, 2);
+        assert_eq!(limbs.a.len(), 2);
+        assert_eq!(limbs.b_negative, form.b.sign() == Sign::Negative);
+
+        let join = |limbs: &[Vec<u8>]| {
+            let be: Vec<u8> = limbs
+                .iter()
+                .rev()
+                .flat_map(|l| l.iter().rev())
+                .cloned()
+                .collect();
+            Mpz::from(&be[..])
+        };
+        assert_eq!(join(&limbs.a), form.a);
+        assert_eq!(join(&limbs.b), form.b.abs());
+    }
+}
@@ -15,6 +15,7 @@
 use super::gmp::mpz::Mpz;
 use super::gmp::mpz::ProbabPrimeResult::NotPrime;
 use super::ClassGroup;
+use crate::deadline::{Cancelled, Deadline};
 use num_traits::{One, Zero};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -24,7 +25,10 @@ use std::{
     ops::{Mul, MulAssign},
 };
 mod congruence;
+pub mod export;
 pub(super) mod ffi;
+pub mod reference;
+pub mod window;
 
 #[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Debug, Clone, Deserialize, Serialize)]
 pub struct GmpClassGroup {
@@ -35,7 +39,7 @@ pub struct GmpClassGroup {
 }
 
 #[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Hash, Debug)]
-pub struct Ctx {
+pub(crate) struct Ctx {
     negative_a: Mpz,
     r: Mpz,
     denom: Mpz,
@@ -70,6 +74,55 @@ impl GmpClassGroup {
         (self.a, self.b)
     }
 
+    pub fn c(&self) -> &Mpz {
+        &self.c
+    }
+
+    pub fn as_abc(&self) -> (&Mpz, &Mpz, &Mpz) {
+        (&self.a, &self.b, &self.c)
+    }
+
+    /// The form `(a, b, c)` of discriminant `b^2 - 4ac`, taken as is.
+    pub fn from_raw_unchecked(a: Mpz, b: Mpz, c: Mpz) -> Self {
+        let discriminant = &(&b * &b) - &(&(&a * &c) * 4u64);
+        GmpClassGroup {
+            a,
+            b,
+            c,
+            discriminant,
+        }
+    }
+
+    /// `(a, b, c)` if it is a reduced positive definite form of
+    /// `discriminant`, the only representation `reduce` produces.
+    pub fn from_raw(a: Mpz, b: Mpz, c: Mpz, discriminant: &Mpz) -> Option<Self> {
+        let form = Self::from_raw_unchecked(a, b, c);
+        let zero = Mpz::zero();
+        let reduced = -&form.a < form.b
+            && form.b <= form.a
+            && form.a <= form.c
+            && !(form.a == form.c && form.b < zero);
+        if &form.discriminant == discriminant && form.a > zero && reduced {
+            Some(form)
+        } else {
+            None
+        }
+    }
+
+    /// Whether every form of `forms` has `b^2 - 4ac` equal to the stored
+    /// discriminant of the first. Verifiers run this on received forms
+    /// before composing them, which would panic or give garbage otherwise.
+    pub(crate) fn share_discriminant(forms: &[&GmpClassGroup]) -> bool {
+        let discriminant = match forms.first() {
+            Some(first) => &first.discriminant,
+            None => return true,
+        };
+        forms.iter().all(|form| {
+            &form.discriminant == discriminant
+                && &(&form.b * &form.b) - &(&(&form.a * &form.c) * 4u64) == *discriminant
+        })
+    }
+
     // 出处: [Cohen1993, Algorithm 5.4.9] NUCOMP算法, 计算两个二次型的复合.
     // 原理: [Cohen1993, Definition 5.4.6, Section 5.2] 二次型的复合就是理想的乘.
     // TODO: 看起来更像[Cohen1993, Algorithm 5.4.7], 而不是5.4.9 (NUCOMP).
@@ -320,7 +373,7 @@ impl GmpClassGroup {
     /// Panics if called recursively.  This library guarantees that it will
     /// never call this function from any function that takes a parameter of
     /// type `&mut Ctx`.
-    pub fn with_context<T, U>(cb: T) -> U
+    pub(crate) fn with_context<T, U>(cb: T) -> U
     where
         T: FnOnce(&mut Ctx) -> U,
     {
@@ -328,6 +381,78 @@ impl GmpClassGroup {
         CTX.with(|x| opt = Some(cb(&mut x.borrow_mut())));
         opt.unwrap()
     }
+
+    /// Like `ClassGroup::repeated_square`, but gives up once `deadline`
+    /// expires. `self` is left with an unspecified intermediate value then.
+    pub fn repeated_square_until(
+        &mut self,
+        iterations: u64,
+        deadline: &Deadline,
+    ) -> Result<(), Cancelled> {
+        Self::with_context(|ctx| {
+            for _ in 0..iterations {
+                deadline.check()?;
+                self.inner_square(ctx)
+            }
+            Ok(())
+        })
+    }
+
+    /// Computes $$\prod_i bases_i^{exponents_i}$$ with one shared chain of
+    /// squarings (Straus' method), instead of one chain per base.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slices are empty or of different lengths. All bases must
+    /// share one discriminant and all exponents must be non-negative.
+    pub fn multi_pow(bases: &[GmpClassGroup], exponents: &[Mpz]) -> GmpClassGroup {
+        assert_eq!(bases.len(), exponents.len());
+        assert!(!bases.is_empty());
+        for base in &bases[1..] {
+            check_same_discriminant(&bases[0], base);
+        }
+        debug_assert!(exponents.iter().all(|e| *e >= Mpz::zero()));
+        let bits = exponents.iter().map(|e| e.bit_length()).max().unwrap_or(0);
+        let mut acc: Option<GmpClassGroup> = None;
+        Self::with_context(|ctx| {
+            for i in (0..bits).rev() {
+                if let Some(acc) = acc.as_mut() {
+                    acc.inner_square(ctx);
+                }
+                for (base, exponent) in bases.iter().zip(exponents) {
+                    if !exponent.tstbit(i) {
+                        continue;
+                    }
+                    if let Some(acc) = acc.as_mut() {
+                        acc.inner_multiply(base, ctx);
+                        continue;
+                    }
+                    acc = Some(base.clone());
+                }
+            }
+        });
+        acc.unwrap_or_else(|| bases[0].identity())
+    }
+
+    /// Like `ClassGroup::pow`, but polls `deadline` once per exponent bit.
+    pub fn pow_until(&mut self, mut exponent: Mpz, deadline: &Deadline) -> Result<(), Cancelled> {
+        self.assert_valid();
+        debug_assert!(exponent >= Mpz::zero());
+        let mut state = self.identity();
+        Self::with_context(|ctx| loop {
+            deadline.check()?;
+            let is_odd = exponent.tstbit(0);
+            exponent >>= 1;
+            if is_odd {
+                state.inner_multiply(self, ctx);
+            }
+            if exponent.is_zero() {
+                *self = state;
+                return Ok(());
+            }
+            self.inner_square(ctx);
+        })
+    }
 }
 
 impl Default for GmpClassGroup {
@@ -341,12 +466,29 @@ impl Default for GmpClassGroup {
     }
 }
 
+/// Composing forms of two discriminants gives a form of neither, with no
+/// error. Debug builds, and release builds with the
+/// `checked-discriminants` feature, panic instead.
+#[cfg_attr(not(debug_assertions), inline(always))]
+fn check_same_discriminant(lhs: &GmpClassGroup, rhs: &GmpClassGroup) {
+    #[cfg(any(debug_assertions, feature = "checked-discriminants"))]
+    assert!(
+        lhs.discriminant == rhs.discriminant,
+        "composing class group elements of different discriminants"
+    );
+    let _ = (lhs, rhs);
+}
+
 impl<B: Borrow<GmpClassGroup>> MulAssign<B> for GmpClassGroup {
     #[cfg_attr(not(debug_assertions), inline(always))]
     fn mul_assign(&mut self, rhs: B) {
         let rhs = rhs.borrow();
-        debug_assert!(self.discriminant == rhs.discriminant);
+        check_same_discriminant(self, rhs);
+        #[cfg(debug_assertions)]
+        let expected = reference::compose(self, rhs);
         GmpClassGroup::with_context(|ctx| self.inner_multiply(rhs, ctx));
+        #[cfg(debug_assertions)]
+        debug_assert_eq!(*self, expected, "composition disagrees with the reference");
     }
 }
 
@@ -516,22 +658,9 @@ impl ClassGroup for GmpClassGroup {
         form
     }
 
-    fn pow(&mut self, mut exponent: Mpz) {
-        self.assert_valid();
-        debug_assert!(exponent >= Mpz::zero());
-        let mut state = self.identity();
-        loop {
-            let is_odd = exponent.tstbit(0);
-            exponent >>= 1;
-            if is_odd {
-                state *= &*self
-            }
-            if exponent.is_zero() {
-                *self = state;
-                break;
-            }
-            self.square();
-        }
+    fn pow(&mut self, exponent: Mpz) {
+        let strategy = window::PowStrategy::for_bits(exponent.bit_length());
+        self.pow_with(exponent, strategy);
     }
 }
 
@@ -621,6 +750,102 @@ mod test {
         s.pow(r);
         println!("s= {:?}", s);
     }
+
+    #[test]
+    fn multi_pow_test() {
+        let s = GmpClassGroup::new(
+            16.into(),
+            (-23).into(),
+            5837_3892.into(),
+            (-0xdead_beefi64).into(),
+        );
+        let mut t = s.clone();
+        t.square();
+        let exponents: Vec<Mpz> = vec![12345.into(), 678.into()];
+        let mut expected = s.clone();
+        expected.pow(exponents[0].clone());
+        let mut t_exp = t.clone();
+        t_exp.pow(exponents[1].clone());
+        expected *= &t_exp;
+        assert_eq!(GmpClassGroup::multi_pow(&[s, t], &exponents), expected);
+    }
+
+    #[test]
+    fn reference_compose_test() {
+        let g = GmpClassGroup::generator_for_discriminant((-0xdead_beefi64).into());
+        let mut forms = vec![g.clone()];
+        for _ in 0..64 {
+            let mut next = forms.last().unwrap().clone();
+            next.square();
+            next *= &g;
+            forms.push(next);
+        }
+        for (i, f1) in forms.iter().enumerate() {
+            for f2 in &forms[i..] {
+                let mut fast = f1.clone();
+                GmpClassGroup::with_context(|ctx| fast.inner_multiply(f2, ctx));
+                assert_eq!(fast, reference::compose(f1, f2));
+                assert_eq!(reference::compose(f1, f2), reference::compose(f2, f1));
+            }
+        }
+
+        // An unreduced representative reduces to the same form.
+        let mut shifted = GmpClassGroup {
+            b: &g.b + &(&g.a * 6u64),
+            ..g.clone()
+        };
+        shifted.c = (&(&shifted.b * &shifted.b) - &g.discriminant).div_floor(&(&g.a * 4u64));
+        reference::reduce(&mut shifted);
+        assert_eq!(shifted, g);
+    }
+
+    #[test]
+    fn raw_abc_test() {
+        let g = GmpClassGroup::generator_for_discriminant((-0xdead_beefi64).into());
+        let (a, b, c) = g.as_abc();
+        assert_eq!(c, g.c());
+        let rebuilt = GmpClassGroup::from_raw(a.clone(), b.clone(), c.clone(), &g.discriminant);
+        assert_eq!(rebuilt, Some(g.clone()));
+        assert_eq!(
+            GmpClassGroup::from_raw_unchecked(a.clone(), b.clone(), c.clone()),
+            g
+        );
+
+        // Same discriminant, but not reduced.
+        let b2 = b + &(a * 2u64);
+        let c2 = (&(&b2 * &b2) - &g.discriminant).div_floor(&(a * 4u64));
+        assert_eq!(
+            GmpClassGroup::from_raw(a.clone(), b2, c2, &g.discriminant),
+            None
+        );
+        assert_eq!(
+            GmpClassGroup::from_raw(a.clone(), b.clone(), c + 1u64, &g.discriminant),
+            None
+        );
+    }
+
+    #[test]
+    fn pow_until_test() {
+        use crate::deadline::CancellationToken;
+        let s = GmpClassGroup::new(
+            16.into(),
+            (-23).into(),
+            5837_3892.into(),
+            (-0xdead_beefi64).into(),
+        );
+        let mut expected = s.clone();
+        expected.pow(12345.into());
+        let mut t = s.clone();
+        t.pow_until(12345.into(), &Deadline::none()).unwrap();
+        assert_eq!(t, expected);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let deadline = Deadline::none().with_token(token);
+        let mut u = s.clone();
+        assert_eq!(u.pow_until(12345.into(), &deadline), Err(Cancelled));
+        assert_eq!(u.repeated_square_until(10, &deadline), Err(Cancelled));
+    }
     #[test]
     fn thread_test() {
         use std::str::FromStr;
@@ -0,0 +1,87 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Textbook composition, kept deliberately slow and simple.
+//!
+//! `inner_multiply` works on a shared `Ctx` and the FFI helpers and has
+//! been rearranged for speed, which makes it hard to audit. This module
+//! composes with [Cohen1993, Algorithm 5.4.7] and reduces with
+//! [Cohen1993, Algorithm 5.4.2], in plain `Mpz` arithmetic and without any
+//! state, so it can serve as an oracle: a reduced form is unique in its
+//! class, so both must return the same `(a, b, c)`. Debug builds check
+//! every `*=` against it.
+use super::GmpClassGroup;
+use crate::gmp::mpz::Mpz;
+use std::mem::swap;
+
+/// Bring `b` into `(-a, a]` without changing the class.
+fn normalize(f: &mut GmpClassGroup) {
+    let two_a = &f.a * 2u64;
+    if -&f.a < f.b && f.b <= f.a {
+        return;
+    }
+    let r = (&f.a - &f.b).div_floor(&two_a);
+    // (a, b, c) -> (a, b + 2ra, ar^2 + br + c)
+    f.c = &(&(&f.a * &r) * &r) + &(&(&f.b * &r) + &f.c);
+    f.b = &f.b + &(&two_a * &r);
+}
+
+pub fn reduce(f: &mut GmpClassGroup) {
+    normalize(f);
+    while f.a > f.c || (f.a == f.c && f.b < Mpz::zero()) {
+        // (a, b, c) -> (c, -b, a)
+        swap(&mut f.a, &mut f.c);
+        f.b = -&f.b;
+        normalize(f);
+    }
+}
+
+/// The reduced composition of `f1` and `f2`, which must be primitive
+/// positive definite forms of the same discriminant.
+pub fn compose(f1: &GmpClassGroup, f2: &GmpClassGroup) -> GmpClassGroup {
+    let (f1, f2) = if f1.a > f2.a { (f2, f1) } else { (f1, f2) };
+    let s = (&f1.b + &f2.b).div_floor(&Mpz::from(2u64));
+    let n = &f2.b - &s;
+
+    let (d, y1) = if f2.a.is_multiple_of(&f1.a) {
+        (f1.a.clone(), Mpz::zero())
+    } else {
+        // u a2 + v a1 = d
+        let (d, u, _v) = f2.a.gcdext(&f1.a);
+        (d, u)
+    };
+
+    let (d1, x2, y2) = if s.is_multiple_of(&d) {
+        (d, Mpz::zero(), -Mpz::one())
+    } else {
+        // x2 s + y2 d = d1
+        let (d1, x2, y2) = s.gcdext(&d);
+        (d1, x2, -y2)
+    };
+
+    let v1 = f1.a.div_floor(&d1);
+    let v2 = f2.a.div_floor(&d1);
+    let r = (&(&(&y1 * &y2) * &n) - &(&x2 * &f2.c)).modulus(&v1);
+    let a = &v1 * &v2;
+    let b = &f2.b + &(&(&v2 * &r) * 2u64);
+    let c = (&(&b * &b) - &f1.discriminant).div_floor(&(&a * 4u64));
+
+    let mut f3 = GmpClassGroup {
+        a,
+        b,
+        c,
+        discriminant: f1.discriminant.clone(),
+    };
+    reduce(&mut f3);
+    f3
+}
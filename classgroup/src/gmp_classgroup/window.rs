@@ -0,0 +1,216 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Choice of the exponentiation algorithm by exponent size.
+//!
+//! Inverting a form only negates `b`, so signed digits are free and a
+//! width-`w` NAF beats a fixed window of the same table size: one
+//! multiplication per `w + 1` bits on average against one per `w - 1`.
+//! The table of odd powers costs `2^(w-2)` multiplications up front, which
+//! only pays off for long exponents; below `BINARY_MAX_BITS` bits plain
+//! square-and-multiply is faster. There are no fixed-base tables, every
+//! call builds its own.
+//!
+//! The thresholds were picked from timings on the 1827-bit group; the
+//! `benchmark` module of the protocol crate measures them on the machine at
+//! hand.
+use super::GmpClassGroup;
+use crate::gmp::mpz::Mpz;
+use crate::ClassGroup;
+use num_traits::Zero;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PowStrategy {
+    /// Right-to-left square-and-multiply.
+    Binary,
+    /// Left-to-right width-`w` NAF, `2 <= w <= 16`.
+    Wnaf(u32),
+}
+
+/// Longest exponent, in bits, for which `Binary` is chosen.
+pub const BINARY_MAX_BITS: usize = 24;
+
+/// `(bits, w)`: exponents shorter than `bits` use `Wnaf(w)`, ascending.
+pub const WNAF_WINDOWS: [(usize, u32); 5] =
+    [(64, 3), (200, 4), (600, 5), (1600, 6), (usize::MAX, 7)];
+
+impl PowStrategy {
+    pub fn for_bits(bits: usize) -> Self {
+        if bits <= BINARY_MAX_BITS {
+            return PowStrategy::Binary;
+        }
+        let w = WNAF_WINDOWS
+            .iter()
+            .find(|(below, _)| bits < *below)
+            .map_or(7, |(_, w)| *w);
+        PowStrategy::Wnaf(w)
+    }
+}
+
+/// Width-`w` NAF of `exponent`, least significant digit first. Nonzero
+/// digits are odd and below `2^(w-1)` in absolute value, and of any `w`
+/// consecutive digits at most one is nonzero.
+pub fn wnaf(exponent: &Mpz, w: u32) -> Vec<i64> {
+    assert!((2..=16).contains(&w));
+    debug_assert!(*exponent >= Mpz::zero());
+    let modulus = 1i64 << w;
+    let mut k = exponent.clone();
+    let mut digits = Vec::with_capacity(exponent.bit_length() + 1);
+    while !k.is_zero() {
+        let mut digit = 0i64;
+        if k.tstbit(0) {
+            for i in 0..w as usize {
+                if k.tstbit(i) {
+                    digit |= 1 << i;
+                }
+            }
+            if digit >= modulus / 2 {
+                digit -= modulus;
+            }
+            k = if digit > 0 {
+                k - digit as u64
+            } else {
+                k + (-digit) as u64
+            };
+        }
+        digits.push(digit);
+        k >>= 1;
+    }
+    digits
+}
+
+impl GmpClassGroup {
+    /// `ClassGroup::pow` with the algorithm given rather than chosen by
+    /// `PowStrategy::for_bits`.
+    pub fn pow_with(&mut self, exponent: Mpz, strategy: PowStrategy) {
+        match strategy {
+            PowStrategy::Binary => self.pow_binary(exponent),
+            PowStrategy::Wnaf(w) => self.pow_wnaf(&exponent, w),
+        }
+    }
+
+    pub(super) fn pow_binary(&mut self, mut exponent: Mpz) {
+        self.assert_valid();
+        debug_assert!(exponent >= Mpz::zero());
+        let mut state = self.identity();
+        loop {
+            let is_odd = exponent.tstbit(0);
+            exponent >>= 1;
+            if is_odd {
+                state *= &*self
+            }
+            if exponent.is_zero() {
+                *self = state;
+                break;
+            }
+            self.square();
+        }
+    }
+
+    fn pow_wnaf(&mut self, exponent: &Mpz, w: u32) {
+        self.assert_valid();
+        let digits = wnaf(exponent, w);
+
+        // self^1, self^3, ..., self^(2^(w-1) - 1) and their inverses.
+        let mut square = self.clone();
+        square.square();
+        let mut odd = vec![self.clone()];
+        for i in 1..1usize << (w - 2) {
+            let next = odd[i - 1].clone() * &square;
+            odd.push(next);
+        }
+        let inverted: Vec<GmpClassGroup> = odd
+            .iter()
+            .map(|power| {
+                let mut power = power.clone();
+                power.inverse();
+                power.reduce();
+                power
+            })
+            .collect();
+
+        let mut acc: Option<GmpClassGroup> = None;
+        Self::with_context(|ctx| {
+            for digit in digits.iter().rev() {
+                if let Some(acc) = acc.as_mut() {
+                    acc.inner_square(ctx);
+                }
+                let power = match *digit {
+                    0 => continue,
+                    d if d > 0 => &odd[(d / 2) as usize],
+                    d => &inverted[(-d / 2) as usize],
+                };
+                if let Some(acc) = acc.as_mut() {
+                    acc.inner_multiply(power, ctx);
+                    continue;
+                }
+                acc = Some(power.clone());
+            }
+        });
+        *self = acc.unwrap_or_else(|| self.identity());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn form() -> GmpClassGroup {
+        GmpClassGroup::from_ab_discriminant(16.into(), (-23).into(), (-0xdead_beefi64).into())
+    }
+
+    #[test]
+    fn wnaf_digits_test() {
+        let exponent = Mpz::from(0x1234_5678_9abc_def1u64);
+        for w in 2..=8 {
+            let digits = wnaf(&exponent, w);
+            let mut value = 0i128;
+            for digit in digits.iter().rev() {
+                value = 2 * value + *digit as i128;
+            }
+            assert_eq!(value, 0x1234_5678_9abc_def1i128);
+            assert!(digits
+                .iter()
+                .all(|d| *d == 0 || (d % 2 != 0 && d.abs() < 1 << (w - 1))));
+            for window in digits.windows(w as usize) {
+                assert!(window.iter().filter(|d| **d != 0).count() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn pow_strategies_agree_test() {
+        let exponents = [
+            Mpz::zero(),
+            Mpz::from(1u64),
+            Mpz::from(123u64),
+            Mpz::from(u64::MAX),
+            (Mpz::from(u64::MAX) << 200) + 0xfeed_u64,
+        ];
+        for exponent in exponents.iter() {
+            let mut expected = form();
+            expected.pow_with(exponent.clone(), PowStrategy::Binary);
+            for w in 2..=7 {
+                let mut actual = form();
+                actual.pow_with(exponent.clone(), PowStrategy::Wnaf(w));
+                assert_eq!(actual, expected);
+            }
+            let mut chosen = form();
+            chosen.pow(exponent.clone());
+            assert_eq!(chosen, expected);
+        }
+        assert_eq!(PowStrategy::for_bits(16), PowStrategy::Binary);
+        assert_eq!(PowStrategy::for_bits(128), PowStrategy::Wnaf(4));
+        assert_eq!(PowStrategy::for_bits(3000), PowStrategy::Wnaf(7));
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![forbid(unsafe_code)]
+//! Hashing labeled transcripts to primes.
+//!
+//! Every prime challenge in the crate comes from here, so the encoding and
+//! sampling rules only need reviewing once. The transcript is SHA-256 over
+//! the length-prefixed label followed by the appended items. Candidate `i`
+//! is the first `bits / 8` bytes of `SHA-256(transcript || i)` read
+//! big-endian, with the top and bottom bits forced to 1. The first candidate
+//! that passes the primality test is returned, so the output is fixed by
+//! the transcript and the parameters alone.
+use super::gmp::mpz::{Mpz, ProbabPrimeResult};
+use super::gmp_classgroup::GmpClassGroup;
+use sha2::{Digest, Sha256};
+
+/// Labeled, length-prefixed input of a hash-to-prime.
+#[derive(Clone)]
+pub struct PrimeTranscript {
+    hasher: Sha256,
+}
+
+impl PrimeTranscript {
+    pub fn new(label: &[u8]) -> Self {
+        let mut transcript = Self {
+            hasher: Sha256::new(),
+        };
+        transcript.append_bytes(label);
+        transcript
+    }
+
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.hasher.update((bytes.len() as u64).to_be_bytes());
+        self.hasher.update(bytes);
+        self
+    }
+
+    /// A sign byte, then the magnitude as length-prefixed bytes.
+    pub fn append_mpz(&mut self, x: &Mpz) -> &mut Self {
+        let magnitude: Vec<u8> = x.into();
+        self.hasher.update([(*x < Mpz::zero()) as u8]);
+        self.append_bytes(&magnitude)
+    }
+
+    /// The `a` and `b` coefficients; `c` follows from the discriminant.
+    pub fn append_element(&mut self, element: &GmpClassGroup) -> &mut Self {
+        self.append_mpz(&element.a).append_mpz(&element.b)
+    }
+
+    fn candidate(&self, counter: u64, bytes: usize) -> Mpz {
+        let digest = self.hasher.clone().chain(counter.to_be_bytes()).finalize();
+        Mpz::from(&digest[..bytes])
+    }
+}
+
+/// Output size and primality confidence of a hash-to-prime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashToPrime {
+    bits: usize,
+    reps: i32,
+}
+
+impl Default for HashToPrime {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl HashToPrime {
+    /// 128-bit primes, 25 Miller-Rabin rounds: what the proofs in this crate
+    /// use.
+    pub const DEFAULT: Self = Self {
+        bits: 128,
+        reps: 25,
+    };
+
+    /// # Panics
+    ///
+    /// Panics unless `bits` is a multiple of 8 in `16..=256` and `reps` is
+    /// positive.
+    pub fn new(bits: usize, reps: i32) -> Self {
+        assert!(bits % 8 == 0 && (16..=256).contains(&bits));
+        assert!(reps > 0);
+        Self { bits, reps }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    pub fn reps(&self) -> i32 {
+        self.reps
+    }
+
+    /// The first candidate that is (probably) prime.
+    pub fn prime(&self, transcript: &PrimeTranscript) -> Mpz {
+        (0u64..)
+            .map(|counter| {
+                let mut candidate = transcript.candidate(counter, self.bits / 8);
+                candidate.setbit(self.bits - 1);
+                candidate.setbit(0);
+                candidate
+            })
+            .find(|candidate| candidate.probab_prime(self.reps) != ProbabPrimeResult::NotPrime)
+            .unwrap()
+    }
+
+    /// A `bits`-bit integer from the same transcript, without the primality
+    /// filter, for protocols that also need a plain challenge.
+    pub fn integer(&self, transcript: &PrimeTranscript) -> Mpz {
+        transcript.candidate(0, self.bits / 8)
+    }
+}
+
+/// `HashToPrime::DEFAULT` applied to `data` under `label`.
+pub fn hash_to_prime(label: &[u8], data: &[u8]) -> Mpz {
+    let mut transcript = PrimeTranscript::new(label);
+    transcript.append_bytes(data);
+    HashToPrime::DEFAULT.prime(&transcript)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LABEL: &[u8] = b"classgroup/hash-to-prime/test";
+
+    #[test]
+    fn hash_to_prime_vectors() {
+        let vectors: [(&[u8], usize, &str); 3] = [
+            (b"", 128, "81ebba40187fe932e96d599589bc7835"),
+            (b"abc", 128, "df21f10f1b355cfb713b8da3dafdf98d"),
+            (b"abc", 64, "deef3e4fb4e77443"),
+        ];
+        for (data, bits, expected) in vectors.iter() {
+            let mut transcript = PrimeTranscript::new(LABEL);
+            transcript.append_bytes(data);
+            let prime = HashToPrime::new(*bits, 25).prime(&transcript);
+            assert_eq!(prime, Mpz::from_str_radix(expected, 16).unwrap());
+            assert_eq!(prime.bit_length(), *bits);
+        }
+        assert_eq!(hash_to_prime(LABEL, b"abc").to_str_radix(16), vectors[1].2);
+    }
+
+    #[test]
+    fn hash_to_prime_is_domain_separated() {
+        assert_ne!(
+            hash_to_prime(LABEL, b"abc"),
+            hash_to_prime(b"other", b"abc")
+        );
+        // The length prefix keeps ("ab", "c") apart from ("a", "bc").
+        let mut split_one = PrimeTranscript::new(LABEL);
+        split_one.append_bytes(b"ab").append_bytes(b"c");
+        let mut split_two = PrimeTranscript::new(LABEL);
+        split_two.append_bytes(b"a").append_bytes(b"bc");
+        assert_ne!(
+            HashToPrime::DEFAULT.prime(&split_one),
+            HashToPrime::DEFAULT.prime(&split_two)
+        );
+    }
+}
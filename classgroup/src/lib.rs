@@ -15,13 +15,16 @@
 use num_traits::{One, Zero};
 use std::ops::{Mul, MulAssign, Rem, ShlAssign};
 
+pub mod accumulator;
+pub mod deadline;
 pub mod gmp;
+pub mod hash_to_prime;
+pub mod poe;
+pub mod prelude;
 
 pub mod gmp_classgroup;
-pub use self::gmp_classgroup::{
-    do_compute,
-    ffi::{export_obj, import_obj},
-};
+pub use self::deadline::{CancellationToken, Cancelled, Deadline};
+pub use self::gmp_classgroup::do_compute;
 pub trait BigNum:
     Zero
     + One
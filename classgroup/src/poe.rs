@@ -0,0 +1,159 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![forbid(unsafe_code)]
+//! Proofs of exponentiation in a class group.
+//!
+//! `PoeProof` is Wesolowski's proof that `u^x = w` for a public `x`. The
+//! verifier does two exponentiations with exponents of ~128 bits instead of
+//! one by `x`. `PokeProof` is the PoKE2 protocol of Boneh, Bünz and Fisch:
+//! the prover shows it knows `x` with `u^x = w` without revealing it, given
+//! a generator `g` both sides agree on. Both protocols are made
+//! non-interactive with `HashToPrime::DEFAULT` challenges.
+use super::gmp::mpz::Mpz;
+use super::gmp_classgroup::GmpClassGroup;
+use super::hash_to_prime::{HashToPrime, PrimeTranscript};
+use super::ClassGroup;
+use serde::{Deserialize, Serialize};
+
+/// Proof that `u^x = w` for a public, non-negative `x`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoeProof {
+    pub q: GmpClassGroup,
+}
+
+impl PoeProof {
+    const LABEL: &'static [u8] = b"classgroup/poe/v1";
+
+    pub fn prove(u: &GmpClassGroup, x: &Mpz, w: &GmpClassGroup) -> Self {
+        let l = Self::challenge(u, x, w);
+        let mut q = u.clone();
+        q.pow(x.div_floor(&l));
+        Self { q }
+    }
+
+    pub fn verify(&self, u: &GmpClassGroup, x: &Mpz, w: &GmpClassGroup) -> bool {
+        if *x < Mpz::zero() || !GmpClassGroup::share_discriminant(&[u, w, &self.q]) {
+            return false;
+        }
+        let l = Self::challenge(u, x, w);
+        let r = x.mod_floor(&l);
+        GmpClassGroup::multi_pow(&[self.q.clone(), u.clone()], &[l, r]) == *w
+    }
+
+    fn challenge(u: &GmpClassGroup, x: &Mpz, w: &GmpClassGroup) -> Mpz {
+        let mut transcript = PrimeTranscript::new(Self::LABEL);
+        transcript.append_element(u).append_element(w).append_mpz(x);
+        HashToPrime::DEFAULT.prime(&transcript)
+    }
+}
+
+/// Proof of knowledge of a non-negative `x` with `u^x = w`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PokeProof {
+    /// `g^x`.
+    pub z: GmpClassGroup,
+    pub q: GmpClassGroup,
+    pub r: Mpz,
+}
+
+impl PokeProof {
+    const LABEL: &'static [u8] = b"classgroup/poke2/v1";
+
+    pub fn prove(g: &GmpClassGroup, u: &GmpClassGroup, x: &Mpz, w: &GmpClassGroup) -> Self {
+        let mut z = g.clone();
+        z.pow(x.clone());
+        let (l, alpha) = Self::challenges(g, u, w, &z);
+        let mut q = Self::combined_base(g, u, &alpha);
+        q.pow(x.div_floor(&l));
+        Self {
+            z,
+            q,
+            r: x.mod_floor(&l),
+        }
+    }
+
+    pub fn verify(&self, g: &GmpClassGroup, u: &GmpClassGroup, w: &GmpClassGroup) -> bool {
+        if !GmpClassGroup::share_discriminant(&[g, u, w, &self.z, &self.q]) {
+            return false;
+        }
+        let (l, alpha) = Self::challenges(g, u, w, &self.z);
+        if self.r < Mpz::zero() || self.r >= l {
+            return false;
+        }
+        let base = Self::combined_base(g, u, &alpha);
+        let mut z_alpha = self.z.clone();
+        z_alpha.pow(alpha);
+        GmpClassGroup::multi_pow(&[self.q.clone(), base], &[l, self.r.clone()])
+            == w.clone() * &z_alpha
+    }
+
+    /// The prime `l`, then `alpha` from the transcript extended by `l`.
+    fn challenges(
+        g: &GmpClassGroup,
+        u: &GmpClassGroup,
+        w: &GmpClassGroup,
+        z: &GmpClassGroup,
+    ) -> (Mpz, Mpz) {
+        let mut transcript = PrimeTranscript::new(Self::LABEL);
+        transcript
+            .append_element(g)
+            .append_element(u)
+            .append_element(w)
+            .append_element(z);
+        let l = HashToPrime::DEFAULT.prime(&transcript);
+        transcript.append_mpz(&l);
+        (l, HashToPrime::DEFAULT.integer(&transcript))
+    }
+
+    /// `u * g^alpha`.
+    fn combined_base(g: &GmpClassGroup, u: &GmpClassGroup, alpha: &Mpz) -> GmpClassGroup {
+        let mut g_alpha = g.clone();
+        g_alpha.pow(alpha.clone());
+        g_alpha * u
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn poe_poke_test() {
+        let g = GmpClassGroup::generator_for_discriminant((-0xdead_beefi64).into());
+        let mut u = g.clone();
+        u.pow(1234567u64.into());
+        let x = Mpz::from(1u64 << 50) * Mpz::from(987654321u64);
+        let mut w = u.clone();
+        w.pow(x.clone());
+
+        let poe = PoeProof::prove(&u, &x, &w);
+        assert!(poe.verify(&u, &x, &w));
+        assert!(!poe.verify(&u, &(&x + 1u64), &w));
+
+        let poke = PokeProof::prove(&g, &u, &x, &w);
+        assert!(poke.verify(&g, &u, &w));
+        assert!(!poke.verify(&g, &u, &u));
+
+        // A form of another discriminant is refused, not composed.
+        let foreign = GmpClassGroup::generator_for_discriminant((-0xdead_bee7i64).into());
+        assert!(!PoeProof { q: foreign.clone() }.verify(&u, &x, &w));
+        assert!(!poe.verify(&u, &x, &foreign));
+        let forged = PokeProof {
+            z: foreign.clone(),
+            ..poke.clone()
+        };
+        assert!(!forged.verify(&g, &u, &w));
+        assert!(!poke.verify(&foreign, &u, &w));
+    }
+}
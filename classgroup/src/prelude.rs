@@ -0,0 +1,29 @@
+// Copyright 2018 Chia Network Inc and POA Networks Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! The types and traits downstream code needs, to be glob imported.
+//!
+//! Everything reachable from here is covered by the crate's semver
+//! guarantees, and it is all `multi_party_ecdsa` imports. The form
+//! arithmetic behind it (the composition context, the GMP bindings) is not
+//! public and may change between any two releases.
+pub use crate::accumulator::{Accumulator, AccumulatorError, NonMembershipWitness};
+pub use crate::deadline::{CancellationToken, Cancelled, Deadline};
+pub use crate::gmp::mpz::{Mpz, ProbabPrimeResult};
+pub use crate::gmp::sign::Sign;
+pub use crate::gmp_classgroup::export::{FormLimbs, LimbLayout};
+pub use crate::gmp_classgroup::window::PowStrategy;
+pub use crate::gmp_classgroup::{do_compute, GmpClassGroup};
+pub use crate::hash_to_prime::{HashToPrime, PrimeTranscript};
+pub use crate::poe::{PoeProof, PokeProof};
+pub use crate::{BigNum, BigNumExt, ClassGroup};
@@ -0,0 +1,137 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Move round messages across an air gap as QR or NFC sized text frames.
+//!
+//!     dmz-airgap show --session S --from ID [--to ID] [--chars N] [FILE]
+//!     dmz-airgap scan [--out FILE]
+//!
+//! `show` reads a payload (stdin without FILE) and prints its frames one per
+//! line, for a QR encoder such as `qrencode` to display in turn. `scan`
+//! reads scanned lines from stdin, for instance `zbarcam --raw`, reports
+//! frames to rescan on stderr, and once the parcel is complete prints its
+//! session, sender and recipient and writes the payload to FILE (stdout
+//! without it).
+use multi_party_ecdsa::communication::airgap::*;
+use std::io::{BufRead, Read, Write};
+use std::process::exit;
+
+const USAGE: &str = "usage: dmz-airgap show --session S --from ID [--to ID] [--chars N] [FILE]
+       dmz-airgap scan [--out FILE]";
+
+fn fail(why: impl std::fmt::Display) -> ! {
+    eprintln!("dmz-airgap: {}", why);
+    exit(1);
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(1);
+}
+
+fn show(mut args: impl Iterator<Item = String>) {
+    let (mut session, mut from, mut to, mut path) = (None, None, None, None);
+    let mut chars = DEFAULT_FRAME_CHARS;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--session" => session = Some(args.next().unwrap_or_else(|| usage())),
+            "--from" => from = Some(args.next().unwrap_or_else(|| usage())),
+            "--to" => to = Some(args.next().unwrap_or_else(|| usage())),
+            "--chars" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => chars = n,
+                None => usage(),
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let (session, from) = match (session, from) {
+        (Some(session), Some(from)) => (session, from),
+        _ => usage(),
+    };
+
+    let mut payload = Vec::new();
+    let read = match &path {
+        Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut payload)),
+        None => std::io::stdin().read_to_end(&mut payload),
+    };
+    if let Err(why) = read {
+        fail(why);
+    }
+    let parcel = Parcel {
+        session,
+        from,
+        to,
+        payload,
+    };
+    match parcel.to_frames(chars) {
+        Ok(frames) => {
+            for frame in frames {
+                println!("{}", frame);
+            }
+        }
+        Err(why) => fail(why),
+    }
+}
+
+fn scan(mut args: impl Iterator<Item = String>) {
+    let out = match (args.next().as_deref(), args.next()) {
+        (None, None) => None,
+        (Some("--out"), Some(path)) => Some(path),
+        _ => usage(),
+    };
+    let mut scanner = Scanner::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.unwrap_or_else(|why| fail(why));
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parcel = match scanner.scan(&line) {
+            Ok(Some(parcel)) => parcel,
+            Ok(None) => continue,
+            Err(why) => {
+                eprintln!("dmz-airgap: {}", why);
+                continue;
+            }
+        };
+        eprintln!(
+            "session {} from {} to {}",
+            parcel.session,
+            parcel.from,
+            parcel.to.as_deref().unwrap_or("all")
+        );
+        let written = match &out {
+            Some(path) => std::fs::write(path, &parcel.payload),
+            None => std::io::stdout().write_all(&parcel.payload),
+        };
+        if let Err(why) = written {
+            fail(why);
+        }
+        return;
+    }
+    fail("input ended before the parcel was complete");
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("show") => show(args),
+        Some("scan") => scan(args),
+        Some("-h") | Some("--help") => println!("{}", USAGE),
+        _ => usage(),
+    }
+}
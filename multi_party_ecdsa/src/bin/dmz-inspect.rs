@@ -0,0 +1,86 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Print what a serialized protocol message, ciphertext, proof or keygen
+//! result decodes to, and whether it is internally consistent.
+//!
+//!     dmz-inspect [--sign | --keygen | --json] [--threshold T] [FILE]
+//!
+//! Reads stdin without FILE. Exits 1 when the input does not decode and 2
+//! when it decodes but has problems.
+use multi_party_ecdsa::protocols::multi_party::dmz21::inspect::*;
+use std::io::Read;
+use std::process::exit;
+
+const USAGE: &str = "usage: dmz-inspect [--sign | --keygen | --json] [--threshold T] [FILE]";
+
+fn main() {
+    let mut kind = None;
+    let mut threshold = 1;
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sign" | "--keygen" | "--json" => kind = Some(arg),
+            "--threshold" => match args.next().and_then(|t| t.parse().ok()) {
+                Some(t) => threshold = t,
+                None => {
+                    eprintln!("{}", USAGE);
+                    exit(1);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(1);
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let read = match &path {
+        Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)),
+        None => std::io::stdin().read_to_end(&mut bytes),
+    };
+    if let Err(why) = read {
+        eprintln!("dmz-inspect: {}", why);
+        exit(1);
+    }
+
+    let inspection = match kind.as_deref() {
+        Some("--sign") => inspect_sign_message(&bytes),
+        Some("--keygen") => inspect_keygen_message(&bytes, threshold),
+        Some("--json") => inspect_json(&bytes),
+        _ => inspect(&bytes, threshold),
+    };
+    match inspection {
+        Ok(inspection) => {
+            print!("{}", inspection);
+            if !inspection.is_consistent() {
+                exit(2);
+            }
+        }
+        Err(why) => {
+            eprintln!("dmz-inspect: {}", why);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Text frames for carrying round messages to and from an air-gapped
+//! cosigner, by QR code or NFC tag.
+//!
+//! A `Parcel` (session, sender, recipient and payload) is bincode encoded,
+//! cut by `chunking::split` and every chunk written as one line
+//!
+//!     DMZ21/<seq>/<total>/<HEX>/<CHECK>
+//!
+//! with `seq` counting from 1, `HEX` the chunk in upper case hex and
+//! `CHECK` the first four bytes of its SHA-256 in hex. The whole line is in
+//! the QR alphanumeric alphabet, which packs 5.5 bits per character. A
+//! misread frame fails `CHECK` and is simply scanned again; a `Scanner`
+//! tolerates frames in any order and repeats, and the chunk headers catch
+//! frames of two different parcels mixed up.
+//!
+//! `dmz-airgap` turns a payload into frames for a QR encoder on one side
+//! and the output of a scanner back into the payload on the other.
+use crate::communication::chunking::{self, Reassembler, HEADER_LEN};
+use crate::communication::sending_messages::SendingMessages;
+use anyhow::format_err;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const PREFIX: &str = "DMZ21";
+
+/// Frame length fitting a version 10 QR code at error correction level M
+/// (311 alphanumeric characters) with room to spare.
+pub const DEFAULT_FRAME_CHARS: usize = 300;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parcel {
+    pub session: String,
+    pub from: String,
+    /// `None` for a broadcast or subset message.
+    pub to: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+impl Parcel {
+    /// One parcel per payload of `msg`; results and empty messages carry
+    /// none.
+    pub fn from_message(session: &str, from: &str, msg: &SendingMessages) -> Vec<Parcel> {
+        let parcel = |to: Option<&String>, payload: &Vec<u8>| Parcel {
+            session: session.to_string(),
+            from: from.to_string(),
+            to: to.cloned(),
+            payload: payload.clone(),
+        };
+        match msg {
+            SendingMessages::NormalMessage(to, payload) => vec![parcel(Some(to), payload)],
+            SendingMessages::P2pMessage(payloads) => {
+                let mut to: Vec<&String> = payloads.keys().collect();
+                to.sort();
+                to.into_iter()
+                    .map(|to| parcel(Some(to), &payloads[to]))
+                    .collect()
+            }
+            SendingMessages::SubsetMessage(payload)
+            | SendingMessages::BroadcastMessage(payload) => {
+                vec![parcel(None, payload)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The frames to display one after another, at most `max_chars` long.
+    pub fn to_frames(&self, max_chars: usize) -> Result<Vec<String>, anyhow::Error> {
+        let overhead = format!("{}/65536/65536//00000000", PREFIX).len();
+        if max_chars <= overhead + 2 * HEADER_LEN {
+            return Err(format_err!(
+                "Frames of {} characters are too short",
+                max_chars
+            ));
+        }
+        let bytes = bincode::serialize(self)
+            .map_err(|why| format_err!("Serialize error in air gap parcel, cause {}", why))?;
+        let chunks = chunking::split(&bytes, (max_chars - overhead) / 2)?;
+        let total = chunks.len();
+        Ok(chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let check = &Sha256::digest(chunk)[..4];
+                format!(
+                    "{}/{}/{}/{}/{}",
+                    PREFIX,
+                    i + 1,
+                    total,
+                    hex::encode_upper(chunk),
+                    hex::encode_upper(check)
+                )
+            })
+            .collect())
+    }
+}
+
+/// Collects scanned frames until a parcel is complete.
+#[derive(Default)]
+pub struct Scanner {
+    reassembler: Reassembler,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in one scanned line. Returns the parcel once all of its frames
+    /// are in. A frame that fails its checksum is an error and changes
+    /// nothing, so the caller can ask for a rescan.
+    pub fn scan(&mut self, frame: &str) -> Result<Option<Parcel>, anyhow::Error> {
+        let fields: Vec<&str> = frame.trim().split('/').collect();
+        if fields.len() != 5 || fields[0] != PREFIX {
+            return Err(format_err!("Not an air gap frame"));
+        }
+        let not_hex = |_: hex::FromHexError| format_err!("Frame {} is not hex", fields[1]);
+        let chunk = hex::decode(fields[3]).map_err(not_hex)?;
+        let check = hex::decode(fields[4]).map_err(not_hex)?;
+        if Sha256::digest(&chunk)[..4] != check[..] {
+            return Err(format_err!(
+                "Frame {} fails its checksum, scan it again",
+                fields[1]
+            ));
+        }
+        let bytes = match self.reassembler.accept("scanner", &chunk)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let parcel = bincode::deserialize(&bytes)
+            .map_err(|why| format_err!("Deserialize error in air gap parcel, cause {}", why))?;
+        Ok(Some(parcel))
+    }
+}
+
+#[test]
+fn airgap_test() {
+    let msg = SendingMessages::BroadcastMessage((0..2000u32).map(|i| i as u8).collect());
+    let parcels = Parcel::from_message("s1", "2", &msg);
+    assert_eq!(parcels.len(), 1);
+    let frames = parcels[0].to_frames(DEFAULT_FRAME_CHARS).unwrap();
+    assert!(frames.len() > 1);
+    for frame in &frames {
+        assert!(frame.len() <= DEFAULT_FRAME_CHARS);
+        assert!(frame
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase() || c == '/'));
+    }
+
+    let mut scanner = Scanner::new();
+    let mut misread = frames[0].clone().into_bytes();
+    misread[20] = if misread[20] == b'A' { b'B' } else { b'A' };
+    assert!(scanner.scan(&String::from_utf8(misread).unwrap()).is_err());
+    let mut parcel = None;
+    for frame in frames.iter().rev().chain(frames.iter().take(1)) {
+        if let Some(done) = scanner.scan(frame).unwrap() {
+            parcel = Some(done);
+        }
+    }
+    assert_eq!(parcel.unwrap(), parcels[0]);
+    assert!(scanner.scan("hello").is_err());
+}
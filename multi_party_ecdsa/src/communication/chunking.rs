@@ -0,0 +1,317 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Splitting of round payloads for transports with a small message size
+//! limit.
+//!
+//! `split` cuts a payload into chunks of at most `max_chunk` bytes. Each
+//! chunk starts with a header: the SHA-256 of the whole payload, its index
+//! and the chunk count (big endian `u32`s). The receiver feeds chunks, in
+//! any order and with repeats, to a `Reassembler`, which hands back the
+//! payload once the last one is in and its hash checks out. A chunk that
+//! contradicts an earlier one of the same payload, or a reassembled payload
+//! of the wrong hash, is an error naming the sender. So is a chunk that
+//! would open more partial payloads for its sender than
+//! `max_partials_per_sender`, or take the buffered bytes of all senders
+//! past `max_buffered`: an honest party has one or two payloads in flight.
+//!
+//! Like compression, this lives at the transport: split after the outbox,
+//! reassemble before the inbox.
+use crate::communication::sending_messages::SendingMessages;
+use anyhow::format_err;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+pub const HEADER_LEN: usize = 32 + 4 + 4;
+
+/// Reassembly refuses payloads of more chunks than this.
+pub const MAX_CHUNKS: u32 = 1 << 16;
+
+/// Default limit on payloads one sender can have partly reassembled.
+pub const MAX_PARTIALS_PER_SENDER: usize = 4;
+
+/// Default limit on chunk data buffered for all senders together.
+pub const MAX_BUFFERED: usize = 64 << 20;
+
+pub fn split(payload: &[u8], max_chunk: usize) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+    if max_chunk <= HEADER_LEN {
+        return Err(format_err!(
+            "Chunk size {} leaves no room for data",
+            max_chunk
+        ));
+    }
+    let room = max_chunk - HEADER_LEN;
+    let count = ((payload.len() + room - 1) / room).max(1);
+    if count > MAX_CHUNKS as usize {
+        return Err(format_err!(
+            "Payload of {} bytes needs too many chunks",
+            payload.len()
+        ));
+    }
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&Sha256::digest(payload));
+    let mut chunks = Vec::with_capacity(count);
+    for index in 0..count {
+        let start = (index * room).min(payload.len());
+        let data = &payload[start..(start + room).min(payload.len())];
+        let mut chunk = Vec::with_capacity(HEADER_LEN + data.len());
+        chunk.extend_from_slice(&digest);
+        chunk.extend_from_slice(&(index as u32).to_be_bytes());
+        chunk.extend_from_slice(&(count as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// `msg` as a sequence of messages of the same kind and recipients, one per
+/// chunk. A p2p recipient with fewer chunks than the others is left out of
+/// the later messages.
+pub fn split_message(
+    msg: SendingMessages,
+    max_chunk: usize,
+) -> Result<Vec<SendingMessages>, anyhow::Error> {
+    let messages = match msg {
+        SendingMessages::NormalMessage(to, payload) => split(&payload, max_chunk)?
+            .into_iter()
+            .map(|chunk| SendingMessages::NormalMessage(to.clone(), chunk))
+            .collect(),
+        SendingMessages::SubsetMessage(payload) => split(&payload, max_chunk)?
+            .into_iter()
+            .map(SendingMessages::SubsetMessage)
+            .collect(),
+        SendingMessages::BroadcastMessage(payload) => split(&payload, max_chunk)?
+            .into_iter()
+            .map(SendingMessages::BroadcastMessage)
+            .collect(),
+        SendingMessages::P2pMessage(payloads) => {
+            let mut messages: Vec<HashMap<String, Vec<u8>>> = Vec::new();
+            for (to, payload) in payloads {
+                for (index, chunk) in split(&payload, max_chunk)?.into_iter().enumerate() {
+                    if messages.len() == index {
+                        messages.push(HashMap::new());
+                    }
+                    messages[index].insert(to.clone(), chunk);
+                }
+            }
+            messages
+                .into_iter()
+                .map(SendingMessages::P2pMessage)
+                .collect()
+        }
+        other => vec![other],
+    };
+    Ok(messages)
+}
+
+struct Partial {
+    count: u32,
+    parts: BTreeMap<u32, Vec<u8>>,
+}
+
+/// Chunks received so far, per sender and payload.
+pub struct Reassembler {
+    pending: HashMap<(String, [u8; 32]), Partial>,
+    buffered: usize,
+    max_partials_per_sender: usize,
+    max_buffered: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::with_limits(MAX_PARTIALS_PER_SENDER, MAX_BUFFERED)
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(max_partials_per_sender: usize, max_buffered: usize) -> Self {
+        Self {
+            pending: HashMap::new(),
+            buffered: 0,
+            max_partials_per_sender,
+            max_buffered,
+        }
+    }
+
+    /// Take in one chunk `from` sent. Returns the payload once complete.
+    pub fn accept(&mut self, from: &str, chunk: &[u8]) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        if chunk.len() < HEADER_LEN {
+            return Err(format_err!(
+                "Chunk from party {} shorter than its header",
+                from
+            ));
+        }
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&chunk[..32]);
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&chunk[32..36]);
+        let index = u32::from_be_bytes(word);
+        word.copy_from_slice(&chunk[36..40]);
+        let count = u32::from_be_bytes(word);
+        if count == 0 || count > MAX_CHUNKS || index >= count {
+            return Err(format_err!(
+                "Chunk {} of {} from party {}",
+                index,
+                count,
+                from
+            ));
+        }
+
+        let key = (from.to_string(), digest);
+        if !self.pending.contains_key(&key) {
+            let open = self
+                .pending
+                .keys()
+                .filter(|(sender, _)| sender == from)
+                .count();
+            if open >= self.max_partials_per_sender {
+                return Err(format_err!(
+                    "Party {} has too many payloads in flight",
+                    from
+                ));
+            }
+        }
+        let data = &chunk[HEADER_LEN..];
+        let partial = self.pending.entry(key.clone()).or_insert_with(|| Partial {
+            count,
+            parts: BTreeMap::new(),
+        });
+        if partial.count != count {
+            return Err(format_err!(
+                "Party {} sent chunks of one payload with two counts",
+                from
+            ));
+        }
+        match partial.parts.get(&index) {
+            Some(first) if first[..] != *data => {
+                return Err(format_err!(
+                    "Party {} sent two different chunks {}",
+                    from,
+                    index
+                ))
+            }
+            Some(_) => return Ok(None),
+            None => {
+                if self.buffered + data.len() > self.max_buffered {
+                    if partial.parts.is_empty() {
+                        self.pending.remove(&key);
+                    }
+                    return Err(format_err!("Chunk from party {} exceeds the buffer", from));
+                }
+                partial.parts.insert(index, data.to_vec());
+                self.buffered += data.len();
+            }
+        }
+        if partial.parts.len() < count as usize {
+            return Ok(None);
+        }
+
+        let partial = self.pending.remove(&key).unwrap();
+        self.buffered -= partial.parts.values().map(Vec::len).sum::<usize>();
+        let payload: Vec<u8> = partial
+            .parts
+            .into_iter()
+            .flat_map(|(_, part)| part)
+            .collect();
+        if Sha256::digest(&payload)[..] != digest[..] {
+            return Err(format_err!(
+                "Chunks from party {} do not hash to their payload",
+                from
+            ));
+        }
+        Ok(Some(payload))
+    }
+
+    /// Payloads with chunks still missing.
+    pub fn incomplete(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Bytes of chunk data held for incomplete payloads.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+}
+
+#[test]
+fn chunking_test() {
+    let payload: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+    let mut chunks = split(&payload, 100).unwrap();
+    assert_eq!(chunks.len(), 17);
+    assert!(chunks.iter().all(|c| c.len() <= 100));
+
+    // Out of order and with a redelivery.
+    chunks.reverse();
+    let again = chunks[3].clone();
+    let mut reassembler = Reassembler::new();
+    let mut done = None;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let out = reassembler.accept("1", chunk).unwrap();
+        if i == 5 {
+            assert!(reassembler.accept("1", &again).unwrap().is_none());
+        }
+        if out.is_some() {
+            done = out;
+        }
+    }
+    assert_eq!(done.unwrap(), payload);
+    assert_eq!(reassembler.incomplete(), 0);
+
+    assert_eq!(split(&[], 100).unwrap().len(), 1);
+    assert!(reassembler
+        .accept("1", &split(&[], 100).unwrap()[0])
+        .unwrap()
+        .unwrap()
+        .is_empty());
+    assert!(split(&payload, HEADER_LEN).is_err());
+
+    let mut forged = split(&payload, 100).unwrap();
+    *forged[0].last_mut().unwrap() ^= 1;
+    let mut reassembler = Reassembler::new();
+    let results: Vec<_> = forged.iter().map(|c| reassembler.accept("2", c)).collect();
+    assert!(results
+        .last()
+        .unwrap()
+        .as_ref()
+        .unwrap_err()
+        .to_string()
+        .contains("party 2"));
+    assert_eq!(reassembler.buffered(), 0);
+
+    // One sender cannot open more payloads than its share.
+    let mut reassembler = Reassembler::with_limits(2, MAX_BUFFERED);
+    let firsts: Vec<Vec<u8>> = (0..3u8)
+        .map(|i| split(&vec![i; 300], 100).unwrap().remove(0))
+        .collect();
+    reassembler.accept("3", &firsts[0]).unwrap();
+    reassembler.accept("3", &firsts[1]).unwrap();
+    assert!(reassembler.accept("3", &firsts[2]).is_err());
+    reassembler.accept("4", &firsts[2]).unwrap();
+    assert_eq!(reassembler.incomplete(), 3);
+
+    // Nor can all of them together buffer more than the budget.
+    let mut reassembler = Reassembler::with_limits(MAX_PARTIALS_PER_SENDER, 150);
+    let chunks = split(&payload, 100).unwrap();
+    reassembler.accept("5", &chunks[0]).unwrap();
+    assert!(reassembler.accept("6", &chunks[1]).unwrap().is_none());
+    assert!(reassembler.accept("6", &chunks[2]).is_err());
+    assert_eq!(reassembler.buffered(), 2 * (100 - HEADER_LEN));
+}
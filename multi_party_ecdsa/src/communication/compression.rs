@@ -0,0 +1,158 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! zstd compression of round payloads on the wire.
+//!
+//! A session compresses only if every party offered `FEATURE` in its
+//! `Hello`; `Agreed::compression` then returns the codec. Every payload is
+//! framed with one leading byte, `RAW` or `ZSTD`. Payloads below the
+//! threshold, and those that do not shrink, go out raw, so small rounds pay
+//! one byte. The CL ciphertexts and proofs of the 3072-bit parameter set
+//! are where it pays off.
+//!
+//! Apply it at the transport, outside any `DeliveryBuffer`: `compress`
+//! right before sending and `decode` right after receiving, so that the
+//! buffers and the state machines only ever see plain payloads.
+use crate::communication::sending_messages::SendingMessages;
+use anyhow::format_err;
+use std::io::Read;
+
+/// Name of the capability in `Hello::features`.
+pub const FEATURE: &str = "compress-zstd";
+
+pub const RAW: u8 = 0;
+pub const ZSTD: u8 = 1;
+
+/// Payloads shorter than this are not worth a compression attempt.
+pub const DEFAULT_THRESHOLD: usize = 1024;
+
+/// No round message of any parameter set comes near this. It is the limit
+/// of a codec that was not given the caps of its session.
+pub const MAX_PAYLOAD: usize = 16 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    pub threshold: usize,
+    pub level: i32,
+    /// A frame that inflates beyond this is refused. Decoding allocates as
+    /// the output grows, never this much up front.
+    pub max_payload: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            level: 3,
+            max_payload: MAX_PAYLOAD,
+        }
+    }
+}
+
+impl Compression {
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        if payload.len() >= self.threshold {
+            if let Ok(packed) = zstd::bulk::compress(payload, self.level) {
+                if packed.len() < payload.len() {
+                    let mut frame = Vec::with_capacity(packed.len() + 1);
+                    frame.push(ZSTD);
+                    frame.extend_from_slice(&packed);
+                    return frame;
+                }
+            }
+        }
+        let mut frame = Vec::with_capacity(payload.len() + 1);
+        frame.push(RAW);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    pub fn decode(&self, frame: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        match frame.split_first() {
+            Some((&RAW, payload)) => Ok(payload.to_vec()),
+            Some((&ZSTD, packed)) => {
+                let decoder = zstd::stream::read::Decoder::with_buffer(packed)
+                    .map_err(|why| format_err!("Decompress error in payload, cause {}", why))?;
+                let mut payload = Vec::new();
+                decoder
+                    .take(self.max_payload as u64 + 1)
+                    .read_to_end(&mut payload)
+                    .map_err(|why| format_err!("Decompress error in payload, cause {}", why))?;
+                if payload.len() > self.max_payload {
+                    return Err(format_err!(
+                        "Payload inflates beyond {} bytes",
+                        self.max_payload
+                    ));
+                }
+                Ok(payload)
+            }
+            Some((tag, _)) => Err(format_err!("Unknown compression tag {} in payload", tag)),
+            None => Err(format_err!("Empty payload frame")),
+        }
+    }
+
+    /// `msg` with every payload encoded; results and empty messages are
+    /// not sent and stay as they are.
+    pub fn compress(&self, msg: SendingMessages) -> SendingMessages {
+        match msg {
+            SendingMessages::NormalMessage(to, payload) => {
+                SendingMessages::NormalMessage(to, self.encode(&payload))
+            }
+            SendingMessages::P2pMessage(payloads) => SendingMessages::P2pMessage(
+                payloads
+                    .into_iter()
+                    .map(|(to, payload)| (to, self.encode(&payload)))
+                    .collect(),
+            ),
+            SendingMessages::SubsetMessage(payload) => {
+                SendingMessages::SubsetMessage(self.encode(&payload))
+            }
+            SendingMessages::BroadcastMessage(payload) => {
+                SendingMessages::BroadcastMessage(self.encode(&payload))
+            }
+            other => other,
+        }
+    }
+}
+
+#[test]
+fn compression_test() {
+    let codec = Compression::default();
+    let small = vec![7u8; 10];
+    assert_eq!(codec.encode(&small)[0], RAW);
+    assert_eq!(codec.decode(&codec.encode(&small)).unwrap(), small);
+
+    let large: Vec<u8> = (0..8192u32).map(|i| (i % 17) as u8).collect();
+    let frame = codec.encode(&large);
+    assert_eq!(frame[0], ZSTD);
+    assert!(frame.len() < large.len() / 4);
+    assert_eq!(codec.decode(&frame).unwrap(), large);
+    let tight = Compression {
+        max_payload: large.len() - 1,
+        ..codec
+    };
+    assert!(tight.decode(&frame).is_err());
+
+    match codec.compress(SendingMessages::BroadcastMessage(large.clone())) {
+        SendingMessages::BroadcastMessage(frame) => {
+            assert_eq!(codec.decode(&frame).unwrap(), large)
+        }
+        _ => panic!("message kind changed"),
+    }
+    assert!(codec.decode(&[]).is_err());
+    assert!(codec.decode(&[9, 1, 2]).is_err());
+}
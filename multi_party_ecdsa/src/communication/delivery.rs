@@ -0,0 +1,236 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Outbox/inbox buffering for at-least-once delivery of round messages.
+//!
+//! The state machines in `protocols` are not idempotent with respect to the
+//! network: a lost message stalls the round, and a message that arrives twice
+//! is silently dropped only as long as it is byte-for-byte the first one seen.
+//! The executor driving them can put a `DeliveryBuffer` in between: every
+//! outgoing payload stays in the outbox until the recipient acknowledges it,
+//! and every incoming payload is checked against the inbox before it is handed
+//! to `msg_handler`. Both halves are keyed by (session, round, party) and the
+//! whole buffer can be serialized, so it survives a restart of the executor.
+use crate::communication::sending_messages::SendingMessages;
+use anyhow::format_err;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Identifies one round message of one session.
+/// `party` is the recipient in the outbox and the sender in the inbox.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeliveryKey {
+    pub session: String,
+    pub round: u32,
+    pub party: String,
+}
+
+/// Outgoing payloads waiting for an acknowledgement.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Outbox {
+    pending: BTreeMap<DeliveryKey, Vec<u8>>,
+}
+
+/// Keys of the payloads already handed to the state machine.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Inbox {
+    seen: BTreeSet<DeliveryKey>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DeliveryBuffer {
+    pub outbox: Outbox,
+    pub inbox: Inbox,
+}
+
+/// Round of a serialized `MultiKeyGenMessage` or `MultiSignMessage`.
+///
+/// Both enums are encoded with bincode's default options, which put the
+/// variant index in the first four bytes (little endian), so the round can be
+/// read without deserializing the whole payload.
+pub fn round_of(msg: &[u8]) -> Option<u32> {
+    if msg.len() < 4 {
+        return None;
+    }
+    let mut tag = [0u8; 4];
+    tag.copy_from_slice(&msg[..4]);
+    Some(u32::from_le_bytes(tag))
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the payloads of `msg` for the given recipients.
+    ///
+    /// P2p and normal messages carry their own recipients, broadcast and
+    /// subset messages are queued once for every entry of `recipients`.
+    /// Results and empty messages are not round messages and are ignored.
+    /// Returns the number of queued payloads.
+    pub fn push(
+        &mut self,
+        session: &str,
+        recipients: &[String],
+        msg: &SendingMessages,
+    ) -> Result<usize, anyhow::Error> {
+        let mut queued = Vec::new();
+        match msg {
+            SendingMessages::NormalMessage(to, payload) => queued.push((to.clone(), payload)),
+            SendingMessages::P2pMessage(payloads) => {
+                for (to, payload) in payloads.iter() {
+                    queued.push((to.clone(), payload));
+                }
+            }
+            SendingMessages::SubsetMessage(payload)
+            | SendingMessages::BroadcastMessage(payload) => {
+                for to in recipients.iter() {
+                    queued.push((to.clone(), payload));
+                }
+            }
+            _ => {}
+        }
+
+        let count = queued.len();
+        for (party, payload) in queued {
+            let round = round_of(payload).ok_or(format_err!(
+                "Payload too short to carry a round in outbox push"
+            ))?;
+            let key = DeliveryKey {
+                session: session.to_string(),
+                round,
+                party,
+            };
+            self.pending.insert(key, payload.clone());
+        }
+        Ok(count)
+    }
+
+    /// Drop a payload once the recipient confirmed it.
+    /// Returns false if nothing was pending under `key`.
+    pub fn ack(&mut self, key: &DeliveryKey) -> bool {
+        self.pending.remove(key).is_some()
+    }
+
+    /// Payloads that still have to be (re)sent, in (session, round, party) order.
+    pub fn pending(&self) -> impl Iterator<Item = (&DeliveryKey, &Vec<u8>)> {
+        self.pending.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Forget everything queued for a finished or aborted session.
+    pub fn clear_session(&mut self, session: &str) {
+        self.pending.retain(|key, _| key.session != session);
+    }
+}
+
+impl Inbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an incoming payload.
+    /// Returns the key to acknowledge and whether the payload is new, i.e.
+    /// whether it should be passed on to `msg_handler`. Duplicates must still
+    /// be acknowledged, since the sender evidently missed the first ack.
+    pub fn accept(
+        &mut self,
+        session: &str,
+        sender: &str,
+        msg: &[u8],
+    ) -> Result<(DeliveryKey, bool), anyhow::Error> {
+        let round = round_of(msg).ok_or(format_err!(
+            "Payload too short to carry a round in inbox accept"
+        ))?;
+        let key = DeliveryKey {
+            session: session.to_string(),
+            round,
+            party: sender.to_string(),
+        };
+        let fresh = self.seen.insert(key.clone());
+        Ok((key, fresh))
+    }
+
+    pub fn clear_session(&mut self, session: &str) {
+        self.seen.retain(|key| key.session != session);
+    }
+}
+
+impl DeliveryBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear_session(&mut self, session: &str) {
+        self.outbox.clear_session(session);
+        self.inbox.clear_session(session);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        bincode::serialize(self)
+            .map_err(|why| format_err!("Serialize error in delivery buffer, cause {}", why))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        bincode::deserialize(bytes)
+            .map_err(|why| format_err!("Deserialize error in delivery buffer, cause {}", why))
+    }
+}
+
+#[test]
+fn delivery_buffer_test() {
+    use std::collections::HashMap;
+
+    let round_two = bincode::serialize(&(1u32, vec![7u8; 8])).unwrap();
+    let mut p2p = HashMap::new();
+    p2p.insert("2".to_string(), round_two.clone());
+    p2p.insert("3".to_string(), round_two.clone());
+
+    let mut buffer = DeliveryBuffer::new();
+    let queued = buffer
+        .outbox
+        .push("s", &[], &SendingMessages::P2pMessage(p2p))
+        .unwrap();
+    assert_eq!(queued, 2);
+
+    let (key, fresh) = buffer.inbox.accept("s", "1", &round_two).unwrap();
+    assert!(fresh);
+    assert_eq!(key.round, 1);
+    let (_, fresh) = buffer.inbox.accept("s", "1", &round_two).unwrap();
+    assert!(!fresh);
+
+    let mut restored = DeliveryBuffer::from_bytes(&buffer.to_bytes().unwrap()).unwrap();
+    let acked = DeliveryKey {
+        session: "s".to_string(),
+        round: 1,
+        party: "2".to_string(),
+    };
+    assert!(restored.outbox.ack(&acked));
+    assert!(!restored.outbox.ack(&acked));
+    assert_eq!(restored.outbox.len(), 1);
+    assert!(!restored.inbox.accept("s", "1", &round_two).unwrap().1);
+
+    restored.clear_session("s");
+    assert!(restored.outbox.is_empty());
+}
@@ -15,4 +15,9 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+pub mod airgap;
+pub mod chunking;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod delivery;
 pub mod sending_messages;
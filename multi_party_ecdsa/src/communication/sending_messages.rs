@@ -19,6 +19,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum SendingMessages {
     NormalMessage(String, Vec<u8>),       // (to, message)
     P2pMessage(HashMap<String, Vec<u8>>), // (to, message)
@@ -15,6 +15,18 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+#[cfg(all(feature = "insecure-small-params", feature = "default"))]
+compile_error!(
+    "`insecure-small-params` has no security and must not ship: build it with \
+     `--no-default-features --features insecure-small-params`, tests only"
+);
+
+#[cfg(all(
+    feature = "cl",
+    not(any(feature = "params-1827", feature = "params-3072"))
+))]
+compile_error!("`cl` needs a parameter set: enable `params-1827`, `params-3072` or both");
+
 /// types for using curv
 use curv::elliptic::curves::{Point, Scalar, Secp256k1};
 pub type CU = Secp256k1;
@@ -22,7 +34,10 @@ pub type FE = Scalar<Secp256k1>;
 pub type GE = Point<Secp256k1>;
 
 pub mod communication;
+/// The stable public API
+pub mod prelude;
 /// Protocols of threshold ECDSA
+#[cfg(feature = "cl")]
 pub mod protocols;
 /// Utilities used in implementing protocols
 pub mod utilities;
@@ -0,0 +1,53 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! What an executor needs to run keygen and signing, to be glob imported.
+//!
+//! The items re-exported here, and the variants of the enums among them,
+//! are the stable surface of the crate: they change only with a new major
+//! version, and enums are `#[non_exhaustive]` so that new variants do not.
+//! The proof systems and helpers under `utilities` stay public for research
+//! and testing but may change in any release.
+pub use crate::communication::delivery::{DeliveryBuffer, DeliveryKey};
+pub use crate::communication::sending_messages::SendingMessages;
+pub use crate::utilities::audit::{AuditEvent, AuditLog, AuditRecord, AuditSink};
+pub use crate::utilities::error::MulEcdsaError;
+pub use crate::utilities::signature::{Signature, SignatureX};
+pub use crate::{CU, FE, GE};
+
+#[cfg(feature = "cl")]
+pub use crate::protocols::multi_party::dmz21::common::{DMZKeyX, Parameters};
+#[cfg(feature = "cl")]
+pub use crate::protocols::multi_party::dmz21::keygen::KeyGenPhase;
+#[cfg(feature = "cl")]
+pub use crate::protocols::multi_party::dmz21::message::{MultiKeyGenMessage, MultiSignMessage};
+#[cfg(feature = "cl")]
+pub use crate::protocols::multi_party::dmz21::negotiate::{negotiate, Agreed, Hello};
+#[cfg(feature = "cl")]
+pub use crate::protocols::multi_party::dmz21::sign::{SignPhase, SignPhaseOnline};
+#[cfg(feature = "params-1827")]
+pub use crate::utilities::class_group::CL_CONTEXT_1827;
+#[cfg(feature = "params-2048")]
+pub use crate::utilities::class_group::CL_CONTEXT_2048;
+#[cfg(feature = "params-2432")]
+pub use crate::utilities::class_group::CL_CONTEXT_2432;
+#[cfg(feature = "params-2944")]
+pub use crate::utilities::class_group::CL_CONTEXT_2944;
+#[cfg(feature = "params-3072")]
+pub use crate::utilities::class_group::CL_CONTEXT_3072;
+#[cfg(feature = "cl")]
+pub use crate::utilities::class_group::{default_context, CLContext};
@@ -0,0 +1,57 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Last-mile approval of online signing.
+//!
+//! Once step five of the online phase has been verified, the only thing a
+//! party still holds back is its share `s_i`; releasing it lets anybody who
+//! collects the shares assemble the signature. A `SignApprover` attached to
+//! `SignPhaseOnline` is consulted at exactly that point. It can approve right
+//! away, deny, or answer `Pending` when the decision is made elsewhere (a
+//! human confirming on a second device, a policy server). In the pending case
+//! the session parks and is resumed with `SignPhaseOnline::approve` or
+//! abandoned with `SignPhaseOnline::deny`.
+use crate::utilities::payload::DecodedPayload;
+use crate::{FE, GE};
+use std::fmt::Debug;
+
+/// What the approver gets to see about the signature being released.
+#[derive(Clone, Debug)]
+pub struct ApprovalRequest {
+    pub party_index: String,
+    pub subset: Vec<String>,
+    pub public_key: GE,
+    pub message: FE,
+    pub r_point: GE,
+    /// What the message is the hash of, if the caller attached it with
+    /// `SignPhaseOnline::set_payload`.
+    pub payload: Option<DecodedPayload>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ApprovalDecision {
+    /// Release the share; the token is recorded with the session.
+    Approved(String),
+    Denied(String),
+    /// Decide later through `SignPhaseOnline::approve`/`deny`.
+    Pending,
+}
+
+pub trait SignApprover: Send + Sync + Debug {
+    fn review(&self, request: &ApprovalRequest) -> ApprovalDecision;
+}
@@ -0,0 +1,226 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Short-lived authorization tokens for sign requests.
+//!
+//! The coordinator that relays a sign request is not trusted to start
+//! signatures on its own. A policy engine holding an ECDSA key issues an
+//! `AuthToken` for one message under one key with a lifetime of seconds,
+//! and every signer checks it with a `TokenVerifier` before releasing its
+//! share. The verifier allows for clock skew between issuer and signer and
+//! remembers the nonces it has accepted until they expire, so a token
+//! cannot be spent twice.
+use crate::utilities::audit::point_to_hex;
+use crate::utilities::eckeypair::EcKeyPair;
+use crate::utilities::signature::Signature;
+use crate::{FE, GE};
+use anyhow::{anyhow, format_err};
+use curv::arithmetic::traits::*;
+use curv::cryptographic_primitives::hashing::{Digest, DigestExt};
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuthToken {
+    pub public_key: String,
+    pub message: String,
+    pub subset: Vec<String>,
+    pub issued_at: i64,
+    pub expires_at: i64,
+    pub nonce: String,
+    pub signature: Signature,
+}
+
+impl AuthToken {
+    fn digest(&self) -> Result<FE, anyhow::Error> {
+        let body = serde_json::to_vec(&(
+            "DMZ21-SIGN-AUTHORIZATION",
+            &self.public_key,
+            &self.message,
+            &self.subset,
+            self.issued_at,
+            self.expires_at,
+            &self.nonce,
+        ))
+        .map_err(|why| format_err!("Serialize authorization token failed, cause {}", why))?;
+        Ok(FE::from_bigint(&Sha256::new().chain(&body).result_bigint()))
+    }
+}
+
+fn sorted(subset: &[String]) -> Vec<String> {
+    let mut subset = subset.to_vec();
+    subset.sort();
+    subset
+}
+
+/// The policy engine's side.
+pub struct TokenIssuer {
+    key: EcKeyPair,
+    ttl: i64,
+}
+
+impl TokenIssuer {
+    /// Tokens are valid for `ttl` seconds from issuance.
+    pub fn new(key: EcKeyPair, ttl: i64) -> Self {
+        Self { key, ttl }
+    }
+
+    pub fn public_key(&self) -> &GE {
+        self.key.get_public_key()
+    }
+
+    /// Authorize signing `message` under `public_key` by the parties of
+    /// `subset`, as of `now` (Unix seconds).
+    pub fn issue(
+        &self,
+        public_key: &GE,
+        message: &FE,
+        subset: &[String],
+        now: i64,
+    ) -> Result<AuthToken, anyhow::Error> {
+        let mut token = AuthToken {
+            public_key: point_to_hex(public_key),
+            message: message.to_bigint().to_hex(),
+            subset: sorted(subset),
+            issued_at: now,
+            expires_at: now + self.ttl,
+            nonce: BigInt::sample(128).to_hex(),
+            signature: Signature {
+                s: FE::zero(),
+                r: FE::zero(),
+                recid: 0,
+            },
+        };
+        token.signature = Signature::sign(self.key.get_secret_key(), &token.digest()?);
+        Ok(token)
+    }
+}
+
+/// The signer's side. Share one verifier between all sessions of a party
+/// so that replays across sessions are caught.
+#[derive(Debug)]
+pub struct TokenVerifier {
+    issuer: GE,
+    skew: i64,
+    max_ttl: i64,
+    spent: Mutex<HashMap<String, i64>>,
+}
+
+impl TokenVerifier {
+    /// Accept tokens of `issuer` living at most `max_ttl` seconds, with
+    /// clocks up to `skew` seconds apart.
+    pub fn new(issuer: GE, skew: i64, max_ttl: i64) -> Self {
+        Self {
+            issuer,
+            skew,
+            max_ttl,
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check `token` for this request at `now` and spend its nonce.
+    pub fn verify(
+        &self,
+        token: &AuthToken,
+        public_key: &GE,
+        message: &FE,
+        subset: &[String],
+        now: i64,
+    ) -> Result<(), anyhow::Error> {
+        token
+            .signature
+            .verify(&self.issuer, &token.digest()?)
+            .map_err(|_| anyhow!("Authorization token is not signed by the policy issuer"))?;
+        if token.public_key != point_to_hex(public_key)
+            || token.message != message.to_bigint().to_hex()
+            || token.subset != sorted(subset)
+        {
+            return Err(anyhow!("Authorization token is for another sign request"));
+        }
+        let lifetime = token.expires_at - token.issued_at;
+        if lifetime < 0 || lifetime > self.max_ttl {
+            return Err(anyhow!(
+                "Authorization token lifetime exceeds {}s",
+                self.max_ttl
+            ));
+        }
+        if now + self.skew < token.issued_at {
+            return Err(anyhow!("Authorization token issued in the future"));
+        }
+        if now - self.skew > token.expires_at {
+            return Err(anyhow!(
+                "Authorization token expired at {}",
+                token.expires_at
+            ));
+        }
+
+        let mut spent = self
+            .spent
+            .lock()
+            .map_err(|_| anyhow!("Authorization nonce lock poisoned"))?;
+        let skew = self.skew;
+        spent.retain(|_, expires_at| *expires_at + skew >= now);
+        if spent.contains_key(&token.nonce) {
+            return Err(anyhow!("Authorization token {} already used", token.nonce));
+        }
+        spent.insert(token.nonce.clone(), token.expires_at);
+        Ok(())
+    }
+}
+
+#[test]
+fn auth_token_test() {
+    let issuer = TokenIssuer::new(EcKeyPair::new(), 60);
+    let verifier = TokenVerifier::new(issuer.public_key().clone(), 5, 300);
+    let key = GE::generator() * FE::random();
+    let message = FE::random();
+    let subset = vec!["2".to_string(), "1".to_string()];
+    let now = 1_700_000_000;
+
+    let token = issuer.issue(&key, &message, &subset, now).unwrap();
+    // another order of the same subset, a clock a little behind
+    let reordered = vec!["1".to_string(), "2".to_string()];
+    verifier
+        .verify(&token, &key, &message, &reordered, now - 3)
+        .unwrap();
+    assert!(verifier
+        .verify(&token, &key, &message, &subset, now + 1)
+        .is_err());
+
+    let token = issuer.issue(&key, &message, &subset, now).unwrap();
+    assert!(verifier
+        .verify(&token, &key, &FE::random(), &subset, now)
+        .is_err());
+    assert!(verifier
+        .verify(&token, &key, &message, &subset, now + 66)
+        .is_err());
+    assert!(verifier
+        .verify(&token, &key, &message, &subset, now - 6)
+        .is_err());
+
+    let mut forged = token.clone();
+    forged.expires_at += 100;
+    assert!(verifier
+        .verify(&forged, &key, &message, &subset, now)
+        .is_err());
+    verifier
+        .verify(&token, &key, &message, &subset, now + 64)
+        .unwrap();
+}
@@ -0,0 +1,325 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Measured cost of the protocols per parameter set, on this machine.
+//!
+//! `profile` runs a keygen, an offline and an online sign among
+//! `params.share_count` parties through the network simulator with ideal
+//! links, and times the verification of the two CL proofs every signer
+//! checks. All parties run on the calling thread, so a phase's time is
+//! the compute of all parties together; with one party per machine the
+//! latency is roughly that divided by the number of parties, plus network
+//! round trips. Bytes count every payload a state machine sends to the
+//! others, once per recipient and without retransmissions.
+//!
+//! The report serializes to JSON for side-by-side comparison:
+//! `profile(&ProfileConfig::new(level))?.to_json()` for each level.
+//!
+//! `pow_timings` times every exponentiation strategy of the class group
+//! crate per exponent length; the thresholds in
+//! `classgroup::gmp_classgroup::window` are read off its output.
+use crate::communication::sending_messages::SendingMessages;
+use crate::protocols::multi_party::dmz21::common::Parameters;
+use crate::protocols::multi_party::dmz21::keygen::KeyGenPhase;
+use crate::protocols::multi_party::dmz21::sign::{SignPhase, SignPhaseOnline};
+use crate::protocols::multi_party::dmz21::simulation::{simulate, SimConfig, SimParty};
+use crate::protocols::multi_party::dmz21::size_report::{SecurityLevel, SizeReport};
+use crate::utilities::cl_dl_proof::{CLDLProof, CLDLState, CLDLWit};
+use crate::utilities::class_group::*;
+use crate::utilities::promise_sigma_multi::{
+    PromiseCipher, PromiseProof, PromiseState, PromiseWit,
+};
+use crate::{FE, GE};
+use anyhow::format_err;
+use classgroup::prelude::PowStrategy;
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct ProfileConfig {
+    pub level: SecurityLevel,
+    pub params: Parameters,
+    /// Runs of each proof verification to average over.
+    pub iterations: usize,
+}
+
+impl ProfileConfig {
+    /// 2-of-3, signing with two parties, five verifications per proof.
+    pub fn new(level: SecurityLevel) -> Self {
+        Self {
+            level,
+            params: Parameters {
+                threshold: 1,
+                share_count: 3,
+            },
+            iterations: 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseProfile {
+    pub millis: u64,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub level: SecurityLevel,
+    pub threshold: usize,
+    pub share_count: usize,
+    pub keygen: PhaseProfile,
+    /// Offline sign with `threshold + 1` parties.
+    pub presign: PhaseProfile,
+    pub sign: PhaseProfile,
+    pub promise_verify_micros: u64,
+    pub cl_dl_verify_micros: u64,
+    pub sizes: SizeReport,
+}
+
+impl ProfileReport {
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        serde_json::to_string_pretty(self)
+            .map_err(|why| format_err!("Serialize error in profile report, cause {}", why))
+    }
+}
+
+/// Counts the bytes `inner` sends to the other `parties`.
+struct Metered<P> {
+    inner: P,
+    party: String,
+    parties: usize,
+    bytes: Arc<AtomicU64>,
+}
+
+impl<P: SimParty> Metered<P> {
+    fn count(&self, out: &SendingMessages) {
+        let bytes = match out {
+            SendingMessages::NormalMessage(to, payload) if *to != self.party => payload.len(),
+            SendingMessages::P2pMessage(payloads) => payloads
+                .iter()
+                .filter(|(to, _)| **to != self.party)
+                .map(|(_, payload)| payload.len())
+                .sum(),
+            SendingMessages::SubsetMessage(payload)
+            | SendingMessages::BroadcastMessage(payload) => payload.len() * (self.parties - 1),
+            _ => 0,
+        };
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl<P: SimParty> SimParty for Metered<P> {
+    fn begin(&mut self) -> Result<SendingMessages, anyhow::Error> {
+        let out = self.inner.begin()?;
+        self.count(&out);
+        Ok(out)
+    }
+
+    fn handle(&mut self, from: String, msg: &Vec<u8>) -> Result<SendingMessages, anyhow::Error> {
+        let out = self.inner.handle(from, msg)?;
+        self.count(&out);
+        Ok(out)
+    }
+}
+
+/// Run `parties` on ideal links and return their results with the cost.
+fn run<P: SimParty>(
+    phase: &str,
+    parties: BTreeMap<String, P>,
+) -> Result<(BTreeMap<String, String>, PhaseProfile), anyhow::Error> {
+    let bytes = Arc::new(AtomicU64::new(0));
+    let count = parties.len();
+    let metered = parties
+        .into_iter()
+        .map(|(party, inner)| {
+            let metered = Metered {
+                inner,
+                party: party.clone(),
+                parties: count,
+                bytes: bytes.clone(),
+            };
+            (party, metered)
+        })
+        .collect();
+    let start = Instant::now();
+    let outcome = simulate(&SimConfig::new(0), metered)?;
+    let millis = start.elapsed().as_millis() as u64;
+    if outcome.results.len() != count {
+        return Err(format_err!("{} did not finish in the profile run", phase));
+    }
+    let profile = PhaseProfile {
+        millis,
+        bytes: bytes.load(Ordering::Relaxed),
+    };
+    Ok((outcome.results, profile))
+}
+
+fn average_micros(iterations: usize, mut f: impl FnMut()) -> u64 {
+    let iterations = iterations.max(1);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed().as_micros() as u64 / iterations as u64
+}
+
+pub fn profile(config: &ProfileConfig) -> Result<ProfileReport, anyhow::Error> {
+    let cl = config.level.context();
+    let params = &config.params;
+    let ids: Vec<String> = (1..=params.share_count).map(|i| i.to_string()).collect();
+
+    let mut keygen = BTreeMap::new();
+    for id in &ids {
+        let party_ids = Some(ids.clone());
+        let phase =
+            KeyGenPhase::new_with_context(id.clone(), params.clone(), &party_ids, cl.clone())?;
+        keygen.insert(id.clone(), phase);
+    }
+    let (keys, keygen) = run("keygen", keygen)?;
+
+    let subset = ids[..=params.threshold].to_vec();
+    let mut presign = BTreeMap::new();
+    for id in &subset {
+        let phase = SignPhase::new_with_context(
+            id.clone(),
+            params.clone(),
+            &subset,
+            &keys[id],
+            cl.clone(),
+        )?;
+        presign.insert(id.clone(), phase);
+    }
+    let (presignatures, presign) = run("offline sign", presign)?;
+
+    let message = vec![0x42u8; 32];
+    let mut sign = BTreeMap::new();
+    for id in &subset {
+        sign.insert(
+            id.clone(),
+            SignPhaseOnline::new(&presignatures[id], message.clone())?,
+        );
+    }
+    let (_, sign) = run("online sign", sign)?;
+
+    let group = &cl.group;
+    let (_, pk) = group.keygen();
+    let m = FE::random();
+    let ec_pk = GE::generator() * &FE::random();
+    let (cipher, r1, r2) = PromiseCipher::encrypt(group, &pk, &ec_pk, &m);
+    let promise_state = PromiseState {
+        cipher,
+        ec_pub_key: ec_pk,
+        cl_pub_key: pk.clone(),
+    };
+    let promise_wit = PromiseWit {
+        m: m.clone(),
+        r1,
+        r2,
+    };
+    let promise_proof = PromiseProof::prove(group, &promise_state, &promise_wit)?;
+    let promise_verify_micros = average_micros(config.iterations, || {
+        assert!(promise_proof.verify(group, &promise_state).is_ok());
+    });
+
+    let (cipher, r) = CLGroup::encrypt(group, &pk, &m);
+    let cl_dl_state = CLDLState {
+        cipher,
+        cl_pub_key: pk,
+        dl_pub: GE::generator() * &m,
+    };
+    let cl_dl_proof = CLDLProof::prove(group, CLDLWit { dl_priv: m, r }, cl_dl_state.clone())?;
+    let cl_dl_verify_micros = average_micros(config.iterations, || {
+        assert!(cl_dl_proof.verify(group, &cl_dl_state).is_ok());
+    });
+
+    Ok(ProfileReport {
+        level: config.level,
+        threshold: params.threshold,
+        share_count: params.share_count,
+        keygen,
+        presign,
+        sign,
+        promise_verify_micros,
+        cl_dl_verify_micros,
+        sizes: SizeReport::for_level(config.level)?,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowTiming {
+    pub bits: usize,
+    /// `Debug` form of the `PowStrategy`.
+    pub strategy: String,
+    pub micros: u64,
+}
+
+/// Times one exponentiation of the generator of `level` with a random
+/// exponent of each of `bit_sizes`, for binary and for windows 2 to 7.
+pub fn pow_timings(level: SecurityLevel, bit_sizes: &[usize], iterations: usize) -> Vec<PowTiming> {
+    let generator = &level.context().group.generator;
+    let mut strategies = vec![PowStrategy::Binary];
+    strategies.extend((2..=7).map(PowStrategy::Wnaf));
+    let mut timings = Vec::new();
+    for bits in bit_sizes {
+        let exponent = bigint_to_mpz(&BigInt::sample(*bits));
+        for strategy in &strategies {
+            let micros = average_micros(iterations, || {
+                let mut power = generator.clone();
+                power.pow_with(exponent.clone(), *strategy);
+            });
+            timings.push(PowTiming {
+                bits: *bits,
+                strategy: format!("{:?}", strategy),
+                micros,
+            });
+        }
+    }
+    timings
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn profile_test() {
+    let mut config = ProfileConfig::new(SecurityLevel::Cl1827);
+    config.params = Parameters {
+        threshold: 1,
+        share_count: 2,
+    };
+    config.iterations = 1;
+    let report = profile(&config).unwrap();
+    assert!(report.keygen.bytes > 0 && report.presign.bytes > report.sign.bytes);
+    assert!(report.presign.bytes as usize >= report.sizes.sign_phase_two);
+    let json = report.to_json().unwrap();
+    let parsed: ProfileReport = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, report);
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn pow_timings_test() {
+    let timings = pow_timings(SecurityLevel::Cl1827, &[16, 256], 1);
+    assert_eq!(timings.len(), 14);
+    assert!(timings
+        .iter()
+        .any(|t| t.bits == 256 && t.strategy == "Wnaf(4)"));
+}
@@ -16,6 +16,7 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 use crate::utilities::class_group::*;
+use crate::utilities::secret::{exposed_scalar, SecretScalar};
 pub use crate::{CU, FE, GE};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -34,12 +35,30 @@ pub struct PublicKey {
     pub share_pks: HashMap<String, GE>,
 }
 
+impl PublicKey {
+    /// BIP-340 x-only encoding of the joint key, and whether its y coordinate
+    /// is odd. The Taproot key with this x is always the even-y point, so an
+    /// odd-y joint key stands for the negation of the shared secret. `None`
+    /// for the point at infinity.
+    pub fn to_xonly(&self) -> Option<([u8; 32], bool)> {
+        let encoded = self.pk.to_bytes(true);
+        if encoded.len() != 33 {
+            return None;
+        }
+        let mut x = [0u8; 32];
+        x.copy_from_slice(&encoded[1..]);
+        Some((x, encoded[0] == 0x03))
+    }
+}
+
 /// Private part of keygen result
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PrivateKey {
     pub cl_sk: SK,
-    pub ec_sk: FE,
-    pub share_sk: FE,
+    #[serde(with = "exposed_scalar")]
+    pub ec_sk: SecretScalar,
+    #[serde(with = "exposed_scalar")]
+    pub share_sk: SecretScalar,
 }
 
 /// Keygen result
@@ -0,0 +1,263 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Security-relevant configuration that only a quorum can change.
+//!
+//! A `SecurityConfig` lists the parties with their identity keys, the
+//! threshold, the CL parameter set and the policy rules. A node never edits
+//! it alone: a `ConfigChange` names the hash of the config it replaces and
+//! the full new config, and it is applied only with signatures of
+//! `threshold + 1` parties of the current config. Each party keeps its own
+//! `ConfigStore` and applies the same change, so all of them move from one
+//! hash to the next together. `Agreed::with_config` puts the hash in the
+//! session id, which makes sessions of nodes whose configs differ fail.
+use crate::protocols::multi_party::dmz21::size_report::SecurityLevel;
+use crate::utilities::audit::point_to_hex;
+use crate::utilities::eckeypair::EcKeyPair;
+use crate::utilities::signature::Signature;
+use crate::utilities::transcript::Transcript;
+use crate::{FE, GE};
+use anyhow::{anyhow, format_err};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    /// Increases by one with every applied change.
+    pub version: u64,
+    /// Party index to its compressed identity key, hex.
+    pub parties: BTreeMap<String, String>,
+    pub threshold: usize,
+    pub param_set: SecurityLevel,
+    /// Policy rules, opaque to this module.
+    #[serde(default)]
+    pub policy: BTreeMap<String, String>,
+}
+
+impl SecurityConfig {
+    pub fn hash(&self) -> [u8; 32] {
+        let encoded = serde_json::to_vec(self).expect("SecurityConfig is always serializable");
+        Transcript::new(b"DMZ21-SECURITY-CONFIG")
+            .append(&encoded[..])
+            .finalize()
+    }
+
+    fn identity(&self, party: &str) -> Result<GE, anyhow::Error> {
+        let encoded = self
+            .parties
+            .get(party)
+            .ok_or_else(|| anyhow!("Party {} is not in config version {}", party, self.version))?;
+        let bytes = hex::decode(encoded)
+            .map_err(|why| format_err!("Identity key of {} is not hex, cause {}", party, why))?;
+        GE::from_bytes(&bytes)
+            .map_err(|why| format_err!("Invalid identity key of party {}, cause {}", party, why))
+    }
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.threshold == 0 || self.threshold >= self.parties.len() {
+            return Err(anyhow!(
+                "Threshold {} is invalid for {} parties",
+                self.threshold,
+                self.parties.len()
+            ));
+        }
+        for party in self.parties.keys() {
+            self.identity(party)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replace the config of hash `base` with `config`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub base: String,
+    pub config: SecurityConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigApproval {
+    pub party_index: String,
+    pub signature: Signature,
+}
+
+impl ConfigChange {
+    pub fn propose(current: &SecurityConfig, mut config: SecurityConfig) -> Self {
+        config.version = current.version + 1;
+        Self {
+            base: hex::encode(current.hash()),
+            config,
+        }
+    }
+
+    fn digest(&self) -> FE {
+        let digest = Transcript::new(b"DMZ21-CONFIG-CHANGE")
+            .append(self.base.as_bytes())
+            .append(&self.config.hash()[..])
+            .finalize();
+        FE::from_bigint(&BigInt::from_bytes(&digest))
+    }
+
+    /// Sign the change with the identity key of `party_index`.
+    pub fn approve(&self, party_index: &str, identity: &EcKeyPair) -> ConfigApproval {
+        ConfigApproval {
+            party_index: party_index.to_string(),
+            signature: Signature::sign(identity.get_secret_key(), &self.digest()),
+        }
+    }
+}
+
+/// One node's view of the config in force.
+#[derive(Clone, Debug)]
+pub struct ConfigStore {
+    current: SecurityConfig,
+}
+
+impl ConfigStore {
+    /// Start from a genesis config, agreed on out of band at key creation.
+    pub fn new(genesis: SecurityConfig) -> Result<Self, anyhow::Error> {
+        genesis.validate()?;
+        Ok(Self { current: genesis })
+    }
+
+    pub fn current(&self) -> &SecurityConfig {
+        &self.current
+    }
+
+    /// Apply `change` if it extends the current config and carries valid
+    /// approvals of at least `threshold + 1` current parties.
+    pub fn apply(
+        &mut self,
+        change: &ConfigChange,
+        approvals: &[ConfigApproval],
+    ) -> Result<(), anyhow::Error> {
+        if change.base != hex::encode(self.current.hash()) {
+            return Err(anyhow!("Config change is not based on the config in force"));
+        }
+        if change.config.version != self.current.version + 1 {
+            return Err(anyhow!(
+                "Config change to version {} does not follow version {}",
+                change.config.version,
+                self.current.version
+            ));
+        }
+        change.config.validate()?;
+
+        let digest = change.digest();
+        let mut approvers = BTreeSet::new();
+        for approval in approvals {
+            let key = self.current.identity(&approval.party_index)?;
+            approval.signature.verify(&key, &digest).map_err(|_| {
+                anyhow!("Invalid config approval of party {}", approval.party_index)
+            })?;
+            approvers.insert(approval.party_index.clone());
+        }
+        if approvers.len() <= self.current.threshold {
+            return Err(anyhow!(
+                "Config change approved by {} parties, {} needed",
+                approvers.len(),
+                self.current.threshold + 1
+            ));
+        }
+        self.current = change.config.clone();
+        Ok(())
+    }
+}
+
+/// A config of `parties` for keys and tests, at version 0.
+pub fn genesis_config(
+    identities: &[(String, GE)],
+    threshold: usize,
+    param_set: SecurityLevel,
+) -> SecurityConfig {
+    SecurityConfig {
+        version: 0,
+        parties: identities
+            .iter()
+            .map(|(party, key)| (party.clone(), point_to_hex(key)))
+            .collect(),
+        threshold,
+        param_set,
+        policy: BTreeMap::new(),
+    }
+}
+
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
+#[test]
+fn config_change_test() {
+    use crate::protocols::multi_party::dmz21::negotiate::{negotiate, Hello, NegotiationError};
+    use std::collections::HashMap;
+
+    let keys: Vec<(String, EcKeyPair)> =
+        (1..=3).map(|i| (i.to_string(), EcKeyPair::new())).collect();
+    let identities: Vec<(String, GE)> = keys
+        .iter()
+        .map(|(party, key)| (party.clone(), key.get_public_key().clone()))
+        .collect();
+    let genesis = genesis_config(&identities, 1, SecurityLevel::Cl1827);
+    let mut store = ConfigStore::new(genesis.clone()).unwrap();
+
+    let mut next = genesis.clone();
+    next.param_set = SecurityLevel::Cl3072;
+    next.policy
+        .insert("max_daily_signatures".to_string(), "100".to_string());
+    let change = ConfigChange::propose(&genesis, next);
+
+    let one = vec![change.approve("1", &keys[0].1)];
+    assert!(store.apply(&change, &one).is_err());
+    // the same party twice is still one approval
+    let twice = vec![one[0].clone(), one[0].clone()];
+    assert!(store.apply(&change, &twice).is_err());
+    // a signature by the wrong key
+    let forged = vec![one[0].clone(), change.approve("2", &keys[2].1)];
+    assert!(store.apply(&change, &forged).is_err());
+
+    let two = vec![one[0].clone(), change.approve("3", &keys[2].1)];
+    store.apply(&change, &two).unwrap();
+    assert_eq!(store.current().version, 1);
+    assert_eq!(store.current().param_set, SecurityLevel::Cl3072);
+    // replaying the same change on top of itself fails
+    assert!(store.apply(&change, &two).is_err());
+
+    let parties: Vec<String> = keys.iter().map(|(party, _)| party.clone()).collect();
+    let hellos: HashMap<String, Hello> = parties
+        .iter()
+        .map(|p| (p.clone(), Hello::local(p.clone())))
+        .collect();
+    let agreed = negotiate(&parties, &hellos).unwrap();
+    assert_eq!(agreed.param_set, SecurityLevel::Cl1827);
+    let bound = agreed.clone().with_config(&genesis, 1).unwrap();
+    assert_ne!(bound.session_id(), agreed.session_id());
+    // the applied change moved to Cl3072, which the session did not agree on
+    assert!(matches!(
+        agreed.clone().with_config(store.current(), 1),
+        Err(NegotiationError::ConfigMismatch(_))
+    ));
+    assert!(agreed.clone().with_config(&genesis, 2).is_err());
+    let mut outsider = agreed.clone();
+    outsider.parties.push("4".to_string());
+    assert!(outsider.with_config(&genesis, 1).is_err());
+    // configs that differ in policy only give different session ids
+    let mut strict = genesis.clone();
+    strict
+        .policy
+        .insert("max_daily_signatures".to_string(), "10".to_string());
+    let strict = agreed.clone().with_config(&strict, 1).unwrap();
+    assert_ne!(bound.session_id(), strict.session_id());
+}
@@ -0,0 +1,190 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Public material of each party of a session, kept once it is validated.
+//!
+//! A party's CL public key, public share and commitment are checked in the
+//! round they arrive in. Later rounds read them from here instead of going
+//! back to the raw round messages, and the bytes kept for each one are
+//! exactly the ones that were checked, so an error raised later can point at
+//! them. A party presenting another value for something already validated is
+//! refused, and the first value is kept.
+use crate::utilities::class_group::PK;
+use crate::GE;
+use anyhow::{anyhow, format_err};
+use curv::BigInt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Material {
+    ClPublicKey,
+    PublicShare,
+    Commitment,
+}
+
+impl Material {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::ClPublicKey => "CL public key",
+            Material::PublicShare => "public share",
+            Material::Commitment => "commitment",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct PartyEntry {
+    cl_pk: Option<PK>,
+    public_share: Option<GE>,
+    commitment: Option<BigInt>,
+    encoded: BTreeMap<Material, Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PartyDirectory {
+    entries: HashMap<String, PartyEntry>,
+}
+
+impl PartyDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `pk` as the validated CL public key of `party`.
+    pub fn insert_cl_pk(&mut self, party: &str, pk: &PK) -> Result<(), anyhow::Error> {
+        if self.admit(party, Material::ClPublicKey, pk)? {
+            self.entry(party).cl_pk = Some(pk.clone());
+        }
+        Ok(())
+    }
+
+    /// Keep `share` as the validated public share of `party`.
+    pub fn insert_public_share(&mut self, party: &str, share: &GE) -> Result<(), anyhow::Error> {
+        if self.admit(party, Material::PublicShare, share)? {
+            self.entry(party).public_share = Some(share.clone());
+        }
+        Ok(())
+    }
+
+    /// Keep `commitment` as the validated commitment of `party`.
+    pub fn insert_commitment(
+        &mut self,
+        party: &str,
+        commitment: &BigInt,
+    ) -> Result<(), anyhow::Error> {
+        if self.admit(party, Material::Commitment, commitment)? {
+            self.entry(party).commitment = Some(commitment.clone());
+        }
+        Ok(())
+    }
+
+    pub fn cl_pk(&self, party: &str) -> Option<&PK> {
+        self.entries.get(party)?.cl_pk.as_ref()
+    }
+
+    pub fn public_share(&self, party: &str) -> Option<&GE> {
+        self.entries.get(party)?.public_share.as_ref()
+    }
+
+    pub fn commitment(&self, party: &str) -> Option<&BigInt> {
+        self.entries.get(party)?.commitment.as_ref()
+    }
+
+    pub fn is_validated(&self, party: &str, material: Material) -> bool {
+        self.validated_bytes(party, material).is_some()
+    }
+
+    /// The bincode encoding of `material` of `party` as it was validated.
+    pub fn validated_bytes(&self, party: &str, material: Material) -> Option<&[u8]> {
+        self.entries
+            .get(party)?
+            .encoded
+            .get(&material)
+            .map(|bytes| bytes.as_slice())
+    }
+
+    /// Parties with at least one validated artifact, sorted.
+    pub fn parties(&self) -> Vec<&str> {
+        let mut parties: Vec<&str> = self.entries.keys().map(|party| party.as_str()).collect();
+        parties.sort();
+        parties
+    }
+
+    /// Whether `value` is new for `party`. Presenting the validated value
+    /// again is not an error, presenting another one is.
+    fn admit<T: Serialize>(
+        &mut self,
+        party: &str,
+        material: Material,
+        value: &T,
+    ) -> Result<bool, anyhow::Error> {
+        let bytes = bincode::serialize(value).map_err(|why| {
+            format_err!(
+                "Serialize {} of party {} failed, cause {}",
+                material.name(),
+                party,
+                why
+            )
+        })?;
+        let entry = self.entry(party);
+        match entry.encoded.get(&material) {
+            Some(first) if *first == bytes => Ok(false),
+            Some(_) => Err(anyhow!(
+                "Party {} presented another {} than the validated one",
+                party,
+                material.name()
+            )),
+            None => {
+                entry.encoded.insert(material, bytes);
+                Ok(true)
+            }
+        }
+    }
+
+    fn entry(&mut self, party: &str) -> &mut PartyEntry {
+        self.entries.entry(party.to_string()).or_default()
+    }
+}
+
+#[test]
+fn party_directory_test() {
+    let mut directory = PartyDirectory::new();
+    let share = GE::generator() * crate::FE::random();
+    directory.insert_public_share("2", &share).unwrap();
+    directory.insert_commitment("1", &BigInt::from(7)).unwrap();
+    assert_eq!(directory.public_share("2"), Some(&share));
+    assert_eq!(directory.commitment("1"), Some(&BigInt::from(7)));
+    assert!(directory.cl_pk("1").is_none());
+    assert!(!directory.is_validated("1", Material::PublicShare));
+    assert_eq!(
+        directory
+            .validated_bytes("2", Material::PublicShare)
+            .unwrap(),
+        &bincode::serialize(&share).unwrap()[..]
+    );
+    assert_eq!(directory.parties(), vec!["1", "2"]);
+
+    // The same value again is fine, another one is blamed on the party and
+    // the validated one stays.
+    directory.insert_commitment("1", &BigInt::from(7)).unwrap();
+    let err = directory
+        .insert_commitment("1", &BigInt::from(8))
+        .unwrap_err();
+    assert!(err.to_string().contains("Party 1"));
+    assert_eq!(directory.commitment("1"), Some(&BigInt::from(7)));
+}
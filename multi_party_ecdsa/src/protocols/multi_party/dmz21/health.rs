@@ -0,0 +1,229 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Self-test for readiness probes.
+//!
+//! `self_test` runs real work rather than reporting that the process is
+//! up: a CL encrypt/decrypt round trip and a membership check of the
+//! generator in the chosen parameter set, a clock sanity check (timeouts
+//! and presignature expiry depend on it), and a structural check of the
+//! oldest presignature of a pool. The report serializes to JSON for a
+//! health endpoint of the embedding service.
+//!
+//! `warm_up` is meant for service start, before the service reports ready:
+//! it pays the one-off costs the first keygen or sign would otherwise hit.
+use crate::protocols::multi_party::dmz21::presign_pool::PresignPool;
+use crate::protocols::multi_party::dmz21::sign::{OfflineResult, OfflineResultX};
+use crate::protocols::multi_party::dmz21::size_report::SecurityLevel;
+use crate::utilities::class_group::CLGroup;
+#[cfg(feature = "params-1827")]
+use crate::utilities::class_group::DISCRIMINANT_1827;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::SECURITY_PARAMETER;
+use crate::{FE, GE};
+use anyhow::{anyhow, format_err};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 2022-01-01T00:00:00Z. A wall clock before this has not been set.
+const CLOCK_FLOOR_SECS: u64 = 1_640_995_200;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub level: SecurityLevel,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+}
+
+fn run(name: &str, check: impl FnOnce() -> Result<String, anyhow::Error>) -> HealthCheck {
+    let start = Instant::now();
+    let (ok, detail) = match check() {
+        Ok(detail) => (true, detail),
+        Err(why) => (false, why.to_string()),
+    };
+    HealthCheck {
+        name: name.to_string(),
+        ok,
+        detail,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    }
+}
+
+fn check_cl(level: SecurityLevel) -> Result<String, anyhow::Error> {
+    let context = level.context();
+    context.base.check_element(&context.group.generator)?;
+    let group: &CLGroup = &context.group;
+    let (sk, pk) = group.keygen();
+    let m = FE::random();
+    let (cipher, _) = CLGroup::encrypt(group, &pk, &m);
+    if CLGroup::decrypt(group, &sk, &cipher) != m {
+        return Err(anyhow!("CL decryption returned a different plaintext"));
+    }
+    Ok(format!("{} round trip", level.name()))
+}
+
+fn check_clock() -> Result<String, anyhow::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| anyhow!("Wall clock is before the Unix epoch"))?;
+    if now < Duration::from_secs(CLOCK_FLOOR_SECS) {
+        return Err(anyhow!(
+            "Wall clock is not set: {}s since the epoch",
+            now.as_secs()
+        ));
+    }
+    let start = Instant::now();
+    std::thread::sleep(Duration::from_millis(1));
+    if start.elapsed() < Duration::from_millis(1) {
+        return Err(anyhow!("Monotonic clock does not advance"));
+    }
+    Ok(format!("{}s since the epoch", now.as_secs()))
+}
+
+/// Decode an offline result the way `SignPhaseOnline::new` does and check
+/// that it is complete and that its nonce point is usable.
+pub fn verify_offline_result(offline_result: &str) -> Result<(), anyhow::Error> {
+    let wrapped: OfflineResultX = serde_json::from_str(offline_result)
+        .map_err(|why| format_err!("Presignature is not an offline result: {}", why))?;
+    let bytes = hex::decode(wrapped.data)
+        .map_err(|why| format_err!("Presignature data is not hex: {}", why))?;
+    let result: OfflineResult = bincode::deserialize(&bytes)
+        .map_err(|why| format_err!("Presignature data does not decode: {}", why))?;
+    if result.subset.len() != result.party_num
+        || !result
+            .subset
+            .iter()
+            .all(|i| result.phase_four_msgs.contains_key(i))
+    {
+        return Err(anyhow!("Presignature is missing phase four messages"));
+    }
+    let delta_inv = result
+        .delta_sum
+        .invert()
+        .ok_or(anyhow!("Presignature has a zero delta"))?;
+    let r = result
+        .phase_four_msgs
+        .values()
+        .fold(GE::zero(), |acc, msg| acc + &msg.open.public_share);
+    if (r * delta_inv).x_coord().is_none() {
+        return Err(anyhow!("Presignature nonce point is the identity"));
+    }
+    Ok(())
+}
+
+fn check_presignature(pool: &PresignPool) -> Result<String, anyhow::Error> {
+    match pool.check_oldest() {
+        Some(checked) => {
+            checked?;
+            Ok(format!("{} ready", pool.metrics().ready))
+        }
+        None => Ok("pool is empty".to_string()),
+    }
+}
+
+/// Run every check and collect the results. Takes about as long as one CL
+/// key generation and encryption at `level`.
+pub fn self_test(level: SecurityLevel, pool: Option<&PresignPool>) -> HealthReport {
+    let mut checks = vec![
+        run("cl_round_trip", || check_cl(level)),
+        run("clock", check_clock),
+    ];
+    if let Some(pool) = pool {
+        checks.push(run("presignature", || check_presignature(pool)));
+    }
+    HealthReport { level, checks }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmUpReport {
+    pub level: SecurityLevel,
+    pub workers: usize,
+    pub elapsed_ms: u64,
+}
+
+/// Initialize the parameter set of `level` and the exponentiation pool.
+///
+/// The lazy statics behind `level.context()` compute the update group on
+/// first use, which is one full-size exponentiation. Every pool worker
+/// then runs one exponentiation with a proof-sized exponent so that GMP
+/// has grown its buffers to the sizes the protocol needs, and this thread
+/// does a CL encrypt/decrypt for the same reason.
+pub fn warm_up(level: SecurityLevel) -> Result<WarmUpReport, MulEcdsaError> {
+    let start = Instant::now();
+    #[cfg(feature = "params-1827")]
+    lazy_static::initialize(&DISCRIMINANT_1827);
+    let context = level.context();
+    let group: &CLGroup = &context.group;
+    group.params_id();
+
+    let pool = exp_pool::current();
+    let mut exponent = group.stilde.clone();
+    exponent.setbit(group.stilde.bit_length() + 80 + SECURITY_PARAMETER);
+    let jobs = vec![(group.generator.clone(), exponent); pool.workers()];
+    pool.pow_all(jobs, Priority::High)?;
+
+    let (sk, pk) = group.keygen();
+    let (cipher, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    CLGroup::decrypt(group, &sk, &cipher);
+
+    Ok(WarmUpReport {
+        level,
+        workers: pool.workers(),
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn health_self_test() {
+    use crate::protocols::multi_party::dmz21::presign_pool::PresignPoolConfig;
+
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 1,
+        refill_threshold: 1,
+        max_concurrent: 1,
+        ttl_secs: None,
+        max_uses: None,
+    })
+    .unwrap();
+    let report = self_test(SecurityLevel::Cl1827, Some(&pool));
+    assert!(report.healthy(), "{:?}", report);
+
+    pool.session_started().unwrap();
+    pool.session_finished("{\"data\":\"00\"}".to_string())
+        .unwrap();
+    let report = self_test(SecurityLevel::Cl1827, Some(&pool));
+    assert!(!report.healthy());
+    assert_eq!(report.checks.iter().filter(|check| !check.ok).count(), 1);
+    assert!(serde_json::to_string(&report)
+        .unwrap()
+        .contains("presignature"));
+}
@@ -0,0 +1,87 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Per-round message buffers of the keygen and sign state machines.
+//!
+//! Networks reorder and redeliver. A message for a later round is kept in
+//! that round's buffer until the session gets there, and a round is only
+//! processed once the one before it is done. A second copy of a message is
+//! ignored if it is byte-for-byte the first one; if it differs, the sender
+//! has equivocated and the session aborts naming it.
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arrival {
+    Fresh,
+    Duplicate,
+}
+
+/// Put `msg` from `from` into the `round` buffer `slot`.
+pub fn record<T: Serialize>(
+    slot: &mut HashMap<String, T>,
+    from: &str,
+    msg: T,
+    round: &str,
+) -> Result<Arrival, anyhow::Error> {
+    match slot.get(from) {
+        None => {
+            slot.insert(from.to_string(), msg);
+            Ok(Arrival::Fresh)
+        }
+        Some(first) => {
+            let same = match (bincode::serialize(first), bincode::serialize(&msg)) {
+                (Ok(first), Ok(again)) => first == again,
+                _ => false,
+            };
+            if same {
+                Ok(Arrival::Duplicate)
+            } else {
+                Err(anyhow!(
+                    "Party {} sent two different messages in {}",
+                    from,
+                    round
+                ))
+            }
+        }
+    }
+}
+
+#[test]
+fn record_test() {
+    let mut slot: HashMap<String, u64> = HashMap::new();
+    assert_eq!(
+        record(&mut slot, "1", 7, "round one").unwrap(),
+        Arrival::Fresh
+    );
+    assert_eq!(
+        record(&mut slot, "2", 8, "round one").unwrap(),
+        Arrival::Fresh
+    );
+    // An exact redelivery is harmless.
+    assert_eq!(
+        record(&mut slot, "1", 7, "round one").unwrap(),
+        Arrival::Duplicate
+    );
+    // A different message from the same party is blamed on it, and the first
+    // one is kept.
+    let err = record(&mut slot, "2", 9, "round one").unwrap_err();
+    assert!(err.to_string().contains("Party 2"));
+    assert_eq!(slot["2"], 8);
+    assert_eq!(slot.len(), 2);
+}
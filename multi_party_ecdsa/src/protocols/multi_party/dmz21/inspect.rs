@@ -0,0 +1,391 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Human-readable summaries of serialized protocol data, for debugging
+//! interop problems. Backs the `dmz-inspect` binary.
+//!
+//! An `Inspection` lists what a buffer decodes to: the round a message
+//! belongs to, its size, the parameter set of every class group element and
+//! the public points. Every element is also checked against its parameter
+//! set and the proofs that can be verified on their own are, and anything
+//! that fails ends up in `problems`. Secret values, the private half of a
+//! key and the share in a keygen phase four message, are never printed.
+use crate::protocols::multi_party::dmz21::common::DMZKeyX;
+use crate::protocols::multi_party::dmz21::message::*;
+use crate::utilities::audit::point_to_hex;
+use crate::utilities::cl_dl_proof::CLDLProof;
+use crate::utilities::class_group::*;
+use crate::utilities::params_id::ParamsId;
+use crate::utilities::promise_sigma_multi::{PromiseProof, PromiseState};
+use crate::{CU, GE};
+use anyhow::format_err;
+use classgroup::prelude::{ClassGroup, GmpClassGroup};
+use curv::arithmetic::traits::*;
+use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
+use std::fmt;
+
+#[derive(Clone, Debug, Default)]
+pub struct Inspection {
+    pub kind: String,
+    pub size: usize,
+    pub fields: Vec<(String, String)>,
+    pub problems: Vec<String>,
+}
+
+/// The base and update group of each parameter set; both share one
+/// discriminant.
+fn known_groups() -> Vec<(&'static str, &'static CLGroup, &'static CLGroup)> {
+    let mut groups = Vec::new();
+    #[cfg(feature = "params-1827")]
+    groups.push(("cl1827", &*GROUP_1827, &*GROUP_UPDATE_1827));
+    #[cfg(feature = "params-3072")]
+    groups.push(("cl3072", &*GROUP_3072, &*GROUP_UPDATE_3072));
+    #[cfg(feature = "params-2048")]
+    groups.push(("cl2048", &*GROUP_2048, &*GROUP_UPDATE_2048));
+    #[cfg(feature = "params-2432")]
+    groups.push(("cl2432", &*GROUP_2432, &*GROUP_UPDATE_2432));
+    #[cfg(feature = "params-2944")]
+    groups.push(("cl2944", &*GROUP_2944, &*GROUP_UPDATE_2944));
+    groups
+}
+
+/// The loosest caps of the known parameter sets: the inspector does not
+/// know which one a message was sent in, so it accepts any `ParamsId`.
+fn loosest_caps() -> MessageCaps {
+    let caps = known_groups()
+        .iter()
+        .map(|(_, _, update)| MessageCaps::for_group(update))
+        .max_by_key(|caps| caps.cl_element)
+        .unwrap_or_default();
+    MessageCaps {
+        params: None,
+        ..caps
+    }
+}
+
+fn update_group_of(form: &GmpClassGroup) -> Option<&'static CLGroup> {
+    known_groups()
+        .iter()
+        .find(|(_, base, _)| base.generator.discriminant() == form.discriminant())
+        .map(|(_, _, update)| *update)
+}
+
+impl Inspection {
+    fn new(kind: &str, size: usize) -> Self {
+        Self {
+            kind: kind.to_string(),
+            size,
+            ..Self::default()
+        }
+    }
+
+    fn field(&mut self, label: &str, value: impl Into<String>) {
+        self.fields.push((label.to_string(), value.into()));
+    }
+
+    fn problem(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+
+    fn point(&mut self, label: &str, point: &GE) {
+        self.field(label, point_to_hex(point));
+    }
+
+    fn bigint(&mut self, label: &str, value: &curv::BigInt) {
+        self.field(label, value.to_hex());
+    }
+
+    fn form(&mut self, label: &str, form: &GmpClassGroup) {
+        let set = known_groups()
+            .iter()
+            .find(|(_, base, _)| base.generator.discriminant() == form.discriminant())
+            .map(|(name, base, _)| (*name, *base));
+        let name = match set {
+            Some((name, base)) => {
+                if base.check_element(form).is_err() {
+                    self.problem(format!("{} is not a valid {} element", label, name));
+                }
+                name
+            }
+            None => {
+                self.problem(format!("{} has an unknown discriminant", label));
+                "unknown"
+            }
+        };
+        self.field(
+            label,
+            format!(
+                "{} form, a {} bits, b {} bits",
+                name,
+                form.a.bit_length(),
+                form.b.bit_length()
+            ),
+        );
+    }
+
+    fn params(&mut self, params: &ParamsId) {
+        let name = known_groups()
+            .iter()
+            .find(|(_, _, update)| update.params_id() == *params)
+            .map(|(name, _, _)| *name);
+        match name {
+            Some(name) => self.field("params", name),
+            None => {
+                self.problem("params is not a known parameter set".to_string());
+                self.field("params", params.to_hex());
+            }
+        }
+    }
+
+    fn cipher(&mut self, label: &str, cipher: &Ciphertext) {
+        self.form(&format!("{}.c1", label), &cipher.c1);
+        self.form(&format!("{}.c2", label), &cipher.c2);
+    }
+
+    fn dlog_proof(&mut self, label: &str, proof: &DLogProof<CU, sha2::Sha256>) {
+        self.point(&format!("{}.pk", label), &proof.pk);
+        if DLogProof::verify(proof).is_err() {
+            self.problem(format!("{} does not verify", label));
+        }
+    }
+
+    fn promise(&mut self, state: &PromiseState, proof: &PromiseProof) {
+        self.cipher("promise_state.cipher.cl", &state.cipher.cl_cipher);
+        self.point("promise_state.cipher.ec.c1", &state.cipher.ec_cipher.c1);
+        self.point("promise_state.cipher.ec.c2", &state.cipher.ec_cipher.c2);
+        self.form("promise_state.cl_pub_key", &state.cl_pub_key.0);
+        self.point("promise_state.ec_pub_key", &state.ec_pub_key);
+        self.form("proof.a1", &proof.a1);
+        self.form("proof.a2", &proof.a2);
+        self.field("proof.z2", format!("{} bits", proof.z2.bit_length()));
+        match update_group_of(&state.cl_pub_key.0) {
+            Some(group) => {
+                if let Err(why) = proof.verify(group, state) {
+                    self.problem(format!("promise proof does not verify: {}", why));
+                }
+            }
+            None => self.problem("promise proof not checked: unknown group".to_string()),
+        }
+    }
+
+    /// Whether nothing was found wrong.
+    pub fn is_consistent(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({} bytes)", self.kind, self.size)?;
+        for (label, value) in &self.fields {
+            writeln!(f, "  {}: {}", label, value)?;
+        }
+        if self.problems.is_empty() {
+            writeln!(f, "consistent")
+        } else {
+            for problem in &self.problems {
+                writeln!(f, "PROBLEM: {}", problem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn inspect_sign_message(bytes: &[u8]) -> Result<Inspection, anyhow::Error> {
+    let msg = MultiSignMessage::decode(bytes, &loosest_caps())?;
+    let round = match &msg {
+        MultiSignMessage::PhaseOneMsg(_) => "phase one",
+        MultiSignMessage::PhaseTwoMsg(_) => "phase two",
+        MultiSignMessage::PhaseThreeMsg(_) => "phase three",
+        MultiSignMessage::PhaseFourMsg(_) => "phase four",
+        MultiSignMessage::PhaseFiveStepOneMsg(_) => "phase five step one",
+        MultiSignMessage::PhaseFiveStepTwoMsg(_) => "phase five step two",
+        MultiSignMessage::PhaseFiveStepFourMsg(_) => "phase five step four",
+        MultiSignMessage::PhaseFiveStepFiveMsg(_) => "phase five step five",
+        MultiSignMessage::PhaseFiveStepSevenMsg(_) => "phase five step seven",
+    };
+    let mut out = Inspection::new(&format!("sign message, {}", round), bytes.len());
+    match &msg {
+        MultiSignMessage::PhaseOneMsg(m) => {
+            out.params(&m.params);
+            out.bigint("commitment", &m.commitment);
+            out.promise(&m.promise_state, &m.proof);
+        }
+        MultiSignMessage::PhaseTwoMsg(m) => {
+            out.params(&m.params);
+            out.cipher("homocipher", &m.homocipher);
+            out.cipher("homocipher_plus", &m.homocipher_plus);
+            out.bigint("t_p", &m.t_p.to_bigint());
+            out.bigint("t_p_plus", &m.t_p_plus.to_bigint());
+            out.point("b", &m.b);
+        }
+        MultiSignMessage::PhaseThreeMsg(m) => out.bigint("delta", &m.delta.to_bigint()),
+        MultiSignMessage::PhaseFourMsg(m) => {
+            out.point("open.public_share", &m.open.public_share);
+            out.dlog_proof("dl_proof", &m.dl_proof);
+        }
+        MultiSignMessage::PhaseFiveStepOneMsg(m) => out.bigint("commitment", &m.commitment),
+        MultiSignMessage::PhaseFiveStepTwoMsg(m) => {
+            out.point("v_i", &m.v_i);
+            out.point("a_i", &m.a_i);
+            out.point("b_i", &m.b_i);
+            out.dlog_proof("dl_proof", &m.dl_proof);
+        }
+        MultiSignMessage::PhaseFiveStepFourMsg(m) => out.bigint("commitment", &m.commitment),
+        MultiSignMessage::PhaseFiveStepFiveMsg(m) => {
+            out.point("u_i", &m.u_i);
+            out.point("t_i", &m.t_i);
+        }
+        MultiSignMessage::PhaseFiveStepSevenMsg(m) => {
+            out.bigint("s_i", &m.s_i.to_bigint());
+            out.bigint("l_i", &m.l_i.to_bigint());
+        }
+    }
+    Ok(out)
+}
+
+pub fn inspect_keygen_message(bytes: &[u8], threshold: usize) -> Result<Inspection, anyhow::Error> {
+    let msg = MultiKeyGenMessage::decode(bytes, &loosest_caps(), threshold)?;
+    let mut out;
+    match &msg {
+        MultiKeyGenMessage::PhaseOneTwoMsg(m) => {
+            out = Inspection::new("keygen message, phase one/two", bytes.len());
+            out.params(&m.params);
+            out.form("h_caret", &m.h_caret.0);
+            out.form("key_proof.t", &m.key_proof.t);
+            out.field("key_proof.u", m.key_proof.u.to_str_radix(16));
+            out.form("h", &m.h.0);
+            out.form("bases.g", &m.bases.g);
+            out.form("bases.gq", &m.bases.gq);
+            out.point("ec_pk", &m.ec_pk);
+            out.bigint("commitment", &m.commitment);
+        }
+        MultiKeyGenMessage::PhaseThreeMsg(m) => {
+            out = Inspection::new("keygen message, phase three", bytes.len());
+            out.point("open.public_share", &m.open.public_share);
+        }
+        MultiKeyGenMessage::PhaseFourMsg(m) => {
+            out = Inspection::new("keygen message, phase four", bytes.len());
+            let params = &m.vss_scheme.parameters;
+            out.field(
+                "vss",
+                format!("t = {}, n = {}", params.threshold, params.share_count),
+            );
+            out.field(
+                "vss.commitments",
+                m.vss_scheme.commitments.len().to_string(),
+            );
+            if m.vss_scheme.commitments.len() != params.threshold as usize + 1 {
+                out.problem("vss does not carry t + 1 commitments".to_string());
+            }
+            out.field("secret_share", "<withheld>");
+        }
+        MultiKeyGenMessage::PhaseFiveMsg(m) => {
+            out = Inspection::new("keygen message, phase five", bytes.len());
+            out.dlog_proof("dl_proof", &m.dl_proof);
+        }
+    }
+    Ok(out)
+}
+
+/// A keygen result (public half only), a ciphertext or a proof, as JSON.
+pub fn inspect_json(bytes: &[u8]) -> Result<Inspection, anyhow::Error> {
+    if let Ok(key) = serde_json::from_slice::<DMZKeyX>(bytes) {
+        let mut out = Inspection::new("keygen result", bytes.len());
+        out.field("index", key.index.clone());
+        out.field("participants", key.participants.join(", "));
+        out.field("pubkey.pk", key.pubkey.pk.join(", "));
+        let mut shares: Vec<&str> = key.pubkey.share_pks.keys().map(|s| s.as_str()).collect();
+        shares.sort_unstable();
+        out.field("pubkey.share_pks", shares.join(", "));
+        if key.pubkey.share_pks.len() != key.participants.len() {
+            out.problem("share keys do not match the participants".to_string());
+        }
+        out.field("privkey", "<withheld>");
+        return Ok(out);
+    }
+    if let Ok(proof) = serde_json::from_slice::<PromiseProof>(bytes) {
+        let mut out = Inspection::new("promise proof", bytes.len());
+        out.form("a1", &proof.a1);
+        out.form("a2", &proof.a2);
+        out.point("A1", &proof.A1);
+        out.point("A2", &proof.A2);
+        return Ok(out);
+    }
+    if let Ok(proof) = serde_json::from_slice::<CLDLProof>(bytes) {
+        let mut out = Inspection::new("CLDL proof", bytes.len());
+        out.form("t1", &proof.t1);
+        out.form("t2", &proof.t2);
+        out.point("t3", &proof.t3);
+        out.field("u1", format!("{} bits", proof.u1.bit_length()));
+        return Ok(out);
+    }
+    if let Ok(cipher) = serde_json::from_slice::<Ciphertext>(bytes) {
+        let mut out = Inspection::new("CL ciphertext", bytes.len());
+        out.cipher("cipher", &cipher);
+        return Ok(out);
+    }
+    Err(format_err!(
+        "Not a keygen result, proof or ciphertext in JSON"
+    ))
+}
+
+/// JSON if it looks like JSON, else a sign message, else a keygen message
+/// of a key with `threshold`.
+pub fn inspect(bytes: &[u8], threshold: usize) -> Result<Inspection, anyhow::Error> {
+    if bytes.first() == Some(&b'{') {
+        return inspect_json(bytes);
+    }
+    inspect_sign_message(bytes).or_else(|sign_err| {
+        inspect_keygen_message(bytes, threshold).map_err(|keygen_err| {
+            format_err!(
+                "Neither a sign message ({}) nor a keygen message ({})",
+                sign_err,
+                keygen_err
+            )
+        })
+    })
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn inspect_test() {
+    use crate::FE;
+
+    let group = &GROUP_UPDATE_1827;
+    let (_sk, pk) = group.keygen();
+    let (cipher, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    let json = serde_json::to_vec(&cipher).unwrap();
+    let out = inspect(&json, 1).unwrap();
+    assert_eq!(out.kind, "CL ciphertext");
+    assert!(out.is_consistent());
+    assert!(out.fields[0].1.starts_with("cl1827 form"));
+
+    let msg = MultiSignMessage::PhaseThreeMsg(SignPhaseThreeMsg {
+        delta: FE::random(),
+    });
+    let bytes = bincode::serialize(&msg).unwrap();
+    let out = inspect(&bytes, 1).unwrap();
+    assert_eq!(out.kind, "sign message, phase three");
+    assert!(out.to_string().contains("consistent"));
+
+    let mut bad = cipher.clone();
+    bad.c2.b = &bad.c2.b + &classgroup::prelude::Mpz::from(2u64);
+    let out = inspect(&serde_json::to_vec(&bad).unwrap(), 1).unwrap();
+    assert!(!out.is_consistent());
+    assert!(inspect(b"\xff\xff\xff\xff", 1).is_err());
+}
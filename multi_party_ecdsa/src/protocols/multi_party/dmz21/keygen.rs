@@ -19,15 +19,17 @@
 use crate::communication::sending_messages::SendingMessages;
 pub use crate::protocols::multi_party::dmz21::common::Parameters; // for compatibility
 use crate::protocols::multi_party::dmz21::common::*;
+use crate::protocols::multi_party::dmz21::directory::{Material, PartyDirectory};
+use crate::protocols::multi_party::dmz21::inbox::record;
 use crate::protocols::multi_party::dmz21::message::*;
+use crate::utilities::audit::{point_to_hex, AuditEvent, AuditLog};
+use crate::utilities::cl_key_proof::CLKeyProof;
 use crate::utilities::class_group::*;
 use crate::utilities::clkeypair::ClKeyPair;
 use crate::utilities::dl_com_zk::*;
 use crate::utilities::eckeypair::EcKeyPair;
 use crate::utilities::vss::*;
 use anyhow::{anyhow, format_err};
-use classgroup::gmp_classgroup::*;
-use classgroup::ClassGroup;
 use curv::arithmetic::Converter;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use std::collections::HashMap;
@@ -69,6 +71,9 @@ pub struct KeyGenPhase {
     pub msgsf: KeyGenMsgsFlag,
     pub dlog_com: DlogCommitment,
     pub mutex: Arc<Mutex<usize>>,
+    pub audit: Option<Arc<AuditLog>>,
+    pub cl: Arc<CLContext>,
+    pub directory: PartyDirectory,
 }
 
 impl KeyGenMsgs {
@@ -110,6 +115,16 @@ impl KeyGenPhase {
         partyid: String,
         params: Parameters,
         party_ids: &Option<Vec<String>>,
+    ) -> Result<Self, anyhow::Error> {
+        Self::new_with_context(partyid, params, party_ids, default_context())
+    }
+
+    /// Same as `new`, running in the CL parameters of `cl`.
+    pub fn new_with_context(
+        partyid: String,
+        params: Parameters,
+        party_ids: &Option<Vec<String>>,
+        cl: Arc<CLContext>,
     ) -> Result<Self, anyhow::Error> {
         // todo: remove the Option for party_ids in the future
         if *party_ids == None {
@@ -118,7 +133,7 @@ impl KeyGenPhase {
 
         let mutex = Arc::new(Mutex::new(0));
         // Generate cl keypair
-        let mut cl_keypair = ClKeyPair::new(&GROUP_1827);
+        let mut cl_keypair = ClKeyPair::new(&cl.base);
         let h_caret = cl_keypair.get_public_key().clone();
         cl_keypair.update_pk_exp_p();
         // Generate elgamal keypair
@@ -140,6 +155,11 @@ impl KeyGenPhase {
             private_signing_key.get_secret_key(),
             (*party_ids).clone(),
         )?;
+        // Our own material needs no checking.
+        let mut directory = PartyDirectory::new();
+        directory.insert_cl_pk(&partyid, cl_keypair.get_public_key())?;
+        directory.insert_commitment(&partyid, &dlog_com.commitment)?;
+        directory.insert_public_share(&partyid, &public_signing_key)?;
         Ok(Self {
             party_index: partyid.clone(),
             party_ids: (*party_ids).clone().unwrap(),
@@ -155,18 +175,60 @@ impl KeyGenPhase {
             msgsf: KeyGenMsgsFlag::new(),
             dlog_com,
             mutex,
+            audit: None,
+            cl,
+            directory,
         })
     }
 
+    /// Record the creation of the key share in `log` when keygen completes.
+    pub fn set_audit_log(&mut self, log: Arc<AuditLog>) {
+        self.audit = Some(log);
+    }
+
+    fn emit_key_created(&self) -> Result<(), anyhow::Error> {
+        if let Some(log) = &self.audit {
+            let event = AuditEvent::KeyCreated {
+                party_index: self.party_index.clone(),
+                participants: self.party_ids.clone(),
+                threshold: self.params.threshold,
+                public_key: point_to_hex(&self.public_signing_key),
+            };
+            log.emit(event, None)
+                .map_err(|why| format_err!("Audit failed in keygen phase five, cause {}", why))?;
+        }
+        Ok(())
+    }
+
     fn verify_phase_one_msg(
         &self,
         h_caret: &PK,
+        key_proof: &CLKeyProof,
         h: &PK,
-        gp: &GmpClassGroup,
+        bases: &DerivedBases,
     ) -> Result<(), anyhow::Error> {
-        let mut h_ret = h_caret.0.clone();
-        h_ret.pow(q());
-        if h_ret != h.0 || *gp != GROUP_UPDATE_1827.generator {
+        bases.verify(&self.cl).map_err(|why| {
+            format_err!(
+                "Verify derived bases failed in keygen phase onetwo, cause {}",
+                why
+            )
+        })?;
+        for pk in [h_caret, h].iter() {
+            self.cl.base.check_element(&pk.0).map_err(|why| {
+                format_err!(
+                    "Invalid CL public key in keygen phase onetwo, cause {}",
+                    why
+                )
+            })?;
+        }
+        key_proof.verify(&self.cl.base, h_caret).map_err(|why| {
+            format_err!(
+                "Verify CL key proof failed in keygen phase onetwo, cause {}",
+                why
+            )
+        })?;
+        let h_ret = self.cl.pow_public(&h_caret.0, &q());
+        if h_ret != h.0 {
             return Err(anyhow!(
                 "Verify phase one msg failed in keygen phase onetwo"
             ));
@@ -180,18 +242,18 @@ impl KeyGenPhase {
         msg: &KeyGenPhaseThreeMsg,
     ) -> Result<(), anyhow::Error> {
         let commitment = self
-            .msgs
-            .phase_one_two_msgs
-            .get(&index)
+            .directory
+            .commitment(&index)
             .ok_or(format_err!(
-                "Index is none in phase_one_two_msgs in keygen phase three"
+                "Index is none in directory in keygen phase three"
             ))?
-            .commitment
             .clone();
         let open = msg.open.clone();
 
         let dlog_com = DlogCommitment { commitment, open };
         dlog_com.verify()?;
+        self.directory
+            .insert_public_share(&index, &dlog_com.get_public_share())?;
         self.public_signing_key = &self.public_signing_key + dlog_com.get_public_share();
 
         Ok(())
@@ -241,15 +303,9 @@ impl KeyGenPhase {
         msg: &KeyGenPhaseFourMsg,
     ) -> Result<(), anyhow::Error> {
         // Check VSS
-        let q = &self
-            .msgs
-            .phase_three_msgs
-            .get(&index)
-            .ok_or(format_err!(
-                "Index is none in phase_one_two_msgs in keygen phase four"
-            ))?
-            .open
-            .public_share;
+        let q = self.directory.public_share(&index).ok_or(format_err!(
+            "Index is none in directory in keygen phase four"
+        ))?;
 
         if !(msg
             .vss_scheme
@@ -328,10 +384,16 @@ impl KeyGenPhase {
         let lock = Arc::clone(&self.mutex);
         let _lock = lock.lock().unwrap();
         let msg = KeyGenPhaseOneTwoMsg {
+            params: self.cl.group.params_id(),
             h_caret: self.h_caret.clone(),
+            key_proof: CLKeyProof::prove(
+                &self.cl.base,
+                self.cl_keypair.get_secret_key(),
+                &self.h_caret,
+            ),
             h: (*self.cl_keypair.get_public_key()).clone(),
             ec_pk: self.ec_keypair.get_public_key().clone(),
-            gp: GROUP_UPDATE_1827.generator.clone(),
+            bases: DerivedBases::derive(&self.cl),
             commitment: self.dlog_com.commitment.clone(),
         };
         self.msgs
@@ -348,8 +410,11 @@ impl KeyGenPhase {
     /// Return a result or the message to be sent in the next round.
     ///
     /// When a message is received, the processing is as follows:
-    ///   If this message already exists, do nothing; otherwise, insert it into the cache.
-    ///   When all the necessary messages have been received, generate the result or the next round of messages.
+    ///   Insert it into the cache of its round, even if that round has not started yet.
+    ///   An identical copy of a cached message is ignored; a different one is an error blaming
+    ///   the sender (see `inbox`).
+    ///   When all the necessary messages have been received and the previous round is done,
+    ///   generate the result or the next round of messages.
     pub fn msg_handler(
         &mut self,
         index: String,
@@ -362,29 +427,39 @@ impl KeyGenPhase {
             index,
             recv_msg
         );
-        let msg = bincode::deserialize(&recv_msg)
-            .map_err(|why| {
+        let caps = MessageCaps::for_context(&self.cl);
+        let msg =
+            MultiKeyGenMessage::decode(&recv_msg, &caps, self.params.threshold).map_err(|why| {
                 format_err!(
                     "Deserialize error in keygen msg_handler recv_msg, cause {}",
                     why
                 )
-            })
-            .unwrap();
+            })?;
         match msg {
             MultiKeyGenMessage::PhaseOneTwoMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_one_two_msgs,
+                    &index,
+                    msg,
+                    "keygen phase one",
+                )?;
                 if self.msgsf.phase_one_two_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_one_two_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_one_two_msgs
-                        .insert(index.clone(), msg.clone());
-                }
-
                 if self.msgs.phase_one_two_msgs.len() == self.params.share_count {
-                    for (_index, msg_) in self.msgs.phase_one_two_msgs.iter() {
-                        self.verify_phase_one_msg(&msg_.h_caret, &msg_.h, &msg_.gp)?;
+                    for (index, msg_) in self.msgs.phase_one_two_msgs.iter() {
+                        if self.directory.is_validated(index, Material::ClPublicKey) {
+                            continue;
+                        }
+                        self.verify_phase_one_msg(
+                            &msg_.h_caret,
+                            &msg_.key_proof,
+                            &msg_.h,
+                            &msg_.bases,
+                        )?;
+                        self.directory.insert_cl_pk(index, &msg_.h)?;
+                        self.directory.insert_commitment(index, &msg_.commitment)?;
                     }
                     let keygen_phase_three_msg = KeyGenPhaseThreeMsg {
                         open: self.dlog_com.open.clone(),
@@ -404,17 +479,19 @@ impl KeyGenPhase {
                 }
             }
             MultiKeyGenMessage::PhaseThreeMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_three_msgs,
+                    &index,
+                    msg,
+                    "keygen phase three",
+                )?;
                 if self.msgsf.phase_three_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_three_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_three_msgs
-                        .insert(index.clone(), msg.clone());
-                }
-
-                if self.msgs.phase_three_msgs.len() == self.params.share_count {
+                if self.msgs.phase_three_msgs.len() == self.params.share_count
+                    && self.msgsf.phase_one_two_msgs == 1
+                {
                     for (index, msg) in self.msgs.phase_three_msgs.clone().iter() {
                         if *index != self.party_index {
                             self.handle_phase_three_msg(index.clone(), &msg)?;
@@ -426,15 +503,19 @@ impl KeyGenPhase {
                 }
             }
             MultiKeyGenMessage::PhaseFourMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_four_msgs,
+                    &index,
+                    msg,
+                    "keygen phase four",
+                )?;
                 if self.msgsf.phase_four_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_four_msgs.get(&index).is_some() {
-                    self.msgs.phase_four_msgs.insert(index.clone(), msg.clone());
-                }
-
-                if self.msgs.phase_four_msgs.len() == self.params.share_count {
+                if self.msgs.phase_four_msgs.len() == self.params.share_count
+                    && self.msgsf.phase_three_msgs == 1
+                {
                     for (index, msg) in self.msgs.phase_four_msgs.clone().iter() {
                         if *index != self.party_index {
                             self.handle_phase_four_msg(index.clone(), &msg)?;
@@ -456,19 +537,24 @@ impl KeyGenPhase {
                 }
             }
             MultiKeyGenMessage::PhaseFiveMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_msgs,
+                    &index,
+                    msg,
+                    "keygen phase five",
+                )?;
                 if self.msgsf.phase_five_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_msgs.get(&index).is_some() {
-                    self.msgs.phase_five_msgs.insert(index.clone(), msg.clone());
-                }
-
-                if self.msgs.phase_five_msgs.len() == self.params.share_count {
+                if self.msgs.phase_five_msgs.len() == self.params.share_count
+                    && self.msgsf.phase_four_msgs == 1
+                {
                     for (index, msg) in self.msgs.phase_five_msgs.clone().iter() {
                         self.handle_phase_five_msg(index.clone(), &msg)?;
                     }
                     let keygen_json = self.generate_result_json_string()?;
+                    self.emit_key_created()?;
                     self.msgsf.phase_five_msgs = 1;
                     return Ok(SendingMessages::KeyGenSuccessWithResult(keygen_json));
                 }
@@ -477,3 +563,74 @@ impl KeyGenPhase {
         Ok(SendingMessages::EmptyMsg)
     }
 }
+
+#[test]
+fn keygen_reordered_delivery_test() {
+    use crate::communication::delivery::round_of;
+
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let ids: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    let mut parties: HashMap<String, KeyGenPhase> = ids
+        .iter()
+        .map(|id| {
+            let phase = KeyGenPhase::new(id.clone(), params.clone(), &Some(ids.clone())).unwrap();
+            (id.clone(), phase)
+        })
+        .collect();
+    // (from, to, bytes), delivered newest first and each one twice, so later
+    // rounds regularly overtake earlier ones.
+    let mut queue: Vec<(String, String, Vec<u8>)> = Vec::new();
+    let route =
+        |queue: &mut Vec<(String, String, Vec<u8>)>, from: &str, out: SendingMessages| match out {
+            SendingMessages::BroadcastMessage(bytes) => {
+                for to in &ids {
+                    queue.push((from.to_string(), to.clone(), bytes.clone()));
+                }
+            }
+            SendingMessages::P2pMessage(map) => {
+                for (to, bytes) in map {
+                    queue.push((from.to_string(), to, bytes));
+                }
+            }
+            _ => {}
+        };
+    for id in &ids {
+        let out = parties.get_mut(id).unwrap().process_begin().unwrap();
+        route(&mut queue, id, out);
+    }
+    let mut first_phase_one = None;
+    let mut results = HashMap::new();
+    while let Some((from, to, bytes)) = queue.pop() {
+        if from == "2" && to == "1" && round_of(&bytes) == Some(0) {
+            first_phase_one = Some(bytes.clone());
+        }
+        let party = parties.get_mut(&to).unwrap();
+        for _ in 0..2 {
+            match party.msg_handler(from.clone(), &bytes).unwrap() {
+                SendingMessages::KeyGenSuccessWithResult(key) => {
+                    results.insert(to.clone(), key);
+                }
+                out => route(&mut queue, &to, out),
+            }
+        }
+    }
+    assert_eq!(results.len(), 3);
+
+    // Party 2 re-sending its round one message with another commitment is
+    // caught and blamed on it.
+    let mut keygen = KeyGenPhase::new("1".to_string(), params, &Some(ids.clone())).unwrap();
+    let original = first_phase_one.unwrap();
+    keygen.msg_handler("2".to_string(), &original).unwrap();
+    let decoded = MultiKeyGenMessage::decode(&original, &MessageCaps::default(), 1).unwrap();
+    let mut forged = match decoded {
+        MultiKeyGenMessage::PhaseOneTwoMsg(msg) => msg,
+        _ => panic!("expected a round one message"),
+    };
+    forged.commitment = forged.commitment + curv::BigInt::from(1);
+    let forged = bincode::serialize(&MultiKeyGenMessage::PhaseOneTwoMsg(forged)).unwrap();
+    let err = keygen.msg_handler("2".to_string(), &forged).unwrap_err();
+    assert!(err.to_string().contains("Party 2"));
+}
@@ -20,6 +20,8 @@ use crate::protocols::multi_party::dmz21::keygen::KeyGenPhase;
 use crate::protocols::multi_party::dmz21::keygen::Parameters;
 use crate::protocols::multi_party::dmz21::sign::SignPhase;
 use crate::protocols::multi_party::dmz21::sign::SignPhaseOnline;
+use crate::utilities::evm::EvmTx;
+use crate::utilities::signature::SignatureX;
 use anyhow::format_err;
 use crossbeam_channel::*;
 use std::thread;
@@ -150,6 +152,24 @@ pub fn dmz_multi_online_sign_local(
     return signature;
 }
 
+/// Local online sign of an EVM transaction
+/// Input
+///   tx, rx, offline_result: as for `dmz_multi_online_sign_local`
+///   evm_tx: the transaction, whose signing hash is the message
+/// Output
+///   raw signed transaction
+pub fn dmz_evm_online_sign_local(
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<(String, Vec<u8>)>,
+    offline_result: String,
+    evm_tx: &EvmTx,
+) -> Vec<u8> {
+    let message = evm_tx.signing_hash().to_vec();
+    let signature = dmz_multi_online_sign_local(tx, rx, offline_result, message);
+    let signature: SignatureX = serde_json::from_str(&signature).unwrap();
+    evm_tx.encode_signed(&signature).unwrap()
+}
+
 pub fn dmz_multi_keygen_local_test(params: Parameters, party_ids: Option<Vec<String>>) {
     let (tx11, rx11) = unbounded::<Vec<u8>>();
     let (tx12, rx12) = unbounded::<(String, Vec<u8>)>();
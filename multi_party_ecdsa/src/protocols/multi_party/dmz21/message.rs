@@ -15,19 +15,26 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::communication::delivery::round_of;
 use crate::protocols::multi_party::dmz21::common::*;
+use crate::utilities::cl_key_proof::CLKeyProof;
 use crate::utilities::class_group::*;
 use crate::utilities::dl_com_zk::*;
+use crate::utilities::params_id::ParamsId;
 use crate::utilities::promise_sigma_multi::{PromiseProof, PromiseState};
 use crate::utilities::vss::Vss;
-use classgroup::gmp_classgroup::*;
+use anyhow::format_err;
+use bincode::Options;
+use classgroup::prelude::GmpClassGroup;
 use curv::arithmetic::One;
 use curv::cryptographic_primitives::proofs::sigma_correct_homomorphic_elgamal_enc::HomoELGamalProof;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use curv::BigInt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum MultiKeyGenMessage {
     PhaseOneTwoMsg(KeyGenPhaseOneTwoMsg),
     PhaseThreeMsg(KeyGenPhaseThreeMsg),
@@ -36,6 +43,7 @@ pub enum MultiKeyGenMessage {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum MultiSignMessage {
     PhaseOneMsg(SignPhaseOneMsg),
     PhaseTwoMsg(SignPhaseTwoMsg),
@@ -50,10 +58,14 @@ pub enum MultiSignMessage {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct KeyGenPhaseOneTwoMsg {
+    /// `ParamsId` of the session's CL group, checked by `decode`.
+    pub params: ParamsId,
     pub h_caret: PK,
+    /// Knowledge of the secret key of `h_caret`.
+    pub key_proof: CLKeyProof,
     pub h: PK,
     pub ec_pk: GE,
-    pub gp: GmpClassGroup,
+    pub bases: DerivedBases,
     pub commitment: BigInt,
 }
 
@@ -75,6 +87,8 @@ pub struct KeyGenPhaseFiveMsg {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SignPhaseOneMsg {
+    /// `ParamsId` of the session's CL group, checked by `decode`.
+    pub params: ParamsId,
     pub commitment: BigInt,
     pub promise_state: PromiseState,
     pub proof: PromiseProof,
@@ -82,6 +96,8 @@ pub struct SignPhaseOneMsg {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SignPhaseTwoMsg {
+    /// `ParamsId` of the session's CL group, checked by `decode`.
+    pub params: ParamsId,
     pub homocipher: Ciphertext,
     pub homocipher_plus: Ciphertext,
     pub t_p: FE,
@@ -90,7 +106,7 @@ pub struct SignPhaseTwoMsg {
 }
 
 impl SignPhaseTwoMsg {
-    pub fn new() -> Self {
+    pub fn new(params: ParamsId) -> Self {
         let c1 = GmpClassGroup::default();
         let c2 = GmpClassGroup::default();
         let homocipher = Ciphertext {
@@ -99,6 +115,7 @@ impl SignPhaseTwoMsg {
         };
         let homocipher_plus = Ciphertext { c1, c2 };
         SignPhaseTwoMsg {
+            params,
             homocipher,
             homocipher_plus,
             t_p: FE::random(),
@@ -159,4 +176,317 @@ impl SignPhaseFiveStepFiveMsg {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SignPhaseFiveStepSevenMsg {
     pub s_i: FE,
+    /// Opens V_i = R^{s_i} * g^{l_i} so the share can be checked on its own.
+    pub l_i: FE,
+}
+
+/// Every secp256k1 point or scalar and every 256-bit `BigInt` fits in
+/// `EC_ITEM_MAX_SIZE` of the bincode wire encoding.
+pub const EC_ITEM_MAX_SIZE: usize = 128;
+/// Enum tag, length prefixes and other framing of one message.
+pub const MSG_OVERHEAD_MAX_SIZE: usize = 64;
+/// The `ParamsId` in front of the CL values of a message.
+pub const PARAMS_ID_SIZE: usize = 32;
+
+const DLOG_PROOF_MAX_SIZE: usize = 3 * EC_ITEM_MAX_SIZE;
+const HOMO_ELGAMAL_PROOF_MAX_SIZE: usize = 4 * EC_ITEM_MAX_SIZE;
+
+pub const KEYGEN_PHASE_THREE_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + 2 * EC_ITEM_MAX_SIZE;
+pub const KEYGEN_PHASE_FIVE_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + DLOG_PROOF_MAX_SIZE;
+pub const SIGN_PHASE_THREE_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + EC_ITEM_MAX_SIZE;
+pub const SIGN_PHASE_FOUR_MAX_SIZE: usize =
+    MSG_OVERHEAD_MAX_SIZE + 2 * EC_ITEM_MAX_SIZE + DLOG_PROOF_MAX_SIZE;
+pub const SIGN_PHASE_FIVE_STEP_ONE_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + EC_ITEM_MAX_SIZE;
+pub const SIGN_PHASE_FIVE_STEP_TWO_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE
+    + 4 * EC_ITEM_MAX_SIZE
+    + DLOG_PROOF_MAX_SIZE
+    + HOMO_ELGAMAL_PROOF_MAX_SIZE;
+pub const SIGN_PHASE_FIVE_STEP_FOUR_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + EC_ITEM_MAX_SIZE;
+pub const SIGN_PHASE_FIVE_STEP_FIVE_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + 3 * EC_ITEM_MAX_SIZE;
+pub const SIGN_PHASE_FIVE_STEP_SEVEN_MAX_SIZE: usize = MSG_OVERHEAD_MAX_SIZE + 2 * EC_ITEM_MAX_SIZE;
+
+/// The VSS commitments grow with the threshold: t + 1 points plus the share.
+pub const fn keygen_phase_four_max_size(threshold: usize) -> usize {
+    MSG_OVERHEAD_MAX_SIZE + (threshold + 2) * EC_ITEM_MAX_SIZE
+}
+
+/// Length prefix, sign and hex digits of an integer of at most `bits` bits.
+const fn hex_max_size(bits: usize) -> usize {
+    8 + 1 + (bits + 3) / 4
+}
+
+/// Size budget of the messages that carry CL values, which follows from
+/// the parameter set.
+///
+/// A class group element is serialized as four hex strings (a, b, c and the
+/// discriminant), each no longer than the discriminant; a CL exponent such
+/// as `z2` in the promise proof is at most a sigma protocol response. These
+/// are the bounds `CLGroup::check_mpz` enforces, so the caps refuse nothing
+/// that would pass it.
+///
+/// `params`, if set, is the only parameter set `decode` accepts CL values of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageCaps {
+    pub cl_element: usize,
+    pub cl_exponent: usize,
+    pub params: Option<ParamsId>,
+}
+
+impl MessageCaps {
+    pub fn for_group(group: &CLGroup) -> Self {
+        Self {
+            cl_element: 4 * hex_max_size(group.max_bits(MpzField::FormOuter)),
+            cl_exponent: hex_max_size(group.max_bits(MpzField::Response)),
+            params: Some(group.params_id()),
+        }
+    }
+
+    pub fn for_context(cl: &CLContext) -> Self {
+        Self::for_group(&cl.group)
+    }
+
+    pub fn keygen_phase_one_two(&self) -> usize {
+        MSG_OVERHEAD_MAX_SIZE
+            + PARAMS_ID_SIZE
+            + 5 * self.cl_element
+            + self.cl_exponent
+            + 2 * EC_ITEM_MAX_SIZE
+    }
+
+    pub fn sign_phase_one(&self) -> usize {
+        MSG_OVERHEAD_MAX_SIZE
+            + PARAMS_ID_SIZE
+            + 5 * self.cl_element
+            + self.cl_exponent
+            + 8 * EC_ITEM_MAX_SIZE
+    }
+
+    pub fn sign_phase_two(&self) -> usize {
+        MSG_OVERHEAD_MAX_SIZE + PARAMS_ID_SIZE + 4 * self.cl_element + 3 * EC_ITEM_MAX_SIZE
+    }
+
+    /// Refuse CL values of a message of another parameter set than `params`.
+    pub fn check_params(&self, params: &ParamsId) -> Result<(), anyhow::Error> {
+        match self.params {
+            Some(expected) if expected != *params => Err(format_err!(
+                "Message of CL parameter set {}, expected {}",
+                params.to_hex(),
+                expected.to_hex()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The cap of the largest keygen or sign message of a session of
+    /// `parties`, whatever its threshold.
+    pub fn largest(&self, parties: usize) -> usize {
+        let keygen = (0..4).filter_map(|v| MultiKeyGenMessage::max_encoded_size(v, self, parties));
+        let sign = (0..9).filter_map(|v| MultiSignMessage::max_encoded_size(v, self));
+        keygen.chain(sign).max().unwrap_or(0)
+    }
+}
+
+/// The caps of `default_context`.
+impl Default for MessageCaps {
+    fn default() -> Self {
+        Self::for_context(&default_context())
+    }
+}
+
+impl MessageCaps {
+    /// Caps of online sign, which exchanges no CL values: the offline
+    /// rounds that carry them are over their caps.
+    pub const NO_CL: MessageCaps = MessageCaps {
+        cl_element: 0,
+        cl_exponent: 0,
+        params: None,
+    };
+}
+
+/// Length of a message in the wire encoding, without encoding it.
+pub trait EncodedSize: Serialize {
+    fn encoded_size(&self) -> usize {
+        bincode::serialized_size(self)
+            .map(|size| size as usize)
+            .unwrap_or(usize::MAX)
+    }
+}
+
+impl EncodedSize for MultiKeyGenMessage {}
+impl EncodedSize for MultiSignMessage {}
+impl EncodedSize for KeyGenPhaseOneTwoMsg {}
+impl EncodedSize for KeyGenPhaseThreeMsg {}
+impl EncodedSize for KeyGenPhaseFourMsg {}
+impl EncodedSize for KeyGenPhaseFiveMsg {}
+impl EncodedSize for SignPhaseOneMsg {}
+impl EncodedSize for SignPhaseTwoMsg {}
+impl EncodedSize for SignPhaseThreeMsg {}
+impl EncodedSize for SignPhaseFourMsg {}
+impl EncodedSize for SignPhaseFiveStepOneMsg {}
+impl EncodedSize for SignPhaseFiveStepTwoMsg {}
+impl EncodedSize for SignPhaseFiveStepFourMsg {}
+impl EncodedSize for SignPhaseFiveStepFiveMsg {}
+impl EncodedSize for SignPhaseFiveStepSevenMsg {}
+
+/// Decode with the same options as `bincode::deserialize`, but refuse to
+/// read, or allocate for, more than `cap` bytes.
+fn decode_capped<T: DeserializeOwned>(bytes: &[u8], cap: usize) -> Result<T, anyhow::Error> {
+    if bytes.len() > cap {
+        return Err(format_err!(
+            "Message of {} bytes exceeds the cap of {} bytes",
+            bytes.len(),
+            cap
+        ));
+    }
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(cap as u64)
+        .deserialize(bytes)
+        .map_err(|why| format_err!("Deserialize error of capped message, cause {}", why))
+}
+
+impl MultiKeyGenMessage {
+    /// Largest accepted encoding of the variant with wire index `variant`.
+    pub fn max_encoded_size(variant: u32, caps: &MessageCaps, threshold: usize) -> Option<usize> {
+        match variant {
+            0 => Some(caps.keygen_phase_one_two()),
+            1 => Some(KEYGEN_PHASE_THREE_MAX_SIZE),
+            2 => Some(keygen_phase_four_max_size(threshold)),
+            3 => Some(KEYGEN_PHASE_FIVE_MAX_SIZE),
+            _ => None,
+        }
+    }
+
+    pub fn decode(
+        bytes: &[u8],
+        caps: &MessageCaps,
+        threshold: usize,
+    ) -> Result<Self, anyhow::Error> {
+        let cap = round_of(bytes)
+            .and_then(|variant| Self::max_encoded_size(variant, caps, threshold))
+            .ok_or(format_err!("Unknown keygen message variant"))?;
+        let msg = decode_capped(bytes, cap)?;
+        if let MultiKeyGenMessage::PhaseOneTwoMsg(msg) = &msg {
+            caps.check_params(&msg.params)?;
+        }
+        Ok(msg)
+    }
+}
+
+impl MultiSignMessage {
+    /// Largest accepted encoding of the variant with wire index `variant`.
+    pub fn max_encoded_size(variant: u32, caps: &MessageCaps) -> Option<usize> {
+        match variant {
+            0 => Some(caps.sign_phase_one()),
+            1 => Some(caps.sign_phase_two()),
+            2 => Some(SIGN_PHASE_THREE_MAX_SIZE),
+            3 => Some(SIGN_PHASE_FOUR_MAX_SIZE),
+            4 => Some(SIGN_PHASE_FIVE_STEP_ONE_MAX_SIZE),
+            5 => Some(SIGN_PHASE_FIVE_STEP_TWO_MAX_SIZE),
+            6 => Some(SIGN_PHASE_FIVE_STEP_FOUR_MAX_SIZE),
+            7 => Some(SIGN_PHASE_FIVE_STEP_FIVE_MAX_SIZE),
+            8 => Some(SIGN_PHASE_FIVE_STEP_SEVEN_MAX_SIZE),
+            _ => None,
+        }
+    }
+
+    pub fn decode(bytes: &[u8], caps: &MessageCaps) -> Result<Self, anyhow::Error> {
+        let cap = round_of(bytes)
+            .and_then(|variant| Self::max_encoded_size(variant, caps))
+            .ok_or(format_err!("Unknown sign message variant"))?;
+        let msg = decode_capped(bytes, cap)?;
+        match &msg {
+            MultiSignMessage::PhaseOneMsg(msg) => caps.check_params(&msg.params)?,
+            MultiSignMessage::PhaseTwoMsg(msg) => caps.check_params(&msg.params)?,
+            _ => {}
+        }
+        Ok(msg)
+    }
+}
+
+#[test]
+fn test_message_caps() {
+    let caps = MessageCaps::default();
+    let msg = MultiSignMessage::PhaseTwoMsg(SignPhaseTwoMsg::new(caps.params.unwrap()));
+    let bytes = bincode::serialize(&msg).unwrap();
+    assert_eq!(msg.encoded_size(), bytes.len());
+    assert!(MultiSignMessage::decode(&bytes, &caps).is_ok());
+    assert_eq!(caps.largest(3), caps.sign_phase_one());
+    // Online sign takes no CL values at all.
+    assert!(MultiSignMessage::decode(&bytes, &MessageCaps::NO_CL).is_err());
+
+    // A phase-three tag in front of a phase-two sized body is over its cap.
+    let mut inflated = 2u32.to_le_bytes().to_vec();
+    inflated.extend(vec![0u8; SIGN_PHASE_THREE_MAX_SIZE]);
+    assert!(MultiSignMessage::decode(&inflated, &caps).is_err());
+
+    // A string length prefix claiming gigabytes is refused before allocating.
+    let mut huge = 2u32.to_le_bytes().to_vec();
+    huge.extend(&(1u64 << 32).to_le_bytes());
+    assert!(MultiSignMessage::decode(&huge, &caps).is_err());
+}
+
+#[test]
+fn test_real_messages_within_caps() {
+    use crate::communication::sending_messages::SendingMessages;
+    use crate::protocols::multi_party::dmz21::keygen::{KeyGenPhase, Parameters};
+    use crate::protocols::multi_party::dmz21::sign::SignPhase;
+    use crate::protocols::multi_party::dmz21::simulation::{simulate, SimConfig};
+    use std::collections::BTreeMap;
+
+    let payloads = |out: SendingMessages| -> Vec<Vec<u8>> {
+        match out {
+            SendingMessages::BroadcastMessage(bytes) | SendingMessages::SubsetMessage(bytes) => {
+                vec![bytes]
+            }
+            SendingMessages::P2pMessage(map) => map.into_iter().map(|(_, bytes)| bytes).collect(),
+            _ => Vec::new(),
+        }
+    };
+    let caps = MessageCaps::default();
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let ids: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    let keygen = |id: &String| KeyGenPhase::new(id.clone(), params.clone(), &Some(ids.clone()));
+    for bytes in payloads(keygen(&ids[0]).unwrap().process_begin().unwrap()) {
+        match MultiKeyGenMessage::decode(&bytes, &caps, params.threshold).unwrap() {
+            MultiKeyGenMessage::PhaseOneTwoMsg(_) => {}
+            _ => panic!("expected a keygen phase one/two message"),
+        }
+    }
+
+    let parties: BTreeMap<String, KeyGenPhase> = ids
+        .iter()
+        .map(|id| (id.clone(), keygen(id).unwrap()))
+        .collect();
+    let keys = simulate(&SimConfig::new(3), parties).unwrap().results;
+    let subset = ids[..2].to_vec();
+    let mut signers: Vec<SignPhase> = subset
+        .iter()
+        .map(|id| SignPhase::new(id.clone(), params.clone(), &subset, &keys[id]).unwrap())
+        .collect();
+    let phase_one: Vec<Vec<u8>> = signers
+        .iter_mut()
+        .flat_map(|signer| payloads(signer.process_begin().unwrap()))
+        .collect();
+    let mut phase_two = Vec::new();
+    for (from, bytes) in subset.iter().zip(&phase_one) {
+        match MultiSignMessage::decode(bytes, &caps).unwrap() {
+            MultiSignMessage::PhaseOneMsg(_) => {}
+            _ => panic!("expected a sign phase one message"),
+        }
+        phase_two.extend(payloads(
+            signers[0].msg_handler(from.clone(), bytes).unwrap(),
+        ));
+    }
+    assert!(!phase_two.is_empty());
+    for bytes in &phase_two {
+        match MultiSignMessage::decode(bytes, &caps).unwrap() {
+            MultiSignMessage::PhaseTwoMsg(_) => {}
+            _ => panic!("expected a sign phase two message"),
+        }
+    }
 }
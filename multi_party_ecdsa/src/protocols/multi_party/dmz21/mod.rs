@@ -15,8 +15,25 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+pub mod approval;
+pub mod authorization;
+pub mod benchmark;
 pub mod common;
+pub mod config;
+pub mod directory;
+pub mod health;
+pub mod inbox;
+pub mod inspect;
 pub mod keygen;
 pub mod local;
 pub mod message;
+pub mod negotiate;
+pub mod presign_pool;
+pub mod recording;
+pub mod replay;
+pub mod revocation;
 pub mod sign;
+pub mod simulation;
+pub mod size_report;
+pub mod typestate;
+pub mod view;
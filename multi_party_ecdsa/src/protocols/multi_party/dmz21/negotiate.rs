@@ -0,0 +1,371 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Agreement on protocol version, parameter set, curve and features before a
+//! session starts.
+//!
+//! Each party broadcasts a `Hello` listing what its build supports. Once all
+//! of them are in, every party runs `negotiate` on the same set and gets the
+//! same `Agreed` tuple, or a `NegotiationError` naming what did not overlap
+//! and who offered what. Hellos are JSON with string identifiers so that a
+//! build can still read the offer of a newer one during a rolling upgrade.
+//!
+//! The outcome goes into the session through `Agreed::session_id`, which
+//! `SignPhase::set_replay_protection` binds every ciphertext tag to, and
+//! `Agreed::context` picks the CL parameters for `new_with_context`.
+#[cfg(feature = "compression")]
+use crate::communication::compression::{self, Compression};
+use crate::protocols::multi_party::dmz21::config::SecurityConfig;
+use crate::protocols::multi_party::dmz21::size_report::SecurityLevel;
+use crate::utilities::class_group::CLContext;
+use crate::utilities::transcript::Transcript;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+/// Wire versions of the keygen and sign messages this build speaks, newest
+/// first.
+pub const PROTOCOL_VERSIONS: &[u16] = &[1];
+
+/// Parameter sets in order of preference when several are shared.
+pub const PARAM_SETS: &[SecurityLevel] = &[
+    #[cfg(feature = "params-1827")]
+    SecurityLevel::Cl1827,
+    #[cfg(feature = "params-2048")]
+    SecurityLevel::Cl2048,
+    #[cfg(feature = "params-2432")]
+    SecurityLevel::Cl2432,
+    #[cfg(feature = "params-2944")]
+    SecurityLevel::Cl2944,
+    #[cfg(feature = "params-3072")]
+    SecurityLevel::Cl3072,
+];
+
+pub const CURVES: &[&str] = &["secp256k1"];
+
+/// Optional behaviour a session can rely on only if every party has it.
+pub const FEATURES: &[&str] = &["approval", "presign-pool", "replay-guard"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub party_index: String,
+    pub versions: Vec<u16>,
+    pub param_sets: Vec<String>,
+    pub curves: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl Hello {
+    /// What this build supports.
+    pub fn local(party_index: String) -> Self {
+        Self {
+            party_index,
+            versions: PROTOCOL_VERSIONS.to_vec(),
+            param_sets: PARAM_SETS.iter().map(|p| p.name().to_string()).collect(),
+            curves: CURVES.iter().map(|c| c.to_string()).collect(),
+            features: Self::local_features(),
+        }
+    }
+
+    fn local_features() -> Vec<String> {
+        #[allow(unused_mut)]
+        let mut features: Vec<String> = FEATURES.iter().map(|f| f.to_string()).collect();
+        #[cfg(feature = "compression")]
+        features.push(compression::FEATURE.to_string());
+        features
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Hello is always serializable")
+    }
+
+    /// Decode the hello `from` sent. Unknown fields are ignored, so a newer
+    /// build may add some.
+    pub fn decode(from: &str, bytes: &[u8]) -> Result<Self, NegotiationError> {
+        let hello: Self =
+            serde_json::from_slice(bytes).map_err(|why| NegotiationError::Malformed {
+                party: from.to_string(),
+                reason: why.to_string(),
+            })?;
+        if hello.party_index != from {
+            return Err(NegotiationError::Malformed {
+                party: from.to_string(),
+                reason: format!("hello claims to be from {}", hello.party_index),
+            });
+        }
+        Ok(hello)
+    }
+}
+
+/// The tuple a session runs with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Agreed {
+    pub version: u16,
+    pub param_set: SecurityLevel,
+    pub curve: String,
+    /// Features every party offered, sorted.
+    pub features: Vec<String>,
+    /// The parties the agreement covers, sorted.
+    pub parties: Vec<String>,
+    /// Hash of the quorum-approved `SecurityConfig` in force, hex.
+    #[serde(default)]
+    pub config: Option<String>,
+}
+
+impl Agreed {
+    pub fn context(&self) -> Arc<CLContext> {
+        self.param_set.context()
+    }
+
+    /// Bind the session to `config`, so that only parties applying the
+    /// same config agree on the session id. The config must be the one the
+    /// session runs under: same parameter set and `threshold`, and every
+    /// agreed party one of its parties.
+    pub fn with_config(
+        self,
+        config: &SecurityConfig,
+        threshold: usize,
+    ) -> Result<Self, NegotiationError> {
+        if config.param_set != self.param_set {
+            return Err(NegotiationError::ConfigMismatch(format!(
+                "config has parameter set {}, the session {}",
+                config.param_set.name(),
+                self.param_set.name()
+            )));
+        }
+        if config.threshold != threshold {
+            return Err(NegotiationError::ConfigMismatch(format!(
+                "config has threshold {}, the session {}",
+                config.threshold, threshold
+            )));
+        }
+        if let Some(party) = self
+            .parties
+            .iter()
+            .find(|p| !config.parties.contains_key(*p))
+        {
+            return Err(NegotiationError::ConfigMismatch(format!(
+                "party {} is not in config version {}",
+                party, config.version
+            )));
+        }
+        Ok(Self {
+            config: Some(hex::encode(config.hash())),
+            ..self
+        })
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// The payload codec, if every party offered compression. It refuses
+    /// frames larger than any round message of the session.
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<Compression> {
+        if self.has_feature(compression::FEATURE) {
+            Some(Compression {
+                max_payload: self.param_set.caps().largest(self.parties.len()),
+                ..Compression::default()
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Hash of the agreed tuple and the parties, to be used as the session
+    /// id so that a transcript cannot be carried into a session that agreed
+    /// on something else.
+    pub fn session_id(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"DMZ21-NEGOTIATION")
+            .append(&self.version.to_be_bytes()[..])
+            .append(self.param_set.name().as_bytes())
+            .append(self.curve.as_bytes())
+            .append(&(self.features.len() as u64).to_be_bytes()[..]);
+        for feature in &self.features {
+            transcript = transcript.append(feature.as_bytes());
+        }
+        transcript = transcript.append(&(self.parties.len() as u64).to_be_bytes()[..]);
+        for party in &self.parties {
+            transcript = transcript.append(party.as_bytes());
+        }
+        if let Some(config) = &self.config {
+            transcript = transcript.append(config.as_bytes());
+        }
+        transcript.finalize()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NegotiationError {
+    Malformed {
+        party: String,
+        reason: String,
+    },
+    MissingHello(String),
+    /// No parties to negotiate for.
+    NoParties,
+    /// Each variant below carries every party's offer for the field.
+    NoCommonVersion(BTreeMap<String, Vec<u16>>),
+    NoCommonParamSet(BTreeMap<String, Vec<String>>),
+    NoCommonCurve(BTreeMap<String, Vec<String>>),
+    /// The security config does not describe the agreed session.
+    ConfigMismatch(String),
+}
+
+impl fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NegotiationError::Malformed { party, reason } => {
+                write!(f, "Malformed hello from party {}: {}", party, reason)
+            }
+            NegotiationError::MissingHello(party) => write!(f, "No hello from party {}", party),
+            NegotiationError::NoParties => write!(f, "No parties to negotiate for"),
+            NegotiationError::NoCommonVersion(offers) => {
+                write!(f, "No protocol version shared by all parties: {:?}", offers)
+            }
+            NegotiationError::NoCommonParamSet(offers) => {
+                write!(f, "No parameter set shared by all parties: {:?}", offers)
+            }
+            NegotiationError::NoCommonCurve(offers) => {
+                write!(f, "No curve shared by all parties: {:?}", offers)
+            }
+            NegotiationError::ConfigMismatch(reason) => {
+                write!(f, "Security config does not match the session: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NegotiationError {}
+
+/// Agree on a session tuple for `parties` from their hellos. Every party
+/// computes the same result: the newest shared version, the first shared
+/// parameter set and curve in this build's order of preference, and all
+/// shared features. Parameter sets and curves this build does not know are
+/// skipped.
+pub fn negotiate(
+    parties: &[String],
+    hellos: &HashMap<String, Hello>,
+) -> Result<Agreed, NegotiationError> {
+    if parties.is_empty() {
+        return Err(NegotiationError::NoParties);
+    }
+    let mut offers = Vec::with_capacity(parties.len());
+    for party in parties {
+        let hello = hellos
+            .get(party)
+            .ok_or_else(|| NegotiationError::MissingHello(party.clone()))?;
+        offers.push(hello);
+    }
+    let offered = |field: fn(&Hello) -> Vec<String>| -> BTreeMap<String, Vec<String>> {
+        offers
+            .iter()
+            .map(|h| (h.party_index.clone(), field(h)))
+            .collect()
+    };
+
+    let version = PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| offers.iter().all(|h| h.versions.contains(v)))
+        .copied()
+        .ok_or_else(|| {
+            NegotiationError::NoCommonVersion(
+                offers
+                    .iter()
+                    .map(|h| (h.party_index.clone(), h.versions.clone()))
+                    .collect(),
+            )
+        })?;
+    let param_set = PARAM_SETS
+        .iter()
+        .find(|p| {
+            offers
+                .iter()
+                .all(|h| h.param_sets.iter().any(|s| s == p.name()))
+        })
+        .copied()
+        .ok_or_else(|| NegotiationError::NoCommonParamSet(offered(|h| h.param_sets.clone())))?;
+    let curve = CURVES
+        .iter()
+        .find(|c| offers.iter().all(|h| h.curves.iter().any(|s| s == *c)))
+        .map(|c| c.to_string())
+        .ok_or_else(|| NegotiationError::NoCommonCurve(offered(|h| h.curves.clone())))?;
+
+    let mut features: BTreeSet<String> = offers[0].features.iter().cloned().collect();
+    for hello in &offers[1..] {
+        features.retain(|f| hello.features.contains(f));
+    }
+    let mut parties = parties.to_vec();
+    parties.sort();
+
+    Ok(Agreed {
+        version,
+        param_set,
+        curve,
+        features: features.into_iter().collect(),
+        parties,
+        config: None,
+    })
+}
+
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
+#[test]
+fn negotiate_test() {
+    let parties: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    let mut hellos: HashMap<String, Hello> = parties
+        .iter()
+        .map(|p| (p.clone(), Hello::local(p.clone())))
+        .collect();
+    // A newer party that also speaks version 2, a future parameter set and
+    // a field this build does not know.
+    let mut newer = serde_json::to_value(Hello::local("3".to_string())).unwrap();
+    newer["versions"] = serde_json::json!([2, 1]);
+    newer["param_sets"] = serde_json::json!(["cl4096", "cl3072", "cl1827"]);
+    newer["features"] = serde_json::json!(["presign-pool", "replay-guard"]);
+    newer["transport"] = serde_json::json!("quic");
+    let newer = Hello::decode("3", &serde_json::to_vec(&newer).unwrap()).unwrap();
+    hellos.insert("3".to_string(), newer);
+
+    let agreed = negotiate(&parties, &hellos).unwrap();
+    assert_eq!(agreed.version, 1);
+    assert_eq!(agreed.param_set, SecurityLevel::Cl1827);
+    assert!(!agreed.has_feature("approval"));
+    assert!(agreed.has_feature("replay-guard"));
+    let mut reordered = parties.clone();
+    reordered.reverse();
+    assert_eq!(
+        negotiate(&reordered, &hellos).unwrap().session_id(),
+        agreed.session_id()
+    );
+
+    hellos.get_mut("2").unwrap().param_sets = vec!["cl3072".to_string()];
+    assert_eq!(
+        negotiate(&parties, &hellos).unwrap().param_set,
+        SecurityLevel::Cl3072
+    );
+    hellos.get_mut("1").unwrap().versions = vec![7];
+    match negotiate(&parties, &hellos) {
+        Err(NegotiationError::NoCommonVersion(offers)) => assert_eq!(offers["1"], vec![7]),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(Hello::decode("2", &Hello::local("1".to_string()).encode()).is_err());
+    assert_eq!(negotiate(&[], &hellos), Err(NegotiationError::NoParties));
+}
@@ -0,0 +1,590 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Bounded pool of offline sign results (presignatures) for one key.
+//!
+//! An offline sign session costs several class group exponentiations per
+//! party, while the online phase is a handful of curve operations. The pool
+//! lets the executor keep presignatures ready ahead of demand without
+//! flooding the CPU: it decides how many offline sessions may start, holds
+//! the finished results until an online sign takes them, and holds off
+//! refilling while online requests are being served and the pool still has
+//! stock. Keep one pool per signing key.
+//!
+//! With a `ForkGuard` attached, the pool drops its stock when the process
+//! was forked or its VM cloned, and discards the results of sessions that
+//! were in flight at that point. The executor should also abort those
+//! sessions, which hold nonce shares of their own.
+//!
+//! Presignatures can also be given a time to live, after which the pool
+//! drops them and refills, and a key a maximum number of presignatures to
+//! serve, after which the pool serves and produces no more until the key is
+//! rotated.
+//!
+//! A presignature is as sensitive as a key share for the one signature it
+//! makes, so every one the pool drops is overwritten before its memory is
+//! freed. `collect_garbage`, meant to run on a schedule, also journals the
+//! deletions since its last run to the audit log.
+use crate::protocols::multi_party::dmz21::health::verify_offline_result;
+use crate::utilities::audit::{AuditEvent, AuditLog};
+use crate::utilities::clock::{self, Clock};
+use crate::utilities::fork_guard::ForkGuard;
+use anyhow::{anyhow, format_err};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroize;
+
+/// Sizing and rotation policy of a presign pool.
+/// Refilling starts when ready plus in-flight presignatures drop below
+/// `refill_threshold` and continues until the pool is at `capacity`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PresignPoolConfig {
+    pub capacity: usize,
+    pub refill_threshold: usize,
+    pub max_concurrent: usize,
+    /// Seconds a presignature may wait in the pool before it is dropped.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Presignatures the key may serve over its lifetime.
+    #[serde(default)]
+    pub max_uses: Option<u64>,
+}
+
+/// Occupancy snapshot of a pool.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PresignPoolMetrics {
+    pub capacity: usize,
+    pub ready: usize,
+    pub in_flight: usize,
+    pub online_active: usize,
+    pub produced: u64,
+    pub failed: u64,
+    pub served: u64,
+    pub misses: u64,
+    pub invalidated: u64,
+    pub expired: u64,
+    /// The key has served `max_uses` presignatures and must be rotated.
+    pub exhausted: bool,
+}
+
+/// Deliberately not `Clone`: two copies would hand out the same
+/// presignature.
+#[derive(Debug)]
+pub struct PresignPool {
+    config: PresignPoolConfig,
+    /// Finished offline results with the `Clock::monotonic` reading when
+    /// they came in, oldest first.
+    ready: VecDeque<(Duration, String)>,
+    in_flight: usize,
+    online_active: usize,
+    refilling: bool,
+    produced: u64,
+    failed: u64,
+    served: u64,
+    misses: u64,
+    invalidated: u64,
+    expired: u64,
+    fork_guard: Option<Arc<ForkGuard>>,
+    generation: u64,
+    /// Sessions started before the last detected fork, whose results are
+    /// dropped when they come in.
+    stale_in_flight: usize,
+    audit: Option<Arc<AuditLog>>,
+    /// Deletions not yet journaled by `collect_garbage`.
+    unjournaled_expired: u64,
+    unjournaled_invalidated: u64,
+    clock: Arc<dyn Clock>,
+}
+
+/// What one `collect_garbage` run deleted and journaled.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GcReport {
+    pub expired: u64,
+    pub invalidated: u64,
+}
+
+fn wipe(mut offline_result: String) {
+    offline_result.zeroize();
+}
+
+impl PresignPoolConfig {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.capacity == 0 || self.max_concurrent == 0 {
+            return Err(anyhow!(
+                "Presign pool capacity and concurrency must be positive"
+            ));
+        }
+        if self.refill_threshold > self.capacity {
+            return Err(anyhow!(
+                "Presign pool refill threshold {} exceeds capacity {}",
+                self.refill_threshold,
+                self.capacity
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl PresignPool {
+    pub fn new(config: PresignPoolConfig) -> Result<Self, anyhow::Error> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            ready: VecDeque::new(),
+            in_flight: 0,
+            online_active: 0,
+            refilling: true,
+            produced: 0,
+            failed: 0,
+            served: 0,
+            misses: 0,
+            invalidated: 0,
+            expired: 0,
+            fork_guard: None,
+            generation: 0,
+            stale_in_flight: 0,
+            audit: None,
+            unjournaled_expired: 0,
+            unjournaled_invalidated: 0,
+            clock: clock::system(),
+        })
+    }
+
+    pub fn set_fork_guard(&mut self, guard: Arc<ForkGuard>) {
+        self.generation = guard.generation();
+        self.fork_guard = Some(guard);
+    }
+
+    /// Measure the time to live on `clock`. Set it before the first
+    /// presignature comes in.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Journal deletions to `log` when `collect_garbage` runs.
+    pub fn set_audit_log(&mut self, log: Arc<AuditLog>) {
+        self.audit = Some(log);
+    }
+
+    /// Whether the key has served all the presignatures it may.
+    pub fn exhausted(&self) -> bool {
+        match self.config.max_uses {
+            Some(max_uses) => self.served >= max_uses,
+            None => false,
+        }
+    }
+
+    /// Drop presignatures older than the time to live and return how many.
+    /// Also runs as part of `sessions_to_start` and `take`.
+    pub fn expire(&mut self) -> usize {
+        let ttl = match self.config.ttl_secs {
+            Some(ttl) => Duration::from_secs(ttl),
+            None => return 0,
+        };
+        let now = self.clock.monotonic();
+        let before = self.ready.len();
+        // Entries are in arrival order, so the expired ones are at the front.
+        while let Some((created, _)) = self.ready.front() {
+            if now.saturating_sub(*created) < ttl {
+                break;
+            }
+            if let Some((_, offline_result)) = self.ready.pop_front() {
+                wipe(offline_result);
+            }
+        }
+        let expired = before - self.ready.len();
+        self.expired += expired as u64;
+        self.unjournaled_expired += expired as u64;
+        expired
+    }
+
+    /// Drop everything produced before a fork the guard has seen since the
+    /// last call.
+    fn check_fork(&mut self) {
+        let generation = match &self.fork_guard {
+            Some(guard) => guard.generation(),
+            None => return,
+        };
+        if generation != self.generation {
+            self.generation = generation;
+            self.invalidated += self.ready.len() as u64;
+            self.unjournaled_invalidated += self.ready.len() as u64;
+            self.ready
+                .drain(..)
+                .for_each(|(_, offline_result)| wipe(offline_result));
+            self.stale_in_flight = self.in_flight;
+        }
+    }
+
+    pub fn config(&self) -> &PresignPoolConfig {
+        &self.config
+    }
+
+    /// Number of offline sign sessions the executor may start right now.
+    ///
+    /// While an online sign is in progress and the pool is not empty, no new
+    /// sessions are started so that the online rounds are not queued behind
+    /// class group work. After a fork nothing starts until the sessions from
+    /// before it have been reported.
+    pub fn sessions_to_start(&mut self) -> usize {
+        self.check_fork();
+        self.expire();
+        if self.stale_in_flight > 0 || self.exhausted() {
+            return 0;
+        }
+        // Never produce more than the key may still serve.
+        let budget = match self.config.max_uses {
+            Some(max_uses) => (max_uses - self.served) as usize,
+            None => usize::MAX,
+        };
+        let stocked = self.ready.len() + self.in_flight;
+        if stocked < self.config.refill_threshold {
+            self.refilling = true;
+        }
+        if stocked >= self.config.capacity {
+            self.refilling = false;
+        }
+        if !self.refilling || (self.online_active > 0 && !self.ready.is_empty()) {
+            return 0;
+        }
+        let room = self.config.capacity - stocked;
+        let slots = self.config.max_concurrent.saturating_sub(self.in_flight);
+        room.min(slots).min(budget.saturating_sub(stocked))
+    }
+
+    /// Register an offline sign session that has been started.
+    pub fn session_started(&mut self) -> Result<(), anyhow::Error> {
+        self.check_fork();
+        if self.stale_in_flight > 0 {
+            return Err(anyhow!(
+                "Presign pool is draining sessions from before a fork"
+            ));
+        }
+        if self.in_flight >= self.config.max_concurrent {
+            return Err(anyhow!("Presign pool concurrency limit reached"));
+        }
+        if self.ready.len() + self.in_flight >= self.config.capacity {
+            return Err(anyhow!("Presign pool is full"));
+        }
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    /// Store the `SignOfflineSuccessWithResult` string of a finished session.
+    pub fn session_finished(&mut self, offline_result: String) -> Result<(), anyhow::Error> {
+        if self.in_flight == 0 {
+            return Err(format_err!("Presign pool has no session in flight"));
+        }
+        self.check_fork();
+        self.in_flight -= 1;
+        if self.stale_in_flight > 0 {
+            self.stale_in_flight -= 1;
+            self.invalidated += 1;
+            self.unjournaled_invalidated += 1;
+            wipe(offline_result);
+            return Ok(());
+        }
+        self.produced += 1;
+        self.ready
+            .push_back((self.clock.monotonic(), offline_result));
+        Ok(())
+    }
+
+    /// Release the slot of a session that aborted.
+    pub fn session_failed(&mut self) {
+        if self.in_flight > 0 {
+            self.in_flight -= 1;
+        }
+        self.stale_in_flight = self.stale_in_flight.min(self.in_flight);
+        self.failed += 1;
+    }
+
+    /// Take the oldest presignature for an online sign.
+    /// Every offline result must be used for one online sign only, so it
+    /// leaves the pool for good. Nothing is served once the key is
+    /// exhausted.
+    pub fn take(&mut self) -> Option<String> {
+        self.check_fork();
+        self.expire();
+        if self.exhausted() {
+            self.misses += 1;
+            return None;
+        }
+        match self.ready.pop_front() {
+            Some((_, offline_result)) => {
+                self.served += 1;
+                Some(offline_result)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Run `verify_offline_result` on the oldest presignature, which stays
+    /// in the pool; `None` if the pool is empty.
+    pub fn check_oldest(&self) -> Option<Result<(), anyhow::Error>> {
+        self.ready
+            .front()
+            .map(|(_, offline_result)| verify_offline_result(offline_result))
+    }
+
+    /// Mark an online sign as started; refilling yields to it.
+    pub fn online_started(&mut self) {
+        self.online_active += 1;
+    }
+
+    pub fn online_finished(&mut self) {
+        if self.online_active > 0 {
+            self.online_active -= 1;
+        }
+    }
+
+    fn journal(&self, reason: &str, count: u64) -> Result<(), anyhow::Error> {
+        if let (Some(log), true) = (&self.audit, count > 0) {
+            let event = AuditEvent::SecretsDeleted {
+                kind: "presignature".to_string(),
+                reason: reason.to_string(),
+                count,
+            };
+            log.emit(event, None).map_err(|why| {
+                format_err!("Journal presignature deletion failed, cause {}", why)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Delete expired presignatures and those invalidated by a fork, and
+    /// journal every deletion since the last run. Counts the journal did not
+    /// take are kept for the next run.
+    pub fn collect_garbage(&mut self) -> Result<GcReport, anyhow::Error> {
+        self.check_fork();
+        self.expire();
+        let report = GcReport {
+            expired: self.unjournaled_expired,
+            invalidated: self.unjournaled_invalidated,
+        };
+        self.journal("expired", report.expired)?;
+        self.unjournaled_expired = 0;
+        self.journal("fork", report.invalidated)?;
+        self.unjournaled_invalidated = 0;
+        Ok(report)
+    }
+
+    pub fn metrics(&self) -> PresignPoolMetrics {
+        PresignPoolMetrics {
+            capacity: self.config.capacity,
+            ready: self.ready.len(),
+            in_flight: self.in_flight,
+            online_active: self.online_active,
+            produced: self.produced,
+            failed: self.failed,
+            served: self.served,
+            misses: self.misses,
+            invalidated: self.invalidated,
+            expired: self.expired,
+            exhausted: self.exhausted(),
+        }
+    }
+}
+
+impl Drop for PresignPool {
+    fn drop(&mut self) {
+        self.ready
+            .drain(..)
+            .for_each(|(_, offline_result)| wipe(offline_result));
+    }
+}
+
+#[test]
+fn presign_pool_test() {
+    let config = PresignPoolConfig {
+        capacity: 4,
+        refill_threshold: 2,
+        max_concurrent: 2,
+        ttl_secs: None,
+        max_uses: None,
+    };
+    let mut pool = PresignPool::new(config).unwrap();
+    assert_eq!(pool.sessions_to_start(), 2);
+    pool.session_started().unwrap();
+    pool.session_started().unwrap();
+    assert!(pool.session_started().is_err());
+    pool.session_finished("a".to_string()).unwrap();
+    pool.session_finished("b".to_string()).unwrap();
+    assert_eq!(pool.sessions_to_start(), 2);
+    pool.session_started().unwrap();
+    pool.session_started().unwrap();
+    pool.session_finished("c".to_string()).unwrap();
+    pool.session_finished("d".to_string()).unwrap();
+
+    // Full: no refill until stock drops below the threshold.
+    assert_eq!(pool.sessions_to_start(), 0);
+    assert_eq!(pool.take(), Some("a".to_string()));
+    assert_eq!(pool.take(), Some("b".to_string()));
+    assert_eq!(pool.sessions_to_start(), 0);
+    assert_eq!(pool.take(), Some("c".to_string()));
+
+    // Below threshold, but an online sign has priority while stock remains.
+    pool.online_started();
+    assert_eq!(pool.sessions_to_start(), 0);
+    pool.online_finished();
+    assert_eq!(pool.sessions_to_start(), 2);
+
+    let metrics = pool.metrics();
+    assert_eq!(metrics.ready, 1);
+    assert_eq!(metrics.served, 3);
+    assert_eq!(metrics.produced, 4);
+}
+
+#[test]
+fn presign_pool_fork_test() {
+    use crate::utilities::fork_guard::TestProbe;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let pid = Arc::new(AtomicU32::new(1));
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 4,
+        refill_threshold: 2,
+        max_concurrent: 2,
+        ttl_secs: None,
+        max_uses: None,
+    })
+    .unwrap();
+    let guard = ForkGuard::with_probe(Box::new(TestProbe(pid.clone())));
+    pool.set_fork_guard(Arc::new(guard));
+    pool.session_started().unwrap();
+    pool.session_started().unwrap();
+    pool.session_finished("a".to_string()).unwrap();
+
+    pid.store(2, Ordering::SeqCst);
+    assert_eq!(pool.take(), None);
+    assert_eq!(pool.sessions_to_start(), 0);
+    assert!(pool.session_started().is_err());
+    pool.session_finished("b".to_string()).unwrap();
+    assert_eq!(pool.take(), None);
+    assert_eq!(pool.metrics().invalidated, 2);
+    assert_eq!(pool.sessions_to_start(), 2);
+}
+
+#[test]
+fn presign_pool_clock_test() {
+    use crate::utilities::clock::ManualClock;
+
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 2,
+        refill_threshold: 2,
+        max_concurrent: 2,
+        ttl_secs: Some(60),
+        max_uses: None,
+    })
+    .unwrap();
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    pool.set_clock(clock.clone());
+    pool.session_started().unwrap();
+    pool.session_finished("a".to_string()).unwrap();
+
+    clock.advance(Duration::from_secs(59));
+    // Stepping the wall clock does not age the presignature.
+    clock.set_unix_secs(1_800_000_000);
+    assert_eq!(pool.expire(), 0);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(pool.expire(), 1);
+    assert_eq!(pool.take(), None);
+}
+
+#[test]
+fn presign_pool_rotation_test() {
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 4,
+        refill_threshold: 4,
+        max_concurrent: 4,
+        ttl_secs: Some(0),
+        max_uses: None,
+    })
+    .unwrap();
+    pool.session_started().unwrap();
+    pool.session_finished("a".to_string()).unwrap();
+    // A zero time to live expires every presignature, which is refilled.
+    assert_eq!(pool.sessions_to_start(), 4);
+    assert_eq!(pool.take(), None);
+    assert_eq!(pool.metrics().expired, 1);
+
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 4,
+        refill_threshold: 4,
+        max_concurrent: 4,
+        ttl_secs: None,
+        max_uses: Some(2),
+    })
+    .unwrap();
+    assert_eq!(pool.sessions_to_start(), 2);
+    pool.session_started().unwrap();
+    pool.session_started().unwrap();
+    pool.session_finished("a".to_string()).unwrap();
+    pool.session_finished("b".to_string()).unwrap();
+    assert_eq!(pool.sessions_to_start(), 0);
+    assert_eq!(pool.take(), Some("a".to_string()));
+    assert_eq!(pool.take(), Some("b".to_string()));
+    assert!(pool.metrics().exhausted);
+    assert_eq!(pool.sessions_to_start(), 0);
+}
+
+#[test]
+fn presign_pool_gc_test() {
+    use crate::utilities::audit::MemorySink;
+
+    let sink = Arc::new(MemorySink::new());
+    let mut pool = PresignPool::new(PresignPoolConfig {
+        capacity: 2,
+        refill_threshold: 2,
+        max_concurrent: 2,
+        ttl_secs: Some(0),
+        max_uses: None,
+    })
+    .unwrap();
+    pool.set_audit_log(Arc::new(AuditLog::new(Box::new(sink.clone()), None)));
+    pool.session_started().unwrap();
+    pool.session_started().unwrap();
+    pool.session_finished("a".to_string()).unwrap();
+    pool.session_finished("b".to_string()).unwrap();
+    // `take` expires both on the way; the next run journals them.
+    assert_eq!(pool.take(), None);
+    let report = pool.collect_garbage().unwrap();
+    assert_eq!(
+        report,
+        GcReport {
+            expired: 2,
+            invalidated: 0
+        }
+    );
+    assert_eq!(pool.collect_garbage().unwrap(), GcReport::default());
+
+    let records = sink.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].event,
+        AuditEvent::SecretsDeleted {
+            kind: "presignature".to_string(),
+            reason: "expired".to_string(),
+            count: 2,
+        }
+    );
+}
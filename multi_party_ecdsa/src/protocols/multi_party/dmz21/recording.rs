@@ -0,0 +1,477 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Recording of a live session and offline replay, for reproducing protocol
+//! failures a partner reports.
+//!
+//! A `Recorder` wraps the local party's state machine: every call the
+//! executor makes into it, together with what it returned, is appended to a
+//! writer as one frame (a little endian `u32` length, then the bincode of an
+//! `Envelope`) and flushed, so a recording taken up to a crash is still
+//! readable. `read_recording` reads the frames back and a `Replayer` feeds
+//! them, one `step` at a time, to a fresh state machine of the same party.
+//! Breaking on `step` runs the replay in lockstep with a debugger.
+//!
+//! The fresh machine draws its own randomness, so its outgoing payloads
+//! differ from the recorded ones. A step therefore only counts as diverged
+//! when the shape of the outcome differs: an error where the recording has
+//! none or the other way round, another message kind or other recipients.
+//! What the party sent to itself is replayed from the fresh machine, not
+//! from the recording, since the rest of its state is fresh as well.
+//!
+//! For a bug report, `redacted_dump` writes the recording as JSON with the
+//! messages, received and sent to others, and the key material of results
+//! redacted. `check_dump` confirms such a dump is of the session a
+//! transcript describes, matching each received message to its marker.
+use crate::communication::delivery::round_of;
+use crate::communication::sending_messages::SendingMessages;
+use crate::protocols::multi_party::dmz21::simulation::SimParty;
+use crate::utilities::redact::{self, SECRET_FIELDS};
+use crate::utilities::schema::{Transcript, TranscriptEntry};
+use anyhow::format_err;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+
+/// What a call into the state machine returned. Errors keep their message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Outcome {
+    Sent(SendingMessages),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Envelope {
+    Begin {
+        party: String,
+        outcome: Outcome,
+    },
+    Received {
+        from: String,
+        msg: Vec<u8>,
+        outcome: Outcome,
+    },
+}
+
+impl Outcome {
+    fn of(result: &Result<SendingMessages, anyhow::Error>) -> Self {
+        match result {
+            Ok(out) => Outcome::Sent(out.clone()),
+            Err(why) => Outcome::Failed(why.to_string()),
+        }
+    }
+
+    /// Everything of the outcome but the payload bytes and results.
+    pub fn shape(&self) -> String {
+        let out = match self {
+            Outcome::Sent(out) => out,
+            Outcome::Failed(_) => return "error".to_string(),
+        };
+        match out {
+            SendingMessages::NormalMessage(to, _) => format!("normal to {}", to),
+            SendingMessages::P2pMessage(payloads) => {
+                let mut to: Vec<&str> = payloads.keys().map(|s| s.as_str()).collect();
+                to.sort_unstable();
+                format!("p2p to {}", to.join(","))
+            }
+            SendingMessages::SubsetMessage(_) => "subset".to_string(),
+            SendingMessages::BroadcastMessage(_) => "broadcast".to_string(),
+            SendingMessages::EmptyMsg => "empty".to_string(),
+            SendingMessages::KeyGenSuccessWithResult(_) => "keygen result".to_string(),
+            SendingMessages::SignOfflineSuccessWithResult(_) => "offline result".to_string(),
+            SendingMessages::SignOnlineSuccessWithResult(_) => "online result".to_string(),
+        }
+    }
+}
+
+impl Envelope {
+    pub fn outcome(&self) -> &Outcome {
+        match self {
+            Envelope::Begin { outcome, .. } | Envelope::Received { outcome, .. } => outcome,
+        }
+    }
+}
+
+/// The recording as a `schema::Transcript`, for export as JSON.
+pub fn transcript(envelopes: &[Envelope]) -> Transcript {
+    let mut party = String::new();
+    let mut entries = Vec::new();
+    for (index, envelope) in envelopes.iter().enumerate() {
+        let (from, received) = match envelope {
+            Envelope::Begin { party: begun, .. } => {
+                party = begun.clone();
+                (None, None)
+            }
+            Envelope::Received { from, msg, .. } => (Some(from.clone()), Some(hex::encode(msg))),
+        };
+        let error = match envelope.outcome() {
+            Outcome::Failed(why) => Some(why.clone()),
+            Outcome::Sent(_) => None,
+        };
+        entries.push(TranscriptEntry {
+            index,
+            from,
+            received,
+            outcome: envelope.outcome().shape(),
+            error,
+        });
+    }
+    Transcript { party, entries }
+}
+
+/// The recording as JSON, with the received messages, the p2p payloads the
+/// party sent and the secret fields of its results replaced by markers.
+pub fn redacted_dump(envelopes: &[Envelope]) -> Result<String, anyhow::Error> {
+    let mut fields = SECRET_FIELDS.to_vec();
+    fields.extend_from_slice(&["msg", "P2pMessage"]);
+    redact::redact(&envelopes, &fields)
+        .map_err(|why| format_err!("Redacting recording failed, cause {}", why))
+}
+
+/// Check that `dump`, a `redacted_dump`, records the calls, the received
+/// messages and the outcomes of `expected`.
+pub fn check_dump(dump: &str, expected: &Transcript) -> Result<(), anyhow::Error> {
+    let mismatch = || format_err!("Redacted dump is not of the transcript's session");
+    let mut value: Value = serde_json::from_str(dump)
+        .map_err(|why| format_err!("Parse error in redacted dump, cause {}", why))?;
+    let calls = value.as_array_mut().ok_or_else(mismatch)?;
+    if calls.len() != expected.entries.len() {
+        return Err(mismatch());
+    }
+    for (call, entry) in calls.iter_mut().zip(&expected.entries) {
+        let msg = call.pointer_mut("/Received/msg");
+        if let (Some(msg), Some(received)) = (msg, &entry.received) {
+            let original = Value::from(hex::decode(received).map_err(|_| mismatch())?);
+            if *msg != redact::marker(&original) {
+                return Err(mismatch());
+            }
+            *msg = original;
+        }
+    }
+    unredact_payloads(&mut value);
+    let envelopes: Vec<Envelope> = serde_json::from_value(value)
+        .map_err(|why| format_err!("Decode error in redacted dump, cause {}", why))?;
+    if transcript(&envelopes) != *expected {
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+/// The markers left outside result strings stand for sent payloads, which
+/// the transcript leaves out, so empty ones let the envelopes decode.
+fn unredact_payloads(value: &mut Value) {
+    if redact::is_marker(value) {
+        *value = Value::Array(Vec::new());
+        return;
+    }
+    match value {
+        Value::Object(entries) => entries.values_mut().for_each(unredact_payloads),
+        Value::Array(items) => items.iter_mut().for_each(unredact_payloads),
+        _ => {}
+    }
+}
+
+fn write_frame<W: Write>(writer: &mut W, envelope: &Envelope) -> Result<(), anyhow::Error> {
+    let bytes = bincode::serialize(envelope)
+        .map_err(|why| format_err!("Serialize error in recording, cause {}", why))?;
+    writer
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .and_then(|_| writer.write_all(&bytes))
+        .and_then(|_| writer.flush())
+        .map_err(|why| format_err!("Write error in recording, cause {}", why))
+}
+
+/// Read all frames of a recording. A frame cut short by a crash ends the
+/// recording; anything else that does not decode is an error.
+pub fn read_recording<R: Read>(mut reader: R) -> Result<Vec<Envelope>, anyhow::Error> {
+    let mut envelopes = Vec::new();
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(why) if why.kind() == ErrorKind::UnexpectedEof => return Ok(envelopes),
+            Err(why) => return Err(format_err!("Read error in recording, cause {}", why)),
+        }
+        let mut frame = vec![0u8; u32::from_le_bytes(len) as usize];
+        match reader.read_exact(&mut frame) {
+            Ok(()) => {}
+            Err(why) if why.kind() == ErrorKind::UnexpectedEof => return Ok(envelopes),
+            Err(why) => return Err(format_err!("Read error in recording, cause {}", why)),
+        }
+        let envelope = bincode::deserialize(&frame).map_err(|why| {
+            format_err!(
+                "Deserialize error in recording frame {}, cause {}",
+                envelopes.len(),
+                why
+            )
+        })?;
+        envelopes.push(envelope);
+    }
+}
+
+/// A state machine whose calls are recorded to `writer`.
+/// A failed write fails the call, a recording with holes is of no use.
+pub struct Recorder<P, W> {
+    party: String,
+    inner: P,
+    writer: W,
+}
+
+impl<P: SimParty, W: Write> Recorder<P, W> {
+    pub fn new(party: String, inner: P, writer: W) -> Self {
+        Self {
+            party,
+            inner,
+            writer,
+        }
+    }
+
+    pub fn into_inner(self) -> (P, W) {
+        (self.inner, self.writer)
+    }
+}
+
+impl<P: SimParty, W: Write> SimParty for Recorder<P, W> {
+    fn begin(&mut self) -> Result<SendingMessages, anyhow::Error> {
+        let result = self.inner.begin();
+        let envelope = Envelope::Begin {
+            party: self.party.clone(),
+            outcome: Outcome::of(&result),
+        };
+        write_frame(&mut self.writer, &envelope)?;
+        result
+    }
+
+    fn handle(&mut self, from: String, msg: &Vec<u8>) -> Result<SendingMessages, anyhow::Error> {
+        let result = self.inner.handle(from.clone(), msg);
+        let envelope = Envelope::Received {
+            from,
+            msg: msg.clone(),
+            outcome: Outcome::of(&result),
+        };
+        write_frame(&mut self.writer, &envelope)?;
+        result
+    }
+}
+
+/// One replayed envelope.
+#[derive(Clone, Debug)]
+pub struct ReplayStep {
+    /// Position in the recording.
+    pub index: usize,
+    pub recorded: Envelope,
+    pub replayed: Outcome,
+}
+
+impl ReplayStep {
+    pub fn diverged(&self) -> bool {
+        self.recorded.outcome().shape() != self.replayed.shape()
+    }
+}
+
+pub struct Replayer<P> {
+    party: String,
+    inner: P,
+    envelopes: Vec<Envelope>,
+    next: usize,
+    /// Payloads the fresh machine sent to itself, by round.
+    own: HashMap<u32, Vec<u8>>,
+}
+
+impl<P: SimParty> Replayer<P> {
+    /// Replay `envelopes` against `inner`, a fresh state machine set up like
+    /// the recorded one. The recording must start with its `Begin`.
+    pub fn new(inner: P, envelopes: Vec<Envelope>) -> Result<Self, anyhow::Error> {
+        let party = match envelopes.first() {
+            Some(Envelope::Begin { party, .. }) => party.clone(),
+            _ => return Err(format_err!("Recording does not start with a begin")),
+        };
+        Ok(Self {
+            party,
+            inner,
+            envelopes,
+            next: 0,
+            own: HashMap::new(),
+        })
+    }
+
+    pub fn party(&self) -> &str {
+        &self.party
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.envelopes.len() - self.next
+    }
+
+    fn keep_own(&mut self, out: &SendingMessages) {
+        let payload = match out {
+            SendingMessages::NormalMessage(to, payload) if *to == self.party => payload,
+            SendingMessages::P2pMessage(payloads) => match payloads.get(&self.party) {
+                Some(payload) => payload,
+                None => return,
+            },
+            SendingMessages::SubsetMessage(payload)
+            | SendingMessages::BroadcastMessage(payload) => payload,
+            _ => return,
+        };
+        if let Some(round) = round_of(payload) {
+            self.own.insert(round, payload.clone());
+        }
+    }
+
+    /// Feed the next envelope, or `None` at the end of the recording.
+    pub fn step(&mut self) -> Option<ReplayStep> {
+        let recorded = self.envelopes.get(self.next)?.clone();
+        let result = match &recorded {
+            Envelope::Begin { .. } => self.inner.begin(),
+            Envelope::Received { from, msg, .. } => {
+                let own = match round_of(msg) {
+                    Some(round) if *from == self.party => self.own.get(&round).cloned(),
+                    _ => None,
+                };
+                self.inner.handle(from.clone(), own.as_ref().unwrap_or(msg))
+            }
+        };
+        if let Ok(out) = &result {
+            self.keep_own(out);
+        }
+        let step = ReplayStep {
+            index: self.next,
+            recorded,
+            replayed: Outcome::of(&result),
+        };
+        self.next += 1;
+        Some(step)
+    }
+
+    /// Replay to the end, or up to the first step that diverged.
+    pub fn run(&mut self) -> Result<Vec<ReplayStep>, ReplayStep> {
+        let mut steps = Vec::new();
+        while let Some(step) = self.step() {
+            if step.diverged() {
+                return Err(step);
+            }
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+}
+
+#[test]
+fn record_replay_test() {
+    use crate::protocols::multi_party::dmz21::keygen::{KeyGenPhase, Parameters};
+    use crate::protocols::multi_party::dmz21::sign::{OfflineResultX, SignPhase};
+    use crate::protocols::multi_party::dmz21::simulation::{simulate, SimConfig};
+    use std::fs::File;
+
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let ids: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    let fresh = |id: &String| KeyGenPhase::new(id.clone(), params.clone(), &Some(ids.clone()));
+    let path = |id: &String| {
+        std::env::temp_dir().join(format!("dmz21-recording-{}-{}", std::process::id(), id))
+    };
+    let parties = ids
+        .iter()
+        .map(|id| {
+            let file = File::create(path(id)).unwrap();
+            (
+                id.clone(),
+                Recorder::new(id.clone(), fresh(id).unwrap(), file),
+            )
+        })
+        .collect();
+    let outcome = simulate(&SimConfig::new(3), parties).unwrap();
+    assert_eq!(outcome.results.len(), 3);
+
+    let bytes = std::fs::read(path(&ids[0])).unwrap();
+    for id in &ids {
+        std::fs::remove_file(path(id)).unwrap();
+    }
+    let envelopes = read_recording(&bytes[..]).unwrap();
+    assert_eq!(envelopes.last().unwrap().outcome().shape(), "keygen result");
+    let exported = transcript(&envelopes);
+    assert_eq!(exported.party, "1");
+    assert_eq!(exported.entries.len(), envelopes.len());
+    let json = crate::utilities::schema::to_document(&exported).unwrap();
+    assert_eq!(
+        crate::utilities::schema::validate(&json).unwrap(),
+        "transcript"
+    );
+
+    // The redacted dump keeps no key share but still matches the transcript.
+    let dump = redacted_dump(&envelopes).unwrap();
+    assert_eq!(dump, redacted_dump(&envelopes).unwrap());
+    assert!(!dump.contains("share_sk\\\":\\\"") && dump.contains("share_sk"));
+    check_dump(&dump, &exported).unwrap();
+    let mut other = exported.clone();
+    other.entries.pop();
+    assert!(check_dump(&dump, &other).is_err());
+
+    // The replay reaches a key of its own along the recorded path.
+    let mut replayer = Replayer::new(fresh(&ids[0]).unwrap(), envelopes.clone()).unwrap();
+    let steps = replayer.run().unwrap();
+    assert_eq!(steps.len(), envelopes.len());
+    assert_eq!(replayer.remaining(), 0);
+
+    // A message the recording holds corrupted shows where things go wrong.
+    let mut corrupted = envelopes.clone();
+    let position = corrupted
+        .iter()
+        .position(|e| matches!(e, Envelope::Received { from, .. } if *from == ids[1]))
+        .unwrap();
+    if let Envelope::Received { msg, .. } = &mut corrupted[position] {
+        msg[0] = 0xff;
+    }
+    let mut replayer = Replayer::new(fresh(&ids[0]).unwrap(), corrupted).unwrap();
+    let diverged = replayer.run().unwrap_err();
+    assert_eq!(diverged.index, position);
+    assert_eq!(diverged.replayed.shape(), "error");
+
+    // A recording cut short by a crash still reads up to its last frame.
+    let cut = read_recording(&bytes[..bytes.len() - 3]).unwrap();
+    assert_eq!(cut.len(), envelopes.len() - 1);
+
+    // The dump of an offline sign hides the presignature blob.
+    let subset = ids[..2].to_vec();
+    let signers = subset
+        .iter()
+        .map(|id| {
+            let keys = &outcome.results[id];
+            let phase = SignPhase::new(id.clone(), params.clone(), &subset, keys).unwrap();
+            let file = File::create(path(id)).unwrap();
+            (id.clone(), Recorder::new(id.clone(), phase, file))
+        })
+        .collect();
+    let signed = simulate(&SimConfig::new(4), signers).unwrap();
+    let bytes = std::fs::read(path(&ids[0])).unwrap();
+    for id in &subset {
+        std::fs::remove_file(path(id)).unwrap();
+    }
+    let envelopes = read_recording(&bytes[..]).unwrap();
+    assert_eq!(
+        envelopes.last().unwrap().outcome().shape(),
+        "offline result"
+    );
+    let offline: OfflineResultX = serde_json::from_str(&signed.results[&ids[0]]).unwrap();
+    let dump = redacted_dump(&envelopes).unwrap();
+    assert!(!dump.contains(&offline.data));
+    check_dump(&dump, &transcript(&envelopes)).unwrap();
+}
@@ -0,0 +1,75 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Detection of CL ciphertexts replayed across sessions.
+//!
+//! Within one session `SignPhase` already rejects a ciphertext whose tag it
+//! has seen in an earlier round or from another party. A `ReplayGuard`
+//! shared by all sessions of a key extends that to ciphertexts lifted from
+//! a previous session. It keys on the session-independent tag
+//! `Ciphertext::tag(&[])` and remembers where each one was first seen.
+use crate::utilities::class_group::Ciphertext;
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct ReplayGuard {
+    seen: Mutex<HashMap<[u8; 32], String>>,
+}
+
+impl ReplayGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `cipher` as received in `context` (session, round, sender).
+    /// Fails if it was already recorded anywhere else.
+    pub fn observe(&self, cipher: &Ciphertext, context: &str) -> Result<(), anyhow::Error> {
+        let mut seen = self
+            .seen
+            .lock()
+            .map_err(|_| anyhow!("Replay guard lock poisoned"))?;
+        match seen.get(&cipher.tag(&[])) {
+            Some(first) if first != context => Err(anyhow!(
+                "Ciphertext received in {} replays one first seen in {}",
+                context,
+                first
+            )),
+            Some(_) => Ok(()),
+            None => {
+                seen.insert(cipher.tag(&[]), context.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.lock().map(|seen| seen.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every recorded tag, e.g. after rotating the CL keys.
+    pub fn clear(&self) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.clear();
+        }
+    }
+}
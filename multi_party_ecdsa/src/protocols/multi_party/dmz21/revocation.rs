@@ -0,0 +1,180 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Revocation of compromised co-signers.
+//!
+//! Once a party is known to be compromised its share must not take part in
+//! any further signature. A `RevocationList`, shared by all sessions of a
+//! key, records the revoked parties; sign sessions with the list attached
+//! refuse a subset containing one of them and drop out if a message from
+//! one arrives. Every revocation is written to the audit log before it
+//! takes effect. The revoked share still reconstructs the key together with
+//! `t` others, so the list also flags that the key has to be replaced; the
+//! flag stays up until `key_rotated` is called.
+//!
+//! There is no share refresh protocol and no key certificate in this crate,
+//! so a revocation neither starts a refresh nor extends a certificate
+//! lineage. Replacing the key is a new keygen among the remaining parties,
+//! driven by the caller on `rotation_required`; the audit log is the record
+//! of the revocation.
+use crate::utilities::audit::{AuditEvent, AuditLog};
+use crate::utilities::clock::{self, Clock};
+use anyhow::{anyhow, format_err};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revocation {
+    pub party_index: String,
+    pub reason: String,
+    pub revoked_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct Revoked {
+    parties: BTreeMap<String, Revocation>,
+    rotation_required: bool,
+}
+
+#[derive(Debug)]
+pub struct RevocationList {
+    inner: Mutex<Revoked>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for RevocationList {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::default(),
+            clock: clock::system(),
+        }
+    }
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Date revocations by `clock` instead of the host.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Revoke `party_index`, recording it in `log` first if given.
+    pub fn revoke(
+        &self,
+        party_index: &str,
+        reason: &str,
+        log: Option<&AuditLog>,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(log) = log {
+            let event = AuditEvent::PartyRevoked {
+                party_index: party_index.to_string(),
+                reason: reason.to_string(),
+            };
+            log.emit(event, None).map_err(|why| {
+                format_err!("Audit failed revoking {}, cause {}", party_index, why)
+            })?;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .map_err(|_| anyhow!("Revocation list lock poisoned"))?;
+        inner.parties.insert(
+            party_index.to_string(),
+            Revocation {
+                party_index: party_index.to_string(),
+                reason: reason.to_string(),
+                revoked_at: self.clock.unix_secs(),
+            },
+        );
+        inner.rotation_required = true;
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, party_index: &str) -> bool {
+        self.inner
+            .lock()
+            .map(|inner| inner.parties.contains_key(party_index))
+            .unwrap_or(true)
+    }
+
+    /// Fail if any of `parties` is revoked.
+    pub fn check(&self, parties: &[String]) -> Result<(), anyhow::Error> {
+        match parties.iter().find(|party| self.is_revoked(party)) {
+            Some(party) => Err(anyhow!("Party {} is revoked", party)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        self.inner
+            .lock()
+            .map(|inner| inner.parties.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether a party was revoked since the key was last replaced.
+    pub fn rotation_required(&self) -> bool {
+        self.inner
+            .lock()
+            .map(|inner| inner.rotation_required)
+            .unwrap_or(true)
+    }
+
+    /// The key has been replaced by one the revoked parties hold no share
+    /// of. They stay revoked.
+    pub fn key_rotated(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.rotation_required = false;
+        }
+    }
+}
+
+#[test]
+fn revocation_list_test() {
+    use crate::utilities::audit::MemorySink;
+    use crate::utilities::clock::ManualClock;
+
+    let sink = Arc::new(MemorySink::new());
+    let log = AuditLog::new(Box::new(sink.clone()), None);
+    let list = RevocationList::new().with_clock(Arc::new(ManualClock::new(1_700_000_000)));
+    let subset = vec!["1".to_string(), "3".to_string()];
+    list.check(&subset).unwrap();
+    assert!(!list.rotation_required());
+
+    list.revoke("3", "host compromised", Some(&log)).unwrap();
+    assert!(list.check(&subset).is_err());
+    list.check(&["1".to_string(), "2".to_string()]).unwrap();
+    assert!(list.rotation_required());
+    list.key_rotated();
+    assert!(!list.rotation_required());
+    assert!(list.is_revoked("3"));
+
+    assert_eq!(list.revocations()[0].reason, "host compromised");
+    assert_eq!(list.revocations()[0].revoked_at, 1_700_000_000);
+    assert_eq!(
+        sink.records()[0].event,
+        AuditEvent::PartyRevoked {
+            party_index: "3".to_string(),
+            reason: "host compromised".to_string(),
+        }
+    );
+}
@@ -17,19 +17,28 @@
 */
 //! Implement sign algorithm of multi-party ECDSA in dmz
 use crate::communication::sending_messages::SendingMessages;
+use crate::protocols::multi_party::dmz21::approval::*;
+use crate::protocols::multi_party::dmz21::authorization::{AuthToken, TokenVerifier};
 use crate::protocols::multi_party::dmz21::common::*;
+use crate::protocols::multi_party::dmz21::directory::{Material, PartyDirectory};
+use crate::protocols::multi_party::dmz21::inbox::record;
 use crate::protocols::multi_party::dmz21::keygen::Parameters;
 use crate::protocols::multi_party::dmz21::message::*;
+use crate::protocols::multi_party::dmz21::replay::ReplayGuard;
+use crate::protocols::multi_party::dmz21::revocation::RevocationList;
+use crate::utilities::audit::{point_to_hex, AuditEvent, AuditLog};
 use crate::utilities::class_group::*;
 use crate::utilities::clkeypair::ClKeyPair;
 use crate::utilities::dl_com_zk::*;
 use crate::utilities::eckeypair::EcKeyPair;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::payload::{DecodedPayload, PayloadDecoder};
 use crate::utilities::promise_sigma_multi::*;
+use crate::utilities::secret::SecretScalar;
 use crate::utilities::signature::{Signature, SignatureX};
 use crate::utilities::vss::map_share_to_new_params;
 use crate::utilities::SECURITY_BITS;
 use anyhow::{anyhow, format_err};
-use classgroup::ClassGroup;
 use curv::arithmetic::traits::*;
 use curv::cryptographic_primitives::commitments::hash_commitment::HashCommitment;
 use curv::cryptographic_primitives::commitments::traits::Commitment;
@@ -38,9 +47,11 @@ use curv::cryptographic_primitives::proofs::sigma_correct_homomorphic_elgamal_en
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use curv::BigInt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
 
 /// Messages of each round in sign
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -94,6 +105,50 @@ pub struct SignPhase {
     pub msgsf: SignMsgsFlag,
     pub dl_com: DlogCommitment,
     pub mutex: Arc<Mutex<usize>>,
+    pub audit: Option<Arc<AuditLog>>,
+    pub session_id: Vec<u8>,
+    pub seen_tags: HashSet<[u8; 32]>,
+    pub replay_guard: Option<Arc<ReplayGuard>>,
+    pub revocations: Option<Arc<RevocationList>>,
+    pub cl: Arc<CLContext>,
+    pub directory: PartyDirectory,
+}
+
+/// Pick the signing subset for a new presignature from the parties that
+/// answered a signing request, fastest first.
+///
+/// Any quorum from t+1 to n works: the Lagrange coefficients are computed
+/// for the chosen subset in `SignPhase::new`. The choice has to be made by
+/// one coordinator and handed to every signer, since all of them must run
+/// offline sign over the same subset, and the online phase reuses it: the
+/// presignature (k, sigma) is bound to those coefficients.
+pub fn select_signers(
+    params: &Parameters,
+    responders: &[String],
+    quorum: usize,
+) -> Result<Vec<String>, anyhow::Error> {
+    if quorum <= params.threshold || quorum > params.share_count {
+        return Err(anyhow!(
+            "Quorum {} out of range [t+1, n] = [{}, {}]",
+            quorum,
+            params.threshold + 1,
+            params.share_count
+        ));
+    }
+    let mut subset: Vec<String> = Vec::with_capacity(quorum);
+    for id in responders.iter() {
+        if !subset.contains(id) {
+            subset.push(id.clone());
+        }
+        if subset.len() == quorum {
+            return Ok(subset);
+        }
+    }
+    Err(anyhow!(
+        "Only {} of the {} signers needed have responded",
+        subset.len(),
+        quorum
+    ))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -136,6 +191,15 @@ pub struct SignPhaseOnline {
     pub msg_step_seven: SignPhaseFiveStepSevenMsg,
     pub msg_step_five: SignPhaseFiveStepFiveMsg,
     pub mutex: Arc<Mutex<usize>>,
+    pub audit: Option<Arc<AuditLog>>,
+    pub audit_context: Option<String>,
+    pub approver: Option<Arc<dyn SignApprover>>,
+    pub approval: Option<String>,
+    pub awaiting_approval: bool,
+    pub payload: Option<DecodedPayload>,
+    pub token_verifier: Option<Arc<TokenVerifier>>,
+    pub authorization: Option<String>,
+    pub revocations: Option<Arc<RevocationList>>,
 }
 
 impl SignMsgs {
@@ -193,6 +257,17 @@ impl SignPhase {
         params: Parameters,
         subset: &Vec<String>,
         keys: &String,
+    ) -> Result<Self, anyhow::Error> {
+        Self::new_with_context(partyid, params, subset, keys, default_context())
+    }
+
+    /// Same as `new`, running in the CL parameters of `cl`.
+    pub fn new_with_context(
+        partyid: String,
+        params: Parameters,
+        subset: &Vec<String>,
+        keys: &String,
+        cl: Arc<CLContext>,
     ) -> Result<Self, anyhow::Error> {
         let mutex = Arc::new(Mutex::new(0));
         let ret: DMZKeyX = serde_json::from_str(keys)
@@ -214,8 +289,12 @@ impl SignPhase {
         };
         let privkey = PrivateKey {
             cl_sk: ret.privkey.cl_sk,
-            ec_sk: FE::from_bigint(&BigInt::from_hex(&ret.privkey.ec_sk).unwrap()),
-            share_sk: FE::from_bigint(&BigInt::from_hex(&ret.privkey.share_sk).unwrap()),
+            ec_sk: SecretScalar::from(FE::from_bigint(
+                &BigInt::from_hex(&ret.privkey.ec_sk).unwrap(),
+            )),
+            share_sk: SecretScalar::from(FE::from_bigint(
+                &BigInt::from_hex(&ret.privkey.share_sk).unwrap(),
+            )),
         };
         let keygen_result = DMZKey {
             index: ret.index,
@@ -238,13 +317,22 @@ impl SignPhase {
             }
         }
 
-        let ec_keypair = EcKeyPair::from_sk(keygen_result.privkey.ec_sk);
-        let cl_keypair = ClKeyPair::from_sk(keygen_result.privkey.cl_sk, &GROUP_UPDATE_1827);
+        let ec_keypair = EcKeyPair::from_sk(keygen_result.privkey.ec_sk.expose());
+        let cl_keypair = ClKeyPair::from_sk(keygen_result.privkey.cl_sk, &cl.group);
         let share_public_key_map = keygen_result.pubkey.share_pks;
 
         let party_num = subset.len();
-        if party_num < params.threshold {
-            return Err(anyhow!("Party number less than threshold"));
+        if party_num <= params.threshold || party_num > params.share_count {
+            return Err(anyhow!(
+                "Party number {} out of range [t+1, n] = [{}, {}] in sign new",
+                party_num,
+                params.threshold + 1,
+                params.share_count
+            ));
+        }
+        let distinct: HashSet<&String> = subset.iter().collect();
+        if distinct.len() != party_num {
+            return Err(anyhow!("Duplicate party id in the signing subset"));
         }
 
         // Compute lambda
@@ -256,7 +344,7 @@ impl SignPhase {
             BigInt::from_str_radix(&partyid, 16).unwrap(),
             &share_ids_sub,
         );
-        let omega = lamda * keygen_result.privkey.share_sk;
+        let omega = lamda * keygen_result.privkey.share_sk.expose();
         let mut big_omega_map = HashMap::new();
         for i in subset.iter().zip(share_ids_sub.clone().iter()) {
             let share_public_key = share_public_key_map.get(i.0).ok_or(format_err!(
@@ -276,6 +364,9 @@ impl SignPhase {
 
         let delta = &k * &gamma;
         let sigma = &k * &omega;
+        let mut directory = PartyDirectory::new();
+        directory.insert_cl_pk(&partyid, cl_keypair.get_public_key())?;
+        directory.insert_commitment(&partyid, &dl_com.commitment)?;
         let mut ret = SignPhase {
             party_index: partyid,
             party_num,
@@ -298,23 +389,89 @@ impl SignPhase {
             msgsf: SignMsgsFlag::new(),
             dl_com,
             mutex,
+            audit: None,
+            session_id: Vec::new(),
+            seen_tags: HashSet::new(),
+            replay_guard: None,
+            revocations: None,
+            cl,
+            directory,
         };
         ret.pre_computation();
         Ok(ret)
     }
 
+    /// Record the presignature in `log` when offline sign completes.
+    pub fn set_audit_log(&mut self, log: Arc<AuditLog>) {
+        self.audit = Some(log);
+    }
+
+    /// Bind the ciphertext tags of this session to `session_id` and, if
+    /// given, check them against `guard` shared with other sessions.
+    pub fn set_replay_protection(&mut self, session_id: Vec<u8>, guard: Option<Arc<ReplayGuard>>) {
+        self.session_id = session_id;
+        self.replay_guard = guard;
+    }
+
+    /// Refuse to run with parties revoked in `list`, now or later in the
+    /// session.
+    pub fn set_revocation_list(&mut self, list: Arc<RevocationList>) -> Result<(), anyhow::Error> {
+        list.check(&self.subset)?;
+        self.revocations = Some(list);
+        Ok(())
+    }
+
+    fn observe_ciphertext(
+        &mut self,
+        cipher: &Ciphertext,
+        round: &str,
+        index: &str,
+    ) -> Result<(), anyhow::Error> {
+        if !self.seen_tags.insert(cipher.tag(&self.session_id)) {
+            return Err(anyhow!(
+                "Replayed ciphertext from party {} in sign offline {}",
+                index,
+                round
+            ));
+        }
+        if let Some(guard) = &self.replay_guard {
+            let context = format!(
+                "session {} {} from {}",
+                hex::encode(&self.session_id),
+                round,
+                index
+            );
+            guard.observe(cipher, &context)?;
+        }
+        Ok(())
+    }
+
+    fn emit_presign_completed(&self) -> Result<(), anyhow::Error> {
+        if let Some(log) = &self.audit {
+            let event = AuditEvent::PresignCompleted {
+                party_index: self.party_index.clone(),
+                subset: self.subset.clone(),
+                public_key: point_to_hex(&self.public_signing_key),
+            };
+            log.emit(event, None).map_err(|why| {
+                format_err!("Audit failed in sign offline phase four, cause {}", why)
+            })?;
+        }
+        Ok(())
+    }
+
     fn pre_computation(&mut self) {
         let base = GE::generator();
         let zero = FE::zero();
         for index in self.subset.iter() {
             let beta = FE::random();
             let (r_cipher_1, _r_blind) =
-                CLGroup::encrypt_without_r(&GROUP_UPDATE_1827, &(zero.clone() - beta.clone()));
+                CLGroup::encrypt_without_r(&self.cl.group, &(zero.clone() - beta.clone()));
             self.beta_map.insert((*index).clone(), beta);
 
             let v = FE::random();
             let (r_cipher_2, _r_blind) =
-                CLGroup::encrypt_without_r(&GROUP_UPDATE_1827, &(zero.clone() - v.clone()));
+                CLGroup::encrypt_without_r(&self.cl.group, &(zero.clone() - v.clone()));
             let b = base * &v;
             self.v_map.insert((*index).clone(), v);
 
@@ -323,14 +480,47 @@ impl SignPhase {
         }
     }
 
+    /// Check the promise proofs of all other parties in one batch, naming
+    /// every party whose proof is invalid. The CL public keys and commitments
+    /// they were made over are then kept in `directory`.
+    fn verify_promise_proofs(&mut self) -> Result<(), anyhow::Error> {
+        let mut parties: Vec<&String> = self
+            .msgs
+            .phase_one_msgs
+            .keys()
+            .filter(|index| !self.directory.is_validated(index, Material::ClPublicKey))
+            .collect();
+        parties.sort();
+        let items: Vec<(&PromiseProof, &PromiseState)> = parties
+            .iter()
+            .map(|index| {
+                let msg = &self.msgs.phase_one_msgs[*index];
+                (&msg.proof, &msg.promise_state)
+            })
+            .collect();
+        PromiseProof::verify_batch(&self.cl.group, &items).map_err(|failed| {
+            let blamed: Vec<&str> = failed.iter().map(|i| parties[*i].as_str()).collect();
+            format_err!(
+                "Invalid promise proof in sign offline phase one from party {}",
+                blamed.join(", ")
+            )
+        })?;
+        for index in parties {
+            let msg = &self.msgs.phase_one_msgs[index];
+            self.directory
+                .insert_cl_pk(index, &msg.promise_state.cl_pub_key)?;
+            self.directory.insert_commitment(index, &msg.commitment)?;
+        }
+        Ok(())
+    }
+
     fn handle_phase_one_msg(
-        &mut self,
+        &self,
         index: String,
         msg: &SignPhaseOneMsg,
     ) -> Result<SignPhaseTwoMsg, anyhow::Error> {
         // TBD: check ec cl pk
-        // Verify promise proof
-        msg.proof.verify(&GROUP_UPDATE_1827, &msg.promise_state)?;
+        // The promise proof was checked by `verify_promise_proofs`.
 
         // Homo
         let cipher = &msg.promise_state.cipher;
@@ -339,55 +529,43 @@ impl SignPhase {
             format_err!("Index is none in pre_cipher in sign offline phase one"),
         )?;
 
-        // todo, optimized
-        let mut homocipher = pre_cipher_1.clone();
-        let mut homocipher_plus = pre_cipher_1.clone();
-        let mut t_p = FE::zero();
-        let mut t_p_plus = FE::zero();
-
-        let upper = mpz_to_bigint(&GROUP_UPDATE_1827.stilde)
+        let upper = mpz_to_bigint(&self.cl.group.stilde)
             * BigInt::from(2 as u32).pow(40)
             * FE::group_order();
-        crossbeam::scope(|thread| {
-            thread.spawn(|_| {
-                {
-                    // Generate random.
-                    let t = BigInt::sample_below(&upper);
-                    t_p = FE::from_bigint(&t.mod_floor(&FE::group_order()));
-                    let rho_plus_t = into_mpz(&self.gamma) + bigint_to_mpz(&t);
-
-                    // Handle CL cipher.
-                    let mut c11 = cipher.cl_cipher.c1.clone();
-                    c11.pow(rho_plus_t.clone());
-                    let mut c21 = cipher.cl_cipher.c2.clone();
-                    c21.pow(rho_plus_t);
-                    let c1 = c11 * pre_cipher_1.c1.clone();
-                    let c2 = c21 * pre_cipher_1.c2.clone();
-                    homocipher = Ciphertext { c1, c2 };
-                }
-            });
-
-            thread.spawn(|_| {
-                {
-                    // Generate random.
-                    let t = BigInt::sample_below(&upper);
-                    t_p_plus = FE::from_bigint(&t.mod_floor(&FE::group_order()));
-                    let omega_plus_t = into_mpz(&self.omega) + bigint_to_mpz(&t);
-
-                    // Handle CL cipher.
-                    let mut c11 = cipher.cl_cipher.c1.clone();
-                    c11.pow(omega_plus_t.clone());
-                    let mut c21 = cipher.cl_cipher.c2.clone();
-                    c21.pow(omega_plus_t);
-                    let c1 = c11 * pre_cipher_2.c1.clone();
-                    let c2 = c21 * pre_cipher_2.c2.clone();
-                    homocipher_plus = Ciphertext { c1, c2 };
-                }
-            });
-        })
-        .map_err(|_| format_err!("crossbeam::scope thread.spawn error"))?;
+        // Generate random.
+        let t = BigInt::sample_below(&upper);
+        let t_p = FE::from_bigint(&t.mod_floor(&FE::group_order()));
+        let rho_plus_t = into_mpz(&self.gamma) + bigint_to_mpz(&t);
+        let t = BigInt::sample_below(&upper);
+        let t_p_plus = FE::from_bigint(&t.mod_floor(&FE::group_order()));
+        let omega_plus_t = into_mpz(&self.omega) + bigint_to_mpz(&t);
+
+        // Handle CL cipher.
+        let c1 = &cipher.cl_cipher.c1;
+        let c2 = &cipher.cl_cipher.c2;
+        let mut powers = exp_pool::current()
+            .pow_all(
+                vec![
+                    (c1.clone(), rho_plus_t.clone()),
+                    (c2.clone(), rho_plus_t),
+                    (c1.clone(), omega_plus_t.clone()),
+                    (c2.clone(), omega_plus_t),
+                ],
+                Priority::Low,
+            )?
+            .into_iter();
+        let mut next = || powers.next().unwrap();
+        let homocipher = Ciphertext {
+            c1: next() * &pre_cipher_1.c1,
+            c2: next() * &pre_cipher_1.c2,
+        };
+        let homocipher_plus = Ciphertext {
+            c1: next() * &pre_cipher_2.c1,
+            c2: next() * &pre_cipher_2.c2,
+        };
 
         let msg_two = SignPhaseTwoMsg {
+            params: self.cl.group.params_id(),
             homocipher,
             homocipher_plus,
             t_p,
@@ -397,6 +575,35 @@ impl SignPhase {
         Ok(msg_two)
     }
 
+    fn observe_phase_one_ciphertexts(
+        &mut self,
+        msgs: &HashMap<String, SignPhaseOneMsg>,
+    ) -> Result<(), anyhow::Error> {
+        for (index, msg) in msgs.iter() {
+            if *index != self.party_index {
+                let cipher = &msg.promise_state.cipher.cl_cipher;
+                self.observe_ciphertext(cipher, "phase one", index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs over a map taken out of `self.msgs`, so the received ciphertexts
+    /// are not cloned for the duration of phase two.
+    fn handle_phase_two_msgs(
+        &mut self,
+        msgs: &HashMap<String, SignPhaseTwoMsg>,
+    ) -> Result<(), anyhow::Error> {
+        for (index, msg) in msgs.iter() {
+            if *index != self.party_index {
+                self.observe_ciphertext(&msg.homocipher, "phase two", index)?;
+                self.observe_ciphertext(&msg.homocipher_plus, "phase two", index)?;
+                self.handle_phase_two_msg(index.clone(), msg)?;
+            }
+        }
+        Ok(())
+    }
+
     fn handle_phase_two_msg(
         &mut self,
         index: String,
@@ -405,7 +612,7 @@ impl SignPhase {
         // Compute delta
         let k_mul_t = self.k.clone() * msg.t_p.clone();
         let alpha = CLGroup::decrypt(
-            &GROUP_UPDATE_1827,
+            &self.cl.group,
             self.cl_keypair.get_secret_key(),
             &msg.homocipher,
         ) - k_mul_t;
@@ -418,7 +625,7 @@ impl SignPhase {
         // Compute sigma
         let k_mul_t_plus = self.k.clone() * msg.t_p_plus.clone();
         let miu = CLGroup::decrypt(
-            &GROUP_UPDATE_1827,
+            &self.cl.group,
             self.cl_keypair.get_secret_key(),
             &msg.homocipher_plus,
         ) - k_mul_t_plus;
@@ -436,10 +643,13 @@ impl SignPhase {
         let k_omega = big_omega * &self.k;
         let base = GE::generator();
         let up_plus_b = base * miu + msg.b.clone();
-        if k_omega != up_plus_b {
+        // Both sides are built from k and the decrypted mu, so the comparison
+        // must not exit early.
+        let matches = Zeroizing::new(k_omega.to_bytes(true).to_vec())
+            .ct_eq(&Zeroizing::new(up_plus_b.to_bytes(true).to_vec()));
+        if !bool::from(matches) {
             return Err(anyhow!("Handle msg failed in sign offline phase two"));
         }
-        assert_eq!(k_omega, up_plus_b);
 
         Ok(())
     }
@@ -470,10 +680,10 @@ impl SignPhase {
         index: String,
         msg: &SignPhaseFourMsg,
     ) -> Result<(), anyhow::Error> {
-        let msg_one = self.msgs.phase_one_msgs.get(&index).ok_or(format_err!(
-            "Index is none in msg_one in sign offline phase four"
+        let commitment = self.directory.commitment(&index).ok_or(format_err!(
+            "Index is none in directory in sign offline phase four"
         ))?;
-        DlogCommitment::verify_dlog(&msg_one.commitment, &msg.open)?;
+        DlogCommitment::verify_dlog(commitment, &msg.open)?;
 
         DLogProof::verify(&msg.dl_proof).map_err(|why| {
             format_err!(
@@ -492,7 +702,7 @@ impl SignPhase {
         // todo: `if` unnecessary
         if self.subset.contains(&self.party_index) {
             let cipher = PromiseCipher::encrypt(
-                &GROUP_UPDATE_1827,
+                &self.cl.group,
                 self.cl_keypair.get_public_key(),
                 self.ec_keypair.get_public_key(),
                 &self.k,
@@ -507,8 +717,10 @@ impl SignPhase {
                 r1: cipher.1,      // r'_i
                 r2: cipher.2,      // r_i
             };
-            let proof = PromiseProof::prove(&GROUP_UPDATE_1827, &promise_state, &promise_wit);
+            let proof = PromiseProof::prove(&self.cl.group, &promise_state, &promise_wit)
+                .map_err(|why| format_err!("Prove failed in sign offline phase one: {}", why))?;
             let msg = SignPhaseOneMsg {
+                params: self.cl.group.params_id(),
                 commitment: self.dl_com.commitment.clone(),
                 promise_state,
                 proof,
@@ -529,8 +741,11 @@ impl SignPhase {
     /// Return a result or the message to be sent in the next round.
     ///
     /// When a message is received, the processing is as follows:
-    ///   If this message already exists, do nothing; otherwise, insert it into the cache.
-    ///   When all the necessary messages have been received, generate the result or the next round of messages.
+    ///   Insert it into the cache of its round, even if that round has not started yet.
+    ///   An identical copy of a cached message is ignored; a different one is an error blaming
+    ///   the sender (see `inbox`).
+    ///   When all the necessary messages have been received and the previous round is done,
+    ///   generate the result or the next round of messages.
     pub fn msg_handler(
         &mut self,
         index: String,
@@ -539,11 +754,15 @@ impl SignPhase {
         if !self.subset.contains(&index) {
             return Ok(SendingMessages::EmptyMsg);
         }
+        if let Some(list) = &self.revocations {
+            list.check(&[index.clone()])?;
+        }
 
         let lock = Arc::clone(&self.mutex);
         let _lock = lock.lock().unwrap();
 
-        let msg = bincode::deserialize(&recv_msg).map_err(|why| {
+        let caps = MessageCaps::for_context(&self.cl);
+        let msg = MultiSignMessage::decode(&recv_msg, &caps).map_err(|why| {
             format_err!(
                 "Deserialize error in sign offline msg_handler recv_msg, cause {}",
                 why
@@ -551,23 +770,29 @@ impl SignPhase {
         })?;
         match msg {
             MultiSignMessage::PhaseOneMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_one_msgs,
+                    &index,
+                    msg,
+                    "sign offline phase one",
+                )?;
                 if self.msgsf.phase_one_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_one_msgs.get(&index).is_some() {
-                    self.msgs.phase_one_msgs.insert(index.clone(), msg.clone());
-                }
-
                 if self.msgs.phase_one_msgs.len() == self.party_num {
+                    let phase_one_msgs = std::mem::take(&mut self.msgs.phase_one_msgs);
+                    let observed = self.observe_phase_one_ciphertexts(&phase_one_msgs);
+                    self.msgs.phase_one_msgs = phase_one_msgs;
+                    observed?;
+                    self.verify_promise_proofs()?;
                     let mut t_msgs = HashMap::new();
-                    for (index, msg) in self.msgs.clone().phase_one_msgs.into_iter() {
+                    for (index, msg) in self.msgs.phase_one_msgs.iter() {
                         if *index == self.party_index {
-                            let msg_two = SignPhaseTwoMsg::new();
+                            let msg_two = SignPhaseTwoMsg::new(self.cl.group.params_id());
                             t_msgs.insert(index.clone(), msg_two);
                         } else {
-                            let mut phase = self.clone();
-                            let msg_two = phase.handle_phase_one_msg(index.clone(), &msg).unwrap();
+                            let msg_two = self.handle_phase_one_msg(index.clone(), msg).unwrap();
                             t_msgs.insert(index.clone(), msg_two);
                         }
                     }
@@ -595,20 +820,23 @@ impl SignPhase {
                 }
             }
             MultiSignMessage::PhaseTwoMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_two_msgs,
+                    &index,
+                    msg,
+                    "sign offline phase two",
+                )?;
                 if self.msgsf.phase_two_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_two_msgs.get(&index).is_some() {
-                    self.msgs.phase_two_msgs.insert(index.clone(), msg.clone());
-                }
-
-                if self.msgs.phase_two_msgs.len() == self.party_num {
-                    for (index_, msg_) in self.msgs.phase_two_msgs.clone().iter() {
-                        if *index_ != self.party_index {
-                            self.handle_phase_two_msg(index_.clone(), &msg_)?;
-                        }
-                    }
+                if self.msgs.phase_two_msgs.len() == self.party_num
+                    && self.msgsf.phase_one_msgs == 1
+                {
+                    let phase_two_msgs = std::mem::take(&mut self.msgs.phase_two_msgs);
+                    let handled = self.handle_phase_two_msgs(&phase_two_msgs);
+                    self.msgs.phase_two_msgs = phase_two_msgs;
+                    handled?;
                     let msg_three = SignPhaseThreeMsg {
                         delta: self.delta.clone(),
                     };
@@ -626,15 +854,19 @@ impl SignPhase {
                 }
             }
             MultiSignMessage::PhaseThreeMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_three_msgs,
+                    &index,
+                    msg,
+                    "sign offline phase three",
+                )?;
                 if self.msgsf.phase_three_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_three_msgs.get(&index).is_some() {
-                    self.msgs.phase_three_msgs.insert(index, msg.clone());
-                }
-
-                if self.msgs.phase_three_msgs.len() == self.party_num {
+                if self.msgs.phase_three_msgs.len() == self.party_num
+                    && self.msgsf.phase_two_msgs == 1
+                {
                     self.phase_two_compute_delta_sum_msg()?;
 
                     let dl_proof = DLogProof::<CU, sha2::Sha256>::prove(&self.gamma);
@@ -657,15 +889,19 @@ impl SignPhase {
                 }
             }
             MultiSignMessage::PhaseFourMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_four_msgs,
+                    &index,
+                    msg,
+                    "sign offline phase four",
+                )?;
                 if self.msgsf.phase_four_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_four_msgs.get(&index).is_some() {
-                    self.msgs.phase_four_msgs.insert(index, msg.clone());
-                }
-
-                if self.msgs.phase_four_msgs.len() == self.party_num {
+                if self.msgs.phase_four_msgs.len() == self.party_num
+                    && self.msgsf.phase_three_msgs == 1
+                {
                     for (index_, msg_) in self.msgs.phase_four_msgs.clone().iter() {
                         if *index_ != self.party_index {
                             self.handle_phase_four_msg(index_.clone(), &msg_)?;
@@ -691,6 +927,7 @@ impl SignPhase {
                     })
                     .map_err(|why| format_err!("To string failed: {}", why))
                     .unwrap();
+                    self.emit_presign_completed()?;
                     self.msgsf.phase_four_msgs = 1;
                     return Ok(SendingMessages::SignOfflineSuccessWithResult(
                         offline_result_string,
@@ -810,7 +1047,10 @@ impl SignPhaseOnline {
             dl_proof,
             proof,
         };
-        let msg_step_seven = SignPhaseFiveStepSevenMsg { s_i };
+        let msg_step_seven = SignPhaseFiveStepSevenMsg {
+            s_i,
+            l_i: l_i.clone(),
+        };
         let online_sign = SignPhaseOnline {
             party_index: offline_result.party_index,
             party_num: offline_result.party_num,
@@ -832,10 +1072,178 @@ impl SignPhaseOnline {
             msg_step_seven,
             msg_step_five: SignPhaseFiveStepFiveMsg::new(),
             mutex,
+            audit: None,
+            audit_context: None,
+            approver: None,
+            approval: None,
+            awaiting_approval: false,
+            payload: None,
+            token_verifier: None,
+            authorization: None,
+            revocations: None,
         };
         return Ok(online_sign);
     }
 
+    /// Record the signature in `log` before it is released.
+    /// `request_context` is stored verbatim, e.g. a request id or the
+    /// identity of the requester.
+    pub fn set_audit_log(&mut self, log: Arc<AuditLog>, request_context: Option<String>) {
+        self.audit = Some(log);
+        self.audit_context = request_context;
+    }
+
+    fn emit_signature_produced(&self, signature: &SignatureX) -> Result<(), anyhow::Error> {
+        if let Some(log) = &self.audit {
+            let event = AuditEvent::SignatureProduced {
+                party_index: self.party_index.clone(),
+                subset: self.subset.clone(),
+                public_key: point_to_hex(&self.public_signing_key),
+                message: self.message.to_bigint().to_hex(),
+                r: signature.r.clone(),
+                s: signature.s.clone(),
+            };
+            log.emit(event, self.audit_context.clone()).map_err(|why| {
+                format_err!(
+                    "Audit failed in sign online phase five step seven, cause {}",
+                    why
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Consult `approver` before this party releases its signature share.
+    pub fn set_approver(&mut self, approver: Arc<dyn SignApprover>) {
+        self.approver = Some(approver);
+    }
+
+    /// Refuse to sign with parties revoked in `list`, now or later in the
+    /// session. The presignature was made by the same subset.
+    pub fn set_revocation_list(&mut self, list: Arc<RevocationList>) -> Result<(), anyhow::Error> {
+        list.check(&self.subset)?;
+        self.revocations = Some(list);
+        Ok(())
+    }
+
+    /// Refuse to release the signature share unless the request was
+    /// authorized with a token `verifier` accepts, see `authorize`.
+    pub fn set_token_verifier(&mut self, verifier: Arc<TokenVerifier>) {
+        self.token_verifier = Some(verifier);
+    }
+
+    /// Check the policy engine's token for this request; `now` is the
+    /// current Unix time in seconds.
+    pub fn authorize(&mut self, token: &AuthToken, now: i64) -> Result<(), anyhow::Error> {
+        let verifier = self
+            .token_verifier
+            .clone()
+            .ok_or_else(|| anyhow!("No token verifier attached to the sign session"))?;
+        verifier.verify(
+            token,
+            &self.public_signing_key,
+            &self.message,
+            &self.subset,
+            now,
+        )?;
+        self.authorization = Some(token.nonce.clone());
+        Ok(())
+    }
+
+    /// Decode the serialized transaction behind the message for the approver
+    /// and the audit log. Fails if `decoder` can hash `payload` and gets
+    /// another message.
+    pub fn set_payload(
+        &mut self,
+        decoder: &dyn PayloadDecoder,
+        payload: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let decoded = decoder
+            .decode(payload)
+            .map_err(|why| format_err!("Decode signing payload failed, cause {}", why))?;
+        let bound = match decoder.signing_hash(payload) {
+            Some(hash) if FE::from_bigint(&BigInt::from_bytes(&hash)) == self.message => true,
+            Some(_) => return Err(anyhow!("Signing payload does not hash to the message")),
+            None => false,
+        };
+        self.payload = Some(DecodedPayload {
+            decoder: decoder.name().to_string(),
+            bound,
+            fields: decoded,
+        });
+        Ok(())
+    }
+
+    pub fn is_awaiting_approval(&self) -> bool {
+        self.awaiting_approval
+    }
+
+    /// Resume a session parked on `ApprovalDecision::Pending` and return the
+    /// step seven message.
+    pub fn approve(&mut self, token: String) -> Result<SendingMessages, anyhow::Error> {
+        let lock = Arc::clone(&self.mutex);
+        let _lock = lock.lock().unwrap();
+        if !self.awaiting_approval {
+            return Err(anyhow!("Sign online session is not awaiting approval"));
+        }
+        self.awaiting_approval = false;
+        self.record_approval(token)?;
+        self.release_step_seven_msg()
+    }
+
+    /// Abandon a session parked on `ApprovalDecision::Pending`. The share is
+    /// never released, so the session cannot complete anymore.
+    pub fn deny(&mut self) {
+        let lock = Arc::clone(&self.mutex);
+        let _lock = lock.lock().unwrap();
+        self.awaiting_approval = false;
+    }
+
+    fn approval_request(&self) -> ApprovalRequest {
+        ApprovalRequest {
+            party_index: self.party_index.clone(),
+            subset: self.subset.clone(),
+            public_key: self.public_signing_key.clone(),
+            message: self.message.clone(),
+            r_point: self.r_point.clone(),
+            payload: self.payload.clone(),
+        }
+    }
+
+    fn record_approval(&mut self, token: String) -> Result<(), anyhow::Error> {
+        if let Some(log) = &self.audit {
+            let event = AuditEvent::SignApproved {
+                party_index: self.party_index.clone(),
+                message: self.message.to_bigint().to_hex(),
+                approval: token.clone(),
+                payload: self.payload.clone(),
+            };
+            log.emit(event, self.audit_context.clone()).map_err(|why| {
+                format_err!("Audit failed in sign online approval, cause {}", why)
+            })?;
+        }
+        self.approval = Some(token);
+        Ok(())
+    }
+
+    fn release_step_seven_msg(&self) -> Result<SendingMessages, anyhow::Error> {
+        let msg_seven = self.msg_step_seven.clone();
+
+        // todo: compatibility(self to self), 20220823
+        // self.msgs
+        //     .phase_five_step_seven_msgs
+        //     .insert(self.party_index.clone(), msg_seven.clone());
+
+        let sending_msg = MultiSignMessage::PhaseFiveStepSevenMsg(msg_seven);
+        let sending_msg_bytes = bincode::serialize(&sending_msg).map_err(|why| {
+            format_err!(
+                "Serialize error in sign online phase five step five, cause {}",
+                why
+            )
+        })?;
+        Ok(SendingMessages::SubsetMessage(sending_msg_bytes))
+    }
+
     fn handle_phase_five_step_two_msg(
         &mut self,
         index: String,
@@ -1016,12 +1424,41 @@ impl SignPhaseOnline {
         Ok(recid)
     }
 
+    /// Check every share s_i against the V_i its sender committed to in step
+    /// one, so that a bad share is blamed on its sender instead of only
+    /// failing the final signature check.
+    fn phase_five_step_seven_verify_shares(&self) -> Result<(), anyhow::Error> {
+        let base = GE::generator();
+        let mut blamed = Vec::new();
+        for (index, msg) in self.msgs.phase_five_step_seven_msgs.iter() {
+            let msg_two = self
+                .msgs
+                .phase_five_step_two_msgs
+                .get(index)
+                .ok_or(format_err!(
+                    "Index is none in phase_five_step_two_msgs in sign online phase five step seven"
+                ))?;
+            if self.r_point.clone() * &msg.s_i + base * msg.l_i.clone() != msg_two.v_i {
+                blamed.push(index.clone());
+            }
+        }
+        if !blamed.is_empty() {
+            blamed.sort();
+            return Err(anyhow!(
+                "Invalid signature share from parties {:?} in sign online phase five step seven",
+                blamed
+            ));
+        }
+        Ok(())
+    }
+
     fn phase_five_step_eight_generate_signature_msg(&self) -> Result<Signature, anyhow::Error> {
         if self.msgs.phase_five_step_seven_msgs.len() != self.party_num {
             return Err(anyhow!(
                 "Left not equal to right in sign online phase five step seven"
             ));
         }
+        self.phase_five_step_seven_verify_shares()?;
 
         let mut s = self
             .msgs
@@ -1067,8 +1504,11 @@ impl SignPhaseOnline {
     /// Return a result or the message to be sent in the next round.
     ///
     /// When a message is received, the processing is as follows:
-    ///   If this message already exists, do nothing; otherwise, insert it into the cache.
-    ///   When all the necessary messages have been received, generate the result or the next round of messages.
+    ///   Insert it into the cache of its round, even if that round has not started yet.
+    ///   An identical copy of a cached message is ignored; a different one is an error blaming
+    ///   the sender (see `inbox`).
+    ///   When all the necessary messages have been received and the previous round is done,
+    ///   generate the result or the next round of messages.
     pub fn msg_handler(
         &mut self,
         index: String,
@@ -1077,24 +1517,26 @@ impl SignPhaseOnline {
         if !self.subset.contains(&index) {
             return Ok(SendingMessages::EmptyMsg);
         }
+        if let Some(list) = &self.revocations {
+            list.check(&[index.clone()])?;
+        }
 
         let lock = Arc::clone(&self.mutex);
         let _lock = lock.lock().unwrap();
-        let msg = bincode::deserialize(&recv_msg)
-            .map_err(|why| format_err!("bincode deserialize error: {}", why))
-            .unwrap();
+        let msg = MultiSignMessage::decode(&recv_msg, &MessageCaps::NO_CL)
+            .map_err(|why| format_err!("bincode deserialize error: {}", why))?;
         match msg {
             MultiSignMessage::PhaseFiveStepOneMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_step_one_msgs,
+                    &index,
+                    msg,
+                    "sign online phase five step one",
+                )?;
                 if self.msgsf.phase_five_step_one_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_step_one_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_five_step_one_msgs
-                        .insert(index, msg.clone());
-                }
-
                 if self.msgs.phase_five_step_one_msgs.len() == self.party_num {
                     let msg_five_two = self.msg_step_two.clone();
 
@@ -1114,17 +1556,19 @@ impl SignPhaseOnline {
                 }
             }
             MultiSignMessage::PhaseFiveStepTwoMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_step_two_msgs,
+                    &index,
+                    msg,
+                    "sign online phase five step two",
+                )?;
                 if self.msgsf.phase_five_step_two_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_step_two_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_five_step_two_msgs
-                        .insert(index.clone(), msg.clone());
-                }
-
-                if self.msgs.phase_five_step_two_msgs.len() == self.party_num {
+                if self.msgs.phase_five_step_two_msgs.len() == self.party_num
+                    && self.msgsf.phase_five_step_one_msgs == 1
+                {
                     for (index_, msg_) in self.msgs.phase_five_step_two_msgs.clone().iter() {
                         self.handle_phase_five_step_two_msg(index_.clone(), &msg_)?;
                     }
@@ -1148,17 +1592,19 @@ impl SignPhaseOnline {
                 }
             }
             MultiSignMessage::PhaseFiveStepFourMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_step_four_msgs,
+                    &index,
+                    msg,
+                    "sign online phase five step four",
+                )?;
                 if self.msgsf.phase_five_step_four_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_step_four_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_five_step_four_msgs
-                        .insert(index, msg.clone());
-                }
-
-                if self.msgs.phase_five_step_four_msgs.len() == self.party_num {
+                if self.msgs.phase_five_step_four_msgs.len() == self.party_num
+                    && self.msgsf.phase_five_step_two_msgs == 1
+                {
                     let msg_five_five = self.msg_step_five.clone();
 
                     self.msgs
@@ -1177,52 +1623,65 @@ impl SignPhaseOnline {
                 }
             }
             MultiSignMessage::PhaseFiveStepFiveMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_step_five_msgs,
+                    &index,
+                    msg,
+                    "sign online phase five step five",
+                )?;
                 if self.msgsf.phase_five_step_five_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_step_five_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_five_step_five_msgs
-                        .insert(index, msg.clone());
-                }
-
-                if self.msgs.phase_five_step_five_msgs.len() == self.party_num {
+                if self.msgs.phase_five_step_five_msgs.len() == self.party_num
+                    && self.msgsf.phase_five_step_four_msgs == 1
+                {
+                    // Before any state changes, so that the round completes on
+                    // a redelivered message once `authorize` succeeds.
+                    if self.token_verifier.is_some() && self.authorization.is_none() {
+                        return Err(anyhow!(
+                            "Sign request is not authorized in sign online phase five"
+                        ));
+                    }
                     for (index_, msg_) in self.msgs.phase_five_step_five_msgs.clone().iter() {
                         self.handle_phase_five_step_five_msg(index_.clone(), &msg_)?;
                     }
                     self.phase_five_step_six_check_sum_a_t()
                             .map_err(|why| format_err!("Verify sum of a and t failed in sign online phase five step five, cause {}", why))?;
-                    let msg_seven = self.msg_step_seven.clone();
-
-                    // todo: compatibility(self to self), 20220823
-                    // self.msgs
-                    //     .phase_five_step_seven_msgs
-                    //     .insert(self.party_index.clone(), msg_seven.clone());
-
-                    let sending_msg = MultiSignMessage::PhaseFiveStepSevenMsg(msg_seven.clone());
-                    let sending_msg_bytes = bincode::serialize(&sending_msg).map_err(|why| {
-                        format_err!(
-                            "Serialize error in sign online phase five step five, cause {}",
-                            why
-                        )
-                    })?;
                     self.msgsf.phase_five_step_five_msgs = 1;
-                    return Ok(SendingMessages::SubsetMessage(sending_msg_bytes));
+
+                    if let Some(approver) = self.approver.clone() {
+                        match approver.review(&self.approval_request()) {
+                            ApprovalDecision::Approved(token) => self.record_approval(token)?,
+                            ApprovalDecision::Denied(reason) => {
+                                return Err(anyhow!(
+                                    "Signing denied by approver in sign online phase five: {}",
+                                    reason
+                                ));
+                            }
+                            ApprovalDecision::Pending => {
+                                self.awaiting_approval = true;
+                                return Ok(SendingMessages::EmptyMsg);
+                            }
+                        }
+                    }
+                    return self.release_step_seven_msg();
                 }
             }
             MultiSignMessage::PhaseFiveStepSevenMsg(msg) => {
+                record(
+                    &mut self.msgs.phase_five_step_seven_msgs,
+                    &index,
+                    msg,
+                    "sign online phase five step seven",
+                )?;
                 if self.msgsf.phase_five_step_seven_msgs == 1 {
                     return Ok(SendingMessages::EmptyMsg);
                 }
 
-                if !self.msgs.phase_five_step_seven_msgs.get(&index).is_some() {
-                    self.msgs
-                        .phase_five_step_seven_msgs
-                        .insert(index, msg.clone());
-                }
-
-                if self.msgs.phase_five_step_seven_msgs.len() == self.party_num {
+                if self.msgs.phase_five_step_seven_msgs.len() == self.party_num
+                    && self.msgsf.phase_five_step_five_msgs == 1
+                {
                     let signature = self.phase_five_step_eight_generate_signature_msg()?;
                     signature.verify(&self.public_signing_key, &self.message)?;
 
@@ -1230,6 +1689,7 @@ impl SignPhaseOnline {
                     let r = signature.r.to_bigint().to_hex();
                     let recid = signature.recid;
                     let ret = SignatureX { s, r, recid };
+                    self.emit_signature_produced(&ret)?;
                     let signature_json = serde_json::to_string(&ret).map_err(|why| {
                         format_err!("To string failed in keygen phase five, cause {}", why)
                     })?;
@@ -1244,3 +1704,115 @@ impl SignPhaseOnline {
         Ok(SendingMessages::EmptyMsg)
     }
 }
+
+#[test]
+fn test_select_signers() {
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let responders = vec!["3".to_string(), "3".to_string(), "1".to_string()];
+    let subset = select_signers(&params, &responders, 2).unwrap();
+    assert_eq!(subset, vec!["3".to_string(), "1".to_string()]);
+    assert!(select_signers(&params, &responders, 1).is_err());
+    assert!(select_signers(&params, &responders, 3).is_err());
+}
+
+#[test]
+fn test_authorize_after_refusal() {
+    use crate::protocols::multi_party::dmz21::authorization::TokenIssuer;
+    use crate::protocols::multi_party::dmz21::keygen::KeyGenPhase;
+    use crate::protocols::multi_party::dmz21::simulation::{simulate, SimConfig};
+    use std::collections::{BTreeMap, VecDeque};
+
+    // Deliver until the queue is empty; returns the message refused, if any.
+    fn drain(
+        subset: &[String],
+        queue: &mut VecDeque<(String, SendingMessages)>,
+        signers: &mut BTreeMap<String, SignPhaseOnline>,
+        results: &mut BTreeMap<String, String>,
+    ) -> Option<(String, Vec<u8>)> {
+        let mut refused = None;
+        while let Some((from, out)) = queue.pop_front() {
+            let sends: Vec<(String, Vec<u8>)> = match out {
+                SendingMessages::SubsetMessage(payload)
+                | SendingMessages::BroadcastMessage(payload) => {
+                    let fan_out = |to: &String| (to.clone(), payload.clone());
+                    subset.iter().map(fan_out).collect()
+                }
+                SendingMessages::P2pMessage(payloads) => payloads.into_iter().collect(),
+                SendingMessages::NormalMessage(to, payload) => vec![(to, payload)],
+                SendingMessages::SignOnlineSuccessWithResult(result) => {
+                    results.insert(from, result);
+                    continue;
+                }
+                _ => continue,
+            };
+            for (to, payload) in sends {
+                let signer = signers.get_mut(&to).unwrap();
+                match signer.msg_handler(from.clone(), &payload) {
+                    Ok(next) => queue.push_back((to, next)),
+                    Err(why) => {
+                        assert!(why.to_string().contains("not authorized"));
+                        assert_eq!(to, "1");
+                        refused = Some((from.clone(), payload));
+                    }
+                }
+            }
+        }
+        refused
+    }
+
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let ids: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    let keygen: BTreeMap<String, KeyGenPhase> = ids
+        .iter()
+        .map(|id| {
+            let phase = KeyGenPhase::new(id.clone(), params.clone(), &Some(ids.clone())).unwrap();
+            (id.clone(), phase)
+        })
+        .collect();
+    let keys = simulate(&SimConfig::new(5), keygen).unwrap().results;
+    let subset = ids[..2].to_vec();
+    let offline: BTreeMap<String, SignPhase> = subset
+        .iter()
+        .map(|id| {
+            let phase = SignPhase::new(id.clone(), params.clone(), &subset, &keys[id]).unwrap();
+            (id.clone(), phase)
+        })
+        .collect();
+    let offline = simulate(&SimConfig::new(6), offline).unwrap().results;
+
+    let online = |id: &String| SignPhaseOnline::new(&offline[id], vec![7u8; 32]).unwrap();
+    let mut signers: BTreeMap<String, SignPhaseOnline> =
+        subset.iter().map(|id| (id.clone(), online(id))).collect();
+    let issuer = TokenIssuer::new(EcKeyPair::new(), 60);
+    let verifier = TokenVerifier::new(issuer.public_key().clone(), 5, 300);
+    let verifier = Arc::new(verifier);
+    signers.get_mut("1").unwrap().set_token_verifier(verifier);
+
+    let mut queue: VecDeque<(String, SendingMessages)> = VecDeque::new();
+    for (id, signer) in signers.iter_mut() {
+        queue.push_back((id.clone(), signer.process_begin().unwrap()));
+    }
+    let mut results = BTreeMap::new();
+    let refused = drain(&subset, &mut queue, &mut signers, &mut results);
+    let (from, payload) = refused.unwrap();
+    assert!(results.is_empty());
+
+    // Authorize the refused party and hand it the message it refused again.
+    let now = 1_700_000_000;
+    let signer = signers.get_mut("1").unwrap();
+    let token = issuer
+        .issue(&signer.public_signing_key, &signer.message, &subset, now)
+        .unwrap();
+    signer.authorize(&token, now).unwrap();
+    let next = signer.msg_handler(from, &payload).unwrap();
+    queue.push_back(("1".to_string(), next));
+    assert!(drain(&subset, &mut queue, &mut signers, &mut results).is_none());
+    assert_eq!(results.len(), 2);
+    assert_eq!(results["1"], results["2"]);
+}
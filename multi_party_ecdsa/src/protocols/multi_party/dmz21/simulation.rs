@@ -0,0 +1,426 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Deterministic simulation of an unreliable network between parties.
+//!
+//! `local` wires the parties together with channels that deliver every
+//! message at once and in order. Here the parties run on one thread against
+//! a virtual clock, and every payload crosses a link with its own latency
+//! distribution and drop rate, optionally cut by a partition for a window
+//! of time. Senders keep payloads in a `DeliveryBuffer` outbox and resend
+//! them every `retry_ms` until the recipient's ack (which crosses the same
+//! lossy link back) comes in; recipients deduplicate with the inbox. A run
+//! that has not finished at `timeout_ms` of virtual time stops and reports
+//! which parties have no result, which is the executor's cue to abort or to
+//! retry with another subset.
+//!
+//! Time spent computing does not advance the virtual clock. A partition
+//! applies to payloads sent while it is active; payloads already in flight
+//! are delivered.
+use crate::communication::delivery::{DeliveryBuffer, DeliveryKey};
+use crate::communication::sending_messages::SendingMessages;
+use crate::protocols::multi_party::dmz21::keygen::KeyGenPhase;
+use crate::protocols::multi_party::dmz21::sign::{SignPhase, SignPhaseOnline};
+use anyhow::format_err;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+const SESSION: &str = "sim";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    Fixed(u64),
+    /// Uniform over `min_ms..=max_ms`, so deliveries get reordered.
+    Uniform {
+        min_ms: u64,
+        max_ms: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkConfig {
+    pub latency: Latency,
+    /// Probability in `[0, 1]` that one transmission is lost.
+    pub drop_rate: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Fixed(10),
+            drop_rate: 0.0,
+        }
+    }
+}
+
+/// Links between `isolated` and everyone else are cut from `from_ms` until
+/// `until_ms`, or for good.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partition {
+    pub isolated: BTreeSet<String>,
+    pub from_ms: u64,
+    pub until_ms: Option<u64>,
+}
+
+impl Partition {
+    fn cuts(&self, from: &str, to: &str, now: u64) -> bool {
+        let active = now >= self.from_ms && self.until_ms.map_or(true, |until| now < until);
+        active && self.isolated.contains(from) != self.isolated.contains(to)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub default_link: LinkConfig,
+    /// Overrides of `default_link` per directed `(from, to)` link.
+    pub links: HashMap<(String, String), LinkConfig>,
+    pub partitions: Vec<Partition>,
+    pub retry_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl SimConfig {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            default_link: LinkConfig::default(),
+            links: HashMap::new(),
+            partitions: Vec::new(),
+            retry_ms: 200,
+            timeout_ms: 60_000,
+        }
+    }
+
+    fn link(&self, from: &str, to: &str) -> LinkConfig {
+        self.links
+            .get(&(from.to_string(), to.to_string()))
+            .copied()
+            .unwrap_or(self.default_link)
+    }
+}
+
+/// A protocol state machine the simulator can drive.
+pub trait SimParty {
+    fn begin(&mut self) -> Result<SendingMessages, anyhow::Error>;
+    fn handle(&mut self, from: String, msg: &Vec<u8>) -> Result<SendingMessages, anyhow::Error>;
+}
+
+macro_rules! impl_sim_party {
+    ($($phase:ty),*) => {$(
+        impl SimParty for $phase {
+            fn begin(&mut self) -> Result<SendingMessages, anyhow::Error> {
+                self.process_begin()
+            }
+
+            fn handle(
+                &mut self,
+                from: String,
+                msg: &Vec<u8>,
+            ) -> Result<SendingMessages, anyhow::Error> {
+                self.msg_handler(from, msg)
+            }
+        }
+    )*};
+}
+
+impl_sim_party!(KeyGenPhase, SignPhase, SignPhaseOnline);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimStats {
+    /// Transmissions of payloads and acks, first attempts and resends.
+    pub sent: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub retransmitted: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimOutcome {
+    /// The `*SuccessWithResult` string of every party that finished.
+    pub results: BTreeMap<String, String>,
+    pub elapsed_ms: u64,
+    pub timed_out: bool,
+    pub stats: SimStats,
+}
+
+impl SimOutcome {
+    pub fn missing<'a>(&self, parties: &'a [String]) -> Vec<&'a String> {
+        parties
+            .iter()
+            .filter(|party| !self.results.contains_key(*party))
+            .collect()
+    }
+}
+
+enum Event {
+    Deliver {
+        from: String,
+        to: String,
+        payload: Vec<u8>,
+    },
+    /// `key` as the sender's outbox holds it, i.e. naming the recipient.
+    Ack {
+        sender: String,
+        key: DeliveryKey,
+    },
+    Retry,
+}
+
+struct Network<'a> {
+    config: &'a SimConfig,
+    rng: StdRng,
+    now: u64,
+    seq: u64,
+    queue: BTreeMap<(u64, u64), Event>,
+    stats: SimStats,
+}
+
+impl<'a> Network<'a> {
+    fn schedule(&mut self, at: u64, event: Event) {
+        self.seq += 1;
+        self.queue.insert((at, self.seq), event);
+    }
+
+    /// Put `event` on the `from -> to` link, or lose it.
+    fn transmit(&mut self, from: &str, to: &str, event: Event) {
+        self.stats.sent += 1;
+        if from == to {
+            self.schedule(self.now, event);
+            return;
+        }
+        let link = self.config.link(from, to);
+        let now = self.now;
+        let cut = self.config.partitions.iter().any(|p| p.cuts(from, to, now));
+        if cut || self.rng.gen::<f64>() < link.drop_rate {
+            self.stats.dropped += 1;
+            return;
+        }
+        let delay = match link.latency {
+            Latency::Fixed(ms) => ms,
+            Latency::Uniform { min_ms, max_ms } => self.rng.gen_range(min_ms, max_ms + 1),
+        };
+        self.schedule(now + delay, event);
+    }
+
+    fn next(&mut self) -> Option<Event> {
+        let key = *self.queue.keys().next()?;
+        self.now = key.0;
+        self.queue.remove(&key)
+    }
+}
+
+/// Record a result, or queue and transmit the payloads of `out`.
+fn dispatch(
+    net: &mut Network,
+    buffers: &mut BTreeMap<String, DeliveryBuffer>,
+    results: &mut BTreeMap<String, String>,
+    ids: &[String],
+    from: &str,
+    out: SendingMessages,
+) -> Result<(), anyhow::Error> {
+    let sends: Vec<(String, Vec<u8>)> = match &out {
+        SendingMessages::KeyGenSuccessWithResult(result)
+        | SendingMessages::SignOfflineSuccessWithResult(result)
+        | SendingMessages::SignOnlineSuccessWithResult(result) => {
+            results.insert(from.to_string(), result.clone());
+            return Ok(());
+        }
+        SendingMessages::EmptyMsg => return Ok(()),
+        SendingMessages::NormalMessage(to, payload) => vec![(to.clone(), payload.clone())],
+        SendingMessages::P2pMessage(payloads) => payloads.clone().into_iter().collect(),
+        SendingMessages::SubsetMessage(payload) | SendingMessages::BroadcastMessage(payload) => {
+            ids.iter().map(|to| (to.clone(), payload.clone())).collect()
+        }
+    };
+    buffers
+        .get_mut(from)
+        .ok_or(format_err!("Unknown sender {} in simulation", from))?
+        .outbox
+        .push(SESSION, ids, &out)?;
+    for (to, payload) in sends {
+        if !buffers.contains_key(&to) {
+            return Err(format_err!("Party {} sent to unknown party {}", from, to));
+        }
+        let event = Event::Deliver {
+            from: from.to_string(),
+            to: to.clone(),
+            payload,
+        };
+        net.transmit(from, &to, event);
+    }
+    Ok(())
+}
+
+/// Run `parties` to completion, or until the virtual clock passes
+/// `config.timeout_ms`. Broadcast and subset messages go to every party of
+/// the run. A protocol error of any party ends the run with that error.
+pub fn simulate<P: SimParty>(
+    config: &SimConfig,
+    mut parties: BTreeMap<String, P>,
+) -> Result<SimOutcome, anyhow::Error> {
+    let ids: Vec<String> = parties.keys().cloned().collect();
+    let mut buffers: BTreeMap<String, DeliveryBuffer> = ids
+        .iter()
+        .map(|id| (id.clone(), DeliveryBuffer::new()))
+        .collect();
+    let mut results = BTreeMap::new();
+    let mut net = Network {
+        config,
+        rng: StdRng::seed_from_u64(config.seed),
+        now: 0,
+        seq: 0,
+        queue: BTreeMap::new(),
+        stats: SimStats::default(),
+    };
+
+    for id in &ids {
+        let out = parties.get_mut(id).unwrap().begin()?;
+        dispatch(&mut net, &mut buffers, &mut results, &ids, id, out)?;
+    }
+    net.schedule(config.retry_ms, Event::Retry);
+
+    let timed_out = loop {
+        if results.len() == ids.len() {
+            break false;
+        }
+        let event = match net.next() {
+            Some(event) => event,
+            None => break true,
+        };
+        if net.now > config.timeout_ms {
+            break true;
+        }
+        match event {
+            Event::Deliver { from, to, payload } => {
+                net.stats.delivered += 1;
+                let inbox = &mut buffers.get_mut(&to).unwrap().inbox;
+                let (key, fresh) = inbox.accept(SESSION, &from, &payload)?;
+                let ack = Event::Ack {
+                    sender: from.clone(),
+                    key: DeliveryKey {
+                        party: to.clone(),
+                        ..key
+                    },
+                };
+                net.transmit(&to, &from, ack);
+                if fresh {
+                    let out = parties.get_mut(&to).unwrap().handle(from, &payload)?;
+                    dispatch(&mut net, &mut buffers, &mut results, &ids, &to, out)?;
+                }
+            }
+            Event::Ack { sender, key } => {
+                net.stats.delivered += 1;
+                buffers.get_mut(&sender).unwrap().outbox.ack(&key);
+            }
+            Event::Retry => {
+                for (sender, buffer) in buffers.iter() {
+                    let pending: Vec<(DeliveryKey, Vec<u8>)> = buffer
+                        .outbox
+                        .pending()
+                        .map(|(key, payload)| (key.clone(), payload.clone()))
+                        .collect();
+                    for (key, payload) in pending {
+                        net.stats.retransmitted += 1;
+                        let event = Event::Deliver {
+                            from: sender.clone(),
+                            to: key.party.clone(),
+                            payload,
+                        };
+                        net.transmit(sender, &key.party, event);
+                    }
+                }
+                let at = net.now + config.retry_ms;
+                net.schedule(at, Event::Retry);
+            }
+        }
+    };
+
+    Ok(SimOutcome {
+        results,
+        elapsed_ms: net.now,
+        timed_out,
+        stats: net.stats,
+    })
+}
+
+#[test]
+fn simulated_network_test() {
+    use crate::protocols::multi_party::dmz21::keygen::Parameters;
+
+    let params = Parameters {
+        threshold: 1,
+        share_count: 3,
+    };
+    let ids: Vec<String> = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+
+    // Jittery, lossy links: keygen still completes, by resending.
+    let mut lossy = SimConfig::new(7);
+    lossy.default_link = LinkConfig {
+        latency: Latency::Uniform {
+            min_ms: 20,
+            max_ms: 300,
+        },
+        drop_rate: 0.2,
+    };
+    let keygen = ids
+        .iter()
+        .map(|id| {
+            let phase = KeyGenPhase::new(id.clone(), params.clone(), &Some(ids.clone())).unwrap();
+            (id.clone(), phase)
+        })
+        .collect();
+    let keys = simulate(&lossy, keygen).unwrap();
+    assert!(!keys.timed_out);
+    assert_eq!(keys.results.len(), 3);
+    assert!(keys.stats.dropped > 0 && keys.stats.retransmitted > 0);
+
+    // Party 3 is cut off for good: signing with it times out...
+    let mut partitioned = SimConfig::new(8);
+    partitioned.timeout_ms = 5_000;
+    partitioned.partitions.push(Partition {
+        isolated: vec!["3".to_string()].into_iter().collect(),
+        from_ms: 0,
+        until_ms: None,
+    });
+    let sign = |subset: &[String]| -> BTreeMap<String, SignPhase> {
+        subset
+            .iter()
+            .map(|id| {
+                let key = &keys.results[id];
+                let phase = SignPhase::new(id.clone(), params.clone(), &subset.to_vec(), key);
+                (id.clone(), phase.unwrap())
+            })
+            .collect()
+    };
+    let stalled = simulate(&partitioned, sign(&ids)).unwrap();
+    assert!(stalled.timed_out);
+    assert!(stalled.missing(&ids).contains(&&ids[2]));
+
+    // ...and the executor fails over to the two parties it can reach.
+    let reachable = ids[..2].to_vec();
+    let offline = simulate(&partitioned, sign(&reachable)).unwrap();
+    assert!(!offline.timed_out);
+    assert!(offline.missing(&reachable).is_empty());
+
+    // A partition that heals lets the run finish after it ends.
+    partitioned.partitions[0].until_ms = Some(1_000);
+    let healed = simulate(&partitioned, sign(&ids)).unwrap();
+    assert!(!healed.timed_out);
+    assert!(healed.elapsed_ms >= 1_000);
+}
@@ -0,0 +1,326 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Serialized sizes of CL objects and round messages per parameter set.
+//!
+//! The numbers are measured with the wire encoder on freshly generated
+//! objects of the chosen group. Hex coefficients of class group elements
+//! lose a digit now and then, so two runs can differ by a few bytes. They
+//! stay within the `MessageCaps` of their level.
+use crate::protocols::multi_party::dmz21::common::*;
+use crate::protocols::multi_party::dmz21::message::*;
+use crate::utilities::cl_dl_proof::{CLDLProof, CLDLState, CLDLWit};
+use crate::utilities::cl_key_proof::CLKeyProof;
+use crate::utilities::cl_proof::{CLProof, CLState, CLWit};
+use crate::utilities::class_group::*;
+use crate::utilities::dl_com_zk::DlogCommitment;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::promise_sigma_multi::{
+    PromiseCipher, PromiseProof, PromiseState, PromiseWit,
+};
+use crate::utilities::zero_enc_proof::{ZeroEncState, ZeroEncWit, ZeroEncryptionProof};
+use crate::utilities::SECURITY_BITS;
+use curv::arithmetic::traits::*;
+use curv::cryptographic_primitives::proofs::sigma_correct_homomorphic_elgamal_enc::*;
+use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityLevel {
+    /// 1827-bit discriminant, the one the protocols run in by default.
+    #[cfg(feature = "params-1827")]
+    Cl1827,
+    /// 3072-bit discriminant.
+    #[cfg(feature = "params-3072")]
+    Cl3072,
+    /// 2048-bit `Delta_K`.
+    #[cfg(feature = "params-2048")]
+    Cl2048,
+    /// 2432-bit `Delta_K`.
+    #[cfg(feature = "params-2432")]
+    Cl2432,
+    /// 2944-bit `Delta_K`.
+    #[cfg(feature = "params-2944")]
+    Cl2944,
+}
+
+impl SecurityLevel {
+    pub fn context(&self) -> Arc<CLContext> {
+        match self {
+            #[cfg(feature = "params-1827")]
+            SecurityLevel::Cl1827 => CL_CONTEXT_1827.clone(),
+            #[cfg(feature = "params-3072")]
+            SecurityLevel::Cl3072 => CL_CONTEXT_3072.clone(),
+            #[cfg(feature = "params-2048")]
+            SecurityLevel::Cl2048 => CL_CONTEXT_2048.clone(),
+            #[cfg(feature = "params-2432")]
+            SecurityLevel::Cl2432 => CL_CONTEXT_2432.clone(),
+            #[cfg(feature = "params-2944")]
+            SecurityLevel::Cl2944 => CL_CONTEXT_2944.clone(),
+        }
+    }
+
+    /// Size caps of round messages in this parameter set.
+    pub fn caps(&self) -> MessageCaps {
+        MessageCaps::for_context(&self.context())
+    }
+
+    /// Stable name of the parameter set, as advertised in negotiation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "params-1827")]
+            SecurityLevel::Cl1827 => "cl1827",
+            #[cfg(feature = "params-3072")]
+            SecurityLevel::Cl3072 => "cl3072",
+            #[cfg(feature = "params-2048")]
+            SecurityLevel::Cl2048 => "cl2048",
+            #[cfg(feature = "params-2432")]
+            SecurityLevel::Cl2432 => "cl2432",
+            #[cfg(feature = "params-2944")]
+            SecurityLevel::Cl2944 => "cl2944",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "params-1827")]
+            "cl1827" => Some(SecurityLevel::Cl1827),
+            #[cfg(feature = "params-3072")]
+            "cl3072" => Some(SecurityLevel::Cl3072),
+            #[cfg(feature = "params-2048")]
+            "cl2048" => Some(SecurityLevel::Cl2048),
+            #[cfg(feature = "params-2432")]
+            "cl2432" => Some(SecurityLevel::Cl2432),
+            #[cfg(feature = "params-2944")]
+            "cl2944" => Some(SecurityLevel::Cl2944),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized sizes in bytes. Messages are measured wrapped in their
+/// `MultiKeyGenMessage` or `MultiSignMessage` variant, as sent. Keygen phase
+/// four grows with the threshold; see `keygen_phase_four_max_size`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeReport {
+    pub level: SecurityLevel,
+    pub cl_element: usize,
+    pub cl_public_key: usize,
+    pub ciphertext: usize,
+    pub cl_proof: usize,
+    pub cl_dl_proof: usize,
+    pub cl_key_proof: usize,
+    pub promise_proof: usize,
+    pub zero_enc_proof: usize,
+    pub keygen_phase_one_two: usize,
+    pub keygen_phase_three: usize,
+    pub keygen_phase_five: usize,
+    pub sign_phase_one: usize,
+    pub sign_phase_two: usize,
+    pub sign_phase_three: usize,
+    pub sign_phase_four: usize,
+    pub sign_phase_five_step_one: usize,
+    pub sign_phase_five_step_two: usize,
+    pub sign_phase_five_step_four: usize,
+    pub sign_phase_five_step_five: usize,
+    pub sign_phase_five_step_seven: usize,
+}
+
+fn size_of<T: Serialize>(value: &T) -> usize {
+    bincode::serialized_size(value)
+        .map(|size| size as usize)
+        .unwrap_or(usize::MAX)
+}
+
+impl SizeReport {
+    /// Generate one object of every kind in the group of `level` and
+    /// measure it. This runs real encryptions and proofs, so it takes about
+    /// as long as one offline sign.
+    pub fn for_level(level: SecurityLevel) -> Result<Self, MulEcdsaError> {
+        let cl = level.context();
+        let group = &cl.group;
+        let (sk, pk) = group.keygen();
+        let m = FE::random();
+        let (ciphertext, r) = CLGroup::encrypt(group, &pk, &m);
+
+        let cl_proof = CLProof::prove(
+            group,
+            CLWit {
+                x: m.clone(),
+                r: r.clone(),
+            },
+            CLState {
+                cipher: ciphertext.clone(),
+                cl_pub_key: pk.clone(),
+            },
+        )?;
+        let cl_dl_proof = CLDLProof::prove(
+            group,
+            CLDLWit {
+                dl_priv: m.clone(),
+                r,
+            },
+            CLDLState {
+                cipher: ciphertext.clone(),
+                cl_pub_key: pk.clone(),
+                dl_pub: GE::generator() * &m,
+            },
+        )?;
+
+        let cl_key_proof = CLKeyProof::prove(group, &sk, &pk);
+
+        let (rerandomized, zero_r) = CLGroup::rerandomize(group, &pk, &ciphertext);
+        let zero_state = ZeroEncState::from_rerandomization(&ciphertext, &rerandomized, &pk);
+        let zero_enc_proof =
+            ZeroEncryptionProof::prove(group, &ZeroEncWit { r: zero_r }, &zero_state)?;
+
+        let ec_sk = FE::random();
+        let ec_pk = GE::generator() * &ec_sk;
+        let (promise_cipher, r1, r2) = PromiseCipher::encrypt(group, &pk, &ec_pk, &m);
+        let promise_state = PromiseState {
+            cipher: promise_cipher,
+            ec_pub_key: ec_pk.clone(),
+            cl_pub_key: pk.clone(),
+        };
+        let promise_wit = PromiseWit {
+            m: m.clone(),
+            r1,
+            r2,
+        };
+        let promise_proof = PromiseProof::prove(group, &promise_state, &promise_wit)?;
+
+        let dl_com = DlogCommitment::new(&ec_pk);
+        let dl_proof = DLogProof::prove(&ec_sk);
+        let keygen_one_two = MultiKeyGenMessage::PhaseOneTwoMsg(KeyGenPhaseOneTwoMsg {
+            params: group.params_id(),
+            h_caret: pk.clone(),
+            key_proof: cl_key_proof.clone(),
+            h: pk.clone(),
+            ec_pk: ec_pk.clone(),
+            bases: DerivedBases::derive(&cl),
+            commitment: dl_com.commitment.clone(),
+        });
+        let keygen_three = MultiKeyGenMessage::PhaseThreeMsg(KeyGenPhaseThreeMsg {
+            open: dl_com.open.clone(),
+        });
+        let keygen_five = MultiKeyGenMessage::PhaseFiveMsg(KeyGenPhaseFiveMsg {
+            dl_proof: dl_proof.clone(),
+        });
+
+        let sign_one = MultiSignMessage::PhaseOneMsg(SignPhaseOneMsg {
+            params: group.params_id(),
+            commitment: dl_com.commitment.clone(),
+            promise_state,
+            proof: promise_proof.clone(),
+        });
+        let sign_two = MultiSignMessage::PhaseTwoMsg(SignPhaseTwoMsg {
+            params: group.params_id(),
+            homocipher: ciphertext.clone(),
+            homocipher_plus: rerandomized,
+            t_p: FE::random(),
+            t_p_plus: FE::random(),
+            b: ec_pk.clone(),
+        });
+        let sign_three = MultiSignMessage::PhaseThreeMsg(SignPhaseThreeMsg { delta: m.clone() });
+        let sign_four = MultiSignMessage::PhaseFourMsg(SignPhaseFourMsg {
+            open: dl_com.open.clone(),
+            dl_proof: dl_proof.clone(),
+        });
+
+        let base = GE::generator();
+        let l_i = FE::random();
+        let rho_i = FE::random();
+        let v_i = &ec_pk * &m + base * &l_i;
+        let a_i = base * &rho_i;
+        let b_i = base * &(&l_i * &rho_i);
+        let homo_proof = HomoELGamalProof::prove(
+            &HomoElGamalWitness {
+                r: l_i.clone(),
+                x: m.clone(),
+            },
+            &HomoElGamalStatement {
+                G: a_i.clone(),
+                H: ec_pk.clone(),
+                Y: base.to_point(),
+                D: v_i.clone(),
+                E: b_i.clone(),
+            },
+        );
+        let step_commitment = MultiSignMessage::PhaseFiveStepOneMsg(SignPhaseFiveStepOneMsg {
+            commitment: dl_com.commitment.clone(),
+        });
+        let step_two = MultiSignMessage::PhaseFiveStepTwoMsg(SignPhaseFiveStepTwoMsg {
+            v_i,
+            a_i,
+            b_i,
+            blind: BigInt::sample(SECURITY_BITS),
+            dl_proof: DLogProof::prove(&rho_i),
+            proof: homo_proof,
+        });
+        let step_five = MultiSignMessage::PhaseFiveStepFiveMsg(SignPhaseFiveStepFiveMsg {
+            blind: BigInt::sample(SECURITY_BITS),
+            u_i: ec_pk.clone(),
+            t_i: ec_pk,
+        });
+        let step_seven =
+            MultiSignMessage::PhaseFiveStepSevenMsg(SignPhaseFiveStepSevenMsg { s_i: m, l_i });
+
+        Ok(Self {
+            level,
+            cl_element: size_of(&ciphertext.c1),
+            cl_public_key: size_of(&pk),
+            ciphertext: size_of(&ciphertext),
+            cl_proof: size_of(&cl_proof),
+            cl_dl_proof: size_of(&cl_dl_proof),
+            cl_key_proof: size_of(&cl_key_proof),
+            promise_proof: size_of(&promise_proof),
+            zero_enc_proof: size_of(&zero_enc_proof),
+            keygen_phase_one_two: size_of(&keygen_one_two),
+            keygen_phase_three: size_of(&keygen_three),
+            keygen_phase_five: size_of(&keygen_five),
+            sign_phase_one: size_of(&sign_one),
+            sign_phase_two: size_of(&sign_two),
+            sign_phase_three: size_of(&sign_three),
+            sign_phase_four: size_of(&sign_four),
+            sign_phase_five_step_one: size_of(&step_commitment),
+            sign_phase_five_step_two: size_of(&step_two),
+            // Steps one and four carry the same kind of commitment.
+            sign_phase_five_step_four: size_of(&step_commitment),
+            sign_phase_five_step_five: size_of(&step_five),
+            sign_phase_five_step_seven: size_of(&step_seven),
+        })
+    }
+}
+
+#[test]
+fn test_size_report_within_caps() {
+    use crate::protocols::multi_party::dmz21::negotiate::PARAM_SETS;
+
+    for level in PARAM_SETS {
+        let report = SizeReport::for_level(*level).unwrap();
+        let caps = level.caps();
+        assert!(report.ciphertext <= 2 * caps.cl_element);
+        assert!(report.cl_element <= caps.cl_element);
+        assert!(report.keygen_phase_one_two <= caps.keygen_phase_one_two());
+        assert!(report.sign_phase_one <= caps.sign_phase_one());
+        assert!(report.sign_phase_two <= caps.sign_phase_two());
+        assert!(report.sign_phase_five_step_two <= SIGN_PHASE_FIVE_STEP_TWO_MAX_SIZE);
+        assert!(report.sign_phase_five_step_seven <= SIGN_PHASE_FIVE_STEP_SEVEN_MAX_SIZE);
+    }
+}
@@ -0,0 +1,178 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Typestate wrapper around `SignPhase` and `SignPhaseOnline`.
+//!
+//! `Sign<Offline>` only accepts offline rounds and becomes `Sign<Presigned>`
+//! when they complete. Only a presignature can start the online phase, and
+//! only `Sign<Signed>` hands out a signature, so starting online sign early
+//! or reading a result that does not exist yet no longer compiles. Messages
+//! of the wrong stage are rejected by their variant tag before decoding.
+use crate::communication::delivery::round_of;
+use crate::communication::sending_messages::SendingMessages;
+use crate::protocols::multi_party::dmz21::common::Parameters;
+use crate::protocols::multi_party::dmz21::sign::{SignPhase, SignPhaseOnline};
+use crate::utilities::signature::SignatureX;
+use anyhow::{anyhow, format_err};
+use std::ops::RangeInclusive;
+
+/// Wire indices of the `MultiSignMessage` variants of each stage.
+const OFFLINE_VARIANTS: RangeInclusive<u32> = 0..=3;
+const ONLINE_VARIANTS: RangeInclusive<u32> = 4..=8;
+
+#[derive(Debug)]
+pub struct Offline(SignPhase);
+
+#[derive(Debug)]
+pub struct Presigned(String);
+
+#[derive(Debug)]
+pub struct Online(SignPhaseOnline);
+
+#[derive(Debug)]
+pub struct Signed(SignatureX);
+
+#[derive(Debug)]
+pub struct Sign<S> {
+    state: S,
+}
+
+/// Outcome of handling one message: still in the same stage, with the
+/// messages to send, or moved on to the next one.
+#[derive(Debug)]
+pub enum Step<Current, Next> {
+    Continue(Current, SendingMessages),
+    Done(Next),
+}
+
+fn check_stage(
+    msg: &[u8],
+    variants: &RangeInclusive<u32>,
+    stage: &str,
+) -> Result<(), anyhow::Error> {
+    match round_of(msg) {
+        Some(variant) if variants.contains(&variant) => Ok(()),
+        Some(variant) => Err(anyhow!(
+            "Message variant {} does not belong to sign {}",
+            variant,
+            stage
+        )),
+        None => Err(anyhow!("Message too short in sign {}", stage)),
+    }
+}
+
+impl Sign<Offline> {
+    /// Start offline sign; returns the first round message to send.
+    pub fn new(
+        partyid: String,
+        params: Parameters,
+        subset: &Vec<String>,
+        keys: &String,
+    ) -> Result<(Self, SendingMessages), anyhow::Error> {
+        Self::from_phase(SignPhase::new(partyid, params, subset, keys)?)
+    }
+
+    /// Wrap a configured `SignPhase` that has not been started yet.
+    pub fn from_phase(mut phase: SignPhase) -> Result<(Self, SendingMessages), anyhow::Error> {
+        let sending = phase.process_begin()?;
+        Ok((
+            Self {
+                state: Offline(phase),
+            },
+            sending,
+        ))
+    }
+
+    pub fn handle(
+        mut self,
+        index: String,
+        msg: &Vec<u8>,
+    ) -> Result<Step<Self, Sign<Presigned>>, anyhow::Error> {
+        check_stage(msg, &OFFLINE_VARIANTS, "offline")?;
+        match self.state.0.msg_handler(index, msg)? {
+            SendingMessages::SignOfflineSuccessWithResult(result) => Ok(Step::Done(Sign {
+                state: Presigned(result),
+            })),
+            sending => Ok(Step::Continue(self, sending)),
+        }
+    }
+}
+
+impl Sign<Presigned> {
+    /// Resume from an offline result stored earlier.
+    pub fn from_offline_result(result: String) -> Self {
+        Self {
+            state: Presigned(result),
+        }
+    }
+
+    pub fn offline_result(&self) -> &String {
+        &self.state.0
+    }
+
+    /// Consume the presignature to sign `message_bytes`; returns the first
+    /// online message to send.
+    pub fn start(
+        self,
+        message_bytes: Vec<u8>,
+    ) -> Result<(Sign<Online>, SendingMessages), anyhow::Error> {
+        let mut phase = SignPhaseOnline::new(&self.state.0, message_bytes)?;
+        let sending = phase.process_begin()?;
+        Ok((
+            Sign {
+                state: Online(phase),
+            },
+            sending,
+        ))
+    }
+}
+
+impl Sign<Online> {
+    pub fn handle(
+        mut self,
+        index: String,
+        msg: &Vec<u8>,
+    ) -> Result<Step<Self, Sign<Signed>>, anyhow::Error> {
+        check_stage(msg, &ONLINE_VARIANTS, "online")?;
+        match self.state.0.msg_handler(index, msg)? {
+            SendingMessages::SignOnlineSuccessWithResult(json) => {
+                let signature: SignatureX = serde_json::from_str(&json).map_err(|why| {
+                    format_err!("From string failed in sign online result, cause {}", why)
+                })?;
+                Ok(Step::Done(Sign {
+                    state: Signed(signature),
+                }))
+            }
+            sending => Ok(Step::Continue(self, sending)),
+        }
+    }
+
+    /// The underlying state machine, e.g. to approve a parked signature.
+    pub fn phase_mut(&mut self) -> &mut SignPhaseOnline {
+        &mut self.state.0
+    }
+}
+
+impl Sign<Signed> {
+    pub fn signature(&self) -> &SignatureX {
+        &self.state.0
+    }
+
+    pub fn into_signature(self) -> SignatureX {
+        self.state.0
+    }
+}
@@ -0,0 +1,210 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Borrowed views of received round messages.
+//!
+//! `MultiSignMessage::decode` allocates an `Mpz` for every coefficient of
+//! every class group element before any of them can be checked. A view
+//! checks the variant and size cap on the raw buffer, and phase-two
+//! ciphertexts can be inspected as hex slices of that buffer, so a burst of
+//! bad messages is rejected without allocating for its integers.
+use crate::communication::delivery::round_of;
+use crate::protocols::multi_party::dmz21::message::*;
+use crate::utilities::class_group::Ciphertext;
+use crate::utilities::params_id::ParamsId;
+use anyhow::format_err;
+use bincode::Options;
+use classgroup::prelude::{GmpClassGroup, Mpz};
+use serde::Deserialize;
+
+/// A sign message whose variant and size cap have been checked.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageView<'a> {
+    pub variant: u32,
+    pub bytes: &'a [u8],
+    pub caps: MessageCaps,
+}
+
+impl<'a> MessageView<'a> {
+    /// Check `bytes` against the caps of the session's parameter set.
+    pub fn parse(bytes: &'a [u8], caps: &MessageCaps) -> Result<Self, anyhow::Error> {
+        let variant = round_of(bytes).ok_or(format_err!("Message too short for a variant tag"))?;
+        let cap = MultiSignMessage::max_encoded_size(variant, caps)
+            .ok_or(format_err!("Unknown sign message variant {}", variant))?;
+        if bytes.len() > cap {
+            return Err(format_err!(
+                "Message of {} bytes exceeds the cap of {} bytes",
+                bytes.len(),
+                cap
+            ));
+        }
+        Ok(Self {
+            variant,
+            bytes,
+            caps: *caps,
+        })
+    }
+
+    /// The ciphertexts of a phase-two message, borrowed from the buffer,
+    /// if the message is of the caps' parameter set.
+    pub fn phase_two(&self) -> Result<SignPhaseTwoView<'a>, anyhow::Error> {
+        if self.variant != 1 {
+            return Err(format_err!(
+                "Variant {} is not a phase two message",
+                self.variant
+            ));
+        }
+        let view: SignPhaseTwoView = bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(self.bytes.len() as u64)
+            .deserialize(&self.bytes[4..])
+            .map_err(|why| format_err!("Deserialize error of phase two view, cause {}", why))?;
+        self.caps.check_params(&view.params)?;
+        Ok(view)
+    }
+
+    /// Fully decode the message.
+    pub fn decode(&self) -> Result<MultiSignMessage, anyhow::Error> {
+        MultiSignMessage::decode(self.bytes, &self.caps)
+    }
+}
+
+/// A class group element as the hex strings it is serialized as.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ClassGroupView<'a> {
+    pub a: &'a str,
+    pub b: &'a str,
+    pub c: &'a str,
+    pub discriminant: &'a str,
+}
+
+impl<'a> ClassGroupView<'a> {
+    /// Reject an element of another group, or with coefficients longer than
+    /// the discriminant. `discriminant` is the hex encoding of the expected
+    /// discriminant. The digits themselves are checked by `to_owned`.
+    pub fn check(&self, discriminant: &str) -> Result<(), anyhow::Error> {
+        if self.discriminant != discriminant {
+            return Err(format_err!("Class group element of another discriminant"));
+        }
+        for coeff in [self.a, self.b, self.c].iter() {
+            if coeff.len() > discriminant.len() {
+                return Err(format_err!("Malformed class group element coefficient"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the coefficients in the canonical encoding only, each no
+    /// longer than the discriminant, into a reduced form of it.
+    pub fn to_owned(&self) -> Result<GmpClassGroup, anyhow::Error> {
+        let max_bits = 4 * self.discriminant.len();
+        let parse = |s: &str| {
+            Mpz::from_canonical_hex(s, max_bits)
+                .map_err(|why| format_err!("Invalid class group element coefficient, {}", why))
+        };
+        let discriminant = parse(self.discriminant)?;
+        GmpClassGroup::from_raw(
+            parse(self.a)?,
+            parse(self.b)?,
+            parse(self.c)?,
+            &discriminant,
+        )
+        .ok_or_else(|| format_err!("Class group element is not a reduced form"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct CiphertextView<'a> {
+    #[serde(borrow)]
+    pub c1: ClassGroupView<'a>,
+    #[serde(borrow)]
+    pub c2: ClassGroupView<'a>,
+}
+
+impl<'a> CiphertextView<'a> {
+    pub fn check(&self, discriminant: &str) -> Result<(), anyhow::Error> {
+        self.c1.check(discriminant)?;
+        self.c2.check(discriminant)
+    }
+
+    pub fn to_owned(&self) -> Result<Ciphertext, anyhow::Error> {
+        Ok(Ciphertext {
+            c1: self.c1.to_owned()?,
+            c2: self.c2.to_owned()?,
+        })
+    }
+}
+
+/// The leading fields of `SignPhaseTwoMsg`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct SignPhaseTwoView<'a> {
+    pub params: ParamsId,
+    #[serde(borrow)]
+    pub homocipher: CiphertextView<'a>,
+    #[serde(borrow)]
+    pub homocipher_plus: CiphertextView<'a>,
+}
+
+impl<'a> SignPhaseTwoView<'a> {
+    pub fn check(&self, discriminant: &str) -> Result<(), anyhow::Error> {
+        self.homocipher.check(discriminant)?;
+        self.homocipher_plus.check(discriminant)
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_phase_two_view() {
+    use crate::protocols::multi_party::dmz21::common::FE;
+    use crate::utilities::class_group::{CLGroup, GROUP_UPDATE_1827};
+
+    let group = &GROUP_UPDATE_1827;
+    let (_, pk) = group.keygen();
+    let mut msg = SignPhaseTwoMsg::new(group.params_id());
+    msg.homocipher = CLGroup::encrypt(group, &pk, &FE::random()).0;
+    msg.homocipher_plus = CLGroup::encrypt(group, &pk, &FE::random()).0;
+    let bytes = bincode::serialize(&MultiSignMessage::PhaseTwoMsg(msg.clone())).unwrap();
+
+    let discriminant = group.generator.discriminant.to_str_radix(16);
+    let caps = MessageCaps::for_group(group);
+    let view = MessageView::parse(&bytes, &caps)
+        .unwrap()
+        .phase_two()
+        .unwrap();
+    view.check(&discriminant).unwrap();
+    assert_eq!(view.homocipher.to_owned().unwrap(), msg.homocipher);
+    assert_eq!(
+        view.homocipher_plus.to_owned().unwrap(),
+        msg.homocipher_plus
+    );
+
+    let other = GROUP_UPDATE_1827.delta_k.to_str_radix(16);
+    assert!(view.check(&other).is_err());
+
+    // A leading zero passes the length check but is not the canonical form.
+    let padded = format!("0{}", view.homocipher.c1.a);
+    let mut noncanonical = view.homocipher.c1;
+    noncanonical.a = &padded;
+    assert!(noncanonical.check(&discriminant).is_ok());
+    assert!(noncanonical.to_owned().is_err());
+
+    // Coefficients that are not a form of the claimed discriminant.
+    let mut foreign = view.homocipher.c1;
+    foreign.discriminant = &other;
+    assert!(foreign.to_owned().is_err());
+}
@@ -0,0 +1,162 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Addresses derived from the joint public key, or from any child key
+//! derived from it, so that integrators do not recompute them from a
+//! second copy of the key.
+use crate::utilities::chain::{hash160, keccak_address};
+use crate::utilities::error::MulEcdsaError;
+use crate::{FE, GE};
+use bech32::{u5, ToBase32, Variant};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BtcNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl BtcNetwork {
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            BtcNetwork::Mainnet => "bc",
+            BtcNetwork::Testnet => "tb",
+            BtcNetwork::Regtest => "bcrt",
+        }
+    }
+}
+
+pub trait ChainAddress {
+    /// `0x`-prefixed with the EIP-55 mixed-case checksum.
+    fn to_eth_address(&self) -> String;
+
+    /// Segwit v0 over `hash160` of the compressed key (BIP-173).
+    fn to_btc_p2wpkh(&self, network: BtcNetwork) -> Result<String, MulEcdsaError>;
+
+    /// Segwit v1 key-path-only output (BIP-86): the key is tweaked with
+    /// `tagged_hash("TapTweak", x)` and its x coordinate is encoded with
+    /// bech32m (BIP-350).
+    fn to_btc_p2tr(&self, network: BtcNetwork) -> Result<String, MulEcdsaError>;
+}
+
+pub(crate) fn segwit_address(
+    network: BtcNetwork,
+    version: u8,
+    program: &[u8],
+    variant: Variant,
+) -> Result<String, MulEcdsaError> {
+    let mut data = vec![u5::try_from_u8(version).map_err(|_| MulEcdsaError::ToStringFailed)?];
+    data.extend(program.to_base32());
+    bech32::encode(network.hrp(), data, variant).map_err(|_| MulEcdsaError::ToStringFailed)
+}
+
+fn tagged_hash(tag: &[u8], msg: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(
+        &Sha256::new()
+            .chain(&tag_hash)
+            .chain(&tag_hash)
+            .chain(msg)
+            .finalize(),
+    );
+    out
+}
+
+/// The BIP-86 output key: the internal key lifted to even y, plus
+/// `tagged_hash("TapTweak", x) * G`.
+pub fn taproot_output_key(pubkey: &GE) -> Result<GE, MulEcdsaError> {
+    let y = pubkey.y_coord().ok_or(MulEcdsaError::InvalidPublicKey)?;
+    let even = if y.is_odd() {
+        pubkey * &(FE::zero() - FE::from_bigint(&BigInt::one()))
+    } else {
+        pubkey.clone()
+    };
+    let x_only = &even.to_bytes(true)[1..];
+    let tweak = FE::from_bigint(&BigInt::from_bytes(&tagged_hash(b"TapTweak", x_only)));
+    Ok(even + GE::generator() * tweak)
+}
+
+impl ChainAddress for GE {
+    fn to_eth_address(&self) -> String {
+        let lower = hex::encode(keccak_address(self));
+        let checksum = Keccak256::digest(lower.as_bytes());
+        let mixed: String = lower
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let nibble = (checksum[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        format!("0x{}", mixed)
+    }
+
+    fn to_btc_p2wpkh(&self, network: BtcNetwork) -> Result<String, MulEcdsaError> {
+        segwit_address(network, 0, &hash160(self), Variant::Bech32)
+    }
+
+    fn to_btc_p2tr(&self, network: BtcNetwork) -> Result<String, MulEcdsaError> {
+        let output_key = taproot_output_key(self)?;
+        segwit_address(
+            network,
+            1,
+            &output_key.to_bytes(true)[1..],
+            Variant::Bech32m,
+        )
+    }
+}
+
+#[test]
+fn address_test() {
+    let key_one = GE::generator() * FE::from_bigint(&BigInt::one());
+    assert_eq!(
+        key_one.to_eth_address(),
+        "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+    );
+    assert_eq!(
+        key_one.to_btc_p2wpkh(BtcNetwork::Mainnet).unwrap(),
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+    );
+    assert_eq!(
+        key_one.to_btc_p2wpkh(BtcNetwork::Testnet).unwrap(),
+        "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+    );
+
+    // BIP-86, m/86'/0'/0'/0/0.
+    let internal = GE::from_bytes(
+        &hex::decode("02cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115").unwrap(),
+    )
+    .unwrap();
+    let output_key = taproot_output_key(&internal).unwrap();
+    assert_eq!(
+        hex::encode(&output_key.to_bytes(true)[1..]),
+        "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+    );
+    assert_eq!(
+        internal.to_btc_p2tr(BtcNetwork::Mainnet).unwrap(),
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+    );
+}
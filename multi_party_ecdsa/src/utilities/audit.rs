@@ -0,0 +1,412 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Structured audit trail of the key lifecycle.
+//!
+//! Protocol objects that have an `AuditLog` attached emit an `AuditEvent`
+//! when they produce a long-lived artifact: a key share at the end of
+//! keygen, a presignature at the end of offline sign and a signature at the
+//! end of online sign, plus the approval token when the release of a
+//! signature share was gated by an approver. Records are numbered, chained
+//! by hash and optionally signed with an ECDSA key of the log, so an auditor
+//! can check that none was dropped or altered. Where the records go is up
+//! to the `AuditSink`; `JsonlFileSink` appends one JSON object per line.
+//!
+//! Emission happens before the artifact is returned. If the sink fails, the
+//! protocol step fails too, so nothing is released without a record. The
+//! same holds for the external anchor of a signature, see `timestamp`.
+use crate::utilities::clock::{self, Clock};
+use crate::utilities::eckeypair::EcKeyPair;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::payload::DecodedPayload;
+use crate::utilities::signature::{Signature, SignatureX};
+use crate::utilities::timestamp::{anchor_digest, TimestampAuthority};
+use crate::{FE, GE};
+use curv::arithmetic::traits::*;
+use curv::cryptographic_primitives::hashing::{Digest, DigestExt};
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuditEvent {
+    KeyCreated {
+        party_index: String,
+        participants: Vec<String>,
+        threshold: usize,
+        public_key: String,
+    },
+    PresignCompleted {
+        party_index: String,
+        subset: Vec<String>,
+        public_key: String,
+    },
+    SignApproved {
+        party_index: String,
+        message: String,
+        approval: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        payload: Option<DecodedPayload>,
+    },
+    PartyRevoked {
+        party_index: String,
+        reason: String,
+    },
+    /// Secret material deleted by housekeeping, e.g. expired presignatures.
+    SecretsDeleted {
+        kind: String,
+        reason: String,
+        count: u64,
+    },
+    SignatureProduced {
+        party_index: String,
+        subset: Vec<String>,
+        public_key: String,
+        message: String,
+        r: String,
+        s: String,
+    },
+    /// Receipt of a `TimestampAuthority` for the record numbered `seq`.
+    TimestampAnchored {
+        seq: u64,
+        hash: String,
+        authority: String,
+        receipt: String,
+    },
+}
+
+/// One line of the audit trail.
+/// `prev_hash` is the `hash` of the previous record of the same log, empty
+/// for the first one. `signature` covers `hash`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: i64,
+    pub event: AuditEvent,
+    pub request_context: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+    pub signature: Option<SignatureX>,
+}
+
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord) -> Result<(), MulEcdsaError>;
+}
+
+/// Appends records to a file, one JSON object per line, flushed per record.
+pub struct JsonlFileSink {
+    file: Mutex<File>,
+}
+
+/// Keeps records in memory; meant for tests and for callers that forward
+/// records elsewhere themselves.
+#[derive(Default)]
+pub struct MemorySink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+/// Numbers, chains and signs events before handing them to a sink.
+/// Share one log (behind an `Arc`) between all sessions of a key.
+pub struct AuditLog {
+    sink: Box<dyn AuditSink>,
+    signer: Option<EcKeyPair>,
+    head: Mutex<(u64, String)>,
+    authority: Option<Arc<dyn TimestampAuthority>>,
+    clock: Arc<dyn Clock>,
+}
+
+pub fn point_to_hex(point: &GE) -> String {
+    hex::encode(point.to_bytes(true).as_ref())
+}
+
+impl AuditRecord {
+    fn digest(&self) -> Result<BigInt, MulEcdsaError> {
+        let body = serde_json::to_vec(&(
+            self.seq,
+            self.timestamp,
+            &self.event,
+            &self.request_context,
+            &self.prev_hash,
+        ))
+        .map_err(|_| MulEcdsaError::SerializeFailed)?;
+        Ok(Sha256::new().chain(&body).result_bigint())
+    }
+
+    /// Check the record hash and, if `signer` is given, its signature.
+    pub fn verify(&self, signer: Option<&GE>) -> Result<(), MulEcdsaError> {
+        let digest = self.digest()?;
+        if digest.to_hex() != self.hash {
+            return Err(MulEcdsaError::VrfyAuditRecordFailed);
+        }
+        if let Some(pk) = signer {
+            let sig = self
+                .signature
+                .as_ref()
+                .ok_or(MulEcdsaError::VrfyAuditRecordFailed)?;
+            let signature = Signature {
+                s: FE::from_bigint(
+                    &BigInt::from_hex(&sig.s).map_err(|_| MulEcdsaError::FromHexFailed)?,
+                ),
+                r: FE::from_bigint(
+                    &BigInt::from_hex(&sig.r).map_err(|_| MulEcdsaError::FromHexFailed)?,
+                ),
+                recid: sig.recid,
+            };
+            signature
+                .verify(pk, &FE::from_bigint(&digest))
+                .map_err(|_| MulEcdsaError::VrfyAuditRecordFailed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `records` form one unbroken chain starting at sequence 0.
+pub fn verify_chain(records: &[AuditRecord], signer: Option<&GE>) -> Result<(), MulEcdsaError> {
+    let mut prev_hash = String::new();
+    for (i, record) in records.iter().enumerate() {
+        if record.seq != i as u64 || record.prev_hash != prev_hash {
+            return Err(MulEcdsaError::VrfyAuditRecordFailed);
+        }
+        record.verify(signer)?;
+        prev_hash = record.hash.clone();
+    }
+    Ok(())
+}
+
+impl JsonlFileSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MulEcdsaError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|_| MulEcdsaError::AuditFailed)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for JsonlFileSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), MulEcdsaError> {
+        let mut line = serde_json::to_vec(record).map_err(|_| MulEcdsaError::SerializeFailed)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().map_err(|_| MulEcdsaError::AuditFailed)?;
+        file.write_all(&line)
+            .and_then(|_| file.flush())
+            .map_err(|_| MulEcdsaError::AuditFailed)
+    }
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn record(&self, record: &AuditRecord) -> Result<(), MulEcdsaError> {
+        self.records
+            .lock()
+            .map_err(|_| MulEcdsaError::AuditFailed)?
+            .push(record.clone());
+        Ok(())
+    }
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for Arc<S> {
+    fn record(&self, record: &AuditRecord) -> Result<(), MulEcdsaError> {
+        (**self).record(record)
+    }
+}
+
+impl AuditLog {
+    /// `signer` signs every record; pass `None` to rely on the hash chain only.
+    pub fn new(sink: Box<dyn AuditSink>, signer: Option<EcKeyPair>) -> Self {
+        Self {
+            sink,
+            signer,
+            head: Mutex::new((0, String::new())),
+            authority: None,
+            clock: clock::system(),
+        }
+    }
+
+    /// Take record timestamps from `clock` instead of the host.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Anchor every `SignatureProduced` record with `authority`.
+    pub fn with_timestamp_authority(mut self, authority: Arc<dyn TimestampAuthority>) -> Self {
+        self.authority = Some(authority);
+        self
+    }
+
+    pub fn signer_public_key(&self) -> Option<&GE> {
+        self.signer.as_ref().map(|signer| signer.get_public_key())
+    }
+
+    /// Number, chain, sign and store `event`.
+    /// The chain only advances if the sink accepted the record.
+    pub fn emit(
+        &self,
+        event: AuditEvent,
+        request_context: Option<String>,
+    ) -> Result<AuditRecord, MulEcdsaError> {
+        let anchored = matches!(event, AuditEvent::SignatureProduced { .. });
+        let record = self.append(event, request_context)?;
+        if anchored && self.authority.is_some() {
+            self.anchor(&record)?;
+        }
+        Ok(record)
+    }
+
+    /// Have `record` timestamped by the attached authority and store the
+    /// receipt as a record of its own.
+    pub fn anchor(&self, record: &AuditRecord) -> Result<AuditRecord, MulEcdsaError> {
+        let authority = self
+            .authority
+            .as_ref()
+            .ok_or(MulEcdsaError::TimestampFailed)?;
+        let receipt = authority
+            .timestamp(&anchor_digest(record)?)
+            .map_err(|_| MulEcdsaError::TimestampFailed)?;
+        let event = AuditEvent::TimestampAnchored {
+            seq: record.seq,
+            hash: record.hash.clone(),
+            authority: authority.name().to_string(),
+            receipt: hex::encode(receipt),
+        };
+        self.append(event, record.request_context.clone())
+    }
+
+    fn append(
+        &self,
+        event: AuditEvent,
+        request_context: Option<String>,
+    ) -> Result<AuditRecord, MulEcdsaError> {
+        let mut head = self.head.lock().map_err(|_| MulEcdsaError::AuditFailed)?;
+        let mut record = AuditRecord {
+            seq: head.0,
+            timestamp: self.clock.unix_secs(),
+            event,
+            request_context,
+            prev_hash: head.1.clone(),
+            hash: String::new(),
+            signature: None,
+        };
+        let digest = record.digest()?;
+        record.hash = digest.to_hex();
+        if let Some(signer) = &self.signer {
+            let signature = Signature::sign(signer.get_secret_key(), &FE::from_bigint(&digest));
+            record.signature = Some(signature.to_hex());
+        }
+        self.sink.record(&record)?;
+        *head = (head.0 + 1, record.hash.clone());
+        Ok(record)
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seq = self.head.lock().map(|head| head.0).unwrap_or_default();
+        f.debug_struct("AuditLog")
+            .field("next_seq", &seq)
+            .field("signed", &self.signer.is_some())
+            .field("anchored", &self.authority.is_some())
+            .finish()
+    }
+}
+
+#[test]
+fn audit_log_test() {
+    let sink = Arc::new(MemorySink::new());
+    let signer = EcKeyPair::new();
+    let signer_pk = signer.get_public_key().clone();
+    let log = AuditLog::new(Box::new(sink.clone()), Some(signer));
+    let public_key = point_to_hex(&GE::generator().to_point());
+    log.emit(
+        AuditEvent::KeyCreated {
+            party_index: "1".to_string(),
+            participants: vec!["1".to_string(), "2".to_string()],
+            threshold: 1,
+            public_key: public_key.clone(),
+        },
+        None,
+    )
+    .unwrap();
+    log.emit(
+        AuditEvent::PresignCompleted {
+            party_index: "1".to_string(),
+            subset: vec!["1".to_string(), "2".to_string()],
+            public_key,
+        },
+        Some("request-42".to_string()),
+    )
+    .unwrap();
+
+    let mut records = sink.records();
+    verify_chain(&records, Some(&signer_pk)).unwrap();
+    records[1].request_context = None;
+    assert!(verify_chain(&records, Some(&signer_pk)).is_err());
+}
+
+#[test]
+fn timestamp_anchor_test() {
+    use crate::utilities::clock::ManualClock;
+    use crate::utilities::timestamp::{verify_anchor, LocalTimestampAuthority};
+
+    let sink = Arc::new(MemorySink::new());
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let authority = LocalTimestampAuthority::new("notary".to_string(), EcKeyPair::new());
+    let authority = Arc::new(authority.with_clock(clock));
+    let log =
+        AuditLog::new(Box::new(sink.clone()), None).with_timestamp_authority(authority.clone());
+    let event = AuditEvent::SignatureProduced {
+        party_index: "1".to_string(),
+        subset: vec!["1".to_string(), "2".to_string()],
+        public_key: point_to_hex(&GE::generator().to_point()),
+        message: "2a".to_string(),
+        r: "1".to_string(),
+        s: "2".to_string(),
+    };
+    log.emit(event, None).unwrap();
+
+    let mut records = sink.records();
+    assert_eq!(records.len(), 2);
+    verify_chain(&records, None).unwrap();
+    let time = verify_anchor(&records, 0, authority.as_ref()).unwrap();
+    assert_eq!(time, 1_700_000_000);
+
+    // The anchor no longer matches an altered record.
+    records[0].hash = records[1].hash.clone();
+    assert!(verify_anchor(&records, 0, authority.as_ref()).is_err());
+    let other = LocalTimestampAuthority::new("notary".to_string(), EcKeyPair::new());
+    assert!(verify_anchor(&sink.records(), 0, &other).is_err());
+}
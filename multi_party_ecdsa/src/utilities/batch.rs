@@ -0,0 +1,74 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Locating the invalid items of a failed batch verification.
+//!
+//! A batch check accepts only if every item would, but a rejection does not
+//! say which item is at fault. `isolate_failures` splits a rejected batch
+//! in halves and checks each again, down to single items, which are checked
+//! on their own. With `b` bad items among `n` that takes `O(b log n)` batch
+//! checks, and a batch of honest items costs one check as before.
+
+/// Indices in `0..count` whose `single` check fails, in ascending order.
+/// `batch` decides a subset at once and must accept every subset of valid
+/// items.
+pub fn isolate_failures<B, S>(count: usize, mut batch: B, mut single: S) -> Vec<usize>
+where
+    B: FnMut(&[usize]) -> bool,
+    S: FnMut(usize) -> bool,
+{
+    let all: Vec<usize> = (0..count).collect();
+    let mut failed = Vec::new();
+    if all.is_empty() || batch(&all) {
+        return failed;
+    }
+    let mut rejected = vec![all];
+    while let Some(indices) = rejected.pop() {
+        if indices.len() == 1 {
+            if !single(indices[0]) {
+                failed.push(indices[0]);
+            }
+            continue;
+        }
+        let (left, right) = indices.split_at(indices.len() / 2);
+        for half in [right, left].iter() {
+            if half.len() == 1 || !batch(half) {
+                rejected.push(half.to_vec());
+            }
+        }
+    }
+    failed.sort_unstable();
+    failed
+}
+
+#[test]
+fn isolate_failures_test() {
+    let bad = [3usize, 4, 17];
+    let mut batches = 0;
+    let failed = isolate_failures(
+        20,
+        |subset| {
+            batches += 1;
+            subset.iter().all(|i| !bad.contains(i))
+        },
+        |i| !bad.contains(&i),
+    );
+    assert_eq!(failed, bad.to_vec());
+    assert!(batches < 20);
+    assert!(isolate_failures(5, |_| true, |_| false).is_empty());
+    assert!(isolate_failures(0, |_| false, |_| false).is_empty());
+}
@@ -0,0 +1,183 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Per-chain rules around a threshold secp256k1 signature.
+//!
+//! The protocol signs a 32-byte digest and returns `(r, s, recid)`. What
+//! gets hashed, how the signature is laid out and how an address is derived
+//! from the joint key differ per chain, and a `Chain` implementation holds
+//! exactly those three rules. Adding a chain means adding one impl and its
+//! test vectors.
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::SignatureX;
+use crate::GE;
+use bech32::{ToBase32, Variant};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+pub trait Chain {
+    fn name(&self) -> &'static str;
+
+    /// The digest to hand to the online sign phase for a serialized
+    /// transaction (or sign doc).
+    fn signing_hash(&self, payload: &[u8]) -> [u8; 32];
+
+    /// The signature as the chain expects it next to the payload.
+    fn encode_signature(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError>;
+
+    fn address(&self, pubkey: &GE) -> Result<String, MulEcdsaError>;
+}
+
+/// `r || s`, each left-padded to 32 bytes.
+pub fn signature_rs(sig: &SignatureX) -> Result<[u8; 64], MulEcdsaError> {
+    let mut out = [0u8; 64];
+    for (digits, slot) in [&sig.r, &sig.s].iter().zip(out.chunks_mut(32)) {
+        let bytes = BigInt::from_hex(digits)
+            .map_err(|_| MulEcdsaError::FromHexFailed)?
+            .to_bytes();
+        if bytes.len() > 32 {
+            return Err(MulEcdsaError::FromHexFailed);
+        }
+        slot[32 - bytes.len()..].copy_from_slice(&bytes);
+    }
+    Ok(out)
+}
+
+/// The last 20 bytes of Keccak-256 over the uncompressed key without its
+/// `0x04` prefix, as used by Ethereum and Tron.
+pub fn keccak_address(pubkey: &GE) -> [u8; 20] {
+    let uncompressed = pubkey.to_bytes(false);
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&Keccak256::digest(&uncompressed[1..])[12..]);
+    out
+}
+
+/// RIPEMD-160 of SHA-256 of the compressed key.
+pub fn hash160(pubkey: &GE) -> [u8; 20] {
+    let compressed = pubkey.to_bytes(true);
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&Ripemd160::digest(&Sha256::digest(&compressed)));
+    out
+}
+
+fn sha256(payload: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Sha256::digest(payload));
+    out
+}
+
+/// Tron: the transaction id `sha256(raw_data)` is signed, signatures are
+/// `r || s || v` with `v = 27 + recid`, addresses are Base58Check over
+/// `0x41 || keccak_address`.
+pub struct Tron;
+
+const TRON_ADDRESS_PREFIX: u8 = 0x41;
+
+impl Chain for Tron {
+    fn name(&self) -> &'static str {
+        "tron"
+    }
+
+    fn signing_hash(&self, payload: &[u8]) -> [u8; 32] {
+        sha256(payload)
+    }
+
+    fn encode_signature(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError> {
+        if sig.recid > 1 {
+            return Err(MulEcdsaError::UnsupportedRecoveryId);
+        }
+        let mut out = signature_rs(sig)?.to_vec();
+        out.push(27 + sig.recid);
+        Ok(out)
+    }
+
+    fn address(&self, pubkey: &GE) -> Result<String, MulEcdsaError> {
+        let mut payload = vec![TRON_ADDRESS_PREFIX];
+        payload.extend_from_slice(&keccak_address(pubkey));
+        Ok(bs58::encode(payload).with_check().into_string())
+    }
+}
+
+/// Cosmos SDK chains: `sha256(sign_doc)` is signed, signatures are plain
+/// `r || s` with low `s`, addresses are bech32 over `hash160` under the
+/// chain's human-readable prefix (`cosmos`, `osmo`, ...).
+pub struct Cosmos {
+    pub hrp: String,
+}
+
+impl Chain for Cosmos {
+    fn name(&self) -> &'static str {
+        "cosmos"
+    }
+
+    fn signing_hash(&self, payload: &[u8]) -> [u8; 32] {
+        sha256(payload)
+    }
+
+    fn encode_signature(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError> {
+        Ok(signature_rs(sig)?.to_vec())
+    }
+
+    fn address(&self, pubkey: &GE) -> Result<String, MulEcdsaError> {
+        bech32::encode(&self.hrp, hash160(pubkey).to_base32(), Variant::Bech32)
+            .map_err(|_| MulEcdsaError::ToStringFailed)
+    }
+}
+
+#[cfg(test)]
+fn key_one() -> GE {
+    GE::generator() * crate::FE::from_bigint(&BigInt::one())
+}
+
+#[test]
+fn tron_chain_test() {
+    let address = Tron.address(&key_one()).unwrap();
+    assert!(address.starts_with('T'));
+    let decoded = bs58::decode(&address).with_check(None).into_vec().unwrap();
+    assert_eq!(
+        hex::encode(decoded),
+        "417e5f4552091a69125d5dfcb7b8c2659029395bdf"
+    );
+
+    let sig = SignatureX {
+        r: "1".to_string(),
+        s: "2".to_string(),
+        recid: 1,
+    };
+    let encoded = Tron.encode_signature(&sig).unwrap();
+    assert_eq!(encoded.len(), 65);
+    assert_eq!((encoded[31], encoded[63], encoded[64]), (1, 2, 28));
+}
+
+#[test]
+fn cosmos_chain_test() {
+    let chain = Cosmos {
+        hrp: "cosmos".to_string(),
+    };
+    let address = chain.address(&key_one()).unwrap();
+    let (hrp, data, variant) = bech32::decode(&address).unwrap();
+    assert_eq!((hrp.as_str(), variant), ("cosmos", Variant::Bech32));
+    let program = bech32::FromBase32::from_base32(&data).map(|b: Vec<u8>| hex::encode(b));
+    assert_eq!(program.unwrap(), "751e76e8199196d454941c45d1b3a323f1433bd6");
+    assert_eq!(
+        hex::encode(chain.signing_hash(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
@@ -0,0 +1,109 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Proof of knowledge of a CL secret key.
+//!
+//! A Schnorr proof in the class group that `pk = g^sk` for an `sk` the
+//! prover knows. The response is bounded, so the key extracted from a
+//! cheating prover is at most `2^(SECURITY_PARAMETER + 41)` times the
+//! `stilde * 2^40` bound `CLGroup::keygen` samples below. Keygen checks it
+//! on every `h_caret` before encrypting shares under `h = h_caret^q`.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::statement::StatementContext;
+use crate::utilities::SECURITY_PARAMETER;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CLKeyProof {
+    pub t: GmpClassGroup,
+    pub u: Mpz,
+}
+
+impl CLKeyProof {
+    pub fn prove(group: &CLGroup, sk: &SK, pk: &PK) -> Self {
+        let r = BigInt::sample_below(
+            &(&mpz_to_bigint(&group.stilde)
+                * BigInt::from(2u32).pow(40)
+                * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+                * BigInt::from(2u32).pow(40)),
+        );
+        let r = bigint_to_mpz(&r);
+        let mut t = group.generator.clone();
+        t.pow(r.clone());
+        let k = Self::challenge(group, pk, &t);
+        let u = r + &bigint_to_mpz(&k) * sk.0.expose();
+        Self { t, u }
+    }
+
+    /// Compute the Fiat-Shamir challenge for the proof.
+    pub fn challenge(group: &CLGroup, public_key: &PK, t: &GmpClassGroup) -> BigInt {
+        StatementContext::new(group)
+            .transcript(b"DMZ21-CL-KEY-PROOF")
+            .append(public_key)
+            .append(t)
+            .challenge()
+    }
+
+    pub fn verify(&self, group: &CLGroup, pk: &PK) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[&pk.0, &self.t])?;
+        let bound = &mpz_to_bigint(&group.stilde)
+            * BigInt::from(2u32).pow(40)
+            * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+            * (BigInt::from(2u32).pow(40) + BigInt::one());
+        if &self.u > &bigint_to_mpz(&bound) || &self.u < &Mpz::zero() {
+            return Err(MulEcdsaError::VrfyCLKeyProofFailed);
+        }
+
+        let k = Self::challenge(group, pk, &self.t);
+        let mut pkk = pk.0.clone();
+        pkk.pow_bigint(&k);
+        let mut gu = group.generator.clone();
+        gu.pow(self.u.clone());
+        if pkk * &self.t == gu {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::VrfyCLKeyProofFailed)
+        }
+    }
+}
+
+#[test]
+fn cl_key_proof_test() {
+    let cl = default_context();
+    let group = &cl.base;
+    let (sk, pk) = group.keygen();
+    let proof = CLKeyProof::prove(group, &sk, &pk);
+    proof.verify(group, &pk).unwrap();
+
+    // The proof is for the key it was made with only.
+    let (_, other) = group.keygen();
+    assert_eq!(
+        proof.verify(group, &other),
+        Err(MulEcdsaError::VrfyCLKeyProofFailed)
+    );
+
+    let mut oversized = proof;
+    oversized.u = &oversized.u + &(&group.stilde << 300);
+    assert_eq!(
+        oversized.verify(group, &pk),
+        Err(MulEcdsaError::VrfyCLKeyProofFailed)
+    );
+}
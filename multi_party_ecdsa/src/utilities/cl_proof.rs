@@ -17,16 +17,14 @@
 */
 use crate::utilities::class_group::*;
 use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::statement::StatementContext;
 use crate::utilities::SECURITY_PARAMETER;
 use crate::FE;
-use classgroup::gmp::mpz::Mpz;
-use classgroup::gmp_classgroup::*;
-use classgroup::ClassGroup;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
 use curv::arithmetic::traits::*;
-use curv::cryptographic_primitives::hashing::{Digest, DigestExt};
 use curv::BigInt;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CLState {
@@ -49,7 +47,11 @@ pub struct CLProof {
 }
 
 impl CLProof {
-    pub fn prove(group: &CLGroup, witness: CLWit, statement: CLState) -> Self {
+    pub fn prove(
+        group: &CLGroup,
+        witness: CLWit,
+        statement: CLState,
+    ) -> Result<Self, MulEcdsaError> {
         let r1 = BigInt::sample_below(
             &(&mpz_to_bigint(&group.stilde)
                 * BigInt::from(2u32).pow(40)
@@ -59,62 +61,68 @@ impl CLProof {
         let r1_mpz = bigint_to_mpz(&r1);
         let r2_fe: FE = FE::random();
         let r2 = into_mpz(&r2_fe);
-        let fr2 = expo_f(&q(), &group.generator.discriminant(), &r2);
-        let mut pkr1 = statement.cl_pub_key.0.clone();
-        pkr1.pow(r1_mpz.clone());
+        let fr2 = expo_f(&group.q, &group.generator.discriminant(), &r2);
+        let mut powers = exp_pool::current().pow_all(
+            vec![
+                (group.generator.clone(), r1_mpz.clone()),
+                (statement.cl_pub_key.0.clone(), r1_mpz.clone()),
+            ],
+            Priority::Low,
+        )?;
+        let pkr1 = powers.pop().unwrap();
+        let t1 = powers.pop().unwrap();
         let t2 = fr2 * pkr1;
-        let mut t1 = group.generator.clone();
-        t1.pow(r1_mpz.clone());
-        let k = Self::challenge(
-            &statement.cl_pub_key,
-            t1.clone(),
-            t2.clone(),
-            &statement.cipher,
-        );
-        let u1 = r1_mpz + &bigint_to_mpz(&k) * &witness.r.0;
+        let k = Self::challenge(group, &statement.cl_pub_key, &t1, &t2, &statement.cipher);
+        let u1 = r1_mpz + &bigint_to_mpz(&k) * witness.r.0.expose();
         let u2 = BigInt::mod_add(
             &mpz_to_bigint(&r2),
             &(&k * witness.x.to_bigint()),
             &FE::group_order(),
         );
 
-        Self {
+        Ok(Self {
             t1,
             t2,
             u1,
             u2: bigint_to_mpz(&u2),
-        }
+        })
     }
 
     /// Compute the Fiat-Shamir challenge for the proof.
     pub fn challenge(
+        group: &CLGroup,
         public_key: &PK,
-        t1: GmpClassGroup,
-        t2: GmpClassGroup,
+        t1: &GmpClassGroup,
+        t2: &GmpClassGroup,
         ciphertext: &Ciphertext,
     ) -> BigInt {
-        let hash256 = Sha256::new()
+        StatementContext::new(group)
+            .transcript(b"DMZ21-CL-PROOF")
             // hash the statement i.e. the discrete log of Q is encrypted in (c1,c2) under encryption key h.
-            .chain_bigint(&BigInt::from_bytes(ciphertext.c1.to_bytes().as_ref()))
-            .chain_bigint(&BigInt::from_bytes(ciphertext.c2.to_bytes().as_ref()))
-            .chain_bigint(&BigInt::from_bytes(public_key.0.to_bytes().as_ref()))
+            .append(ciphertext)
+            .append(public_key)
             // hash Sigma protocol commitments
-            .chain_bigint(&BigInt::from_bytes(t1.to_bytes().as_ref()))
-            .chain_bigint(&BigInt::from_bytes(t2.to_bytes().as_ref()))
-            .result_bigint();
-
-        let hash128 = &BigInt::to_bytes(&hash256)[..SECURITY_PARAMETER / 8];
-        BigInt::from_bytes(hash128)
+            .append(t1)
+            .append(t2)
+            .challenge()
     }
 
-    pub fn verify(&self, group: &CLGroup, statement: CLState) -> Result<(), MulEcdsaError> {
+    pub fn verify(&self, group: &CLGroup, statement: &CLState) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[
+            &statement.cipher.c1,
+            &statement.cipher.c2,
+            &statement.cl_pub_key.0,
+            &self.t1,
+            &self.t2,
+        ])?;
         let mut flag = true;
 
         // reconstruct k
         let k = Self::challenge(
+            group,
             &statement.cl_pub_key,
-            self.t1.clone(),
-            self.t2.clone(),
+            &self.t1,
+            &self.t2,
             &statement.cipher,
         );
 
@@ -128,25 +136,25 @@ impl CLProof {
             flag = false;
         }
         // length test u2:
-        if &self.u2 > &q() || &self.u2 < &Mpz::zero() {
+        if &self.u2 > &group.q || &self.u2 < &Mpz::zero() {
             flag = false;
         }
 
-        let mut c1k = statement.cipher.c1;
-        c1k.pow(bigint_to_mpz(&k));
-        let t1c1k = self.t1.clone() * c1k;
+        let mut c1k = statement.cipher.c1.clone();
+        c1k.pow_bigint(&k);
+        let t1c1k = c1k * &self.t1;
         let mut gqu1 = group.generator.clone();
         gqu1.pow(self.u1.clone());
         if t1c1k != gqu1 {
             flag = false;
         };
 
-        let mut pku1 = statement.cl_pub_key.0;
+        let mut pku1 = statement.cl_pub_key.0.clone();
         pku1.pow(self.u1.clone());
-        let fu2 = expo_f(&q(), &group.generator.discriminant(), &self.u2);
-        let mut c2k = statement.cipher.c2;
-        c2k.pow(bigint_to_mpz(&k));
-        let t2c2k = self.t2.clone() * c2k;
+        let fu2 = expo_f(&group.q, &group.generator.discriminant(), &self.u2);
+        let mut c2k = statement.cipher.c2.clone();
+        c2k.pow_bigint(&k);
+        let t2c2k = c2k * &self.t2;
         let pku1fu2 = pku1 * fu2;
         if t2c2k != pku1fu2 {
             flag = false;
@@ -0,0 +1,187 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! CL public keys and ciphertexts tagged with the group they live in.
+//!
+//! `CLContext::base` and `CLContext::group` share a discriminant and differ
+//! only in the generator (`g` and `g^q`), so nothing in an element tells
+//! which one it came from. Encrypting under a base-group key with the
+//! updated generator, or the reverse, gives ciphertexts that decrypt to
+//! garbage rather than failing. The wrappers here carry the group in their
+//! type, and the context only encrypts, decrypts and combines values of
+//! matching kinds.
+//!
+//! The one legitimate crossing is a key: `h^q` is the updated-group key for
+//! the secret of a base-group key `h`. Anyone can recompute it, so
+//! `TypedPK::<Updated>::from_base` checks a claimed pair instead of needing
+//! a proof. Ciphertexts never cross: raising one to `q` also cancels the
+//! message.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::FE;
+use classgroup::prelude::{ClassGroup, Mpz};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+pub trait GroupKind: sealed::Sealed + Copy + Debug + Eq {
+    const NAME: &'static str;
+    fn group(cl: &CLContext) -> &CLGroup;
+}
+
+/// The group CL keys are generated in, generator `g`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base {}
+
+/// The `g^q` group used for encryption and proofs during signing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Updated {}
+
+impl sealed::Sealed for Base {}
+impl sealed::Sealed for Updated {}
+
+impl GroupKind for Base {
+    const NAME: &'static str = "base";
+    fn group(cl: &CLContext) -> &CLGroup {
+        &cl.base
+    }
+}
+
+impl GroupKind for Updated {
+    const NAME: &'static str = "updated";
+    fn group(cl: &CLContext) -> &CLGroup {
+        &cl.group
+    }
+}
+
+/// A `T` known to belong to the group of kind `K`. Serializes exactly as the
+/// bare `T`, so the kind of a received value is whatever the receiver
+/// decodes it as, as with the untyped messages.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    transparent,
+    bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+)]
+pub struct Typed<T, K: GroupKind> {
+    value: T,
+    #[serde(skip)]
+    kind: PhantomData<K>,
+}
+
+pub type TypedPK<K> = Typed<PK, K>;
+pub type TypedCiphertext<K> = Typed<Ciphertext, K>;
+
+impl<T, K: GroupKind> Typed<T, K> {
+    /// Tag `value` as belonging to `K`. The caller vouches for it, e.g.
+    /// because it just computed it in that group.
+    pub fn assume(value: T) -> Self {
+        Self {
+            value,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl TypedPK<Base> {
+    /// The key for the same secret in the updated group, `h^q`.
+    pub fn to_updated(&self) -> TypedPK<Updated> {
+        let mut pk = self.value.0.clone();
+        pk.pow(q());
+        Typed::assume(PK(pk))
+    }
+}
+
+impl TypedPK<Updated> {
+    /// Accept a peer's claimed updated key if it matches its base key.
+    pub fn from_base(base: &TypedPK<Base>, updated: PK) -> Result<Self, MulEcdsaError> {
+        if base.to_updated().value.0 == updated.0 {
+            Ok(Typed::assume(updated))
+        } else {
+            Err(MulEcdsaError::VrfyUpdatedPKFailed)
+        }
+    }
+}
+
+impl<K: GroupKind> TypedCiphertext<K> {
+    /// Encryption of the sum of both plaintexts.
+    pub fn add(&self, other: &Self) -> Self {
+        Typed::assume(CLGroup::eval_sum(&self.value, &other.value))
+    }
+
+    /// Encryption of the plaintext times `scalar`.
+    pub fn scale(&self, scalar: Mpz) -> Self {
+        Typed::assume(CLGroup::eval_scal(&self.value, scalar))
+    }
+}
+
+impl CLContext {
+    pub fn keygen_typed<K: GroupKind>(&self) -> (SK, TypedPK<K>) {
+        let (sk, pk) = K::group(self).keygen();
+        (sk, Typed::assume(pk))
+    }
+
+    pub fn pk_for_sk_typed<K: GroupKind>(&self, sk: SK) -> TypedPK<K> {
+        Typed::assume(K::group(self).pk_for_sk(sk))
+    }
+
+    pub fn encrypt_typed<K: GroupKind>(
+        &self,
+        public_key: &TypedPK<K>,
+        m: &FE,
+    ) -> (TypedCiphertext<K>, SK) {
+        let (c, r) = CLGroup::encrypt(K::group(self), &public_key.value, m);
+        (Typed::assume(c), r)
+    }
+
+    pub fn decrypt_typed<K: GroupKind>(&self, secret_key: &SK, c: &TypedCiphertext<K>) -> FE {
+        CLGroup::decrypt(K::group(self), secret_key, &c.value)
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_typed_encrypt_decrypt() {
+    let cl = &CL_CONTEXT_1827;
+    let (sk, base_pk) = cl.keygen_typed::<Base>();
+    let updated_pk = base_pk.to_updated();
+    assert_eq!(
+        updated_pk.get().0,
+        cl.pk_for_sk_typed::<Updated>(sk.clone()).get().0
+    );
+    assert!(TypedPK::<Updated>::from_base(&base_pk, updated_pk.get().clone()).is_ok());
+    assert!(TypedPK::<Updated>::from_base(&base_pk, base_pk.get().clone()).is_err());
+
+    let (m1, m2) = (FE::random(), FE::random());
+    let (c1, _) = cl.encrypt_typed(&updated_pk, &m1);
+    let (c2, _) = cl.encrypt_typed(&updated_pk, &m2);
+    assert_eq!(cl.decrypt_typed(&sk, &c1.add(&c2)), &m1 + &m2);
+
+    let (c3, _) = cl.encrypt_typed(&base_pk, &m1);
+    assert_eq!(cl.decrypt_typed(&sk, &c3), m1);
+}
@@ -15,17 +15,25 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::kdf::{self, CL_SETUP};
+use crate::utilities::secret::{exposed_mpz, mpz_ct_eq, SecretMpz};
+use crate::utilities::transcript::Transcript;
+use crate::utilities::SECURITY_PARAMETER;
 use crate::FE;
-use classgroup::gmp::mpz::Mpz;
-use classgroup::gmp_classgroup::*;
-use classgroup::ClassGroup;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz, ProbabPrimeResult, Sign};
 use curv::arithmetic::Converter;
 use curv::arithmetic::*;
 use curv::elliptic::curves::Scalar;
 use curv::BigInt;
 use lazy_static::lazy_static;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use subtle::{Choice, ConstantTimeEq};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CLGroup {
@@ -35,17 +43,113 @@ pub struct CLGroup {
 
     pub generator: GmpClassGroup,
     pub stilde: Mpz,
+    /// The plaintext modulus, the secp256k1 order for the built-in sets.
+    #[serde(default = "q")]
+    pub q: Mpz,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PK(pub GmpClassGroup);
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// A CL ciphertext `(c1, c2)`.
+///
+/// Every class has exactly one reduced form, but a form need not be
+/// reduced, so one ciphertext has many encodings. Equality and hashing go
+/// through the reduced forms, deserialization reduces (and rejects forms
+/// that are not of their discriminant), and the transcript absorbs the
+/// reduced forms. A sender therefore cannot change what a proof or a tag
+/// binds to by re-encoding.
+#[derive(Clone, Debug, Serialize)]
 pub struct Ciphertext {
     pub c1: GmpClassGroup,
     pub c2: GmpClassGroup,
 }
 
+#[derive(Deserialize)]
+struct RawCiphertext {
+    c1: GmpClassGroup,
+    c2: GmpClassGroup,
+}
+
+impl<'de> Deserialize<'de> for Ciphertext {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawCiphertext::deserialize(deserializer)?;
+        let well_formed =
+            is_form(&raw.c1) && is_form(&raw.c2) && raw.c1.discriminant() == raw.c2.discriminant();
+        if !well_formed {
+            return Err(serde::de::Error::custom(
+                "invalid class group form in ciphertext",
+            ));
+        }
+        Ok(Ciphertext::new(raw.c1, raw.c2))
+    }
+}
+
+/// `(a, b, c)` is a positive definite form of its discriminant, which is
+/// what reduction needs in order to terminate.
+fn is_form(f: &GmpClassGroup) -> bool {
+    let four_ac = Mpz::from(4u64) * &f.a * &f.c;
+    f.a > Mpz::zero() && f.c > Mpz::zero() && &(&f.b * &f.b) - &four_ac == f.discriminant
+}
+
+impl PartialEq for Ciphertext {
+    fn eq(&self, other: &Self) -> bool {
+        let (x, y) = (self.canonical(), other.canonical());
+        x.c1 == y.c1 && x.c2 == y.c2
+    }
+}
+
+impl Eq for Ciphertext {}
+
+impl Hash for Ciphertext {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let c = self.canonical();
+        c.c1.hash(state);
+        c.c2.hash(state);
+    }
+}
+
+impl Ciphertext {
+    /// The ciphertext with both components reduced.
+    pub fn new(mut c1: GmpClassGroup, mut c2: GmpClassGroup) -> Self {
+        c1.reduce();
+        c2.reduce();
+        Self { c1, c2 }
+    }
+
+    pub fn canonical(&self) -> Self {
+        Self::new(self.c1.clone(), self.c2.clone())
+    }
+
+    /// Deterministic tag of this ciphertext bound to `session_id`.
+    ///
+    /// The same ciphertext always gets the same tag within a session, so a
+    /// transport can use it to drop duplicates, and the protocol layer uses
+    /// it to reject ciphertexts replayed from another round or party. Fresh
+    /// encryptions collide only with negligible probability.
+    pub fn tag(&self, session_id: &[u8]) -> [u8; 32] {
+        Transcript::new(b"DMZ21-CL-CIPHERTEXT-TAG")
+            .append(session_id)
+            .append(self)
+            .finalize()
+    }
+}
+
+/// Compares the reduced forms, which are unique per class, so it is
+/// equality of the ciphertexts as group elements.
+impl ConstantTimeEq for Ciphertext {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let (x, y) = (self.canonical(), other.canonical());
+        [(&x.c1, &y.c1), (&x.c2, &y.c2)]
+            .iter()
+            .fold(Choice::from(1), |acc, (x, y)| {
+                acc & mpz_ct_eq(&x.a, &y.a)
+                    & mpz_ct_eq(&x.b, &y.b)
+                    & mpz_ct_eq(&x.discriminant, &y.discriminant)
+            })
+    }
+}
+
 impl From<PK> for GmpClassGroup {
     fn from(pk: PK) -> Self {
         pk.0
@@ -58,21 +162,30 @@ impl From<GmpClassGroup> for PK {
     }
 }
 
+/// A CL secret key or encryption randomness. Kept in a `SecretMpz`, but
+/// serialized as the bare exponent so that key files keep their format.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SK(pub Mpz);
+pub struct SK(#[serde(with = "exposed_mpz")] pub SecretMpz);
+
+impl ConstantTimeEq for SK {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
 
 impl From<SK> for Mpz {
     fn from(sk: SK) -> Self {
-        sk.0
+        sk.0.expose().clone()
     }
 }
 
 impl From<Mpz> for SK {
     fn from(mpz: Mpz) -> Self {
-        Self(mpz)
+        Self(SecretMpz::from(mpz))
     }
 }
 
+#[cfg(feature = "params-1827")]
 lazy_static! {
     pub static ref DISCRIMINANT_1827: Mpz = Mpz::from_str("-75257495770792601579408435348799912112609846029965206820064851604692987230254538914853608976971793980958712372789231634579578971529235823075608739231635687425758158575368321348137900869894119507551586698602273331769113654968615517566745786072923103207661147676790644792111452136974276225728730910712947503901232735129687891775293591232029998265064837518833536297518857716272011348573253397254136847763813364524813537416619588617528698171849359403663703760169261184343946919401092992684996593982744033815507830560787451354075275532210193117085590501285653650352846925182015277946751628767130269342252523310043345421861896214174850131607385236887381965429994384214519104490505249675175386383257705274311668138257554180057201072703457873180274207162029503126883077609392094864657038777406276133886450239").unwrap();
 }
@@ -86,8 +199,11 @@ lazy_static! {
 /// |100                |1200|1500|1600|1900|2300|2600|
 /// |128                |1536|1920|2048|2432|2944|3392|
 /// security level (lambda,rho) means the attacker succeeds with probability 1/2^{rho} requires 2^{lambda} bit operations.
-/// We provides two groups options: 1827-bit discriminant and 3072-bit discriminant.
+/// We provide 1827-bit and 3072-bit discriminants, together with `Delta_K` of 2048, 2432 and
+/// 2944 bits for the intermediate trade-offs of the lambda = 128 row, each behind its
+/// `params-*` feature.
 impl CLGroup {
+    #[cfg(feature = "params-1827")]
     pub fn new_1827() -> Self {
         // 是[CL15, Proposition 1]中的 $$\Delta_k$$.
         // 已验证过是两个质数 $$p, q$$ 的乘积.
@@ -115,9 +231,11 @@ impl CLGroup {
             delta_k,
             generator,
             stilde,
+            q: q(),
         }
     }
 
+    #[cfg(feature = "params-3072")]
     pub fn new_3072() -> Self {
         let delta_k = Mpz::from_str("-4059187479482350050615628258855828167626431824732199036597668525464616895922000411261718516567731632732286800934600249406393974357768444047141581621951155803795734117021495676831593033172450357785597776576612281305223919414836213766354372816990863555296830253123574199460146205334642841425167146191511265843560519935132843345652241452096808325636749679870044168299284188041110855817763388520168386219623910310164928704787483081634387756726626535065281682599731277374016734081858737636466840542887162979503417512544889504232167650829937659939952944676065304893114687576168003023224828141758525768773373824222139881461335520424806873120226629820060875152488085708505799289587546695067879685280385374856021956449469249646800629229020371797593504643496190406594392765693007499422572180546825466666141075563827212225011483631613617098804995744522667871405671831585120704467080787250858292339350012462220525281878018038188111302643").unwrap();
 
@@ -132,40 +250,264 @@ impl CLGroup {
             delta_k,
             generator: gene,
             stilde,
+            q: q(),
+        }
+    }
+
+    /// [CL15, Appendix B.3] parameters with a 2048-bit `Delta_K`, generated
+    /// as in `setup` with `q` the secp256k1 order.
+    #[cfg(feature = "params-2048")]
+    pub fn new_2048() -> Self {
+        let delta_k = Mpz::from_str("-30211109378499478538926413036831375515967946772755741360619886035911791334721220160179442563172182657071008944990493689535503251846545692191485686696627831087920235331314275672190686251357406114564962801651143911011820250408104014269860356296237416899621080439176525386627490739741737599202943385354511485005205727481048930695930172530947507873060215987887539739201016919426581518571676278188973829356762136464398191761951740715532680350789118583500252064162987795077805566370663198567596246286519420466177226319856123148378146366107930850230079387107810185223537281773561189639792393848051634222912649498751484084371").unwrap();
+
+        let a = Mpz::from_str("6143725780185788040748256038779028502539505698943871526247166744616557173254192847852876931286598534873909199816722295968502185301317713817476052291802662729140995373602778105065170849257360418565891763496362691103603861734164225651344522906924188543559469487429507765063949522777367021327335137622882644884666946414196961568784139109684579528189753157417892331491754829759833963481631").unwrap();
+        let b = Mpz::from_str("-3676897441674784350143539875110340467774656223163352411933168223621525016005980338484851814036962336556909191088852849639362082193040834563927161735727387531055643255265630334529948371602465161246278441827236582442751004248989447915040433886287826538082518251566273343874503415001464853999783023933017888406625607770234202756932916725575240124778986929428962424481565845245093604240565").unwrap();
+
+        let discriminant = Mpz::from_str("-405064751897408474551670129313351265462667090240117018347457552134498775069907850262472571529685408306464198574491829067280578197819734012536770711908944117079515214277651829475085045009279794905680060132615748070582476317993740833404196066184474837235128238359897150078977685435766404270906878782275944724198757053251818076234020293380969967738757553236800380482113175964430256091252966997160095251643502956188346810212923429858313399116884634922515873289580616403509421113922766947577480648962862684256951408091080266554975241210342700451317999036263757930422331423758794983457437820869063692985928939045000606789745726692718970826042540672483437737714745898632805303012755560252231511115023708140823864738964446647306408833863720939331524675346762112822298287858606099").unwrap();
+        let generator = ClassGroup::from_ab_discriminant(a, b, discriminant);
+
+        let stilde = Mpz::from_str("78539738274473620085401085394888068858655064556143218697210787564346117763027189956822943304665519969019031609085753154693005653612254978175566614737034895944967637093296297261415622285440898898154146851616083757430525528298240839541425488915486052820066770527946185745294685112205484192698526923953173892285601").unwrap();
+        Self {
+            delta_k,
+            generator,
+            stilde,
+            q: q(),
         }
     }
 
+    /// As `new_2048`, with a 2432-bit `Delta_K`.
+    #[cfg(feature = "params-2432")]
+    pub fn new_2432() -> Self {
+        let delta_k = Mpz::from_str("-870032435989341779479775370201194179895816359857769741579019952387546991597688271495367261018116283379953555245498316970103225105452695966705493742258482758101261123370177875517273885889106892526172398660032467155914903930349006301227932742583664421131052176327038703457934569363186455894856827724461027234904050861930825317252311244488294021955410290168377210730571451954153881054547121136947719859695091025176731721444167941226812083819344786412958416034916183934744281760681723355113894073044872152322414472141110702903472982161797026576911444099322821029991977557985865686432138020683694911273425424028280443858721758303353050176793500228969431562386832585495206266012292526682904766560623920850826720481468788568396991705933791").unwrap();
+
+        let a = Mpz::from_str("14334755608835760257157217880763281066780090888053556084873408354002739917061772270603643508620528978853422446550919768784296107804222135108440663016348277298270705577806111940921922565215961572170667852750905017944129668235267937495831461285287064256605420963255524407375480846777874204437197436012061038334131968714690060014887762273560303264019828242038285362022062746883610075677310017377977732460607583748518167756755775265776055747955039").unwrap();
+        let b = Mpz::from_str("-8891546430691237761728164798226584678442650872816307926336564546344317994234078926794846692837200264698461762097763585205129468243370623495206646833997076247603928076295554970725653920025142398643245008786071287888072022459297416219294435235002023949890423525067505197583524878465900114474454303755467159189665007440352853275752737692848515496419072447394651392406548837127690561669977292582570617750848721206867655702371170568236521957843675").unwrap();
+
+        let discriminant = Mpz::from_str("-11665227794565171721098778827487425494228988848482773163763754118165369332357750628880064310975032036401349232182035845485172627133782314621707275088708910655315958061803397721001980695658726395357393692118719910865668017158863350246862755216711199767134786770790209924131413416285115900823300666839424848556399422714149285970203077104420466150099749710192975168678609150170770806528482219585298750817714920797690016203756837768861730259872846109078567329218711642552185068777238948474282618570227213978887158849573018257319852029039869046798248975913855095714192202411844582648447397953791236104548673052571447709698068032700888307584836548134934808575241350390848888971698610266241248789212155512377604565564559145628401198210186366297804937888325777137856962345627399253419330670745652463056487784286024516968453405671312573075782451510414150445166924298278231427782617485159560906079").unwrap();
+        let generator = ClassGroup::from_ab_discriminant(a, b, discriminant);
+
+        let stilde = Mpz::from_str("500503373171232136508828621165683294243252187184498015559155266436536752157853071246452925931654939943826914374001263868789723339146717396380025591690789798485503210305888235463141780203489273243201094007295036562015010398229617573955184497125777693857839504451637013728490601638577732264958058904981607183663578345819045536661884669126261970946755244461271454848354727").unwrap();
+        Self {
+            delta_k,
+            generator,
+            stilde,
+            q: q(),
+        }
+    }
+
+    /// As `new_2048`, with a 2944-bit `Delta_K`.
+    #[cfg(feature = "params-2944")]
+    pub fn new_2944() -> Self {
+        let delta_k = Mpz::from_str("-11759387260980883475722702533549297501430315808607185895877390164636823738090953589764200519917095929234070805362384134792091539365414786411276652970275170820319297661240091304065263961766835410805316972439530701674418493648577677358042854638283834900547992178945064636071876542258017691346550822181088116581425494217642342206012724227981025899225563780053043877985502279944383495018266749726075721612300313055558304888420831975096534284692180778441096491301014458706660150056519690026962622083944367210818545882605829566293302254469062853240933445717745732534923587142963377745523656212085303468878806137406151852548566621484481779878305228353958062044758753297183899951969231011846421649544335508810718537795743533776956025110234857652844995271360295708590898707963577085818824423500556452110173738633551940449739843848894139778384779298110017730796497486754624924817334881683093004871").unwrap();
+
+        let a = Mpz::from_str("5193333429132349535168604666041069294825729289631310289261631059737833979200633594374293264462410435081920306717953809841716578887707171498842609569242550877290095107941800102458159441256991222881810039770482318711512682865926837007755173117395147957674154253970327358719675249901594884855581805457595196193583647396010115916475866756892156668824350385888776650898645612824612508947178347956310246551117146654413055568805486441982256991722652881221786304925842586075065413886452565675573512148318971494459870334208465336").unwrap();
+        let b = Mpz::from_str("-1831927609310637440278314963179196979017211533250152371557251745628357141076202621873323404441817373580967682622804380368712727569323640122321576720569141220925306724007120556190321381893990515121722312835730110755158455653502113540946315250821794878264011556044488266774464007371197878932434676099803525418451006034752016536820766581742606860475937484703565566099554588402164927344948482611755410295844308364385654920144423610265446284896731788607087782750903327977418472777754896517931278017133476613943250195870606811").unwrap();
+
+        let discriminant = Mpz::from_str("-157667605769045446108188248944690317331836248650660738242705502785550541394223283866534943872006454115777500738030165291115289214059169539911950599939885075134411119209404321786564425271932912605204203195235250627098977761734626091277275131246047039292153698818138637287553303276471185893047577382699346599576767389790077231646528805027900031116606728059698802460310885998667830282069081023626880468993461025912407771541634199559001378779793458019961079035604249860787683303830448073489822957170574749444065266999966386859178445367228325191056153757392540227788354171172159663115739289064737586644661531037654229300950411102338729312800854285414985472103040381142354532477594115606880540678235232743395767559541940105346730087553632898938970028819109362753834969111644496290674413115131973231075354100650782843916611731094856432574245056698005840873018731928198955667003869333561067026259299735433109140110835816790973619265352440484200918865230411724703739129155007525257861286675012818921103587364537816755741166067989329825760190348870599").unwrap();
+        let generator = ClassGroup::from_ab_discriminant(a, b, discriminant);
+
+        let stilde = Mpz::from_str("70437852827599612034299981720947344700143121947749608647852234370035972684242131409795577012458385634497441974627253810457796179404636778687890712822402283289088409093900185576689605253837409432669624398933913321700056836636864708680657514735767978253722401960690018228659684187839338344191835986299827963478837854767819253897296736297140540037072040499694050213691960415723464716174888368420136256923866552979388314419954645380410036383468569509").unwrap();
+        Self {
+            delta_k,
+            generator,
+            stilde,
+            q: q(),
+        }
+    }
+
+    /// A toy parameter set for fast unit tests of protocol logic. It has NO
+    /// security and only exists with the `insecure-small-params` feature.
+    ///
+    /// The message space is the secp256k1 order `q`, and `Delta_q` carries
+    /// `q^2`, so the discriminant cannot go below ~768 bits. What shrinks is
+    /// `Delta_K = -q * p~` with a 64-bit `p~`: the discriminant is 832 bits
+    /// instead of 2658, and `stilde` 167 bits instead of 1083.
+    ///
+    /// The generator follows [CL15, Fig. 2] and is computed here instead of
+    /// being pasted in: `r = (5, 7)` is a prime form of `Delta_K`, its square
+    /// is lifted to `Delta_q` and raised to `q`, then multiplied by `f^k`.
+    #[cfg(feature = "insecure-small-params")]
+    pub fn new_insecure_small() -> Self {
+        // [CL15, Appendix B.3]: q * p~ = 3 mod 4 and (q / p~) = -1, so
+        // Delta_K = 1 mod 4 is fundamental.
+        let p_tilde = Mpz::from_str("15845176372676427463").unwrap();
+        let delta_k = -(q() * &p_tilde);
+        let discriminant = &delta_k * &(q() * &q());
+
+        let mut r2 =
+            GmpClassGroup::from_ab_discriminant(Mpz::from(5u64), Mpz::from(7u64), delta_k.clone());
+        r2.reduce();
+        r2.square();
+        // [CL15, Appendix B.1]: (a, b) of Delta_K lifts to (a, b * q) of
+        // Delta_q when gcd(a, q) = 1, which holds for a reduced form this
+        // small.
+        let mut lifted =
+            GmpClassGroup::from_ab_discriminant(r2.a.clone(), &r2.b * &q(), discriminant.clone());
+        lifted.reduce();
+        lifted.pow(q());
+        let k = Mpz::from_str(
+            "65272223474723348995715654734697364952966569661261790575348255867683020013028",
+        )
+        .unwrap();
+        let generator = lifted * expo_f(&q(), &discriminant, &k);
+
+        // ceil(ln|Delta_K| * sqrt|Delta_K| / pi), the class number bound of
+        // `setup` and the 2048, 2432 and 2944-bit sets. The 1827 and
+        // 3072-bit sets carry about twice it.
+        let stilde = Mpz::from_str("95568724661722757419690628773965880481379906605387").unwrap();
+        Self {
+            delta_k,
+            generator,
+            stilde,
+            q: q(),
+        }
+    }
+
+    /// Fresh parameters for the message space `q`, an odd prime, with a
+    /// `disc_bits`-bit fundamental discriminant `Delta_K`. This is the
+    /// construction of `new_insecure_small` with the constants drawn from
+    /// `rng`:
+    ///
+    /// - `Delta_K = -q * p~` for a prime `p~` with `q * p~ = 3 mod 4` and
+    ///   `(q / p~) = -1`, and `Delta_q = Delta_K * q^2`;
+    /// - `r` is the smallest prime form of `Delta_K` whose square lifts to
+    ///   `Delta_q`, and the generator `lift(r^2)^q * f^k` for a random `k`;
+    /// - `stilde` is `ln|Delta_K| * sqrt|Delta_K| / pi`, rounded up.
+    ///
+    /// The protocols encrypt modulo the secp256k1 order and only run on a
+    /// group set up for it; other primes serve the CL scheme on its own,
+    /// through `encrypt_bigint` and `decrypt_bigint`.
+    pub fn setup<R: RngCore>(
+        q: &Mpz,
+        disc_bits: usize,
+        rng: &mut R,
+    ) -> Result<Self, MulEcdsaError> {
+        let q_bits = q.bit_length();
+        if q_bits < 3
+            || matches!(q.probab_prime(30), ProbabPrimeResult::NotPrime)
+            || disc_bits < q_bits + 16
+        {
+            return Err(MulEcdsaError::InvalidClParameters);
+        }
+        let four = Mpz::from(4u64);
+
+        let delta_k = loop {
+            let p_tilde = random_bits(disc_bits - q_bits, rng).nextprime();
+            let delta_k = -(q * &p_tilde);
+            if delta_k.bit_length() == disc_bits
+                && delta_k.mod_floor(&four) == Mpz::one()
+                && legendre(q, &p_tilde) == -1
+            {
+                break delta_k;
+            }
+        };
+        let discriminant = &delta_k * &(q * q);
+
+        let mut r = 1u64;
+        let lifted = loop {
+            r += 2;
+            let r_mpz = Mpz::from(r);
+            if matches!(r_mpz.probab_prime(30), ProbabPrimeResult::NotPrime)
+                || r_mpz == *q
+                || legendre(&delta_k, &r_mpz) != 1
+            {
+                continue;
+            }
+            // b odd with b^2 = Delta_K mod 4r.
+            let four_r = &r_mpz * &four;
+            let b = (1..2 * r)
+                .step_by(2)
+                .find(|b| (Mpz::from(b * b) - &delta_k).mod_floor(&four_r).is_zero())
+                .ok_or(MulEcdsaError::InvalidClParameters)?;
+            let mut r2 = GmpClassGroup::from_ab_discriminant(r_mpz, Mpz::from(b), delta_k.clone());
+            r2.reduce();
+            r2.square();
+            // [CL15, Appendix B.1]: the lift (a, b * q) needs gcd(a, q) = 1.
+            if r2.a.gcd(q) != Mpz::one() {
+                continue;
+            }
+            let mut lifted =
+                GmpClassGroup::from_ab_discriminant(r2.a.clone(), &r2.b * q, discriminant.clone());
+            lifted.reduce();
+            lifted.pow(q.clone());
+            break lifted;
+        };
+        let k = random_bits(q_bits + 64, rng).mod_floor(&(q.clone() - 1u64)) + 1u64;
+        let generator = lifted * expo_f(q, &discriminant, &k);
+
+        // |Delta_K| < 2^disc_bits, so this rounds ln|Delta_K| / pi up.
+        let ln_over_pi = disc_bits as f64 * std::f64::consts::LN_2 / std::f64::consts::PI;
+        let scale = Mpz::from((ln_over_pi * 1024.0).ceil() as u64);
+        let stilde = ((-delta_k.clone()).sqrt() * scale >> 10) + 1u64;
+        Ok(Self {
+            delta_k,
+            generator,
+            stilde,
+            q: q.clone(),
+        })
+    }
+
+    /// `setup` at the size of `new_2048`, with all randomness derived
+    /// from the public `seed`. `verify_setup` replays it.
+    pub fn setup_from_seed(seed: &[u8]) -> Self {
+        Self::setup_from_seed_bits(seed, SEEDED_DISC_BITS)
+            .expect("the secp256k1 order fits a 2048-bit discriminant")
+    }
+
+    /// `setup_from_seed` with a `disc_bits`-bit `Delta_K`.
+    pub fn setup_from_seed_bits(seed: &[u8], disc_bits: usize) -> Result<Self, MulEcdsaError> {
+        Self::setup(&q(), disc_bits, &mut SeedStream { seed, draws: 0 })
+    }
+
     // 2025.07.16. 此时的generator是 $$f=(p^2, p)$$ 吗?
     pub fn update_class_group_by_p(group: &CLGroup) -> CLGroup {
-        let q: Mpz = q();
         let mut gq_new = group.generator.clone();
-        gq_new.pow(q);
+        gq_new.pow(group.q.clone());
         CLGroup {
             delta_k: group.delta_k.clone(),
             generator: gq_new,
             stilde: group.stilde.clone(),
+            q: group.q.clone(),
         }
     }
 
     // 源码 `keygen.rs` 用的是 `GROUP_1827`
     pub fn keygen(&self) -> (SK, PK) {
-        let sk = SK(bigint_to_mpz(&BigInt::sample_below(
+        let sk = SK::from(bigint_to_mpz(&BigInt::sample_below(
             &(&(mpz_to_bigint(&self.stilde)) * BigInt::from(2u32).pow(40)),
         )));
         let mut generator = self.generator.clone();
-        generator.pow(sk.clone().0);
+        generator.pow(sk.0.expose().clone());
         let pk = PK(generator);
         (sk, pk)
     }
 
     // 在源码 `sign.rs` 中, `group` 是 `GROUP_UPDATE_1827`
     pub fn encrypt(group: &CLGroup, public_key: &PK, m: &FE) -> (Ciphertext, SK) {
-        let m = into_mpz(m);
+        Self::encrypt_mpz(group, public_key, &into_mpz(m))
+    }
+
+    /// `encrypt` of a plaintext given as an integer. Values outside of
+    /// $$[0, q)$$ are refused rather than reduced: the plaintext space is
+    /// $$\mathbb{Z}_q$$, so a reduced value would decrypt to another integer.
+    pub fn encrypt_bigint(
+        group: &CLGroup,
+        public_key: &PK,
+        m: &BigInt,
+    ) -> Result<(Ciphertext, SK), MulEcdsaError> {
+        let m = bigint_to_mpz(m);
+        if m < Mpz::zero() || m >= group.q {
+            return Err(MulEcdsaError::PlaintextOutOfRange);
+        }
+        Ok(Self::encrypt_mpz(group, public_key, &m))
+    }
+
+    fn encrypt_mpz(group: &CLGroup, public_key: &PK, m: &Mpz) -> (Ciphertext, SK) {
         let (r, r_big) = group.keygen();
         let delta = group.generator.discriminant().clone();
-        let exp_f = expo_f(&q(), &delta, &m);
+        let exp_f = expo_f(&group.q, &delta, m);
         let mut h_exp_r = public_key.0.clone();
-        h_exp_r.pow(r.0.clone());
+        h_exp_r.pow(r.0.expose().clone());
 
         // [CL15, Fig. 1] $$h=g^x, c_1=g^r, c_2=f^mh^r$$.
         let ct = Ciphertext {
@@ -176,27 +518,79 @@ impl CLGroup {
         (ct, r)
     }
 
+    /// `encrypt` with the randomness given, for fixtures and tests. Reusing
+    /// `r` for two plaintexts reveals their difference.
+    pub fn encrypt_with_r(group: &CLGroup, public_key: &PK, m: &Mpz, r: &Mpz) -> Ciphertext {
+        let mut c1 = group.generator.clone();
+        c1.pow(r.clone());
+        let mut h_exp_r = public_key.0.clone();
+        h_exp_r.pow(r.clone());
+        let exp_f = expo_f(&group.q, group.generator.discriminant(), m);
+        Ciphertext {
+            c1,
+            c2: h_exp_r * exp_f,
+        }
+    }
+
     pub fn decrypt(group: &CLGroup, secret_key: &SK, c: &Ciphertext) -> FE {
+        let plaintext = Self::decrypt_mpz(group, secret_key, c);
+        debug_assert!(plaintext < group.q);
+        Scalar::from(&mpz_to_bigint(&plaintext))
+    }
+
+    /// `decrypt` to an integer. A ciphertext that does not decrypt into
+    /// $$[0, q)$$ was not made by `encrypt` under this key and is an error.
+    pub fn decrypt_bigint(
+        group: &CLGroup,
+        secret_key: &SK,
+        c: &Ciphertext,
+    ) -> Result<BigInt, MulEcdsaError> {
+        let plaintext = Self::decrypt_mpz(group, secret_key, c);
+        if plaintext < Mpz::zero() || plaintext >= group.q {
+            return Err(MulEcdsaError::PlaintextOutOfRange);
+        }
+        Ok(mpz_to_bigint(&plaintext))
+    }
+
+    fn decrypt_mpz(group: &CLGroup, secret_key: &SK, c: &Ciphertext) -> Mpz {
         // $$(c_1^x)^{-1} == g^{-xr} == h^{-r}$$.
         let mut c1_x_inv = c.c1.clone();
-        c1_x_inv.pow(secret_key.0.clone());
+        c1_x_inv.pow(secret_key.0.expose().clone());
         c1_x_inv.inverse();
 
         // 用 `c1_x_inv` 消掉 $$h^r$$.
         let tmp = c.c2.clone() * &c1_x_inv;
 
         // 调用离散对数函数, 解出明文.
-        let plaintext = discrete_log_f(&q(), &group.generator.discriminant(), &tmp);
-        debug_assert!(plaintext < q());
-        let plaintext_big = BigInt::from_str_radix(&plaintext.to_str_radix(16), 16).unwrap();
-        Scalar::from(&plaintext_big)
+        discrete_log_f(&group.q, &group.generator.discriminant(), &tmp)
+    }
+
+    /// $$(g^r, h^r)$$ for a fresh $$r$$, which is also returned.
+    pub fn encrypt_zero(group: &CLGroup, public_key: &PK) -> (Ciphertext, SK) {
+        let (r, r_big) = group.keygen();
+        let mut h_exp_r = public_key.0.clone();
+        h_exp_r.pow(r.0.expose().clone());
+        (
+            Ciphertext {
+                c1: r_big.0,
+                c2: h_exp_r,
+            },
+            r,
+        )
+    }
+
+    /// Same plaintext, fresh randomness: `c` times an encryption of zero.
+    /// Returns the randomness of that zero encryption.
+    pub fn rerandomize(group: &CLGroup, public_key: &PK, c: &Ciphertext) -> (Ciphertext, SK) {
+        let (zero, r) = CLGroup::encrypt_zero(group, public_key);
+        (CLGroup::eval_sum(c, &zero), r)
     }
 
     pub fn encrypt_without_r(group: &CLGroup, m: &FE) -> (Ciphertext, SK) {
         let r = SK::from(Mpz::from(0));
         let r_big = group.pk_for_sk(r.clone());
         let m_mpz = Mpz::from_str(&m.to_bigint().to_str_radix(10)).unwrap();
-        let exp_f = expo_f(&q(), &group.generator.discriminant(), &m_mpz);
+        let exp_f = expo_f(&group.q, &group.generator.discriminant(), &m_mpz);
 
         (
             Ciphertext {
@@ -209,7 +603,7 @@ impl CLGroup {
 
     pub fn pk_for_sk(&self, sk: SK) -> PK {
         let mut group_element = self.generator.clone();
-        group_element.pow(sk.0);
+        group_element.pow(sk.0.expose().clone());
         PK(group_element)
     }
 
@@ -222,6 +616,26 @@ impl CLGroup {
         c_new
     }
 
+    /// $$\prod_i cts_i^{scalars_i}$$, i.e. an encryption of
+    /// $$\sum_i m_i \cdot scalars_i$$, computed with one multi-exponentiation
+    /// per component rather than `eval_scal` and `eval_sum` per term.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cts` is empty or `scalars` has a different length.
+    pub fn eval_inner_product(cts: &[Ciphertext], scalars: &[Mpz]) -> Ciphertext {
+        let c1s: Vec<GmpClassGroup> = cts.iter().map(|c| c.c1.clone()).collect();
+        let c2s: Vec<GmpClassGroup> = cts.iter().map(|c| c.c2.clone()).collect();
+        Ciphertext {
+            c1: GmpClassGroup::multi_pow(&c1s, scalars),
+            c2: GmpClassGroup::multi_pow(&c2s, scalars),
+        }
+    }
+
+    pub fn eval_scal_bigint(c: &Ciphertext, val: &BigInt) -> Ciphertext {
+        Self::eval_scal(c, bigint_to_mpz(val))
+    }
+
     pub fn eval_sum(c1: &Ciphertext, c2: &Ciphertext) -> Ciphertext {
         let c_new = Ciphertext {
             c1: c1.c1.clone() * c2.c1.clone(),
@@ -231,6 +645,77 @@ impl CLGroup {
     }
 }
 
+/// Integer fields of CL values, each with a size and sign bound that
+/// follows from the parameter set. `Deserialize` on `Mpz` only applies a
+/// global cap, so values from the network are checked with
+/// `CLGroup::check_mpz` before they are used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MpzField {
+    /// `a` or `c` of a form: positive, no longer than the discriminant.
+    FormOuter,
+    /// `b` of a form: any sign, no longer than the discriminant.
+    FormMiddle,
+    /// CL secret key or encryption randomness, in `[0, stilde * 2^40)`.
+    Exponent,
+    /// Response of a CL sigma protocol such as `u1` in `CLDLProof`.
+    Response,
+    /// A plaintext, in `[0, q)`.
+    Plaintext,
+}
+
+impl CLGroup {
+    pub fn max_bits(&self, field: MpzField) -> usize {
+        let disc_bits = self.generator.discriminant().bit_length();
+        let stilde_bits = self.stilde.bit_length();
+        match field {
+            MpzField::FormOuter | MpzField::FormMiddle => disc_bits,
+            MpzField::Exponent => stilde_bits + 40,
+            // stilde * 2^40 * 2^SECURITY_PARAMETER * (2^40 + 1)
+            MpzField::Response => stilde_bits + 40 + SECURITY_PARAMETER + 41,
+            MpzField::Plaintext => self.q.bit_length(),
+        }
+    }
+
+    pub fn check_mpz(&self, field: MpzField, value: &Mpz) -> Result<(), MulEcdsaError> {
+        let sign_ok = match field {
+            MpzField::FormOuter => value > &Mpz::zero(),
+            MpzField::FormMiddle => true,
+            _ => value >= &Mpz::zero(),
+        };
+        if sign_ok && value.bit_length() <= self.max_bits(field) {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::MpzOutOfBounds)
+        }
+    }
+
+    /// All `elements` are forms of this group's discriminant, checked before composing.
+    pub fn check_discriminants(&self, elements: &[&GmpClassGroup]) -> Result<(), MulEcdsaError> {
+        let discriminant = self.generator.discriminant();
+        if elements.iter().all(|e| e.discriminant() == discriminant) {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::DiscriminantMismatch)
+        }
+    }
+
+    /// A received element is a form of this group's discriminant with
+    /// coefficients in bounds.
+    pub fn check_element(&self, element: &GmpClassGroup) -> Result<(), MulEcdsaError> {
+        if element.discriminant() != self.generator.discriminant() {
+            return Err(MulEcdsaError::InvalidClassGroupElement);
+        }
+        self.check_mpz(MpzField::FormOuter, &element.a)?;
+        self.check_mpz(MpzField::FormMiddle, &element.b)?;
+        self.check_mpz(MpzField::FormOuter, &element.c)?;
+        let four_ac = Mpz::from(4u64) * &element.a * &element.c;
+        if &(&element.b * &element.b) - &four_ac != *element.discriminant() {
+            return Err(MulEcdsaError::InvalidClassGroupElement);
+        }
+        Ok(())
+    }
+}
+
 // secp256k1曲线群的阶
 pub fn q() -> Mpz {
     let q = Mpz::from_str(&FE::group_order().to_str_radix(10)).unwrap();
@@ -288,18 +773,115 @@ pub fn discrete_log_f(p: &Mpz, delta: &Mpz, fm: &GmpClassGroup) -> Mpz {
     }
 }
 
+/// Size of `Delta_K` in `CLGroup::setup_from_seed`.
+pub const SEEDED_DISC_BITS: usize = 2048;
+
+/// Check that `group` is what `CLGroup::setup_from_seed` derives from
+/// `seed`, at the size of its `Delta_K`.
+pub fn verify_setup(seed: &[u8], group: &CLGroup) -> Result<(), MulEcdsaError> {
+    let derived = CLGroup::setup_from_seed_bits(seed, group.delta_k.bit_length())?;
+    if derived.delta_k != group.delta_k
+        || derived.generator != group.generator
+        || derived.stilde != group.stilde
+    {
+        return Err(MulEcdsaError::SetupMismatch);
+    }
+    Ok(())
+}
+
+/// Draw `i` from the stream is the HKDF expansion of `seed` under
+/// `CL_SETUP` with context `i`, in blocks of at most 255 * 32 bytes.
+struct SeedStream<'a> {
+    seed: &'a [u8],
+    draws: u64,
+}
+
+impl RngCore for SeedStream<'_> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_be_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_be_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for block in dest.chunks_mut(255 * 32) {
+            kdf::derive(self.seed, &[], CL_SETUP, &self.draws.to_be_bytes(), block)
+                .expect("a block fits one HKDF expansion");
+            self.draws += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A `bits`-bit number: uniform below `2^bits` with the top bit set.
+fn random_bits<R: RngCore>(bits: usize, rng: &mut R) -> Mpz {
+    let mut bytes = vec![0u8; (bits + 7) / 8];
+    rng.fill_bytes(&mut bytes);
+    let mut value = Mpz::from(&bytes[..]) >> (bytes.len() * 8 - bits);
+    value.setbit(bits - 1);
+    value
+}
+
+/// `(a / p)` for an odd prime `p`, by Euler's criterion.
+fn legendre(a: &Mpz, p: &Mpz) -> i32 {
+    let power = a.powm(&((p.clone() - 1u64) >> 1), p);
+    if power.is_zero() {
+        0
+    } else if power == Mpz::one() {
+        1
+    } else {
+        -1
+    }
+}
+
+// Both conversions copy the big-endian magnitude and fix up the sign,
+// rather than printing and parsing a string.
 pub fn mpz_to_bigint(value: &Mpz) -> BigInt {
-    BigInt::from_str_radix(&value.to_str_radix(16), 16).unwrap()
+    let magnitude = BigInt::from_bytes(&Vec::<u8>::from(value));
+    match value.sign() {
+        Sign::Negative => BigInt::zero() - magnitude,
+        _ => magnitude,
+    }
 }
 
 pub fn bigint_to_mpz(value: &BigInt) -> Mpz {
-    Mpz::from_str_radix(&value.to_str_radix(16), 16).unwrap()
+    let magnitude = Mpz::from(&value.to_bytes()[..]);
+    if value < &BigInt::zero() {
+        -magnitude
+    } else {
+        magnitude
+    }
 }
 
 pub fn into_mpz(f: &FE) -> Mpz {
-    Mpz::from_str(&f.to_bigint().to_str_radix(10)).unwrap()
+    Mpz::from(&f.to_bigint().to_bytes()[..])
+}
+
+/// `ClassGroup::pow` for exponents held as curv integers.
+pub trait PowBigInt {
+    /// # Panics
+    ///
+    /// Panics in debug builds if `exponent` is negative.
+    fn pow_bigint(&mut self, exponent: &BigInt);
 }
 
+impl PowBigInt for GmpClassGroup {
+    fn pow_bigint(&mut self, exponent: &BigInt) {
+        self.pow(bigint_to_mpz(exponent));
+    }
+}
+
+#[cfg(feature = "params-1827")]
 lazy_static! {
     // [CL15, Fig. 2]
     // $$g:=\left[ \varphi_p^{-1}(\mathfrak{r}^2) \right]^p f^k$$.
@@ -307,19 +889,285 @@ lazy_static! {
     pub static ref GROUP_1827: CLGroup = CLGroup::new_1827();
 }
 
+#[cfg(feature = "params-1827")]
 lazy_static! {
     // [CL15, Fig. 2]
     pub static ref GROUP_UPDATE_1827: CLGroup = CLGroup::update_class_group_by_p(&GROUP_1827);
 }
 
+#[cfg(feature = "params-3072")]
 lazy_static! {
     pub static ref GROUP_3072: CLGroup = CLGroup::new_3072();
 }
 
+#[cfg(feature = "params-3072")]
 lazy_static! {
     pub static ref GROUP_UPDATE_3072: CLGroup = CLGroup::update_class_group_by_p(&GROUP_3072);
 }
 
+#[cfg(feature = "params-2048")]
+lazy_static! {
+    pub static ref GROUP_2048: CLGroup = CLGroup::new_2048();
+}
+
+#[cfg(feature = "params-2048")]
+lazy_static! {
+    pub static ref GROUP_UPDATE_2048: CLGroup = CLGroup::update_class_group_by_p(&GROUP_2048);
+}
+
+#[cfg(feature = "params-2432")]
+lazy_static! {
+    pub static ref GROUP_2432: CLGroup = CLGroup::new_2432();
+}
+
+#[cfg(feature = "params-2432")]
+lazy_static! {
+    pub static ref GROUP_UPDATE_2432: CLGroup = CLGroup::update_class_group_by_p(&GROUP_2432);
+}
+
+#[cfg(feature = "params-2944")]
+lazy_static! {
+    pub static ref GROUP_2944: CLGroup = CLGroup::new_2944();
+}
+
+#[cfg(feature = "params-2944")]
+lazy_static! {
+    pub static ref GROUP_UPDATE_2944: CLGroup = CLGroup::update_class_group_by_p(&GROUP_2944);
+}
+
+/// The CL parameters a session runs in: `base` is the group CL keys are
+/// generated in, `group` its `g^q` update used for encryption and proofs.
+///
+/// Sessions hold it behind an `Arc`, so any number of them share one copy
+/// of the generators and constants.
+#[derive(Debug)]
+pub struct CLContext {
+    pub base: CLGroup,
+    pub group: CLGroup,
+    /// Optional cache for `pow_public`, shared by every session on the
+    /// context.
+    pub pow_cache: Option<PowCache>,
+}
+
+impl CLContext {
+    pub fn new(base: CLGroup) -> Self {
+        let group = CLGroup::update_class_group_by_p(&base);
+        Self {
+            base,
+            group,
+            pow_cache: None,
+        }
+    }
+
+    pub fn with_pow_cache(mut self, capacity: usize) -> Self {
+        self.pow_cache = Some(PowCache::new(capacity));
+        self
+    }
+
+    /// `base^exponent` for public values, served from the cache if there is
+    /// one.
+    pub fn pow_public(&self, base: &GmpClassGroup, exponent: &Mpz) -> GmpClassGroup {
+        match &self.pow_cache {
+            Some(cache) => cache.pow(base, exponent),
+            None => {
+                let mut result = base.clone();
+                result.pow(exponent.clone());
+                result
+            }
+        }
+    }
+
+    pub fn pow_public_bigint(&self, base: &GmpClassGroup, exponent: &BigInt) -> GmpClassGroup {
+        self.pow_public(base, &bigint_to_mpz(exponent))
+    }
+}
+
+/// The base generator `g` of a session and the `g^q` derived from it, as a
+/// party claims them in keygen round one.
+///
+/// `g` is fixed by the parameter set and `g^q` is a public function of it,
+/// so every party recomputes both instead of trusting the sender. A party
+/// that could pick its own `g^q` could pick one whose discrete log to `f` it
+/// knows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DerivedBases {
+    pub g: GmpClassGroup,
+    pub gq: GmpClassGroup,
+}
+
+impl DerivedBases {
+    pub fn derive(cl: &CLContext) -> Self {
+        Self {
+            g: cl.base.generator.clone(),
+            gq: cl.pow_public(&cl.base.generator, &cl.base.q),
+        }
+    }
+
+    /// Check the bases against `cl`, recomputing `g^q` rather than taking
+    /// `cl.group` on faith.
+    pub fn verify(&self, cl: &CLContext) -> Result<(), MulEcdsaError> {
+        if self.g != cl.base.generator
+            || self.gq != cl.group.generator
+            || cl.pow_public(&self.g, &cl.base.q) != self.gq
+        {
+            return Err(MulEcdsaError::VrfyDerivedBasesFailed);
+        }
+        Ok(())
+    }
+}
+
+/// Bounded least-recently-used map from `(base, exponent)` to
+/// `base^exponent`.
+///
+/// Verifiers keep raising the same keys to the same public exponents, `q`
+/// above all, once per session. Only ever pass public values: whether a
+/// lookup hits is observable through timing, and entries stay in memory
+/// until evicted.
+#[derive(Debug)]
+pub struct PowCache {
+    capacity: usize,
+    entries: Mutex<PowCacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct PowCacheEntries {
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    map: HashMap<(GmpClassGroup, Mpz), (GmpClassGroup, u64)>,
+}
+
+impl PowCache {
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            capacity,
+            entries: Mutex::new(PowCacheEntries::default()),
+        }
+    }
+
+    pub fn pow(&self, base: &GmpClassGroup, exponent: &Mpz) -> GmpClassGroup {
+        let key = (base.clone(), exponent.clone());
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.tick += 1;
+            let tick = entries.tick;
+            if let Some((result, last_used)) = entries.map.get_mut(&key) {
+                *last_used = tick;
+                let result = result.clone();
+                entries.hits += 1;
+                return result;
+            }
+            entries.misses += 1;
+        }
+
+        // Exponentiate without holding the lock; a concurrent miss on the
+        // same key just computes it twice.
+        let mut result = base.clone();
+        result.pow(exponent.clone());
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&key) {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        let tick = entries.tick;
+        entries.map.insert(key, (result.clone(), tick));
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `(hits, misses)` since creation or the last `clear`.
+    pub fn stats(&self) -> (u64, u64) {
+        let entries = self.entries.lock().unwrap();
+        (entries.hits, entries.misses)
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = PowCacheEntries::default();
+    }
+}
+
+#[cfg(feature = "params-1827")]
+lazy_static! {
+    pub static ref CL_CONTEXT_1827: Arc<CLContext> = Arc::new(CLContext {
+        base: GROUP_1827.clone(),
+        group: GROUP_UPDATE_1827.clone(),
+        pow_cache: None,
+    });
+}
+
+#[cfg(feature = "params-3072")]
+lazy_static! {
+    pub static ref CL_CONTEXT_3072: Arc<CLContext> = Arc::new(CLContext {
+        base: GROUP_3072.clone(),
+        group: GROUP_UPDATE_3072.clone(),
+        pow_cache: None,
+    });
+}
+
+#[cfg(feature = "params-2048")]
+lazy_static! {
+    pub static ref CL_CONTEXT_2048: Arc<CLContext> = Arc::new(CLContext {
+        base: GROUP_2048.clone(),
+        group: GROUP_UPDATE_2048.clone(),
+        pow_cache: None,
+    });
+}
+
+#[cfg(feature = "params-2432")]
+lazy_static! {
+    pub static ref CL_CONTEXT_2432: Arc<CLContext> = Arc::new(CLContext {
+        base: GROUP_2432.clone(),
+        group: GROUP_UPDATE_2432.clone(),
+        pow_cache: None,
+    });
+}
+
+#[cfg(feature = "params-2944")]
+lazy_static! {
+    pub static ref CL_CONTEXT_2944: Arc<CLContext> = Arc::new(CLContext {
+        base: GROUP_2944.clone(),
+        group: GROUP_UPDATE_2944.clone(),
+        pow_cache: None,
+    });
+}
+
+/// The parameters `KeyGenPhase::new` and `SignPhase::new` run in: the
+/// 1827-bit set if it is compiled in, else the 3072-bit one.
+#[cfg(feature = "params-1827")]
+pub fn default_context() -> Arc<CLContext> {
+    CL_CONTEXT_1827.clone()
+}
+
+#[cfg(not(feature = "params-1827"))]
+pub fn default_context() -> Arc<CLContext> {
+    CL_CONTEXT_3072.clone()
+}
+
+#[cfg(feature = "insecure-small-params")]
+lazy_static! {
+    /// Test-only context on [`CLGroup::new_insecure_small`].
+    pub static ref CL_CONTEXT_INSECURE_SMALL: Arc<CLContext> =
+        Arc::new(CLContext::new(CLGroup::new_insecure_small()));
+}
+
 // #[test]
 // pub fn test_expo_f() {
 //     use curv::elliptic::curves::traits::ECScalar;
@@ -360,6 +1208,7 @@ lazy_static! {
 //     println!("comp = {:?}", comp);
 // }
 
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
 #[test]
 pub fn test_encrypt_decrypt() {
     let start_1827 = time::now();
@@ -380,6 +1229,24 @@ pub fn test_encrypt_decrypt() {
     println!("time with 3072bit = {:?}", end_3072 - start_3072);
 }
 
+#[cfg(feature = "params-1827")]
+#[test]
+fn encrypt_bigint_test() {
+    let group = &GROUP_UPDATE_1827;
+    let (sk, pk) = group.keygen();
+    let q = FE::group_order();
+    for m in [BigInt::zero(), BigInt::from(7u32), &q - BigInt::one()].iter() {
+        let (c, _) = CLGroup::encrypt_bigint(group, &pk, m).unwrap();
+        assert_eq!(&CLGroup::decrypt_bigint(group, &sk, &c).unwrap(), m);
+    }
+    assert_eq!(
+        CLGroup::encrypt_bigint(group, &pk, &q).unwrap_err(),
+        MulEcdsaError::PlaintextOutOfRange
+    );
+    assert!(CLGroup::encrypt_bigint(group, &pk, &(BigInt::zero() - BigInt::one())).is_err());
+}
+
+#[cfg(feature = "params-1827")]
 #[test]
 pub fn pow_a() {
     use crate::GE;
@@ -416,6 +1283,26 @@ pub fn pow_a() {
     println!("c ={}", c.len());
 }
 
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_eval_inner_product() {
+    let group = &GROUP_UPDATE_1827;
+    let (sk, pk) = group.keygen();
+    let ms: Vec<FE> = (0..3).map(|_| FE::random()).collect();
+    let ws: Vec<FE> = (0..3).map(|_| FE::random()).collect();
+    let cts: Vec<Ciphertext> = ms
+        .iter()
+        .map(|m| CLGroup::encrypt(group, &pk, m).0)
+        .collect();
+    let scalars: Vec<Mpz> = ws.iter().map(into_mpz).collect();
+    let ct = CLGroup::eval_inner_product(&cts, &scalars);
+    let expected = ms
+        .iter()
+        .zip(ws.iter())
+        .fold(FE::zero(), |acc, (m, w)| acc + m * w);
+    assert_eq!(CLGroup::decrypt(group, &sk, &ct), expected);
+}
+
 #[test]
 fn test_big_to_mpz() {
     let a = BigInt::from_str_radix("123", 16).unwrap();
@@ -424,3 +1311,227 @@ fn test_big_to_mpz() {
     let end = time::now();
     println!("duration = {:?}", end - start);
 }
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_pow_bigint() {
+    for value in ["0", "1", "-1", "ff", "-123456789abcdef0123456789abcdef"].iter() {
+        let expected = BigInt::from_str_radix(value, 16).unwrap();
+        let mpz = bigint_to_mpz(&expected);
+        assert_eq!(mpz, Mpz::from_str_radix(value, 16).unwrap());
+        assert_eq!(mpz_to_bigint(&mpz), expected);
+    }
+    let m = FE::random();
+    assert_eq!(
+        into_mpz(&m),
+        Mpz::from_str(&m.to_bigint().to_str_radix(10)).unwrap()
+    );
+
+    let group = &*GROUP_UPDATE_1827;
+    let e = BigInt::sample(300);
+    let mut expected = group.generator.clone();
+    expected.pow(bigint_to_mpz(&e));
+    let mut actual = group.generator.clone();
+    actual.pow_bigint(&e);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_setup() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(1501);
+    let group = CLGroup::setup(&q(), 400, &mut rng).unwrap();
+    assert_eq!(group.delta_k.bit_length(), 400);
+    assert_eq!(
+        group.generator.discriminant(),
+        &(&group.delta_k * &(q() * q()))
+    );
+    let (sk, pk) = group.keygen();
+    let m = FE::random();
+    let (c, _) = CLGroup::encrypt(&group, &pk, &m);
+    assert_eq!(CLGroup::decrypt(&group, &sk, &c), m);
+    assert!(CLGroup::setup(&Mpz::from(15u64), 400, &mut rng).is_err());
+
+    // Any prime works as the message space: 2^61 - 1 here.
+    let p = Mpz::from((1u64 << 61) - 1);
+    let group = CLGroup::setup(&p, 400, &mut rng).unwrap();
+    assert_eq!(group.q, p);
+    let group = CLGroup::update_class_group_by_p(&group);
+    let (sk, pk) = group.keygen();
+    let m = BigInt::from((1u64 << 61) - 2);
+    let (c, _) = CLGroup::encrypt_bigint(&group, &pk, &m).unwrap();
+    assert_eq!(CLGroup::decrypt_bigint(&group, &sk, &c).unwrap(), m);
+    let too_big = BigInt::from((1u64 << 61) - 1);
+    assert!(CLGroup::encrypt_bigint(&group, &pk, &too_big).is_err());
+    assert!(CLGroup::setup(&q(), 260, &mut rng).is_err());
+}
+
+#[test]
+fn test_setup_from_seed() {
+    let seed = b"dmz21 public setup test";
+    let group = CLGroup::setup_from_seed_bits(seed, 400).unwrap();
+    let again = CLGroup::setup_from_seed_bits(seed, 400).unwrap();
+    assert_eq!(group.generator, again.generator);
+    verify_setup(seed, &group).unwrap();
+    assert_eq!(
+        verify_setup(b"another seed", &group).unwrap_err(),
+        MulEcdsaError::SetupMismatch
+    );
+    let mut tampered = group.clone();
+    tampered.stilde = tampered.stilde.clone() + 1u64;
+    assert!(verify_setup(seed, &tampered).is_err());
+}
+
+#[cfg(all(
+    feature = "params-2048",
+    feature = "params-2432",
+    feature = "params-2944"
+))]
+#[test]
+fn test_intermediate_groups() {
+    let groups = [
+        (2048, &*GROUP_2048, &*GROUP_UPDATE_2048),
+        (2432, &*GROUP_2432, &*GROUP_UPDATE_2432),
+        (2944, &*GROUP_2944, &*GROUP_UPDATE_2944),
+    ];
+    for (bits, base, group) in groups.iter() {
+        assert_eq!(base.delta_k.bit_length(), *bits);
+        assert_eq!(
+            base.generator.discriminant(),
+            &(&base.delta_k * &(q() * q()))
+        );
+        let (sk, pk) = group.keygen();
+        let m = FE::random();
+        let (c, _) = CLGroup::encrypt(group, &pk, &m);
+        assert_eq!(CLGroup::decrypt(group, &sk, &c), m);
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_ciphertext_tag() {
+    let group = &GROUP_UPDATE_1827;
+    let (_, pk) = group.keygen();
+    let (ct, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    let (other, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    assert_eq!(ct.tag(b"session-a"), ct.clone().tag(b"session-a"));
+    assert_ne!(ct.tag(b"session-a"), ct.tag(b"session-b"));
+    assert_ne!(ct.tag(b"session-a"), other.tag(b"session-a"));
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_pow_cache() {
+    let cache = PowCache::new(2);
+    let g = &GROUP_1827.generator;
+    let expected = CLContext::new(GROUP_1827.clone()).pow_public(g, &q());
+    assert_eq!(cache.pow(g, &q()), expected);
+    assert_eq!(cache.pow(g, &q()), expected);
+    assert_eq!(cache.stats(), (1, 1));
+
+    // Filling past capacity evicts the least recently used entry.
+    cache.pow(g, &Mpz::from(3));
+    cache.pow(g, &q());
+    cache.pow(g, &Mpz::from(5));
+    assert_eq!(cache.len(), 2);
+    cache.pow(g, &q());
+    assert_eq!(cache.stats(), (3, 3));
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_derived_bases() {
+    let cl = &CL_CONTEXT_1827;
+    let bases = DerivedBases::derive(cl);
+    bases.verify(cl).unwrap();
+
+    let mut forged = bases.clone();
+    forged.gq = cl.pow_public(&bases.g, &Mpz::from(7));
+    assert!(forged.verify(cl).is_err());
+    // A context whose update group is not g^q is caught too.
+    let inconsistent = CLContext {
+        base: GROUP_1827.clone(),
+        group: GROUP_1827.clone(),
+        pow_cache: None,
+    };
+    assert!(DerivedBases::derive(&inconsistent)
+        .verify(&inconsistent)
+        .is_err());
+}
+
+#[cfg(feature = "insecure-small-params")]
+#[test]
+fn test_insecure_small_params() {
+    let cl = &CL_CONTEXT_INSECURE_SMALL;
+    assert_eq!(cl.base.generator.discriminant().bit_length(), 832);
+    let (sk, pk) = cl.base.keygen();
+    let updated = cl.group.pk_for_sk(sk.clone());
+    let m = FE::random();
+    let (c, _) = CLGroup::encrypt(&cl.group, &updated, &m);
+    assert_eq!(CLGroup::decrypt(&cl.group, &sk, &c), m);
+    // The base key is still the g^sk of the base group.
+    assert_eq!(cl.base.pk_for_sk(sk).0, pk.0);
+}
+
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
+#[test]
+fn test_mpz_bounds() {
+    let group = &GROUP_UPDATE_1827;
+    group.check_element(&group.generator).unwrap();
+    let (sk, pk) = group.keygen();
+    group.check_element(&pk.0).unwrap();
+    group.check_mpz(MpzField::Exponent, sk.0.expose()).unwrap();
+
+    let mut oversized = Mpz::one();
+    oversized.setbit(group.max_bits(MpzField::Response));
+    assert_eq!(
+        group.check_mpz(MpzField::Response, &oversized),
+        Err(MulEcdsaError::MpzOutOfBounds)
+    );
+    assert!(group.check_mpz(MpzField::Plaintext, &-Mpz::one()).is_err());
+    let mut tampered = pk.0.clone();
+    tampered.c += 1u64;
+    assert_eq!(
+        group.check_element(&tampered),
+        Err(MulEcdsaError::InvalidClassGroupElement)
+    );
+    assert!(group.check_element(&GROUP_3072.generator).is_err());
+    // Malformed hex is an error, not a panic.
+    assert!(serde_json::from_str::<Mpz>("\"-0\"").is_err());
+    assert!(serde_json::from_str::<Mpz>("\"xyz\"").is_err());
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_ciphertext_canonical() {
+    let group = &GROUP_UPDATE_1827;
+    let (_, pk) = group.keygen();
+    let (c, _) = CLGroup::encrypt(group, &pk, &FE::random());
+
+    // (a, b + 2a) is the same class as (a, b), but not reduced.
+    let shifted_b = &c.c1.b + &(&c.c1.a * &Mpz::from(2u64));
+    let shifted = Ciphertext {
+        c1: GmpClassGroup::from_ab_discriminant(
+            c.c1.a.clone(),
+            shifted_b,
+            c.c1.discriminant.clone(),
+        ),
+        c2: c.c2.clone(),
+    };
+    assert_ne!(shifted.c1, c.c1);
+    assert_eq!(shifted, c);
+    assert_eq!(shifted.tag(b"s"), c.tag(b"s"));
+    let mut hashes = std::collections::HashSet::new();
+    hashes.insert(c.clone());
+    assert!(hashes.contains(&shifted));
+
+    let decoded: Ciphertext =
+        serde_json::from_str(&serde_json::to_string(&shifted).unwrap()).unwrap();
+    assert_eq!(decoded.c1, c.c1);
+
+    let mut broken = c.clone();
+    broken.c1.c += 1u64;
+    assert!(serde_json::from_str::<Ciphertext>(&serde_json::to_string(&broken).unwrap()).is_err());
+}
@@ -16,7 +16,7 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 use crate::utilities::class_group::*;
-use classgroup::ClassGroup;
+use classgroup::prelude::ClassGroup;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -0,0 +1,110 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Time source of the expiry and scheduling logic.
+//!
+//! Two readings are kept apart. `unix_secs` is wall time, for timestamps
+//! shown to or compared with other machines: audit records, token
+//! lifetimes (`TokenVerifier::verify` takes it as `now`). `monotonic` only
+//! ever moves forward and is what local durations are measured on, so that
+//! a step of the host clock neither expires nor revives presignatures.
+//!
+//! `SystemClock` reads the host, `ManualClock` moves only when a test tells
+//! it to.
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub trait Clock: Debug + Send + Sync {
+    /// Seconds since the Unix epoch.
+    fn unix_secs(&self) -> i64;
+    /// Time since an origin fixed for the lifetime of the clock.
+    fn monotonic(&self) -> Duration;
+}
+
+#[derive(Debug)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn unix_secs(&self) -> i64 {
+        time::get_time().sec
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// The host clock, for components that were not given one.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock::new())
+}
+
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    unix_secs: AtomicI64,
+    monotonic_nanos: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(unix_secs: i64) -> Self {
+        Self {
+            unix_secs: AtomicI64::new(unix_secs),
+            monotonic_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Let `by` pass on both readings.
+    pub fn advance(&self, by: Duration) {
+        self.monotonic_nanos
+            .fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+        self.unix_secs
+            .fetch_add(by.as_secs() as i64, Ordering::SeqCst);
+    }
+
+    /// Step the wall clock only, as an NTP correction or an operator would.
+    pub fn set_unix_secs(&self, unix_secs: i64) {
+        self.unix_secs.store(unix_secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn unix_secs(&self) -> i64 {
+        self.unix_secs.load(Ordering::SeqCst)
+    }
+
+    fn monotonic(&self) -> Duration {
+        Duration::from_nanos(self.monotonic_nanos.load(Ordering::SeqCst))
+    }
+}
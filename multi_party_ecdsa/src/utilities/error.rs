@@ -19,6 +19,7 @@ use thiserror::Error;
 
 /// Represents errors.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum MulEcdsaError {
     #[error("Open dlcommitment failed")]
     OpenDLCommFailed,
@@ -42,6 +43,8 @@ pub enum MulEcdsaError {
     SerializeFailed,
     #[error("Verify VSS failed")]
     VrfyVSSFailed,
+    #[error("Verify PVSS transcript failed")]
+    VrfyPVSSFailed,
     #[error("To string failed")]
     ToStringFailed,
     #[error("From string failed")]
@@ -70,10 +73,44 @@ pub enum MulEcdsaError {
     VrfyCLDLProofFailed,
     #[error("Verify CLProof Failed")]
     VrfyCLProofFailed,
+    #[error("Verify CLKeyProof failed")]
+    VrfyCLKeyProofFailed,
+    #[error("Verify ZeroEncryptionProof failed")]
+    VrfyZeroEncProofFailed,
+    #[error("Verify MultiEncProof failed")]
+    VrfyMultiEncProofFailed,
+    #[error("Verify signature escrow failed")]
+    VrfyEscrowFailed,
+    #[error("Verify MtA response proof failed")]
+    VrfyMtaProofFailed,
     #[error("Not load keygen result")]
     VrfyPKFailed,
+    #[error("Updated CL public key is not the base key raised to q")]
+    VrfyUpdatedPKFailed,
+    #[error("Verify derived class group bases failed")]
+    VrfyDerivedBasesFailed,
+    #[error("Value belongs to another CL parameter set")]
+    ParamsMismatch,
+    #[error("Integer field out of bounds for the parameter set")]
+    MpzOutOfBounds,
+    #[error("Invalid class group element")]
+    InvalidClassGroupElement,
+    #[error("Class group element of another discriminant")]
+    DiscriminantMismatch,
+    #[error("No CL parameters for this message space and discriminant size")]
+    InvalidClParameters,
+    #[error("CL parameters were not derived from this seed")]
+    SetupMismatch,
+    #[error("Conformance fixture does not match this implementation")]
+    FixtureMismatch,
     #[error("verify update pk failed")]
     NotLoadKeyGenResult,
+    #[error("Signature recovery id has no Ethereum encoding")]
+    UnsupportedRecoveryId,
+    #[error("Payload does not hash to the message being signed")]
+    PayloadMismatch,
+    #[error("Payload could not be decoded")]
+    PayloadDecodeFailed,
     #[error("Invalid public key")]
     InvalidPublicKey,
     #[error("From Hex Failed")]
@@ -84,6 +121,33 @@ pub enum MulEcdsaError {
     MissingMsg,
     #[error("Invert a zero element")]
     InvertZero,
+    #[error("Writing audit record failed")]
+    AuditFailed,
+    #[error("Verify audit record failed")]
+    VrfyAuditRecordFailed,
+    #[error("Timestamping audit record failed")]
+    TimestampFailed,
+    #[error("Verify timestamp anchor failed")]
+    VrfyTimestampFailed,
+    #[error("Operation cancelled or past its deadline")]
+    Cancelled,
+    #[error("Exponentiation job failed on its worker")]
+    ExpWorkerFailed,
+    #[error("Requested key derivation output too long")]
+    KdfOutputTooLong,
+    #[error("Process was forked or its VM cloned since the state was cached")]
+    ForkDetected,
+    #[error("Plaintext outside of [0, q)")]
+    PlaintextOutOfRange,
+    #[error("Artifact document of unknown kind or schema version")]
+    UnsupportedSchema,
     #[error("General error")]
     GeneralError,
 }
+
+#[cfg(feature = "cl")]
+impl From<classgroup::prelude::Cancelled> for MulEcdsaError {
+    fn from(_: classgroup::prelude::Cancelled) -> Self {
+        MulEcdsaError::Cancelled
+    }
+}
@@ -0,0 +1,292 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Ethereum transaction encoding around a threshold signature.
+//!
+//! Covers legacy transactions with EIP-155 replay protection and EIP-1559
+//! (type 2) transactions: the RLP payload, the Keccak-256 hash the parties
+//! sign, and the raw signed transaction with `v` (or `y_parity`) derived
+//! from the recovery id. Integers are RLP-encoded big-endian without leading
+//! zeros, and `to: None` is a contract creation.
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::SignatureX;
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+pub type Address = [u8; 20];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegacyTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u64,
+    pub to: Option<Address>,
+    pub value: u128,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Eip1559Tx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    pub to: Option<Address>,
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvmTx {
+    Legacy(LegacyTx),
+    Eip1559(Eip1559Tx),
+}
+
+const EIP1559_TX_TYPE: u8 = 0x02;
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn rlp_len(out: &mut Vec<u8>, len: usize, offset: u8) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let len_bytes = trim(&len_bytes);
+        out.push(offset + 55 + len_bytes.len() as u8);
+        out.extend_from_slice(len_bytes);
+    }
+}
+
+fn rlp_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        rlp_len(out, bytes.len(), 0x80);
+        out.extend_from_slice(bytes);
+    }
+}
+
+fn rlp_uint(out: &mut Vec<u8>, value: u128) {
+    rlp_bytes(out, trim(&value.to_be_bytes()));
+}
+
+fn rlp_list(out: &mut Vec<u8>, payload: &[u8]) {
+    rlp_len(out, payload.len(), 0xc0);
+    out.extend_from_slice(payload);
+}
+
+fn rlp_to(out: &mut Vec<u8>, to: &Option<Address>) {
+    match to {
+        Some(address) => rlp_bytes(out, address),
+        None => rlp_bytes(out, &[]),
+    }
+}
+
+fn signature_parts(sig: &SignatureX) -> Result<(u8, Vec<u8>, Vec<u8>), MulEcdsaError> {
+    // Bit 1 of the recovery id says r overflowed the group order, which
+    // Ethereum has no encoding for.
+    if sig.recid > 1 {
+        return Err(MulEcdsaError::UnsupportedRecoveryId);
+    }
+    let parse = |hex: &str| {
+        BigInt::from_hex(hex)
+            .map(|n| n.to_bytes())
+            .map_err(|_| MulEcdsaError::FromHexFailed)
+    };
+    Ok((sig.recid, parse(&sig.r)?, parse(&sig.s)?))
+}
+
+impl LegacyTx {
+    fn rlp_fields(&self, out: &mut Vec<u8>) {
+        rlp_uint(out, self.nonce as u128);
+        rlp_uint(out, self.gas_price);
+        rlp_uint(out, self.gas_limit as u128);
+        rlp_to(out, &self.to);
+        rlp_uint(out, self.value);
+        rlp_bytes(out, &self.data);
+    }
+
+    /// EIP-155 payload: the fields followed by `chain_id, 0, 0`.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        self.rlp_fields(&mut fields);
+        rlp_uint(&mut fields, self.chain_id as u128);
+        rlp_uint(&mut fields, 0);
+        rlp_uint(&mut fields, 0);
+        let mut out = Vec::new();
+        rlp_list(&mut out, &fields);
+        out
+    }
+
+    pub fn encode_signed(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError> {
+        let (recid, r, s) = signature_parts(sig)?;
+        let v = recid as u128 + 35 + 2 * self.chain_id as u128;
+        let mut fields = Vec::new();
+        self.rlp_fields(&mut fields);
+        rlp_uint(&mut fields, v);
+        rlp_bytes(&mut fields, trim(&r));
+        rlp_bytes(&mut fields, trim(&s));
+        let mut out = Vec::new();
+        rlp_list(&mut out, &fields);
+        Ok(out)
+    }
+}
+
+impl Eip1559Tx {
+    fn rlp_fields(&self, out: &mut Vec<u8>) {
+        rlp_uint(out, self.chain_id as u128);
+        rlp_uint(out, self.nonce as u128);
+        rlp_uint(out, self.max_priority_fee_per_gas);
+        rlp_uint(out, self.max_fee_per_gas);
+        rlp_uint(out, self.gas_limit as u128);
+        rlp_to(out, &self.to);
+        rlp_uint(out, self.value);
+        rlp_bytes(out, &self.data);
+        let mut items = Vec::new();
+        for item in &self.access_list {
+            let mut keys = Vec::new();
+            for key in &item.storage_keys {
+                rlp_bytes(&mut keys, key);
+            }
+            let mut entry = Vec::new();
+            rlp_bytes(&mut entry, &item.address);
+            rlp_list(&mut entry, &keys);
+            rlp_list(&mut items, &entry);
+        }
+        rlp_list(out, &items);
+    }
+
+    /// `0x02 || rlp([chain_id, nonce, ..., access_list])`.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        self.rlp_fields(&mut fields);
+        let mut out = vec![EIP1559_TX_TYPE];
+        rlp_list(&mut out, &fields);
+        out
+    }
+
+    pub fn encode_signed(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError> {
+        let (y_parity, r, s) = signature_parts(sig)?;
+        let mut fields = Vec::new();
+        self.rlp_fields(&mut fields);
+        rlp_uint(&mut fields, y_parity as u128);
+        rlp_bytes(&mut fields, trim(&r));
+        rlp_bytes(&mut fields, trim(&s));
+        let mut out = vec![EIP1559_TX_TYPE];
+        rlp_list(&mut out, &fields);
+        Ok(out)
+    }
+}
+
+impl EvmTx {
+    pub fn signing_payload(&self) -> Vec<u8> {
+        match self {
+            EvmTx::Legacy(tx) => tx.signing_payload(),
+            EvmTx::Eip1559(tx) => tx.signing_payload(),
+        }
+    }
+
+    /// The 32-byte message to hand to the online sign phase.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&Keccak256::digest(&self.signing_payload()));
+        out
+    }
+
+    /// The raw transaction, ready for `eth_sendRawTransaction`.
+    pub fn encode_signed(&self, sig: &SignatureX) -> Result<Vec<u8>, MulEcdsaError> {
+        match self {
+            EvmTx::Legacy(tx) => tx.encode_signed(sig),
+            EvmTx::Eip1559(tx) => tx.encode_signed(sig),
+        }
+    }
+}
+
+#[test]
+fn evm_legacy_eip155_test() {
+    // The example transaction from EIP-155.
+    let tx = EvmTx::Legacy(LegacyTx {
+        chain_id: 1,
+        nonce: 9,
+        gas_price: 20_000_000_000,
+        gas_limit: 21000,
+        to: Some([0x35; 20]),
+        value: 1_000_000_000_000_000_000,
+        data: vec![],
+    });
+    assert_eq!(
+        hex::encode(tx.signing_payload()),
+        "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+         80018080"
+    );
+    assert_eq!(
+        hex::encode(tx.signing_hash()),
+        "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+    );
+
+    let sig = SignatureX {
+        r: "1".to_string(),
+        s: "2".to_string(),
+        recid: 1,
+    };
+    let raw = tx.encode_signed(&sig).unwrap();
+    // v = 1 + 35 + 2 * 1, then r and s.
+    assert_eq!(&raw[raw.len() - 3..], &[0x26, 0x01, 0x02]);
+    let overflowed = SignatureX { recid: 2, ..sig };
+    assert_eq!(
+        tx.encode_signed(&overflowed),
+        Err(MulEcdsaError::UnsupportedRecoveryId)
+    );
+}
+
+#[test]
+fn evm_eip1559_test() {
+    let tx = Eip1559Tx {
+        chain_id: 1,
+        nonce: 0,
+        max_priority_fee_per_gas: 0,
+        max_fee_per_gas: 0,
+        gas_limit: 0,
+        to: None,
+        value: 0,
+        data: vec![],
+        access_list: vec![],
+    };
+    assert_eq!(hex::encode(tx.signing_payload()), "02c90180808080808080c0");
+
+    let sig = SignatureX {
+        r: "1".to_string(),
+        s: "2".to_string(),
+        recid: 0,
+    };
+    let raw = EvmTx::Eip1559(tx).encode_signed(&sig).unwrap();
+    assert_eq!(hex::encode(raw), "02cc0180808080808080c0800102");
+}
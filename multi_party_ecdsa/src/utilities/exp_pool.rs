@@ -0,0 +1,311 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Worker pool for class group exponentiations.
+//!
+//! A single `pow` with a 1827-bit discriminant and a secret-sized exponent
+//! takes tens to hundreds of milliseconds. Running it on the thread that
+//! drives the protocol blocks everything else scheduled there, e.g. the
+//! online rounds of other sessions on an async runtime. The pool runs such
+//! jobs on dedicated threads; callers either wait for the batch with
+//! `pow_all` or take the receiver from `submit` and poll it. A job that
+//! panics is reported on its receiver as `MulEcdsaError::ExpWorkerFailed`
+//! and leaves its worker running.
+//!
+//! Jobs come in two priorities. Idle workers always drain the high queue
+//! first, so latency-sensitive work is not queued behind presign batches.
+//! Batches submitted with a `Deadline` are abandoned once it expires: queued
+//! jobs are skipped and running ones stop at the next exponent bit.
+//!
+//! The provers and the signing rounds run on `current()`: the global
+//! `EXP_POOL` unless the caller installed its own pool on the thread with
+//! `ExpPool::install`. A process that shares its cores with other CPU-bound
+//! work can install a small pool around the protocol calls, or cap the
+//! global pool with the `DMZ21_EXP_THREADS` environment variable.
+use crate::utilities::error::MulEcdsaError;
+use classgroup::prelude::{ClassGroup, Deadline, GmpClassGroup, Mpz};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    High,
+    Low,
+}
+
+/// Fixed set of worker threads fed by a high and a low priority queue.
+/// Workers exit once the pool is dropped and the queues are drained.
+pub struct ExpPool {
+    high: Sender<Job>,
+    low: Sender<Job>,
+    workers: usize,
+}
+
+lazy_static! {
+    /// Pool used when none is installed: `DMZ21_EXP_THREADS` workers if set,
+    /// otherwise one per available core.
+    pub static ref EXP_POOL: Arc<ExpPool> = Arc::new(ExpPool::new(global_workers()));
+}
+
+thread_local! {
+    static INSTALLED: RefCell<Option<Arc<ExpPool>>> = RefCell::new(None);
+}
+
+fn global_workers() -> usize {
+    std::env::var("DMZ21_EXP_THREADS")
+        .ok()
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+}
+
+/// The pool installed on this thread, or `EXP_POOL`.
+pub fn current() -> Arc<ExpPool> {
+    INSTALLED
+        .with(|installed| installed.borrow().clone())
+        .unwrap_or_else(|| EXP_POOL.clone())
+}
+
+/// Puts the previously installed pool back, also when the closure panics.
+struct Restore(Option<Arc<ExpPool>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        INSTALLED.with(|installed| *installed.borrow_mut() = previous);
+    }
+}
+
+/// Jobs report their own panics, see `ExpPool::spawn`; this only keeps a
+/// panicking job from taking the worker down with it.
+fn run(job: Job) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(job));
+}
+
+fn run_worker(high: Receiver<Job>, low: Receiver<Job>) {
+    loop {
+        if let Ok(job) = high.try_recv() {
+            run(job);
+            continue;
+        }
+        if let Ok(job) = low.try_recv() {
+            run(job);
+            continue;
+        }
+        select! {
+            recv(high) -> job => match job {
+                Ok(job) => run(job),
+                Err(_) => return,
+            },
+            recv(low) -> job => match job {
+                Ok(job) => run(job),
+                Err(_) => return,
+            },
+        }
+    }
+}
+
+impl ExpPool {
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let (high, high_rx) = unbounded::<Job>();
+        let (low, low_rx) = unbounded::<Job>();
+        for i in 0..workers {
+            let high_rx = high_rx.clone();
+            let low_rx = low_rx.clone();
+            thread::Builder::new()
+                .name(format!("cl-exp-{}", i))
+                .spawn(move || run_worker(high_rx, low_rx))
+                .expect("spawn exponentiation worker");
+        }
+        Self { high, low, workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Run `f` with this pool as `current()` on the calling thread. Threads
+    /// spawned inside `f` still see their own installed pool or the global
+    /// one.
+    pub fn install<R>(self: &Arc<Self>, f: impl FnOnce() -> R) -> R {
+        let previous = INSTALLED.with(|installed| installed.replace(Some(self.clone())));
+        let _restore = Restore(previous);
+        f()
+    }
+
+    fn queue(&self, priority: Priority) -> &Sender<Job> {
+        match priority {
+            Priority::High => &self.high,
+            Priority::Low => &self.low,
+        }
+    }
+
+    /// Queue `f` and return the receiver its result, or
+    /// `MulEcdsaError::ExpWorkerFailed` if it panicked, is delivered on.
+    fn spawn<T: Send + 'static>(
+        &self,
+        priority: Priority,
+        f: impl FnOnce() -> Result<T, MulEcdsaError> + Send + 'static,
+    ) -> Receiver<Result<T, MulEcdsaError>> {
+        let (tx, rx) = bounded(1);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f))
+                .unwrap_or(Err(MulEcdsaError::ExpWorkerFailed));
+            // The caller may have given up on the result.
+            let _ = tx.send(result);
+        });
+        // Workers outlive the senders, see `run`. Should the send fail
+        // anyway, `tx` is dropped with the job and `collect` reports it.
+        let _ = self.queue(priority).send(job);
+        rx
+    }
+
+    /// Queue `base^exp` and return the receiver the result is delivered on.
+    pub fn submit(
+        &self,
+        base: GmpClassGroup,
+        exp: Mpz,
+        priority: Priority,
+    ) -> Receiver<Result<GmpClassGroup, MulEcdsaError>> {
+        self.spawn(priority, move || {
+            let mut result = base;
+            result.pow(exp);
+            Ok(result)
+        })
+    }
+
+    /// Queue `base^exp` under a deadline. The receiver yields
+    /// `MulEcdsaError::Cancelled` if the deadline expired before the job
+    /// finished.
+    pub fn submit_until(
+        &self,
+        base: GmpClassGroup,
+        exp: Mpz,
+        priority: Priority,
+        deadline: Deadline,
+    ) -> Receiver<Result<GmpClassGroup, MulEcdsaError>> {
+        self.spawn(priority, move || {
+            let mut result = base;
+            result.pow_until(exp, &deadline)?;
+            Ok(result)
+        })
+    }
+
+    fn collect(
+        pending: Vec<Receiver<Result<GmpClassGroup, MulEcdsaError>>>,
+    ) -> Result<Vec<GmpClassGroup>, MulEcdsaError> {
+        pending
+            .into_iter()
+            .map(|rx| rx.recv().unwrap_or(Err(MulEcdsaError::ExpWorkerFailed)))
+            .collect()
+    }
+
+    /// `pow_all` that gives up with `MulEcdsaError::Cancelled` once
+    /// `deadline` expires.
+    pub fn pow_all_until(
+        &self,
+        jobs: Vec<(GmpClassGroup, Mpz)>,
+        priority: Priority,
+        deadline: &Deadline,
+    ) -> Result<Vec<GmpClassGroup>, MulEcdsaError> {
+        let pending = jobs
+            .into_iter()
+            .map(|(base, exp)| self.submit_until(base, exp, priority, deadline.clone()))
+            .collect();
+        Self::collect(pending)
+    }
+
+    /// Compute every `base^exp` of `jobs` on the pool and wait for all of them.
+    /// Results are in the order of `jobs`.
+    pub fn pow_all(
+        &self,
+        jobs: Vec<(GmpClassGroup, Mpz)>,
+        priority: Priority,
+    ) -> Result<Vec<GmpClassGroup>, MulEcdsaError> {
+        let pending = jobs
+            .into_iter()
+            .map(|(base, exp)| self.submit(base, exp, priority))
+            .collect();
+        Self::collect(pending)
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn exp_pool_test() {
+    use crate::utilities::class_group::GROUP_1827;
+
+    let pool = ExpPool::new(2);
+    let g = GROUP_1827.generator.clone();
+    let exps: Vec<Mpz> = (1..5u64).map(|e| Mpz::from(e * 1000 + 7)).collect();
+    let jobs = exps.iter().map(|e| (g.clone(), e.clone())).collect();
+    let results = pool.pow_all(jobs, Priority::Low).unwrap();
+    for (e, r) in exps.into_iter().zip(results) {
+        let mut expected = g.clone();
+        expected.pow(e);
+        assert_eq!(r, expected);
+    }
+    let high = pool.submit(g.clone(), Mpz::from(2u64), Priority::High);
+    let mut expected = g.clone();
+    expected.pow(Mpz::from(2u64));
+    assert_eq!(high.recv().unwrap(), Ok(expected));
+
+    let token = classgroup::prelude::CancellationToken::new();
+    token.cancel();
+    let cancelled = pool.pow_all_until(
+        vec![(g.clone(), Mpz::from(1234u64))],
+        Priority::Low,
+        &Deadline::none().with_token(token),
+    );
+    assert_eq!(cancelled, Err(MulEcdsaError::Cancelled));
+}
+
+#[test]
+fn exp_pool_install_test() {
+    let small = Arc::new(ExpPool::new(1));
+    let big = Arc::new(ExpPool::new(3));
+    assert!(Arc::ptr_eq(&current(), &*EXP_POOL));
+    small.install(|| {
+        assert!(Arc::ptr_eq(&current(), &small));
+        big.install(|| assert_eq!(current().workers(), 3));
+        assert!(Arc::ptr_eq(&current(), &small));
+    });
+    assert!(Arc::ptr_eq(&current(), &*EXP_POOL));
+}
+
+#[test]
+fn exp_pool_panic_test() {
+    let pool = ExpPool::new(1);
+    let failed = pool.spawn(Priority::Low, || -> Result<(), MulEcdsaError> {
+        panic!("exponentiation job panicked")
+    });
+    assert_eq!(failed.recv().unwrap(), Err(MulEcdsaError::ExpWorkerFailed));
+    // The only worker survived the panic.
+    let done = pool.spawn(Priority::High, || Ok(7u64));
+    assert_eq!(done.recv().unwrap(), Ok(7));
+}
@@ -0,0 +1,229 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Deterministic conformance fixtures for the CL primitives.
+//!
+//! A fixture set is JSON. Every integer is an `Mpz` in the crate's serde
+//! encoding (lowercase hex, `-` for negatives, no leading zeros) and every
+//! form is `{a, b, c, discriminant}` reduced. All randomness is derived from
+//! a seed with `kdf::TEST_RANDOMNESS`, so `generate` on the same group and
+//! seed gives the same bytes, and another implementation can check each
+//! case against its own `expo_f`, `discrete_log_f`, encryption with fixed
+//! randomness, decryption, `eval_scal` and `eval_sum`. `verify` replays a
+//! set against this crate.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::kdf::{self, TEST_RANDOMNESS};
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use serde::{Deserialize, Serialize};
+
+pub const FIXTURE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FixtureCase {
+    /// `f^k` for `f = [(q^2, q)]`.
+    ExpoF {
+        k: Mpz,
+        f_k: GmpClassGroup,
+    },
+    DiscreteLogF {
+        f_m: GmpClassGroup,
+        m: Mpz,
+    },
+    /// `pk = g^sk`, `c = (g^r, pk^r f^m)`, and `c` decrypts to `m` under
+    /// `sk`.
+    Encrypt {
+        sk: Mpz,
+        pk: GmpClassGroup,
+        m: Mpz,
+        r: Mpz,
+        ciphertext: Ciphertext,
+    },
+    EvalScal {
+        ciphertext: Ciphertext,
+        scalar: Mpz,
+        result: Ciphertext,
+    },
+    EvalSum {
+        a: Ciphertext,
+        b: Ciphertext,
+        result: Ciphertext,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClFixtures {
+    pub version: u32,
+    pub params: String,
+    pub q: Mpz,
+    pub discriminant: Mpz,
+    pub generator: GmpClassGroup,
+    pub stilde: Mpz,
+    pub cases: Vec<FixtureCase>,
+}
+
+/// A value in `[0, bound)` derived from `seed` and `context`, with 128 bits
+/// of slack so the reduction is statistically uniform.
+fn seeded_below(seed: &[u8], context: &str, bound: &Mpz) -> Mpz {
+    let mut wide = vec![0u8; bound.bit_length() / 8 + 17];
+    kdf::derive(seed, &[], TEST_RANDOMNESS, context.as_bytes(), &mut wide)
+        .expect("fixture randomness fits one HKDF expansion");
+    Mpz::from(&wide[..]).modulus(bound)
+}
+
+/// The fixture set of `group`, published under the parameter set name
+/// `params`.
+pub fn generate(group: &CLGroup, params: &str, seed: &[u8]) -> ClFixtures {
+    let q = group.q.clone();
+    let discriminant = group.generator.discriminant().clone();
+    let r_bound = &group.stilde * &Mpz::from(1u64 << 40);
+    let mut cases = Vec::new();
+
+    let mut ks = vec![Mpz::zero(), Mpz::one(), &q - 1u64];
+    ks.push(seeded_below(seed, "expo_f", &q));
+    for k in ks {
+        let f_k = expo_f(&q, &discriminant, &k);
+        cases.push(FixtureCase::DiscreteLogF {
+            f_m: f_k.clone(),
+            m: k.clone(),
+        });
+        cases.push(FixtureCase::ExpoF { k, f_k });
+    }
+
+    let sk = seeded_below(seed, "sk", &r_bound);
+    let pk = group.pk_for_sk(SK::from(sk.clone()));
+    let mut ciphertexts = Vec::new();
+    for i in 0..2 {
+        let m = seeded_below(seed, &format!("m/{}", i), &q);
+        let r = seeded_below(seed, &format!("r/{}", i), &r_bound);
+        let ciphertext = CLGroup::encrypt_with_r(group, &pk, &m, &r);
+        ciphertexts.push(ciphertext.clone());
+        cases.push(FixtureCase::Encrypt {
+            sk: sk.clone(),
+            pk: pk.0.clone(),
+            m,
+            r,
+            ciphertext,
+        });
+    }
+
+    let scalar = seeded_below(seed, "scalar", &q);
+    cases.push(FixtureCase::EvalScal {
+        ciphertext: ciphertexts[0].clone(),
+        scalar: scalar.clone(),
+        result: CLGroup::eval_scal(&ciphertexts[0], scalar),
+    });
+    cases.push(FixtureCase::EvalSum {
+        a: ciphertexts[0].clone(),
+        b: ciphertexts[1].clone(),
+        result: CLGroup::eval_sum(&ciphertexts[0], &ciphertexts[1]),
+    });
+
+    ClFixtures {
+        version: FIXTURE_VERSION,
+        params: params.to_string(),
+        q,
+        discriminant,
+        generator: group.generator.clone(),
+        stilde: group.stilde.clone(),
+        cases,
+    }
+}
+
+fn check(ok: bool) -> Result<(), MulEcdsaError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(MulEcdsaError::FixtureMismatch)
+    }
+}
+
+impl ClFixtures {
+    pub fn to_json(&self) -> Result<String, MulEcdsaError> {
+        serde_json::to_string_pretty(self).map_err(|_| MulEcdsaError::ToStringFailed)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MulEcdsaError> {
+        serde_json::from_str(json).map_err(|_| MulEcdsaError::FromStringFailed)
+    }
+
+    /// Recompute every case with this crate's primitives under `group`.
+    pub fn verify(&self, group: &CLGroup) -> Result<(), MulEcdsaError> {
+        check(self.version == FIXTURE_VERSION)?;
+        check(self.q == q() && self.generator == group.generator)?;
+        check(self.stilde == group.stilde && &self.discriminant == group.generator.discriminant())?;
+        let q = q();
+        for case in &self.cases {
+            match case {
+                FixtureCase::ExpoF { k, f_k } => {
+                    check(&expo_f(&q, &self.discriminant, k) == f_k)?;
+                }
+                FixtureCase::DiscreteLogF { f_m, m } => {
+                    check(&discrete_log_f(&q, &self.discriminant, f_m) == m)?;
+                }
+                FixtureCase::Encrypt {
+                    sk,
+                    pk,
+                    m,
+                    r,
+                    ciphertext,
+                } => {
+                    let sk = SK::from(sk.clone());
+                    let expected_pk = group.pk_for_sk(sk.clone());
+                    check(&expected_pk.0 == pk)?;
+                    check(&CLGroup::encrypt_with_r(group, &expected_pk, m, r) == ciphertext)?;
+                    let decrypted = into_mpz(&CLGroup::decrypt(group, &sk, ciphertext));
+                    check(&decrypted == m)?;
+                }
+                FixtureCase::EvalScal {
+                    ciphertext,
+                    scalar,
+                    result,
+                } => {
+                    check(&CLGroup::eval_scal(ciphertext, scalar.clone()) == result)?;
+                }
+                FixtureCase::EvalSum { a, b, result } => {
+                    check(&CLGroup::eval_sum(a, b) == result)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn fixtures_test() {
+    let group = &GROUP_UPDATE_1827;
+    let fixtures = generate(group, "cl1827", b"fixtures");
+    let json = fixtures.to_json().unwrap();
+    assert_eq!(
+        json,
+        generate(group, "cl1827", b"fixtures").to_json().unwrap()
+    );
+
+    let decoded = ClFixtures::from_json(&json).unwrap();
+    assert_eq!(decoded, fixtures);
+    decoded.verify(group).unwrap();
+
+    let mut tampered = decoded;
+    if let FixtureCase::ExpoF { k, .. } = &mut tampered.cases[1] {
+        *k += 1u64;
+    }
+    assert_eq!(tampered.verify(group), Err(MulEcdsaError::FixtureMismatch));
+}
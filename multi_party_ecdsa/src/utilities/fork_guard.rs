@@ -0,0 +1,204 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Detection of process forks and VM clones.
+//!
+//! Presignatures and other cached nonce material must be used once. A forked
+//! process or a cloned or restored VM holds a second copy of that cache, and
+//! if both copies finish a signature with the same presignature the key
+//! leaks. `ForkGuard` compares a snapshot of the environment against the one
+//! taken when it was armed and bumps its generation whenever they differ.
+//! Holders of cached secrets remember the generation they were created in
+//! and throw them away once it moves on.
+//!
+//! The default `SystemProbe` looks at the pid, the kernel boot id and the
+//! drift between the wall clock and the monotonic clock, which jumps when a
+//! VM snapshot is restored. Platforms with a VM generation counter can plug
+//! it in through `EnvironmentProbe`. None of this is perfect. Consult the
+//! guard right before a cached secret is used, not only when it is loaded.
+use crate::utilities::error::MulEcdsaError;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// What a probe observed. Two snapshots compare equal iff no fork or clone
+/// was detected between them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvironmentSnapshot {
+    pub pid: u32,
+    pub boot_id: Option<String>,
+    pub vm_generation: Option<Vec<u8>>,
+    /// Whether the clocks drifted apart beyond tolerance since the previous
+    /// snapshot. Always false on a baseline.
+    pub clock_jump: bool,
+}
+
+pub trait EnvironmentProbe: Send + Sync {
+    fn snapshot(&self) -> EnvironmentSnapshot;
+}
+
+/// Pid, `/proc/sys/kernel/random/boot_id` and clock drift.
+pub struct SystemProbe {
+    tolerance: Duration,
+    clocks: Mutex<(Instant, SystemTime)>,
+}
+
+impl SystemProbe {
+    /// `tolerance` bounds how far the wall clock may move against the
+    /// monotonic clock between two snapshots, e.g. through NTP, before it
+    /// counts as a restore. Too small only costs discarded presignatures.
+    pub fn new(tolerance: Duration) -> Self {
+        Self {
+            tolerance,
+            clocks: Mutex::new((Instant::now(), SystemTime::now())),
+        }
+    }
+}
+
+impl Default for SystemProbe {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+impl EnvironmentProbe for SystemProbe {
+    fn snapshot(&self) -> EnvironmentSnapshot {
+        let now = (Instant::now(), SystemTime::now());
+        let mut clocks = self.clocks.lock().unwrap();
+        let monotonic = now.0.duration_since(clocks.0);
+        let wall = now.1.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let expected_wall = clocks.1.duration_since(UNIX_EPOCH).unwrap_or_default() + monotonic;
+        let drift = if wall > expected_wall {
+            wall - expected_wall
+        } else {
+            expected_wall - wall
+        };
+        *clocks = now;
+        EnvironmentSnapshot {
+            pid: std::process::id(),
+            boot_id: std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+                .ok()
+                .map(|id| id.trim().to_string()),
+            vm_generation: None,
+            clock_jump: drift > self.tolerance,
+        }
+    }
+}
+
+struct GuardState {
+    baseline: EnvironmentSnapshot,
+    generation: u64,
+}
+
+pub struct ForkGuard {
+    probe: Box<dyn EnvironmentProbe>,
+    state: Mutex<GuardState>,
+}
+
+impl fmt::Debug for ForkGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("ForkGuard")
+            .field("baseline", &state.baseline)
+            .field("generation", &state.generation)
+            .finish()
+    }
+}
+
+impl Default for ForkGuard {
+    fn default() -> Self {
+        Self::with_probe(Box::new(SystemProbe::default()))
+    }
+}
+
+impl ForkGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_probe(probe: Box<dyn EnvironmentProbe>) -> Self {
+        let baseline = probe.snapshot();
+        Self {
+            probe,
+            state: Mutex::new(GuardState {
+                baseline,
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Probe the environment and return the current generation. It is
+    /// bumped, and the probe re-armed, whenever the snapshot differs from
+    /// the one taken at the last bump.
+    pub fn generation(&self) -> u64 {
+        let mut snapshot = self.probe.snapshot();
+        let mut state = self.state.lock().unwrap();
+        if snapshot != state.baseline {
+            snapshot.clock_jump = false;
+            state.baseline = snapshot;
+            state.generation += 1;
+            log::warn!(
+                "Fork or VM clone detected, cached presignatures of generation {} are void",
+                state.generation - 1
+            );
+        }
+        state.generation
+    }
+
+    /// `Err(ForkDetected)` unless the environment is still in `generation`.
+    pub fn ensure(&self, generation: u64) -> Result<(), MulEcdsaError> {
+        if self.generation() == generation {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::ForkDetected)
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) struct TestProbe(pub std::sync::Arc<std::sync::atomic::AtomicU32>);
+
+#[cfg(test)]
+impl EnvironmentProbe for TestProbe {
+    fn snapshot(&self) -> EnvironmentSnapshot {
+        EnvironmentSnapshot {
+            pid: self.0.load(std::sync::atomic::Ordering::SeqCst),
+            boot_id: None,
+            vm_generation: None,
+            clock_jump: false,
+        }
+    }
+}
+
+#[test]
+fn fork_guard_test() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    let pid = Arc::new(AtomicU32::new(1));
+    let guard = ForkGuard::with_probe(Box::new(TestProbe(pid.clone())));
+    assert_eq!(guard.generation(), 0);
+    guard.ensure(0).unwrap();
+
+    pid.store(2, Ordering::SeqCst);
+    assert_eq!(guard.ensure(0), Err(MulEcdsaError::ForkDetected));
+    assert_eq!(guard.generation(), 1);
+
+    // The real probe is stable within one process.
+    let system = ForkGuard::new();
+    assert_eq!(system.generation(), 0);
+}
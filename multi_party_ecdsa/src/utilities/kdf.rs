@@ -0,0 +1,110 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! HKDF-SHA256 with labeled domains for deriving sub-keys from a master
+//! secret.
+//!
+//! The HKDF info field is the length-prefixed label followed by the
+//! caller's context, so a key derived for one purpose can never equal one
+//! derived for another, whatever context bytes are used.
+use crate::utilities::error::MulEcdsaError;
+use crate::FE;
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// Purpose of a derived key. New purposes get a new constant; an existing
+/// label is never reused for something else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfLabel(&'static str);
+
+impl KdfLabel {
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+/// Symmetric key of one protocol session between two parties.
+pub const SESSION_KEY: KdfLabel = KdfLabel("dmz21/session-key/v1");
+/// Key encrypting a secret share for storage or transfer.
+pub const SHARE_ENCRYPTION_KEY: KdfLabel = KdfLabel("dmz21/share-encryption-key/v1");
+/// Randomness of `CLGroup::setup_from_seed`, which anyone holding the seed
+/// replays to check the parameters.
+pub const CL_SETUP: KdfLabel = KdfLabel("dmz21/cl-setup/v1");
+/// Seed of deterministic randomness in tests and fixtures. Never use it
+/// for anything that leaves a test.
+pub const TEST_RANDOMNESS: KdfLabel = KdfLabel("dmz21/test-randomness/v1");
+
+fn info(label: KdfLabel, context: &[u8]) -> Vec<u8> {
+    let label = label.as_str().as_bytes();
+    let mut info = Vec::with_capacity(8 + label.len() + context.len());
+    info.extend_from_slice(&(label.len() as u64).to_be_bytes());
+    info.extend_from_slice(label);
+    info.extend_from_slice(context);
+    info
+}
+
+/// Fill `out` with key material for `label` and `context` from `master`.
+/// `salt` may be empty. At most 255 * 32 bytes can be derived at once.
+pub fn derive(
+    master: &[u8],
+    salt: &[u8],
+    label: KdfLabel,
+    context: &[u8],
+    out: &mut [u8],
+) -> Result<(), MulEcdsaError> {
+    let salt = if salt.is_empty() { None } else { Some(salt) };
+    Hkdf::<Sha256>::new(salt, master)
+        .expand(&info(label, context), out)
+        .map_err(|_| MulEcdsaError::KdfOutputTooLong)
+}
+
+/// A 256-bit key for `label` and `context`, wiped when dropped.
+pub fn derive_key(master: &[u8], label: KdfLabel, context: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    derive(master, &[], label, context, &mut key[..]).expect("32 bytes is a valid HKDF length");
+    key
+}
+
+/// A secp256k1 scalar for `label` and `context`. 64 bytes are reduced mod
+/// the group order so the result is statistically uniform.
+pub fn derive_scalar(master: &[u8], label: KdfLabel, context: &[u8]) -> FE {
+    let mut wide = Zeroizing::new([0u8; 64]);
+    derive(master, &[], label, context, &mut wide[..]).expect("64 bytes is a valid HKDF length");
+    FE::from_bigint(&BigInt::from_bytes(&wide[..]).mod_floor(&FE::group_order()))
+}
+
+#[test]
+fn test_kdf_domain_separation() {
+    let master = b"master secret";
+    let session = derive_key(master, SESSION_KEY, b"1:2");
+    assert_eq!(*session, *derive_key(master, SESSION_KEY, b"1:2"));
+    assert_ne!(*session, *derive_key(master, SESSION_KEY, b"1:3"));
+    assert_ne!(*session, *derive_key(master, SHARE_ENCRYPTION_KEY, b"1:2"));
+    assert_eq!(
+        derive_scalar(master, TEST_RANDOMNESS, b"k"),
+        derive_scalar(master, TEST_RANDOMNESS, b"k")
+    );
+
+    let mut too_long = vec![0u8; 255 * 32 + 1];
+    assert_eq!(
+        derive(master, &[], SESSION_KEY, &[], &mut too_long),
+        Err(MulEcdsaError::KdfOutputTooLong)
+    );
+}
@@ -18,15 +18,64 @@
 pub const SECURITY_BITS: usize = 256;
 pub const SECURITY_PARAMETER: usize = 128;
 
+pub mod address;
+pub mod audit;
+pub mod batch;
+pub mod chain;
+#[cfg(feature = "cl")]
 pub mod cl_dl_proof;
+#[cfg(feature = "cl")]
+pub mod cl_key_proof;
+#[cfg(feature = "cl")]
 pub mod cl_proof;
+#[cfg(feature = "cl")]
+pub mod cl_typed;
+#[cfg(feature = "cl")]
 pub mod class_group;
+#[cfg(feature = "cl")]
 pub mod clkeypair;
+pub mod clock;
+#[cfg(feature = "cl")]
 pub mod dl_com_zk;
 pub mod eckeypair;
+#[cfg(feature = "cl")]
 pub mod elgamal;
 pub mod error;
+pub mod evm;
+#[cfg(feature = "cl")]
+pub mod exp_pool;
+#[cfg(feature = "cl")]
+pub mod fixtures;
+pub mod fork_guard;
+pub mod kdf;
+#[cfg(feature = "cl")]
+pub mod mta_proof;
+#[cfg(feature = "cl")]
+pub mod multi_recipient;
+#[cfg(feature = "cl")]
+pub mod params_id;
+pub mod payload;
+#[cfg(feature = "cl")]
+pub mod poseidon;
+#[cfg(feature = "cl")]
 pub mod promise_sigma_multi;
+#[cfg(feature = "cl")]
+pub mod pvss;
+pub mod redact;
+pub mod schema;
+#[cfg(feature = "cl")]
+pub mod secret;
+#[cfg(feature = "params-1827")]
 pub mod serialize;
+#[cfg(feature = "cl")]
+pub mod sig_escrow;
 pub mod signature;
+#[cfg(feature = "cl")]
+pub mod statement;
+pub mod timestamp;
+#[cfg(feature = "cl")]
+pub mod transcript;
+#[cfg(feature = "cl")]
 pub mod vss;
+#[cfg(feature = "cl")]
+pub mod zero_enc_proof;
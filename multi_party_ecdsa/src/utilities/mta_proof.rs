@@ -0,0 +1,262 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Proof that an MtA response was computed honestly.
+//!
+//! In MtA Alice sends `c = Enc(a)` and Bob answers with
+//! `d = c^b * Enc(beta)`, from which Alice decrypts `a*b + beta`. Bob proves
+//! that `d` has this form for the `b` inside a Pedersen commitment
+//! $$B = bG + sH$$ on the curve, and some `beta` and encryption randomness
+//! he knows. Tying `b` to `B` is what lets the rest of the protocol check
+//! that Bob used the same `b` everywhere. Since `f` has order `q`, `beta`
+//! needs no range proof: any value is a valid plaintext.
+//!
+//! $$d_1 = c_1^b g^r$$ and $$d_2 = c_2^b h^r f^\beta$$; the responses for
+//! `b` and `r` are integers, the one for `beta` and `s` are reduced mod `q`.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::statement::StatementContext;
+use crate::utilities::SECURITY_PARAMETER;
+use crate::{FE, GE};
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use curv::arithmetic::traits::*;
+use curv::elliptic::curves::Scalar;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MtaState {
+    /// Alice's ciphertext.
+    pub cipher: Ciphertext,
+    /// Bob's answer to it.
+    pub response: Ciphertext,
+    pub cl_pub_key: PK,
+    /// $$bG + sH$$.
+    pub b_commitment: GE,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MtaWit {
+    pub b: FE,
+    pub s: FE,
+    pub beta: FE,
+    pub r: SK,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MtaProof {
+    pub t: GE,
+    pub t1: GmpClassGroup,
+    pub t2: GmpClassGroup,
+    pub z_b: Mpz,
+    pub z_s: FE,
+    pub z_beta: FE,
+    pub z_r: Mpz,
+}
+
+/// $$bG + sH$$, `H` being curv's second generator, whose discrete log to
+/// `G` is unknown.
+pub fn commit_b(b: &FE, s: &FE) -> GE {
+    GE::generator() * b + GE::base_point2() * s
+}
+
+/// Bob's side of MtA: the response to `cipher` and the witness proving it.
+pub fn respond(
+    group: &CLGroup,
+    cl_pub_key: &PK,
+    cipher: &Ciphertext,
+    b: &FE,
+    beta: &FE,
+) -> (Ciphertext, MtaWit) {
+    let (masked, r) = CLGroup::encrypt(group, cl_pub_key, beta);
+    let scaled = CLGroup::eval_scal(cipher, into_mpz(b));
+    let witness = MtaWit {
+        b: b.clone(),
+        s: FE::random(),
+        beta: beta.clone(),
+        r,
+    };
+    (CLGroup::eval_sum(&scaled, &masked), witness)
+}
+
+fn b_mask_bound() -> BigInt {
+    FE::group_order() * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32 + 40)
+}
+
+fn r_mask_bound(group: &CLGroup) -> BigInt {
+    mpz_to_bigint(&group.stilde)
+        * BigInt::from(2u32).pow(40)
+        * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+        * BigInt::from(2u32).pow(40)
+}
+
+impl MtaProof {
+    pub fn prove(
+        group: &CLGroup,
+        witness: &MtaWit,
+        statement: &MtaState,
+    ) -> Result<Self, MulEcdsaError> {
+        let a_b = BigInt::sample_below(&b_mask_bound());
+        let a_s = FE::random();
+        let a_beta = FE::random();
+        let a_r = bigint_to_mpz(&BigInt::sample_below(&r_mask_bound(group)));
+        let a_b_mpz = bigint_to_mpz(&a_b);
+
+        let t = commit_b(&Scalar::from(&a_b), &a_s);
+        let mut powers = exp_pool::current()
+            .pow_all(
+                vec![
+                    (statement.cipher.c1.clone(), a_b_mpz.clone()),
+                    (group.generator.clone(), a_r.clone()),
+                    (statement.cipher.c2.clone(), a_b_mpz.clone()),
+                    (statement.cl_pub_key.0.clone(), a_r.clone()),
+                ],
+                Priority::Low,
+            )?
+            .into_iter();
+        let mut next = || powers.next().unwrap();
+        let t1 = next() * &next();
+        let f_beta = expo_f(&group.q, group.generator.discriminant(), &into_mpz(&a_beta));
+        let t2 = next() * &next() * f_beta;
+
+        let e = Self::challenge(group, statement, &t, &t1, &t2);
+        let e_fe: FE = Scalar::from(&e);
+        let e_mpz = bigint_to_mpz(&e);
+        Ok(Self {
+            t,
+            t1,
+            t2,
+            z_b: a_b_mpz + &e_mpz * &into_mpz(&witness.b),
+            z_s: a_s + &e_fe * &witness.s,
+            z_beta: a_beta + &e_fe * &witness.beta,
+            z_r: a_r + &e_mpz * witness.r.0.expose(),
+        })
+    }
+
+    /// Compute the Fiat-Shamir challenge for the proof.
+    pub fn challenge(
+        group: &CLGroup,
+        statement: &MtaState,
+        t: &GE,
+        t1: &GmpClassGroup,
+        t2: &GmpClassGroup,
+    ) -> BigInt {
+        StatementContext::new(group)
+            .transcript(b"DMZ21-MTA-PROOF")
+            .append(&statement.cipher)
+            .append(&statement.response)
+            .append(&statement.cl_pub_key)
+            .append(&statement.b_commitment)
+            .append(t)
+            .append(t1)
+            .append(t2)
+            .challenge()
+    }
+
+    pub fn verify(&self, group: &CLGroup, statement: &MtaState) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[
+            &statement.cipher.c1,
+            &statement.cipher.c2,
+            &statement.response.c1,
+            &statement.response.c2,
+            &statement.cl_pub_key.0,
+            &self.t1,
+            &self.t2,
+        ])?;
+        let e = Self::challenge(group, statement, &self.t, &self.t1, &self.t2);
+
+        // With e below 2^SECURITY_PARAMETER, honest responses stay below
+        // the mask bound plus one more multiple of the challenge space.
+        let slack = BigInt::from(2u32).pow(SECURITY_PARAMETER as u32);
+        let z_b_bound = b_mask_bound() + &slack * FE::group_order();
+        let z_r_bound = r_mask_bound(group)
+            + &slack * mpz_to_bigint(&group.stilde) * BigInt::from(2u32).pow(40);
+        if self.z_b < Mpz::zero()
+            || self.z_b > bigint_to_mpz(&z_b_bound)
+            || self.z_r < Mpz::zero()
+            || self.z_r > bigint_to_mpz(&z_r_bound)
+        {
+            return Err(MulEcdsaError::VrfyMtaProofFailed);
+        }
+
+        let e_fe: FE = Scalar::from(&e);
+        let z_b_fe: FE = Scalar::from(&mpz_to_bigint(&self.z_b));
+        let curve_ok = commit_b(&z_b_fe, &self.z_s) == &self.t + &statement.b_commitment * &e_fe;
+
+        let e_mpz = bigint_to_mpz(&e);
+        let mut powers = exp_pool::current()
+            .pow_all(
+                vec![
+                    (statement.cipher.c1.clone(), self.z_b.clone()),
+                    (group.generator.clone(), self.z_r.clone()),
+                    (statement.response.c1.clone(), e_mpz.clone()),
+                    (statement.cipher.c2.clone(), self.z_b.clone()),
+                    (statement.cl_pub_key.0.clone(), self.z_r.clone()),
+                    (statement.response.c2.clone(), e_mpz),
+                ],
+                Priority::Low,
+            )?
+            .into_iter();
+        let mut next = || powers.next().unwrap();
+        let first_ok = next() * &next() == next() * &self.t1;
+        let f_beta = expo_f(
+            &group.q,
+            group.generator.discriminant(),
+            &into_mpz(&self.z_beta),
+        );
+        let second_ok = next() * &next() * f_beta == next() * &self.t2;
+
+        if curve_ok && first_ok && second_ok {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::VrfyMtaProofFailed)
+        }
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn mta_proof_test() {
+    let group = &GROUP_UPDATE_1827;
+    let (sk, pk) = group.keygen();
+    let a = FE::random();
+    let (cipher, _) = CLGroup::encrypt(group, &pk, &a);
+
+    let (b, beta) = (FE::random(), FE::random());
+    let (response, witness) = respond(group, &pk, &cipher, &b, &beta);
+    assert_eq!(CLGroup::decrypt(group, &sk, &response), &a * &b + &beta);
+    let statement = MtaState {
+        cipher: cipher.clone(),
+        response,
+        cl_pub_key: pk.clone(),
+        b_commitment: commit_b(&witness.b, &witness.s),
+    };
+    let proof = MtaProof::prove(group, &witness, &statement).unwrap();
+    assert!(proof.verify(group, &statement).is_ok());
+
+    // A response made with another b than the committed one.
+    let (other, other_witness) = respond(group, &pk, &cipher, &FE::random(), &beta);
+    let mut cheat = statement.clone();
+    cheat.response = other;
+    let forged = MtaProof::prove(group, &other_witness, &cheat).unwrap();
+    assert!(forged.verify(group, &cheat).is_err());
+
+    let mut wrong_commitment = statement.clone();
+    wrong_commitment.b_commitment = commit_b(&witness.b, &FE::random());
+    assert!(proof.verify(group, &wrong_commitment).is_err());
+}
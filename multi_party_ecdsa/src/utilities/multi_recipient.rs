@@ -0,0 +1,251 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! CL encryption of one plaintext to several recipients.
+//!
+//! All recipients share the randomness r, so the ciphertext is a single
+//! c1 = g^r plus one c2_i = f^m * pk_i^r per recipient. Against n separate
+//! encryptions this saves n - 1 class group elements on the wire and n - 1
+//! exponentiations by the generator. Reusing r is safe here because every
+//! c2_i carries the same m: a recipient who can strip pk_i^r from its own
+//! component learns nothing it could not decrypt anyway. Never use it for
+//! different plaintexts.
+//!
+//! `MultiEncProof` shows that all components encrypt the same m under the
+//! shared c1, with one commitment per recipient plus one shared, and two
+//! responses in total.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::statement::StatementContext;
+use crate::utilities::SECURITY_PARAMETER;
+use crate::FE;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MultiCiphertext {
+    pub c1: GmpClassGroup,
+    /// One component per recipient, in the order of the public keys.
+    pub c2: Vec<GmpClassGroup>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultiEncState {
+    pub cipher: MultiCiphertext,
+    pub cl_pub_keys: Vec<PK>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultiEncWit {
+    pub m: FE,
+    pub r: SK,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultiEncProof {
+    pub t1: GmpClassGroup,
+    pub t2: Vec<GmpClassGroup>,
+    pub u1: Mpz,
+    pub u2: Mpz,
+}
+
+impl MultiCiphertext {
+    pub fn len(&self) -> usize {
+        self.c2.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.c2.is_empty()
+    }
+
+    /// The ordinary ciphertext seen by recipient `i`, which decrypts with
+    /// `CLGroup::decrypt` and its own secret key.
+    pub fn for_recipient(&self, i: usize) -> Option<Ciphertext> {
+        self.c2.get(i).map(|c2| Ciphertext {
+            c1: self.c1.clone(),
+            c2: c2.clone(),
+        })
+    }
+}
+
+/// Encrypt `m` to every key in `public_keys` with shared randomness.
+/// Returns the ciphertext and the randomness, the witness of
+/// `MultiEncProof`.
+pub fn encrypt_multi(
+    group: &CLGroup,
+    public_keys: &[PK],
+    m: &FE,
+) -> Result<(MultiCiphertext, SK), MulEcdsaError> {
+    let (r, c1) = group.keygen();
+    let exp_f = expo_f(&group.q, &group.generator.discriminant(), &into_mpz(m));
+    let c2 = exp_pool::current()
+        .pow_all(
+            public_keys
+                .iter()
+                .map(|pk| (pk.0.clone(), r.0.expose().clone()))
+                .collect(),
+            Priority::Low,
+        )?
+        .into_iter()
+        .map(|h_exp_r| h_exp_r * &exp_f)
+        .collect();
+    Ok((MultiCiphertext { c1: c1.0, c2 }, r))
+}
+
+impl MultiEncProof {
+    pub fn prove(
+        group: &CLGroup,
+        witness: &MultiEncWit,
+        statement: &MultiEncState,
+    ) -> Result<Self, MulEcdsaError> {
+        let s1 = bigint_to_mpz(&BigInt::sample_below(
+            &(&mpz_to_bigint(&group.stilde)
+                * BigInt::from(2u32).pow(40)
+                * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+                * BigInt::from(2u32).pow(40)),
+        ));
+        let s2_fe = FE::random();
+        let s2 = into_mpz(&s2_fe);
+        let fs2 = expo_f(&group.q, &group.generator.discriminant(), &s2);
+
+        let mut bases = vec![(group.generator.clone(), s1.clone())];
+        bases.extend(
+            statement
+                .cl_pub_keys
+                .iter()
+                .map(|pk| (pk.0.clone(), s1.clone())),
+        );
+        let mut powers = exp_pool::current()
+            .pow_all(bases, Priority::Low)?
+            .into_iter();
+        let t1 = powers.next().unwrap();
+        let t2: Vec<GmpClassGroup> = powers.map(|pks1| pks1 * &fs2).collect();
+
+        let k = Self::challenge(group, statement, &t1, &t2);
+        let u1 = s1 + &bigint_to_mpz(&k) * witness.r.0.expose();
+        let u2 = BigInt::mod_add(
+            &s2_fe.to_bigint(),
+            &(&k * witness.m.to_bigint()),
+            &FE::group_order(),
+        );
+        Ok(Self {
+            t1,
+            t2,
+            u1,
+            u2: bigint_to_mpz(&u2),
+        })
+    }
+
+    /// Compute the Fiat-Shamir challenge for the proof.
+    pub fn challenge(
+        group: &CLGroup,
+        statement: &MultiEncState,
+        t1: &GmpClassGroup,
+        t2: &[GmpClassGroup],
+    ) -> BigInt {
+        let recipients = (statement.cl_pub_keys.len() as u64).to_be_bytes();
+        let mut transcript = StatementContext::new(group)
+            .transcript(b"DMZ21-MULTI-ENC-PROOF")
+            .append(&recipients[..])
+            .append(&statement.cipher.c1);
+        for (pk, c2) in statement.cl_pub_keys.iter().zip(&statement.cipher.c2) {
+            transcript = transcript.append(pk).append(c2);
+        }
+        transcript = transcript.append(t1);
+        for t in t2 {
+            transcript = transcript.append(t);
+        }
+        transcript.challenge()
+    }
+
+    pub fn verify(&self, group: &CLGroup, statement: &MultiEncState) -> Result<(), MulEcdsaError> {
+        let n = statement.cl_pub_keys.len();
+        if n == 0 || statement.cipher.len() != n || self.t2.len() != n {
+            return Err(MulEcdsaError::VrfyMultiEncProofFailed);
+        }
+        let mut elements = vec![&statement.cipher.c1, &self.t1];
+        elements.extend(statement.cl_pub_keys.iter().map(|pk| &pk.0));
+        elements.extend(statement.cipher.c2.iter().chain(&self.t2));
+        group.check_discriminants(&elements)?;
+
+        let sample_size = &mpz_to_bigint(&group.stilde)
+            * (BigInt::from(2u32).pow(40))
+            * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+            * (BigInt::from(2u32).pow(40) + BigInt::one());
+        if self.u1 > bigint_to_mpz(&sample_size)
+            || self.u1 < Mpz::zero()
+            || self.u2 >= group.q
+            || self.u2 < Mpz::zero()
+        {
+            return Err(MulEcdsaError::VrfyMultiEncProofFailed);
+        }
+
+        let k = bigint_to_mpz(&Self::challenge(group, statement, &self.t1, &self.t2));
+        let mut bases = vec![
+            (group.generator.clone(), self.u1.clone()),
+            (statement.cipher.c1.clone(), k.clone()),
+        ];
+        for (pk, c2) in statement.cl_pub_keys.iter().zip(&statement.cipher.c2) {
+            bases.push((pk.0.clone(), self.u1.clone()));
+            bases.push((c2.clone(), k.clone()));
+        }
+        let powers = exp_pool::current().pow_all(bases, Priority::Low)?;
+
+        if self.t1.clone() * &powers[1] != powers[0] {
+            return Err(MulEcdsaError::VrfyMultiEncProofFailed);
+        }
+        let fu2 = expo_f(&group.q, &group.generator.discriminant(), &self.u2);
+        for (i, t2) in self.t2.iter().enumerate() {
+            let pku1 = &powers[2 + 2 * i];
+            let c2k = &powers[3 + 2 * i];
+            if t2.clone() * c2k != pku1.clone() * &fu2 {
+                return Err(MulEcdsaError::VrfyMultiEncProofFailed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn multi_enc_proof_test() {
+    let group = &GROUP_UPDATE_1827;
+    let keys: Vec<(SK, PK)> = (0..3).map(|_| group.keygen()).collect();
+    let pks: Vec<PK> = keys.iter().map(|(_, pk)| pk.clone()).collect();
+    let m = FE::random();
+    let (cipher, r) = encrypt_multi(group, &pks, &m).unwrap();
+    for (i, (sk, _)) in keys.iter().enumerate() {
+        let c = cipher.for_recipient(i).unwrap();
+        assert_eq!(CLGroup::decrypt(group, sk, &c), m);
+    }
+
+    let statement = MultiEncState {
+        cipher,
+        cl_pub_keys: pks.clone(),
+    };
+    let proof = MultiEncProof::prove(group, &MultiEncWit { m, r }, &statement).unwrap();
+    proof.verify(group, &statement).unwrap();
+
+    // Swap one component for an encryption of another value.
+    let mut forged = statement.clone();
+    let (other, _) = CLGroup::encrypt(group, &pks[1], &FE::random());
+    forged.cipher.c2[1] = other.c2;
+    assert!(proof.verify(group, &forged).is_err());
+}
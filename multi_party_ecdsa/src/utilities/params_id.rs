@@ -0,0 +1,80 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Binding of serialized CL artifacts to the parameter set they were made in.
+//!
+//! Class group elements carry their discriminant, but `GROUP_1827` and
+//! `GROUP_UPDATE_1827` share one, and a proof checked against the wrong
+//! `stilde` or generator just fails to verify without saying why. A
+//! `ParamsId` hashes everything that defines a `CLGroup`. The round
+//! messages that carry ciphertexts, public keys or proofs start with the id
+//! of the sender's group, and `MessageCaps` refuses them on decode if it is
+//! not the session's.
+use crate::utilities::class_group::*;
+use crate::utilities::transcript::Transcript;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ParamsId(pub [u8; 32]);
+
+impl ParamsId {
+    pub fn of(group: &CLGroup) -> Self {
+        ParamsId(
+            Transcript::new(b"DMZ21-CL-PARAMS")
+                .append(&group.delta_k)
+                .append(&group.generator)
+                .append(&group.stilde)
+                .append(&group.q)
+                .finalize(),
+        )
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl CLGroup {
+    pub fn params_id(&self) -> ParamsId {
+        ParamsId::of(self)
+    }
+}
+
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
+#[test]
+fn test_params_binding() {
+    use crate::protocols::multi_party::dmz21::message::{
+        MessageCaps, MultiSignMessage, SignPhaseTwoMsg,
+    };
+
+    // A phase two message with ciphertexts of the base group, which shares
+    // its discriminant, and so its size caps, with the update group.
+    let (_, pk) = GROUP_1827.keygen();
+    let mut msg = SignPhaseTwoMsg::new(GROUP_1827.params_id());
+    msg.homocipher = CLGroup::encrypt(&GROUP_1827, &pk, &crate::FE::random()).0;
+    msg.homocipher_plus = msg.homocipher.clone();
+    let bytes = bincode::serialize(&MultiSignMessage::PhaseTwoMsg(msg)).unwrap();
+
+    let base_caps = MessageCaps::for_group(&GROUP_1827);
+    assert!(MultiSignMessage::decode(&bytes, &base_caps).is_ok());
+    let caps = MessageCaps::for_group(&GROUP_UPDATE_1827);
+    assert_eq!(caps.cl_element, base_caps.cl_element);
+    assert!(MultiSignMessage::decode(&bytes, &caps).is_err());
+
+    assert_ne!(GROUP_1827.params_id(), GROUP_UPDATE_1827.params_id());
+    assert_ne!(GROUP_1827.params_id(), GROUP_3072.params_id());
+}
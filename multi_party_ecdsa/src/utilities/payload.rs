@@ -0,0 +1,556 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Human-reviewable views of what is being signed.
+//!
+//! The protocol only ever sees a digest, and an approver shown a bare hash
+//! is signing blind. A `PayloadDecoder` turns the serialized transaction
+//! behind the digest into labelled fields for the approval prompt and the
+//! audit log. Where the decoder can recompute the digest from the payload
+//! the two are checked against each other, so a decoded view cannot be
+//! attached to an unrelated message; `DecodedPayload::bound` tells the
+//! approver whether that check was possible.
+use crate::utilities::address::{segwit_address, BtcNetwork};
+use crate::utilities::error::MulEcdsaError;
+use bech32::Variant;
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadField {
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedPayload {
+    pub decoder: String,
+    /// Whether the payload was checked to hash to the signed digest.
+    pub bound: bool,
+    pub fields: Vec<PayloadField>,
+}
+
+pub trait PayloadDecoder: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The digest `payload` is signed as, if this decoder can compute it.
+    fn signing_hash(&self, payload: &[u8]) -> Option<[u8; 32]>;
+
+    fn decode(&self, payload: &[u8]) -> Result<Vec<PayloadField>, MulEcdsaError>;
+}
+
+/// Decode `payload` for the signing of `digest`, refusing a payload that
+/// hashes to something else.
+pub fn decode_payload(
+    decoder: &dyn PayloadDecoder,
+    payload: &[u8],
+    digest: &[u8; 32],
+) -> Result<DecodedPayload, MulEcdsaError> {
+    let bound = match decoder.signing_hash(payload) {
+        Some(hash) if &hash == digest => true,
+        Some(_) => return Err(MulEcdsaError::PayloadMismatch),
+        None => false,
+    };
+    Ok(DecodedPayload {
+        decoder: decoder.name().to_string(),
+        bound,
+        fields: decoder.decode(payload)?,
+    })
+}
+
+fn field(label: impl Into<String>, value: impl Into<String>) -> PayloadField {
+    PayloadField {
+        label: label.into(),
+        value: value.into(),
+    }
+}
+
+fn decimal(be_bytes: &[u8]) -> String {
+    BigInt::from_bytes(be_bytes).to_str_radix(10)
+}
+
+fn hex_address(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], MulEcdsaError> {
+    if input.len() < len {
+        return Err(MulEcdsaError::PayloadDecodeFailed);
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Ok(head)
+}
+
+fn be_len(bytes: &[u8]) -> Result<usize, MulEcdsaError> {
+    if bytes.len() > 8 {
+        return Err(MulEcdsaError::PayloadDecodeFailed);
+    }
+    Ok(bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize))
+}
+
+/// Deepest list nesting accepted. A transaction nests four deep, in its
+/// access list; more only serves to exhaust the stack.
+const RLP_MAX_DEPTH: usize = 16;
+
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Rlp<'a>>),
+}
+
+impl<'a> Rlp<'a> {
+    fn parse(input: &mut &'a [u8]) -> Result<Self, MulEcdsaError> {
+        Self::parse_at(input, 0)
+    }
+
+    fn parse_at(input: &mut &'a [u8], depth: usize) -> Result<Self, MulEcdsaError> {
+        let prefix = *input.first().ok_or(MulEcdsaError::PayloadDecodeFailed)?;
+        if prefix < 0x80 {
+            return Ok(Rlp::Bytes(take(input, 1)?));
+        }
+        *input = &input[1..];
+        match prefix {
+            0x80..=0xb7 => Ok(Rlp::Bytes(take(input, (prefix - 0x80) as usize)?)),
+            0xb8..=0xbf => {
+                let len = be_len(take(input, (prefix - 0xb7) as usize)?)?;
+                Ok(Rlp::Bytes(take(input, len)?))
+            }
+            0xc0..=0xf7 => Self::list(take(input, (prefix - 0xc0) as usize)?, depth + 1),
+            _ => {
+                let len = be_len(take(input, (prefix - 0xf7) as usize)?)?;
+                Self::list(take(input, len)?, depth + 1)
+            }
+        }
+    }
+
+    fn list(mut payload: &'a [u8], depth: usize) -> Result<Self, MulEcdsaError> {
+        if depth > RLP_MAX_DEPTH {
+            return Err(MulEcdsaError::PayloadDecodeFailed);
+        }
+        let mut items = Vec::new();
+        while !payload.is_empty() {
+            items.push(Self::parse_at(&mut payload, depth)?);
+        }
+        Ok(Rlp::List(items))
+    }
+
+    fn bytes(&self) -> Result<&'a [u8], MulEcdsaError> {
+        match self {
+            Rlp::Bytes(bytes) => Ok(bytes),
+            Rlp::List(_) => Err(MulEcdsaError::PayloadDecodeFailed),
+        }
+    }
+}
+
+fn evm_to(bytes: &[u8]) -> Result<String, MulEcdsaError> {
+    match bytes.len() {
+        0 => Ok("contract creation".to_string()),
+        20 => Ok(hex_address(bytes)),
+        _ => Err(MulEcdsaError::PayloadDecodeFailed),
+    }
+}
+
+const ERC20_TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+const ERC20_APPROVE: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+const ERC20_TRANSFER_FROM: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+
+fn abi_address(word: &[u8]) -> Result<String, MulEcdsaError> {
+    if word[..12].iter().any(|&b| b != 0) {
+        return Err(MulEcdsaError::PayloadDecodeFailed);
+    }
+    Ok(hex_address(&word[12..]))
+}
+
+fn abi_amount(word: &[u8]) -> String {
+    if word.iter().all(|&b| b == 0xff) {
+        "unlimited".to_string()
+    } else {
+        decimal(word)
+    }
+}
+
+/// Calldata of the ERC-20 calls by name; anything else as selector and raw
+/// argument words.
+fn decode_calldata(data: &[u8], fields: &mut Vec<PayloadField>) -> Result<(), MulEcdsaError> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    if data.len() < 4 || (data.len() - 4) % 32 != 0 {
+        fields.push(field("data", format!("0x{}", hex::encode(data))));
+        return Ok(());
+    }
+    let words: Vec<&[u8]> = data[4..].chunks(32).collect();
+    let selector = &data[..4];
+    match (selector, words.len()) {
+        (s, 2) if s == ERC20_TRANSFER => {
+            fields.push(field("call", "ERC-20 transfer"));
+            fields.push(field("recipient", abi_address(words[0])?));
+            fields.push(field("amount", abi_amount(words[1])));
+        }
+        (s, 2) if s == ERC20_APPROVE => {
+            fields.push(field("call", "ERC-20 approve"));
+            fields.push(field("spender", abi_address(words[0])?));
+            fields.push(field("amount", abi_amount(words[1])));
+        }
+        (s, 3) if s == ERC20_TRANSFER_FROM => {
+            fields.push(field("call", "ERC-20 transferFrom"));
+            fields.push(field("owner", abi_address(words[0])?));
+            fields.push(field("recipient", abi_address(words[1])?));
+            fields.push(field("amount", abi_amount(words[2])));
+        }
+        _ => {
+            fields.push(field("selector", format!("0x{}", hex::encode(selector))));
+            for (i, word) in words.iter().enumerate() {
+                fields.push(field(
+                    format!("arg{}", i),
+                    format!("0x{}", hex::encode(word)),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unsigned EVM transactions as produced by `EvmTx::signing_payload`:
+/// legacy (with or without EIP-155) and EIP-1559.
+pub struct EvmDecoder;
+
+impl PayloadDecoder for EvmDecoder {
+    fn name(&self) -> &'static str {
+        "evm"
+    }
+
+    fn signing_hash(&self, payload: &[u8]) -> Option<[u8; 32]> {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&Keccak256::digest(payload));
+        Some(out)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Vec<PayloadField>, MulEcdsaError> {
+        let typed = payload.first() == Some(&0x02);
+        let mut input = if typed { &payload[1..] } else { payload };
+        let items = match Rlp::parse(&mut input)? {
+            Rlp::List(items) if input.is_empty() => items,
+            _ => return Err(MulEcdsaError::PayloadDecodeFailed),
+        };
+        let item = |i: usize| {
+            items
+                .get(i)
+                .ok_or(MulEcdsaError::PayloadDecodeFailed)?
+                .bytes()
+        };
+        let mut fields = Vec::new();
+        let data = if typed {
+            if items.len() != 9 {
+                return Err(MulEcdsaError::PayloadDecodeFailed);
+            }
+            fields.push(field("type", "EIP-1559"));
+            fields.push(field("chain_id", decimal(item(0)?)));
+            fields.push(field("nonce", decimal(item(1)?)));
+            fields.push(field("to", evm_to(item(5)?)?));
+            fields.push(field("value_wei", decimal(item(6)?)));
+            fields.push(field("max_priority_fee_per_gas", decimal(item(2)?)));
+            fields.push(field("max_fee_per_gas", decimal(item(3)?)));
+            fields.push(field("gas_limit", decimal(item(4)?)));
+            item(7)?
+        } else {
+            match items.len() {
+                6 => fields.push(field("type", "legacy, no replay protection")),
+                9 => {
+                    fields.push(field("type", "legacy"));
+                    fields.push(field("chain_id", decimal(item(6)?)));
+                }
+                _ => return Err(MulEcdsaError::PayloadDecodeFailed),
+            }
+            fields.push(field("nonce", decimal(item(0)?)));
+            fields.push(field("to", evm_to(item(3)?)?));
+            fields.push(field("value_wei", decimal(item(4)?)));
+            fields.push(field("gas_price", decimal(item(1)?)));
+            fields.push(field("gas_limit", decimal(item(2)?)));
+            item(5)?
+        };
+        decode_calldata(data, &mut fields)?;
+        Ok(fields)
+    }
+}
+
+fn varint(input: &mut &[u8]) -> Result<usize, MulEcdsaError> {
+    let first = take(input, 1)?[0];
+    let len = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        _ => return Ok(first as usize),
+    };
+    let mut le = take(input, len)?.to_vec();
+    le.reverse();
+    be_len(&le)
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn base58_address(version: u8, hash: &[u8]) -> String {
+    let mut payload = vec![version];
+    payload.extend_from_slice(hash);
+    bs58::encode(payload).with_check().into_string()
+}
+
+/// Bitcoin transactions in network serialization. The sighash of an input
+/// also covers the amounts and scripts it spends, which are not part of the
+/// transaction, so the decoded view is not bound to the digest.
+pub struct BtcDecoder {
+    pub network: BtcNetwork,
+}
+
+impl BtcDecoder {
+    fn destination(&self, script: &[u8]) -> Result<String, MulEcdsaError> {
+        let mainnet = self.network == BtcNetwork::Mainnet;
+        Ok(match script {
+            [0x00, 0x14, program @ ..] if program.len() == 20 => {
+                segwit_address(self.network, 0, program, Variant::Bech32)?
+            }
+            [0x00, 0x20, program @ ..] if program.len() == 32 => {
+                segwit_address(self.network, 0, program, Variant::Bech32)?
+            }
+            [0x51, 0x20, program @ ..] if program.len() == 32 => {
+                segwit_address(self.network, 1, program, Variant::Bech32m)?
+            }
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+                base58_address(if mainnet { 0x00 } else { 0x6f }, hash)
+            }
+            [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => {
+                base58_address(if mainnet { 0x05 } else { 0xc4 }, hash)
+            }
+            [0x6a, data @ ..] => format!("OP_RETURN {}", hex::encode(data)),
+            _ => format!("script {}", hex::encode(script)),
+        })
+    }
+}
+
+impl PayloadDecoder for BtcDecoder {
+    fn name(&self) -> &'static str {
+        "bitcoin"
+    }
+
+    fn signing_hash(&self, _payload: &[u8]) -> Option<[u8; 32]> {
+        None
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Vec<PayloadField>, MulEcdsaError> {
+        let mut input = payload;
+        let version = le_u64(take(&mut input, 4)?);
+        if input.starts_with(&[0x00, 0x01]) {
+            take(&mut input, 2)?;
+        }
+        let mut fields = vec![field("version", version.to_string())];
+        let inputs = varint(&mut input)?;
+        for i in 0..inputs {
+            let mut txid = take(&mut input, 32)?.to_vec();
+            txid.reverse();
+            let vout = le_u64(take(&mut input, 4)?);
+            let script_len = varint(&mut input)?;
+            let skip = script_len
+                .checked_add(4)
+                .ok_or(MulEcdsaError::PayloadDecodeFailed)?;
+            take(&mut input, skip)?;
+            fields.push(field(
+                format!("input{}", i),
+                format!("{}:{}", hex::encode(txid), vout),
+            ));
+        }
+        let outputs = varint(&mut input)?;
+        let mut total = 0u64;
+        for i in 0..outputs {
+            let value = le_u64(take(&mut input, 8)?);
+            let script_len = varint(&mut input)?;
+            let script = take(&mut input, script_len)?;
+            total = total
+                .checked_add(value)
+                .ok_or(MulEcdsaError::PayloadDecodeFailed)?;
+            let value_field = format!("{} sat to {}", value, self.destination(script)?);
+            fields.push(field(format!("output{}", i), value_field));
+        }
+        fields.push(field("total_sat", total.to_string()));
+        Ok(fields)
+    }
+}
+
+fn flatten(prefix: &str, value: &serde_json::Value, fields: &mut Vec<PayloadField>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&join(key), value, fields);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&join(&i.to_string()), value, fields);
+            }
+        }
+        serde_json::Value::String(s) => fields.push(field(prefix, s.clone())),
+        other => fields.push(field(prefix, other.to_string())),
+    }
+}
+
+/// Cosmos SDK amino JSON sign docs (`SIGN_MODE_LEGACY_AMINO_JSON`), every
+/// leaf as a dotted path such as `msgs.0.value.amount.0.denom`.
+pub struct CosmosAminoDecoder;
+
+impl PayloadDecoder for CosmosAminoDecoder {
+    fn name(&self) -> &'static str {
+        "cosmos-amino-json"
+    }
+
+    fn signing_hash(&self, payload: &[u8]) -> Option<[u8; 32]> {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&Sha256::digest(payload));
+        Some(out)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Vec<PayloadField>, MulEcdsaError> {
+        let doc: serde_json::Value =
+            serde_json::from_slice(payload).map_err(|_| MulEcdsaError::PayloadDecodeFailed)?;
+        if !doc.is_object() {
+            return Err(MulEcdsaError::PayloadDecodeFailed);
+        }
+        let mut fields = Vec::new();
+        flatten("", &doc, &mut fields);
+        Ok(fields)
+    }
+}
+
+#[test]
+fn payload_decoder_test() {
+    use crate::utilities::evm::{Eip1559Tx, EvmTx};
+
+    let value = |fields: &[PayloadField], label: &str| {
+        fields
+            .iter()
+            .find(|f| f.label == label)
+            .map(|f| f.value.clone())
+    };
+
+    let mut data = ERC20_TRANSFER.to_vec();
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(&[0x35; 20]);
+    data.extend_from_slice(&[0u8; 31]);
+    data.push(100);
+    let tx = EvmTx::Eip1559(Eip1559Tx {
+        chain_id: 1,
+        nonce: 7,
+        max_priority_fee_per_gas: 1,
+        max_fee_per_gas: 2,
+        gas_limit: 60000,
+        to: Some([0x11; 20]),
+        value: 0,
+        data,
+        access_list: vec![],
+    });
+    let payload = tx.signing_payload();
+    let decoded = decode_payload(&EvmDecoder, &payload, &tx.signing_hash()).unwrap();
+    assert!(decoded.bound);
+    assert_eq!(value(&decoded.fields, "call").unwrap(), "ERC-20 transfer");
+    assert_eq!(
+        value(&decoded.fields, "recipient").unwrap(),
+        hex_address(&[0x35; 20])
+    );
+    assert_eq!(value(&decoded.fields, "amount").unwrap(), "100");
+    assert_eq!(value(&decoded.fields, "nonce").unwrap(), "7");
+    assert_eq!(
+        decode_payload(&EvmDecoder, &payload, &[0u8; 32]),
+        Err(MulEcdsaError::PayloadMismatch)
+    );
+    // lists nested past RLP_MAX_DEPTH are refused, not recursed into
+    let nest = |depth: usize| {
+        let mut nested = vec![0xc0];
+        for _ in 1..depth {
+            let mut outer = vec![0xf8, nested.len() as u8];
+            outer.extend_from_slice(&nested);
+            nested = outer;
+        }
+        nested
+    };
+    assert!(Rlp::parse(&mut &nest(RLP_MAX_DEPTH)[..]).is_ok());
+    assert!(Rlp::parse(&mut &nest(RLP_MAX_DEPTH + 1)[..]).is_err());
+    assert_eq!(
+        EvmDecoder.decode(&nest(64)),
+        Err(MulEcdsaError::PayloadDecodeFailed)
+    );
+
+    // version 2, one input, a p2wpkh and an OP_RETURN output, no witness
+    let mut raw = hex::decode("02000000").unwrap();
+    raw.push(1);
+    raw.extend_from_slice(&[0xaa; 32]);
+    raw.extend_from_slice(&[1, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+    raw.push(2);
+    raw.extend_from_slice(&50_000u64.to_le_bytes());
+    raw.extend_from_slice(&[22, 0x00, 0x14]);
+    raw.extend_from_slice(&hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap());
+    raw.extend_from_slice(&0u64.to_le_bytes());
+    raw.extend_from_slice(&[3, 0x6a, 0x01, 0x02]);
+    raw.extend_from_slice(&[0, 0, 0, 0]);
+    let btc = BtcDecoder {
+        network: BtcNetwork::Mainnet,
+    };
+    let decoded = decode_payload(&btc, &raw, &[0u8; 32]).unwrap();
+    assert!(!decoded.bound);
+    assert_eq!(
+        value(&decoded.fields, "output0").unwrap(),
+        "50000 sat to bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+    );
+    assert_eq!(
+        value(&decoded.fields, "output1").unwrap(),
+        "0 sat to OP_RETURN 0102"
+    );
+    assert_eq!(
+        value(&decoded.fields, "input0").unwrap(),
+        format!("{}:1", "aa".repeat(32))
+    );
+
+    // an input script length of u64::MAX must not overflow
+    let mut huge = hex::decode("02000000").unwrap();
+    huge.push(1);
+    huge.extend_from_slice(&[0xaa; 32]);
+    huge.extend_from_slice(&[1, 0, 0, 0, 0xff]);
+    huge.extend_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(btc.decode(&huge), Err(MulEcdsaError::PayloadDecodeFailed));
+
+    let doc = br#"{"chain_id":"cosmoshub-4","msgs":[{"type":"cosmos-sdk/MsgSend","value":{"amount":[{"amount":"5","denom":"uatom"}]}}]}"#;
+    let digest = CosmosAminoDecoder.signing_hash(doc).unwrap();
+    let decoded = decode_payload(&CosmosAminoDecoder, doc, &digest).unwrap();
+    assert_eq!(value(&decoded.fields, "chain_id").unwrap(), "cosmoshub-4");
+    assert_eq!(
+        value(&decoded.fields, "msgs.0.value.amount.0.denom").unwrap(),
+        "uatom"
+    );
+    assert!(CosmosAminoDecoder.decode(b"not json").is_err());
+}
@@ -0,0 +1,286 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Poseidon over the BN254 scalar field, as a transcript hash.
+//!
+//! Deployments that later prove protocol execution in a SNARK over BN254
+//! can recompute Poseidon challenges in a few hundred constraints, where
+//! SHA-256 costs tens of thousands. The permutation is the one of
+//! [GKRRS19] with `t = 3`, `alpha = 5`, 8 full and 57 partial rounds, and
+//! its constants are generated with the paper's Grain LFSR, which gives
+//! exactly the constants of circomlib's `Poseidon(2)`.
+//!
+//! `Poseidon` hashes bytes like any other `ProtocolHash`: the input is
+//! padded with `0x01` and zeros to whole blocks, each block is two field
+//! elements of 31 big-endian bytes absorbed into the rate, and the digest
+//! is the first rate element after the last block, written little-endian
+//! so that the challenge prefix is its low 128 bits.
+use crate::utilities::transcript::{ProtocolHash, ProtocolHashId};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use lazy_static::lazy_static;
+use sha2::digest::consts::U32;
+use sha2::digest::generic_array::GenericArray;
+use sha2::digest::{FixedOutput, Reset, Update};
+use std::collections::VecDeque;
+
+const WIDTH: usize = 3;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 57;
+const FIELD_BITS: usize = 254;
+/// Bytes per absorbed field element; any 31 bytes are below the modulus.
+const ELEMENT_BYTES: usize = 31;
+const BLOCK_BYTES: usize = ELEMENT_BYTES * (WIDTH - 1);
+
+pub struct PoseidonParams {
+    pub modulus: BigInt,
+    pub round_constants: Vec<BigInt>,
+    pub mds: Vec<Vec<BigInt>>,
+}
+
+lazy_static! {
+    pub static ref POSEIDON_BN254: PoseidonParams = PoseidonParams::generate(
+        BigInt::from_hex("30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001")
+            .unwrap()
+    );
+}
+
+/// The parameter LFSR of [GKRRS19, Appendix F].
+struct Grain {
+    bits: VecDeque<bool>,
+}
+
+impl Grain {
+    fn new() -> Self {
+        let mut bits = VecDeque::with_capacity(80);
+        let mut push = |value: usize, len: usize| {
+            for i in (0..len).rev() {
+                bits.push_back((value >> i) & 1 == 1);
+            }
+        };
+        // prime field, x^alpha S-box, field size, width and round numbers
+        push(1, 2);
+        push(0, 4);
+        push(FIELD_BITS, 12);
+        push(WIDTH, 12);
+        push(FULL_ROUNDS, 10);
+        push(PARTIAL_ROUNDS, 10);
+        push((1 << 30) - 1, 30);
+        let mut grain = Grain { bits };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let bit = [62, 51, 38, 23, 13, 0]
+            .iter()
+            .fold(false, |acc, &i| acc ^ self.bits[i]);
+        self.bits.pop_front();
+        self.bits.push_back(bit);
+        bit
+    }
+
+    /// Output bits are taken in pairs: the second is kept if the first is 1.
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.step();
+            let bit = self.step();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    fn next_int(&mut self) -> BigInt {
+        (0..FIELD_BITS).fold(BigInt::zero(), |acc, _| {
+            (acc << 1) + BigInt::from(self.next_bit() as u32)
+        })
+    }
+}
+
+impl PoseidonParams {
+    pub fn generate(modulus: BigInt) -> Self {
+        let mut grain = Grain::new();
+        let round_constants = (0..(FULL_ROUNDS + PARTIAL_ROUNDS) * WIDTH)
+            .map(|_| loop {
+                let c = grain.next_int();
+                if c < modulus {
+                    break c;
+                }
+            })
+            .collect();
+        // Cauchy matrix 1 / (x_i + y_j)
+        let xy: Vec<BigInt> = (0..2 * WIDTH).map(|_| grain.next_int()).collect();
+        let mds = (0..WIDTH)
+            .map(|i| {
+                (0..WIDTH)
+                    .map(|j| {
+                        let sum = BigInt::mod_add(&xy[i], &xy[WIDTH + j], &modulus);
+                        BigInt::mod_inv(&sum, &modulus).expect("singular Cauchy entry")
+                    })
+                    .collect()
+            })
+            .collect();
+        PoseidonParams {
+            modulus,
+            round_constants,
+            mds,
+        }
+    }
+
+    pub fn permute(&self, state: &mut [BigInt; WIDTH]) {
+        let p = &self.modulus;
+        let sbox = |x: &BigInt| BigInt::mod_pow(x, &BigInt::from(5u32), p);
+        for (round, constants) in self.round_constants.chunks(WIDTH).enumerate() {
+            for (x, c) in state.iter_mut().zip(constants) {
+                *x = BigInt::mod_add(x, c, p);
+            }
+            let half = FULL_ROUNDS / 2;
+            if round < half || round >= half + PARTIAL_ROUNDS {
+                for x in state.iter_mut() {
+                    *x = sbox(x);
+                }
+            } else {
+                state[0] = sbox(&state[0]);
+            }
+            let mixed: Vec<BigInt> = self
+                .mds
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .zip(state.iter())
+                        .fold(BigInt::zero(), |acc, (m, x)| {
+                            BigInt::mod_add(&acc, &BigInt::mod_mul(m, x, p), p)
+                        })
+                })
+                .collect();
+            state.clone_from_slice(&mixed);
+        }
+    }
+}
+
+/// Byte-oriented Poseidon sponge with rate 2 and capacity 1.
+#[derive(Clone)]
+pub struct Poseidon {
+    state: [BigInt; WIDTH],
+    buffer: Vec<u8>,
+}
+
+impl Default for Poseidon {
+    fn default() -> Self {
+        Poseidon {
+            state: [BigInt::zero(), BigInt::zero(), BigInt::zero()],
+            buffer: Vec::with_capacity(BLOCK_BYTES),
+        }
+    }
+}
+
+impl Poseidon {
+    fn absorb_block(&mut self) {
+        let params = &*POSEIDON_BN254;
+        for (i, chunk) in self.buffer.chunks(ELEMENT_BYTES).enumerate() {
+            let x = BigInt::from_bytes(chunk);
+            self.state[1 + i] = BigInt::mod_add(&self.state[1 + i], &x, &params.modulus);
+        }
+        params.permute(&mut self.state);
+        self.buffer.clear();
+    }
+
+    fn finish(&mut self) -> [u8; 32] {
+        self.buffer.push(1);
+        self.buffer.resize(BLOCK_BYTES, 0);
+        self.absorb_block();
+        let be = self.state[1].to_bytes();
+        let mut out = [0u8; 32];
+        for (o, b) in out.iter_mut().zip(be.iter().rev()) {
+            *o = *b;
+        }
+        out
+    }
+}
+
+impl Update for Poseidon {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        for &byte in data.as_ref() {
+            self.buffer.push(byte);
+            if self.buffer.len() == BLOCK_BYTES {
+                self.absorb_block();
+            }
+        }
+    }
+}
+
+impl FixedOutput for Poseidon {
+    type OutputSize = U32;
+
+    fn finalize_into(mut self, out: &mut GenericArray<u8, U32>) {
+        out.copy_from_slice(&self.finish());
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut GenericArray<u8, U32>) {
+        out.copy_from_slice(&self.finish());
+        Reset::reset(self);
+    }
+}
+
+impl Reset for Poseidon {
+    fn reset(&mut self) {
+        *self = Poseidon::default();
+    }
+}
+
+impl ProtocolHash for Poseidon {
+    const ID: ProtocolHashId = ProtocolHashId::Poseidon;
+}
+
+#[test]
+fn test_poseidon_matches_circomlib() {
+    let params = &*POSEIDON_BN254;
+    assert_eq!(
+        params.round_constants[0],
+        BigInt::from_hex("0ee9a592ba9a9518d05986d656f40c2114c4993c11bb29938d21d47304cd8e6e")
+            .unwrap()
+    );
+    // circomlib poseidon([1, 2])
+    let mut state = [BigInt::zero(), BigInt::from(1u32), BigInt::from(2u32)];
+    params.permute(&mut state);
+    assert_eq!(
+        state[0],
+        BigInt::from_hex("115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a")
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_poseidon_transcript() {
+    use crate::utilities::transcript::Transcript;
+    let digest = |data: &[u8]| {
+        Transcript::<Poseidon>::with_hash(b"test")
+            .append(data)
+            .finalize()
+    };
+    assert_eq!(digest(b"x"), digest(b"x"));
+    assert_ne!(digest(b"x"), digest(b"y"));
+    // inputs spanning several blocks
+    assert_ne!(digest(&[0u8; 100]), digest(&[0u8; 101]));
+    assert_ne!(
+        digest(b"x"),
+        Transcript::new(b"test").append(&b"x"[..]).finalize()
+    );
+}
@@ -18,21 +18,20 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
+use crate::utilities::batch::isolate_failures;
 use crate::utilities::class_group::Ciphertext as CLCipher;
 use crate::utilities::class_group::*;
 use crate::utilities::elgamal::ElgamalCipher;
 use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::statement::StatementContext;
 use crate::utilities::SECURITY_PARAMETER;
 use crate::{FE, GE};
-use classgroup::gmp::mpz::Mpz;
-use classgroup::gmp_classgroup::*;
-use classgroup::ClassGroup;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
 use curv::arithmetic::*;
-use curv::cryptographic_primitives::hashing::{Digest, DigestExt};
 use curv::elliptic::curves::{Point, Scalar};
 use curv::BigInt;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PromiseCipher {
@@ -86,7 +85,11 @@ impl PromiseCipher {
 }
 
 impl PromiseProof {
-    pub fn prove(group: &CLGroup, stat: &PromiseState, wit: &PromiseWit) -> Self {
+    pub fn prove(
+        group: &CLGroup,
+        stat: &PromiseState,
+        wit: &PromiseWit,
+    ) -> Result<Self, MulEcdsaError> {
         // First round
         let G = Point::generator();
         let P = stat.ec_pub_key.clone();
@@ -102,23 +105,22 @@ impl PromiseProof {
 
         let A1 = G * &s1;
         let A2 = G * &sm + &P * &s1;
-        let mut a1 = group.generator.clone();
-        let mut pkr1 = stat.cl_pub_key.0.clone();
-        crossbeam::scope(|thread| {
-            thread.spawn(|_| {
-                a1.pow(bigint_to_mpz(&s2));
-            });
-            thread.spawn(|_| {
-                pkr1.pow(bigint_to_mpz(&s2));
-            });
-        })
-        .unwrap();
+        let s2_mpz = bigint_to_mpz(&s2);
+        let mut powers = exp_pool::current().pow_all(
+            vec![
+                (group.generator.clone(), s2_mpz.clone()),
+                (stat.cl_pub_key.0.clone(), s2_mpz),
+            ],
+            Priority::Low,
+        )?;
+        let pkr1 = powers.pop().unwrap();
+        let a1 = powers.pop().unwrap();
 
-        let fr = expo_f(&q(), &group.generator.discriminant(), &into_mpz(&sm));
+        let fr = expo_f(&group.q, &group.generator.discriminant(), &into_mpz(&sm));
         let a2 = fr * pkr1;
 
         // Second round: get challenge
-        let e = Self::challenge(&stat, &A1, &A2, &a1, &a2);
+        let e = Self::challenge(group, &stat, &A1, &A2, &a1, &a2);
 
         // Third round
         let z11 = BigInt::mod_add(
@@ -127,7 +129,7 @@ impl PromiseProof {
             &FE::group_order(),
         );
         let z1 = Scalar::from(&z11);
-        let z2 = bigint_to_mpz(&s2) + &bigint_to_mpz(&e) * &wit.r2.0;
+        let z2 = bigint_to_mpz(&s2) + &bigint_to_mpz(&e) * wit.r2.0.expose();
         let zm1 = BigInt::mod_add(
             &sm.to_bigint(),
             &(&e * &wit.m.to_bigint()),
@@ -135,7 +137,7 @@ impl PromiseProof {
         );
         let zm = Scalar::from(&zm1);
 
-        Self {
+        Ok(Self {
             A1,
             A2,
             a1,
@@ -143,36 +145,39 @@ impl PromiseProof {
             z1,
             z2,
             zm,
-        }
+        })
     }
 
     pub fn challenge(
+        group: &CLGroup,
         state: &PromiseState,
         A1: &GE,
         A2: &GE,
         a1: &GmpClassGroup,
         a2: &GmpClassGroup,
     ) -> BigInt {
-        let hash256 = Sha256::new()
-            .chain_bigint(&BigInt::from_bytes(A1.to_bytes(true).as_ref()))
-            .chain_bigint(&BigInt::from_bytes(A2.to_bytes(true).as_ref()))
-            .chain_bigint(&BigInt::from_bytes(&a1.to_bytes()))
-            .chain_bigint(&BigInt::from_bytes(&a2.to_bytes()))
-            .chain_bigint(&BigInt::from_bytes(&state.cipher.cl_cipher.c1.to_bytes()))
-            .chain_bigint(&BigInt::from_bytes(&state.cipher.cl_cipher.c2.to_bytes()))
-            .chain_bigint(&BigInt::from_bytes(
-                state.cipher.ec_cipher.c1.to_bytes(true).as_ref(),
-            ))
-            .chain_bigint(&BigInt::from_bytes(
-                state.cipher.ec_cipher.c2.to_bytes(true).as_ref(),
-            ))
-            .result_bigint();
-
-        let hash128 = &BigInt::to_bytes(&hash256)[..SECURITY_PARAMETER / 8];
-        BigInt::from_bytes(hash128)
+        StatementContext::new(group)
+            .transcript(b"DMZ21-PROMISE-PROOF")
+            .append(&state.cl_pub_key)
+            .append(&state.ec_pub_key)
+            .append(A1)
+            .append(A2)
+            .append(a1)
+            .append(a2)
+            .append(&state.cipher.cl_cipher)
+            .append(&state.cipher.ec_cipher.c1)
+            .append(&state.cipher.ec_cipher.c2)
+            .challenge()
     }
 
     pub fn verify(&self, group: &CLGroup, stat: &PromiseState) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[
+            &stat.cipher.cl_cipher.c1,
+            &stat.cipher.cl_cipher.c2,
+            &stat.cl_pub_key.0,
+            &self.a1,
+            &self.a2,
+        ])?;
         let (C1, C2, c1, c2) = (
             &stat.cipher.ec_cipher.c1,
             &stat.cipher.ec_cipher.c2,
@@ -182,33 +187,34 @@ impl PromiseProof {
         let G = Point::generator();
         let P = &stat.ec_pub_key;
         let cl_pub_key = &stat.cl_pub_key;
-        let e: BigInt = Self::challenge(&stat, &self.A1, &self.A2, &self.a1, &self.a2);
+        let e: BigInt = Self::challenge(group, &stat, &self.A1, &self.A2, &self.a1, &self.a2);
         let e_fe: FE = Scalar::from(&e);
         let r1_left = G * &self.z1;
         let r1_right = &self.A1 + &(C1 * &e_fe);
-        let mut r2_left = group.generator.clone();
-        let mut c1k = c1.clone();
-        let mut pkz2 = cl_pub_key.0.clone();
-        let mut c2k = c2.clone();
-
-        crossbeam::scope(|thread| {
-            thread.spawn(|_| {
-                r2_left.pow(self.z2.clone());
-                c1k.pow(bigint_to_mpz(&e));
-            });
-            thread.spawn(|_| {
-                pkz2.pow(self.z2.clone());
-                c2k.pow(bigint_to_mpz(&e));
-            });
-        })
-        .unwrap();
+        let mut powers = exp_pool::current()
+            .pow_all(
+                vec![
+                    (group.generator.clone(), self.z2.clone()),
+                    (c1.clone(), bigint_to_mpz(&e)),
+                    (cl_pub_key.0.clone(), self.z2.clone()),
+                    (c2.clone(), bigint_to_mpz(&e)),
+                ],
+                Priority::Low,
+            )?
+            .into_iter();
+        let mut next = || powers.next().unwrap();
+        let (r2_left, c1k, pkz2, c2k) = (next(), next(), next(), next());
 
-        let r2_right = self.a1.clone() * c1k;
+        let r2_right = c1k * &self.a1;
         let m_ec_left = G * &self.zm + P * &self.z1;
         let m_ec_right = &self.A2 + &(C2 * &e_fe);
-        let fz3 = expo_f(&q(), &group.generator.discriminant(), &into_mpz(&self.zm));
+        let fz3 = expo_f(
+            &group.q,
+            &group.generator.discriminant(),
+            &into_mpz(&self.zm),
+        );
         let m_cl_left = pkz2 * fz3;
-        let m_cl_right = self.a2.clone() * c2k;
+        let m_cl_right = c2k * &self.a2;
         if r1_left == r1_right
             && r2_left == r2_right
             && m_cl_left == m_cl_right
@@ -219,4 +225,125 @@ impl PromiseProof {
             Err(MulEcdsaError::VrfyPromiseFailed)
         }
     }
+
+    /// Verify all `items` at once; on failure, return the indices of the
+    /// invalid ones.
+    ///
+    /// The two class group equations of the proofs are raised to random
+    /// `SECURITY_PARAMETER`-bit weights and multiplied up, so a batch costs
+    /// three multi-exponentiations and two plain ones instead of four
+    /// exponentiations per proof. Like the proofs themselves, this relies
+    /// on low order elements being hard to find. The curve equations are
+    /// cheap and checked one by one.
+    pub fn verify_batch(
+        group: &CLGroup,
+        items: &[(&PromiseProof, &PromiseState)],
+    ) -> Result<(), Vec<usize>> {
+        let failed = isolate_failures(
+            items.len(),
+            |subset| Self::batch_holds(group, items, subset),
+            |i| items[i].0.verify(group, items[i].1).is_ok(),
+        );
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+
+    fn batch_holds(
+        group: &CLGroup,
+        items: &[(&PromiseProof, &PromiseState)],
+        subset: &[usize],
+    ) -> bool {
+        let G = Point::generator();
+        let mut c1_terms = (Vec::new(), Vec::new());
+        let mut pk_terms = (Vec::new(), Vec::new());
+        let mut c2_terms = (Vec::new(), Vec::new());
+        let mut z2_sum = Mpz::zero();
+        let mut zm_sum = FE::zero();
+        for i in subset {
+            let (proof, stat) = items[*i];
+            let (C1, C2, c1, c2) = (
+                &stat.cipher.ec_cipher.c1,
+                &stat.cipher.ec_cipher.c2,
+                &stat.cipher.cl_cipher.c1,
+                &stat.cipher.cl_cipher.c2,
+            );
+            if group
+                .check_discriminants(&[c1, c2, &stat.cl_pub_key.0, &proof.a1, &proof.a2])
+                .is_err()
+                || proof.z2 < Mpz::zero()
+            {
+                return false;
+            }
+            let e = Self::challenge(group, stat, &proof.A1, &proof.A2, &proof.a1, &proof.a2);
+            let e_fe: FE = Scalar::from(&e);
+            if G * &proof.z1 != &proof.A1 + &(C1 * &e_fe)
+                || G * &proof.zm + &stat.ec_pub_key * &proof.z1 != &proof.A2 + &(C2 * &e_fe)
+            {
+                return false;
+            }
+
+            let rho = BigInt::sample(SECURITY_PARAMETER);
+            let rho_fe: FE = Scalar::from(&rho);
+            let rho = bigint_to_mpz(&rho);
+            let rho_e = &rho * &bigint_to_mpz(&e);
+            let rho_z2 = &rho * &proof.z2;
+            // g^z2 = c1^e * a1
+            z2_sum = z2_sum + &rho_z2;
+            c1_terms
+                .0
+                .extend_from_slice(&[c1.clone(), proof.a1.clone()]);
+            c1_terms.1.extend_from_slice(&[rho_e.clone(), rho.clone()]);
+            // pk^z2 * f^zm = c2^e * a2
+            zm_sum = zm_sum + &rho_fe * &proof.zm;
+            pk_terms.0.push(stat.cl_pub_key.0.clone());
+            pk_terms.1.push(rho_z2);
+            c2_terms
+                .0
+                .extend_from_slice(&[c2.clone(), proof.a2.clone()]);
+            c2_terms.1.extend_from_slice(&[rho_e, rho]);
+        }
+        let mut g_z2 = group.generator.clone();
+        g_z2.pow(z2_sum);
+        if g_z2 != GmpClassGroup::multi_pow(&c1_terms.0, &c1_terms.1) {
+            return false;
+        }
+        let f_zm = expo_f(
+            &group.q,
+            &group.generator.discriminant(),
+            &into_mpz(&zm_sum),
+        );
+        GmpClassGroup::multi_pow(&pk_terms.0, &pk_terms.1) * f_zm
+            == GmpClassGroup::multi_pow(&c2_terms.0, &c2_terms.1)
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn verify_batch_test() {
+    let group = &*GROUP_UPDATE_1827;
+    let mut proofs = Vec::new();
+    let mut states = Vec::new();
+    for _ in 0..5 {
+        let (_, cl_pub_key) = group.keygen();
+        let ec_pub_key = GE::generator() * &FE::random();
+        let m = FE::random();
+        let (cipher, r1, r2) = PromiseCipher::encrypt(group, &cl_pub_key, &ec_pub_key, &m);
+        let stat = PromiseState {
+            cipher,
+            ec_pub_key,
+            cl_pub_key,
+        };
+        proofs.push(PromiseProof::prove(group, &stat, &PromiseWit { m, r1, r2 }).unwrap());
+        states.push(stat);
+    }
+    // A proof for another statement.
+    states.swap(1, 3);
+    let items: Vec<(&PromiseProof, &PromiseState)> = proofs.iter().zip(states.iter()).collect();
+    assert_eq!(PromiseProof::verify_batch(group, &items), Err(vec![1, 3]));
+    states.swap(1, 3);
+    let items: Vec<(&PromiseProof, &PromiseState)> = proofs.iter().zip(states.iter()).collect();
+    assert_eq!(PromiseProof::verify_batch(group, &items), Ok(()));
 }
@@ -0,0 +1,185 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Publicly verifiable secret sharing over CL encryption.
+//!
+//! The dealer Feldman-shares a secret, encrypts share i under party i's CL
+//! public key and attaches a `CLDLProof` that the ciphertext encrypts the
+//! discrete log of the commitment point for index i. Anyone holding the
+//! transcript and the public keys can check every share, so a bad dealer is
+//! caught without a complaint round, and each party recovers its own share
+//! by decryption.
+use crate::utilities::cl_dl_proof::{CLDLProof, CLDLState, CLDLWit};
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::vss::{share_at_indices, Vss};
+use crate::FE;
+use curv::elliptic::curves::Point;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PvssShare {
+    pub index: String,
+    pub cipher: Ciphertext,
+    pub proof: CLDLProof,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PvssTranscript {
+    pub vss: Vss,
+    pub shares: Vec<PvssShare>,
+}
+
+impl PvssTranscript {
+    /// Share `secret` with threshold `t` among `recipients`, given as
+    /// (index, CL public key) pairs. Indices are hex strings as in `vss`.
+    pub fn deal(
+        group: &CLGroup,
+        t: usize,
+        secret: &FE,
+        recipients: &[(String, PK)],
+    ) -> Result<Self, MulEcdsaError> {
+        let indices: Vec<String> = recipients.iter().map(|(index, _)| index.clone()).collect();
+        let (vss, mut secret_shares) = share_at_indices(t, recipients.len(), secret, &indices);
+        let shares = recipients
+            .iter()
+            .map(|(index, pk)| {
+                let share = secret_shares.remove(index).unwrap();
+                let (cipher, r) = CLGroup::encrypt(group, pk, &share);
+                let proof = CLDLProof::prove(
+                    group,
+                    CLDLWit {
+                        dl_priv: share.clone(),
+                        r,
+                    },
+                    CLDLState {
+                        cipher: cipher.clone(),
+                        cl_pub_key: pk.clone(),
+                        dl_pub: Point::generator() * &share,
+                    },
+                )?;
+                Ok(PvssShare {
+                    index: index.clone(),
+                    cipher,
+                    proof,
+                })
+            })
+            .collect::<Result<_, MulEcdsaError>>()?;
+        Ok(Self { vss, shares })
+    }
+
+    /// Indices of `recipients` whose share is missing or fails its proof,
+    /// sorted. Empty means the dealer behaved for everyone. A transcript
+    /// with the wrong threshold or share count blames every recipient.
+    pub fn blame(&self, group: &CLGroup, t: usize, recipients: &[(String, PK)]) -> Vec<String> {
+        let mut blamed: Vec<String> =
+            if self.vss.commitments.len() != t + 1 || self.shares.len() != recipients.len() {
+                recipients.iter().map(|(index, _)| index.clone()).collect()
+            } else {
+                recipients
+                    .iter()
+                    .filter(|(index, pk)| self.verify_share(group, index, pk).is_err())
+                    .map(|(index, _)| index.clone())
+                    .collect()
+            };
+        blamed.sort();
+        blamed
+    }
+
+    pub fn verify(
+        &self,
+        group: &CLGroup,
+        t: usize,
+        recipients: &[(String, PK)],
+    ) -> Result<(), MulEcdsaError> {
+        if self.blame(group, t, recipients).is_empty() {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::VrfyPVSSFailed)
+        }
+    }
+
+    /// Check the share for `index`, encrypted under `pk`.
+    pub fn verify_share(&self, group: &CLGroup, index: &str, pk: &PK) -> Result<(), MulEcdsaError> {
+        let share = self.share(index)?;
+        let statement = CLDLState {
+            cipher: share.cipher.clone(),
+            cl_pub_key: pk.clone(),
+            dl_pub: self.vss.get_point_commitment(index.to_string()),
+        };
+        share
+            .proof
+            .verify(group, &statement)
+            .map_err(|_| MulEcdsaError::VrfyPVSSFailed)
+    }
+
+    /// Decrypt the share for `index` and check it against the commitments.
+    pub fn decrypt_share(
+        &self,
+        group: &CLGroup,
+        index: &str,
+        sk: &SK,
+    ) -> Result<FE, MulEcdsaError> {
+        let share = CLGroup::decrypt(group, sk, &self.share(index)?.cipher);
+        self.vss
+            .validate_share(&share, index.to_string())
+            .map_err(|_| MulEcdsaError::VrfyPVSSFailed)?;
+        Ok(share)
+    }
+
+    fn share(&self, index: &str) -> Result<&PvssShare, MulEcdsaError> {
+        self.shares
+            .iter()
+            .find(|share| share.index == index)
+            .ok_or(MulEcdsaError::VrfyPVSSFailed)
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_pvss() {
+    use crate::CU;
+    use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
+    use curv::BigInt;
+
+    let group = &GROUP_UPDATE_1827;
+    let keys: Vec<(SK, PK)> = (0..3).map(|_| group.keygen()).collect();
+    let recipients: Vec<(String, PK)> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, (_, pk))| (format!("{:x}", i + 1), pk.clone()))
+        .collect();
+    let secret = FE::random();
+    let transcript = PvssTranscript::deal(group, 1, &secret, &recipients).unwrap();
+    transcript.verify(group, 1, &recipients).unwrap();
+
+    let shares: Vec<FE> = keys
+        .iter()
+        .zip(&recipients)
+        .map(|((sk, _), (index, _))| transcript.decrypt_share(group, index, sk).unwrap())
+        .collect();
+    let points: Vec<FE> = (1..=3u32).map(|i| FE::from(&BigInt::from(i))).collect();
+    assert_eq!(
+        VerifiableSS::<CU>::lagrange_interpolation_at_zero(&points[..2], &shares[..2]),
+        secret
+    );
+
+    // Replacing a share with an encryption of another value blames its recipient.
+    let mut forged = transcript.clone();
+    forged.shares[2].cipher = CLGroup::encrypt(group, &recipients[2].1, &FE::random()).0;
+    assert_eq!(forged.blame(group, 1, &recipients), vec!["3".to_string()]);
+}
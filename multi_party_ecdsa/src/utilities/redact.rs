@@ -0,0 +1,132 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Shape-preserving redaction, for dumps of keystores and protocol state
+//! attached to support tickets and bug reports.
+//!
+//! `redact` writes a value as JSON with the value of every field named in
+//! `fields` replaced by a marker `{"redacted": hash}`, the hash being the
+//! hex SHA-256 of the canonical JSON of what it replaces. Equal secrets
+//! thus give equal markers, and a dump can be matched to another one of the
+//! same state without revealing it. A redacted field holding an object
+//! keeps its keys, only their values are replaced. A string holding JSON,
+//! as the keystore in a keygen result, is redacted in place and written
+//! back as a string. Keys come out sorted, so the dump of a value is
+//! always the same bytes.
+use crate::utilities::error::MulEcdsaError;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+pub const MARKER: &str = "redacted";
+
+/// The fields of keystores, protocol states and results that hold key
+/// material. `data` is the hex bincode of an offline sign result, which
+/// holds `k` and `sigma` and cannot be redacted field by field, so it is
+/// hidden whole.
+pub const SECRET_FIELDS: [&str; 14] = [
+    "beta_map",
+    "cl_priv_key",
+    "cl_sk",
+    "data",
+    "ec_sk",
+    "gamma",
+    "k",
+    "l",
+    "omega",
+    "rho",
+    "secret_share",
+    "share_sk",
+    "sigma",
+    "v_map",
+];
+
+pub fn redact<T: Serialize>(value: &T, fields: &[&str]) -> Result<String, MulEcdsaError> {
+    let mut value = serde_json::to_value(value).map_err(|_| MulEcdsaError::ToStringFailed)?;
+    redact_value(&mut value, fields);
+    serde_json::to_string(&value).map_err(|_| MulEcdsaError::ToStringFailed)
+}
+
+pub fn redact_value(value: &mut Value, fields: &[&str]) {
+    match value {
+        Value::Object(entries) => {
+            for (key, entry) in entries.iter_mut() {
+                if fields.contains(&key.as_str()) {
+                    hide(entry);
+                } else {
+                    redact_value(entry, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(item, fields)),
+        Value::String(text) => {
+            if let Ok(mut nested) = serde_json::from_str::<Value>(text) {
+                if nested.is_object() || nested.is_array() {
+                    redact_value(&mut nested, fields);
+                    *text = nested.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn is_marker(value: &Value) -> bool {
+    value.as_object().map_or(false, |entries| {
+        entries.len() == 1 && entries.get(MARKER).map_or(false, Value::is_string)
+    })
+}
+
+/// The marker that replaces `value`.
+pub fn marker(value: &Value) -> Value {
+    let digest = Sha256::digest(value.to_string().as_bytes());
+    let mut marker = serde_json::Map::new();
+    marker.insert(MARKER.to_string(), Value::from(hex::encode(digest)));
+    Value::Object(marker)
+}
+
+fn hide(value: &mut Value) {
+    match value {
+        Value::Object(entries) => entries.values_mut().for_each(hide),
+        _ => *value = marker(value),
+    }
+}
+
+#[test]
+fn redact_test() {
+    use serde_json::json;
+
+    let keystore = json!({
+        "index": "1",
+        "pubkey": { "pk": "02ab" },
+        "privkey": { "cl_sk": "17", "ec_sk": "2a", "share_sk": "2a" },
+    });
+    let result = Value::from(keystore.to_string());
+    let offline = json!({ "data": "00ab" }).to_string();
+    let state = json!({ "result": result, "sigma": { "2": [1, 2] }, "offline": offline });
+    let dump = redact(&state, &SECRET_FIELDS).unwrap();
+
+    let value: Value = serde_json::from_str(&dump).unwrap();
+    assert!(is_marker(&value["sigma"]["2"]));
+    assert!(!dump.contains("00ab"));
+    let inner: Value = serde_json::from_str(value["result"].as_str().unwrap()).unwrap();
+    assert_eq!(inner["index"], "1");
+    assert_eq!(inner["pubkey"]["pk"], "02ab");
+    // Equal secrets share a marker, distinct ones do not.
+    assert_eq!(inner["privkey"]["ec_sk"], inner["privkey"]["share_sk"]);
+    assert_ne!(inner["privkey"]["cl_sk"], inner["privkey"]["ec_sk"]);
+}
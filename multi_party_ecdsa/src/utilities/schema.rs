@@ -0,0 +1,156 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Versioned JSON documents for the artifacts handed to outside systems.
+//!
+//! A document is one JSON object: `schema`, the artifact kind,
+//! `schema_version`, and the fields of the artifact next to them.
+//!
+//! `audit_record`, an `AuditRecord`:
+//! - `seq` (integer), `timestamp` (unix seconds), `request_context` (string
+//!   or null);
+//! - `event`, an object whose own `event` field names the kind
+//!   (`key_created`, `signature_produced`, ...), points and scalars as hex;
+//! - `prev_hash` and `hash`, hex SHA-256 of the chain;
+//! - `signature`, `{ "r", "s", "recid" }` or null.
+//!
+//! `transcript`, a `Transcript` of one party's session recording:
+//! - `party`;
+//! - `entries`, one per call into its state machine: `index`, `from` and
+//!   `received` (hex) when the call delivered a message, `outcome` (the
+//!   message kind and recipients, or `error`) and `error`. What the party
+//!   sent is left out: it is in the binary recording, and the results hold
+//!   key material.
+//!
+//! Within a version fields are only added, and only optional ones. Any
+//! other change bumps `SCHEMA_VERSION`, and `validate` rejects documents of
+//! another version than the one this build writes.
+use crate::utilities::audit::AuditRecord;
+use crate::utilities::error::MulEcdsaError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub trait Artifact: Serialize + DeserializeOwned {
+    const KIND: &'static str;
+}
+
+impl Artifact for AuditRecord {
+    const KIND: &'static str = "audit_record";
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub party: String,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub index: usize,
+    pub from: Option<String>,
+    pub received: Option<String>,
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+impl Artifact for Transcript {
+    const KIND: &'static str = "transcript";
+}
+
+pub const KINDS: [&str; 2] = [AuditRecord::KIND, Transcript::KIND];
+
+pub fn to_document<T: Artifact>(artifact: &T) -> Result<String, MulEcdsaError> {
+    let mut value = serde_json::to_value(artifact).map_err(|_| MulEcdsaError::ToStringFailed)?;
+    let fields = value.as_object_mut().ok_or(MulEcdsaError::ToStringFailed)?;
+    fields.insert("schema".to_string(), Value::from(T::KIND));
+    fields.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    serde_json::to_string(&value).map_err(|_| MulEcdsaError::ToStringFailed)
+}
+
+pub fn from_document<T: Artifact>(json: &str) -> Result<T, MulEcdsaError> {
+    let value = header(json, Some(T::KIND))?.1;
+    serde_json::from_value(value).map_err(|_| MulEcdsaError::FromStringFailed)
+}
+
+/// Check that `json` is a document this build writes and return its kind.
+pub fn validate(json: &str) -> Result<&'static str, MulEcdsaError> {
+    let (kind, value) = header(json, None)?;
+    let parsed = match kind {
+        "audit_record" => serde_json::from_value::<AuditRecord>(value).map(|_| ()),
+        _ => serde_json::from_value::<Transcript>(value).map(|_| ()),
+    };
+    parsed.map_err(|_| MulEcdsaError::FromStringFailed)?;
+    Ok(kind)
+}
+
+fn header(json: &str, expected: Option<&str>) -> Result<(&'static str, Value), MulEcdsaError> {
+    let value: Value = serde_json::from_str(json).map_err(|_| MulEcdsaError::FromStringFailed)?;
+    let kind = value.get("schema").and_then(Value::as_str);
+    let kind = *KINDS
+        .iter()
+        .find(|known| Some(**known) == kind && expected.map_or(true, |e| e == **known))
+        .ok_or(MulEcdsaError::UnsupportedSchema)?;
+    let version = value.get("schema_version").and_then(Value::as_u64);
+    if version != Some(SCHEMA_VERSION as u64) {
+        return Err(MulEcdsaError::UnsupportedSchema);
+    }
+    Ok((kind, value))
+}
+
+#[test]
+fn schema_test() {
+    use crate::utilities::audit::{AuditEvent, AuditLog, MemorySink};
+
+    let log = AuditLog::new(Box::new(MemorySink::new()), None);
+    let record = log
+        .emit(
+            AuditEvent::PartyRevoked {
+                party_index: "2".to_string(),
+                reason: "lost device".to_string(),
+            },
+            None,
+        )
+        .unwrap();
+    let json = to_document(&record).unwrap();
+    assert_eq!(validate(&json).unwrap(), "audit_record");
+    let value: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["event"]["event"], "party_revoked");
+    assert_eq!(from_document::<AuditRecord>(&json).unwrap(), record);
+    assert!(from_document::<Transcript>(&json).is_err());
+
+    let transcript = Transcript {
+        party: "1".to_string(),
+        entries: vec![TranscriptEntry {
+            index: 0,
+            from: None,
+            received: None,
+            outcome: "broadcast".to_string(),
+            error: None,
+        }],
+    };
+    let json = to_document(&transcript).unwrap();
+    assert_eq!(validate(&json).unwrap(), "transcript");
+    assert_eq!(from_document::<Transcript>(&json).unwrap(), transcript);
+
+    let newer = json.replace("\"schema_version\":1", "\"schema_version\":2");
+    assert!(validate(&newer).is_err());
+    assert!(validate("{\"schema\":\"transcript\"}").is_err());
+    assert!(validate("{\"schema\":\"transcript\",\"schema_version\":1}").is_err());
+}
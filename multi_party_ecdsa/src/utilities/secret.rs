@@ -0,0 +1,254 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Holders for long-lived secrets: CL secret keys and the ECDSA key shares.
+//!
+//! Both keep their value in a buffer that is `mlock`ed where the OS allows
+//! it and wiped on drop, print as `<redacted>` and have no `Serialize`
+//! unless a field opts in with `#[serde(with = "exposed_mpz")]` or
+//! `exposed_scalar`, as the key file does. Arithmetic still runs on copies
+//! returned by `expose`, which GMP and curv allocate and free as usual, so
+//! this narrows how long a secret sits in ordinary memory rather than
+//! removing it.
+//!
+//! Equality on secrets goes through `subtle::ConstantTimeEq`, and
+//! `mpz_ct_eq`/`scalar_ct_eq` do the same for the plain values that come
+//! out of a decryption.
+use crate::FE;
+use classgroup::prelude::Mpz;
+use curv::elliptic::curves::Scalar;
+use std::fmt;
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::{Zeroize, Zeroizing};
+
+pub struct SecretMpz {
+    inner: Mpz,
+    locked: bool,
+}
+
+impl SecretMpz {
+    pub fn new(value: &Mpz) -> Self {
+        // Sized up front so that `set` does not reallocate out of the locked
+        // buffer.
+        let mut inner = Mpz::new_reserve(value.bit_length().max(64));
+        inner.set(value);
+        let locked = inner.mlock_limbs();
+        Self { inner, locked }
+    }
+
+    pub fn expose(&self) -> &Mpz {
+        &self.inner
+    }
+
+    /// Whether the buffer could be locked in RAM.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl From<Mpz> for SecretMpz {
+    fn from(mut value: Mpz) -> Self {
+        let secret = Self::new(&value);
+        value.zeroize();
+        secret
+    }
+}
+
+impl Clone for SecretMpz {
+    fn clone(&self) -> Self {
+        Self::new(&self.inner)
+    }
+}
+
+impl Drop for SecretMpz {
+    fn drop(&mut self) {
+        self.inner.zeroize();
+        if self.locked {
+            self.inner.munlock_limbs();
+        }
+    }
+}
+
+impl ConstantTimeEq for SecretMpz {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        mpz_ct_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Debug for SecretMpz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretMpz(<redacted>)")
+    }
+}
+
+pub struct SecretScalar {
+    bytes: Box<[u8; 32]>,
+    locked: bool,
+}
+
+impl SecretScalar {
+    pub fn new(value: &FE) -> Self {
+        let mut bytes = Box::new([0u8; 32]);
+        bytes.copy_from_slice(&value.to_bytes());
+        let locked = lock(bytes.as_ptr(), bytes.len());
+        Self { bytes, locked }
+    }
+
+    pub fn expose(&self) -> FE {
+        Scalar::from_bytes(&self.bytes[..]).expect("stored bytes are a canonical scalar")
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl From<FE> for SecretScalar {
+    fn from(value: FE) -> Self {
+        Self::new(&value)
+    }
+}
+
+impl Clone for SecretScalar {
+    fn clone(&self) -> Self {
+        Self::new(&self.expose())
+    }
+}
+
+impl Drop for SecretScalar {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+        if self.locked {
+            unlock(self.bytes.as_ptr(), self.bytes.len());
+        }
+    }
+}
+
+impl ConstantTimeEq for SecretScalar {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.bytes[..].ct_eq(&other.bytes[..])
+    }
+}
+
+impl fmt::Debug for SecretScalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretScalar(<redacted>)")
+    }
+}
+
+/// Compares sign and magnitude without an early exit. Only the lengths of
+/// the two values, which the encoding pads to the longer one, can show in
+/// the timing.
+pub fn mpz_ct_eq(a: &Mpz, b: &Mpz) -> Choice {
+    let a_bytes = Zeroizing::new(Vec::<u8>::from(a));
+    let b_bytes = Zeroizing::new(Vec::<u8>::from(b));
+    let width = a_bytes.len().max(b_bytes.len());
+    let pad = |v: &[u8]| {
+        let mut out = Zeroizing::new(vec![0u8; width]);
+        out[width - v.len()..].copy_from_slice(v);
+        out
+    };
+    let same_sign = Choice::from((a.sign() == b.sign()) as u8);
+    pad(&a_bytes[..]).ct_eq(&pad(&b_bytes[..])) & same_sign
+}
+
+pub fn scalar_ct_eq(a: &FE, b: &FE) -> Choice {
+    let a_bytes = Zeroizing::new(a.to_bytes().to_vec());
+    let b_bytes = Zeroizing::new(b.to_bytes().to_vec());
+    a_bytes.ct_eq(&b_bytes)
+}
+
+#[cfg(unix)]
+fn lock(ptr: *const u8, len: usize) -> bool {
+    unsafe { libc::mlock(ptr as *const libc::c_void, len) == 0 }
+}
+
+#[cfg(unix)]
+fn unlock(ptr: *const u8, len: usize) {
+    unsafe {
+        libc::munlock(ptr as *const libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_ptr: *const u8, _len: usize) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unlock(_ptr: *const u8, _len: usize) {}
+
+/// Serialize a `SecretMpz` as the plain `Mpz` it holds.
+pub mod exposed_mpz {
+    use super::SecretMpz;
+    use classgroup::prelude::Mpz;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &SecretMpz, serializer: S) -> Result<S::Ok, S::Error> {
+        value.expose().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SecretMpz, D::Error> {
+        Mpz::deserialize(deserializer).map(SecretMpz::from)
+    }
+}
+
+/// Serialize a `SecretScalar` as the plain scalar it holds.
+pub mod exposed_scalar {
+    use super::SecretScalar;
+    use crate::FE;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &SecretScalar,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.expose().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SecretScalar, D::Error> {
+        FE::deserialize(deserializer).map(SecretScalar::from)
+    }
+}
+
+#[test]
+fn test_secret_holders() {
+    use curv::arithmetic::Converter;
+
+    let x = Mpz::from(123_456_789u64);
+    let secret = SecretMpz::new(&x);
+    assert_eq!(secret.expose(), &x);
+    assert_eq!(secret.clone().expose(), &x);
+    assert_eq!(format!("{:?}", secret), "SecretMpz(<redacted>)");
+
+    let s = FE::random();
+    let secret = SecretScalar::from(s.clone());
+    assert_eq!(secret.expose(), s);
+    assert!(!format!("{:?}", secret).contains(&s.to_bigint().to_hex()));
+
+    assert!(bool::from(secret.ct_eq(&SecretScalar::new(&s))));
+    let other = SecretScalar::new(&(s.clone() + FE::from(&curv::BigInt::from(1))));
+    assert!(!bool::from(secret.ct_eq(&other)));
+    assert!(bool::from(
+        SecretMpz::new(&x).ct_eq(&SecretMpz::from(x.clone()))
+    ));
+    assert!(!bool::from(mpz_ct_eq(&x, &-x.clone())));
+    assert!(!bool::from(mpz_ct_eq(&x, &Mpz::from(1u64))));
+    assert!(bool::from(scalar_ct_eq(&s, &s.clone())));
+}
@@ -26,9 +26,7 @@ use crate::utilities::promise_sigma_multi::{
     PromiseState as MulPromiseState,
 };
 use crate::{CU, FE, GE};
-use classgroup::gmp::mpz::Mpz;
-use classgroup::gmp_classgroup::GmpClassGroup;
-use classgroup::ClassGroup;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
 use curv::arithmetic::Converter;
 use curv::cryptographic_primitives::proofs::sigma_correct_homomorphic_elgamal_enc::HomoELGamalProof;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
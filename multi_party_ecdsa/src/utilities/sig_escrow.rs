@@ -0,0 +1,285 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Verifiable encryption of an ECDSA signature under a CL key.
+//!
+//! For a signature `(r, s)` on `m` under `Q`, let `R = s^-1 (mG + rQ)`, the
+//! point whose x-coordinate gives `r`. The holder publishes `R` and a CL
+//! encryption of `s` under an escrow key, and proves that the plaintext
+//! satisfies `s R = mG + rQ`. Anyone can check that decryption, by the
+//! escrow agent or after a swap completes, yields a valid signature without
+//! learning it beforehand.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::signature::Signature;
+use crate::utilities::statement::StatementContext;
+use crate::utilities::SECURITY_PARAMETER;
+use crate::{FE, GE};
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use curv::arithmetic::traits::*;
+use curv::elliptic::curves::{Point, Scalar};
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignatureEscrow {
+    pub r_point: GE,
+    pub cipher: Ciphertext,
+    pub proof: EscrowProof,
+}
+
+/// Knowledge of `s` and the encryption randomness with `cipher = Enc(s)`
+/// and `s R = H`, a CLDL proof on the base `R` instead of the generator.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EscrowProof {
+    pub t1: GmpClassGroup,
+    pub t2: GmpClassGroup,
+    pub t3: GE,
+    pub u1: Mpz,
+    pub u2: Mpz,
+}
+
+/// `mG + rQ`, the point `s R` has to hit.
+fn target(pubkey: &GE, message: &FE, r: &FE) -> GE {
+    Point::generator() * message + pubkey * r
+}
+
+fn r_of(r_point: &GE) -> Option<FE> {
+    let rx = r_point.x_coord()?;
+    let r = FE::from_bigint(&rx.mod_floor(FE::group_order()));
+    if r == FE::zero() {
+        None
+    } else {
+        Some(r)
+    }
+}
+
+impl SignatureEscrow {
+    /// Escrow `signature` under `escrow_pk`. The signature is checked first
+    /// so that a bad one is not locked away.
+    pub fn create(
+        group: &CLGroup,
+        escrow_pk: &PK,
+        signature: &Signature,
+        pubkey: &GE,
+        message: &FE,
+    ) -> Result<Self, MulEcdsaError> {
+        signature.verify(pubkey, message)?;
+        let h = target(pubkey, message, &signature.r);
+        let r_point = &h * &signature.s.invert().ok_or(MulEcdsaError::InvertZero)?;
+        let (cipher, rho) = CLGroup::encrypt(group, escrow_pk, &signature.s);
+        let proof =
+            EscrowProof::prove(group, escrow_pk, &r_point, &h, &cipher, &signature.s, &rho)?;
+        Ok(Self {
+            r_point,
+            cipher,
+            proof,
+        })
+    }
+
+    /// Check that `cipher` decrypts to a signature on `message` under
+    /// `pubkey`.
+    pub fn verify(
+        &self,
+        group: &CLGroup,
+        escrow_pk: &PK,
+        pubkey: &GE,
+        message: &FE,
+    ) -> Result<(), MulEcdsaError> {
+        let r = r_of(&self.r_point).ok_or(MulEcdsaError::VrfyEscrowFailed)?;
+        let h = target(pubkey, message, &r);
+        self.proof
+            .verify(group, escrow_pk, &self.r_point, &h, &self.cipher)
+    }
+
+    /// Decrypt with the escrow key, low-s normalized with its recovery id.
+    pub fn open(
+        &self,
+        group: &CLGroup,
+        escrow_sk: &SK,
+        pubkey: &GE,
+        message: &FE,
+    ) -> Result<Signature, MulEcdsaError> {
+        let q = FE::group_order();
+        let r = r_of(&self.r_point).ok_or(MulEcdsaError::VrfyEscrowFailed)?;
+        let rx = self
+            .r_point
+            .x_coord()
+            .ok_or(MulEcdsaError::VrfyEscrowFailed)?;
+        let ry = self
+            .r_point
+            .y_coord()
+            .ok_or(MulEcdsaError::VrfyEscrowFailed)?;
+        let mut s = CLGroup::decrypt(group, escrow_sk, &self.cipher);
+        let mut recid = if &rx >= q { 2u8 } else { 0u8 };
+        if ry.is_odd() {
+            recid |= 1;
+        }
+        if s.to_bigint() > q >> 1 {
+            s = FE::from_bigint(&(q - &s.to_bigint()));
+            recid ^= 1;
+        }
+        let signature = Signature { s, r, recid };
+        signature.verify(pubkey, message)?;
+        Ok(signature)
+    }
+}
+
+impl EscrowProof {
+    fn sample_size(group: &CLGroup) -> BigInt {
+        &mpz_to_bigint(&group.stilde)
+            * BigInt::from(2u32).pow(40)
+            * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+            * BigInt::from(2u32).pow(40)
+    }
+
+    pub fn prove(
+        group: &CLGroup,
+        escrow_pk: &PK,
+        r_point: &GE,
+        h: &GE,
+        cipher: &Ciphertext,
+        s: &FE,
+        rho: &SK,
+    ) -> Result<Self, MulEcdsaError> {
+        let r1 = bigint_to_mpz(&BigInt::sample_below(&Self::sample_size(group)));
+        let r2_fe = FE::random();
+        let r2 = into_mpz(&r2_fe);
+        let mut powers = exp_pool::current().pow_all(
+            vec![
+                (group.generator.clone(), r1.clone()),
+                (escrow_pk.0.clone(), r1.clone()),
+            ],
+            Priority::Low,
+        )?;
+        let pkr1 = powers.pop().unwrap();
+        let t1 = powers.pop().unwrap();
+        let t2 = expo_f(&group.q, group.generator.discriminant(), &r2) * pkr1;
+        let t3 = r_point * &r2_fe;
+        let k = Self::challenge(group, escrow_pk, r_point, h, cipher, &t1, &t2, &t3);
+        let u1 = r1 + &bigint_to_mpz(&k) * rho.0.expose();
+        let u2 = BigInt::mod_add(&r2_fe.to_bigint(), &(&k * s.to_bigint()), FE::group_order());
+        Ok(Self {
+            t1,
+            t2,
+            t3,
+            u1,
+            u2: bigint_to_mpz(&u2),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn challenge(
+        group: &CLGroup,
+        escrow_pk: &PK,
+        r_point: &GE,
+        h: &GE,
+        cipher: &Ciphertext,
+        t1: &GmpClassGroup,
+        t2: &GmpClassGroup,
+        t3: &GE,
+    ) -> BigInt {
+        StatementContext::new(group)
+            .transcript(b"DMZ21-SIGNATURE-ESCROW")
+            .append(escrow_pk)
+            .append(r_point)
+            .append(h)
+            .append(cipher)
+            .append(t1)
+            .append(t2)
+            .append(t3)
+            .challenge()
+    }
+
+    pub fn verify(
+        &self,
+        group: &CLGroup,
+        escrow_pk: &PK,
+        r_point: &GE,
+        h: &GE,
+        cipher: &Ciphertext,
+    ) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[&cipher.c1, &cipher.c2, &escrow_pk.0, &self.t1, &self.t2])?;
+        let bound = &mpz_to_bigint(&group.stilde)
+            * BigInt::from(2u32).pow(40)
+            * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+            * (BigInt::from(2u32).pow(40) + BigInt::one());
+        if self.u1 < Mpz::zero() || self.u1 > bigint_to_mpz(&bound) {
+            return Err(MulEcdsaError::VrfyEscrowFailed);
+        }
+        if self.u2 < Mpz::zero() || self.u2 >= group.q {
+            return Err(MulEcdsaError::VrfyEscrowFailed);
+        }
+        let k = Self::challenge(
+            group, escrow_pk, r_point, h, cipher, &self.t1, &self.t2, &self.t3,
+        );
+        let k_mpz = bigint_to_mpz(&k);
+        let mut powers = exp_pool::current()
+            .pow_all(
+                vec![
+                    (group.generator.clone(), self.u1.clone()),
+                    (cipher.c1.clone(), k_mpz.clone()),
+                    (escrow_pk.0.clone(), self.u1.clone()),
+                    (cipher.c2.clone(), k_mpz),
+                ],
+                Priority::Low,
+            )?
+            .into_iter();
+        let mut next = || powers.next().unwrap();
+        let (gu1, c1k, pku1, c2k) = (next(), next(), next(), next());
+
+        let u2_fe: FE = Scalar::from(&mpz_to_bigint(&self.u2));
+        let k_fe: FE = Scalar::from(&k);
+        let fu2 = expo_f(&group.q, group.generator.discriminant(), &self.u2);
+        if c1k * &self.t1 == gu1
+            && r_point * &u2_fe == &self.t3 + &(h * &k_fe)
+            && c2k * &self.t2 == pku1 * fu2
+        {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::VrfyEscrowFailed)
+        }
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn signature_escrow_test() {
+    let group = &GROUP_UPDATE_1827;
+    let (escrow_sk, escrow_pk) = group.keygen();
+    let secret = FE::random();
+    let pubkey = GE::generator() * &secret;
+    let message = FE::random();
+    let signature = Signature::sign(&secret, &message);
+
+    let escrow = SignatureEscrow::create(group, &escrow_pk, &signature, &pubkey, &message).unwrap();
+    escrow.verify(group, &escrow_pk, &pubkey, &message).unwrap();
+    let opened = escrow.open(group, &escrow_sk, &pubkey, &message).unwrap();
+    assert_eq!(opened, signature);
+
+    let other = FE::random();
+    assert!(escrow.verify(group, &escrow_pk, &pubkey, &other).is_err());
+
+    // a ciphertext of anything else does not pass
+    let mut forged = escrow.clone();
+    forged.cipher = CLGroup::encrypt(group, &escrow_pk, &FE::random()).0;
+    assert_eq!(
+        forged.verify(group, &escrow_pk, &pubkey, &message),
+        Err(MulEcdsaError::VrfyEscrowFailed)
+    );
+}
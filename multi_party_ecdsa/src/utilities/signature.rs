@@ -20,14 +20,14 @@ use crate::{FE, GE};
 use curv::arithmetic::traits::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Signature {
     pub s: FE,
     pub r: FE,
     pub recid: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SignatureX {
     pub s: String,
     pub r: String,
@@ -35,6 +35,49 @@ pub struct SignatureX {
 }
 
 impl Signature {
+    /// Plain single-key ECDSA, for material the crate signs on its own
+    /// behalf (audit records and the like). The result is low-s normalized
+    /// like the threshold signatures.
+    pub fn sign(secret: &FE, message: &FE) -> Self {
+        let q = FE::group_order();
+        let half_q = q >> 1;
+        loop {
+            let k = FE::random();
+            let r_point = GE::generator() * &k;
+            let (rx, ry) = match (r_point.x_coord(), r_point.y_coord()) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                _ => continue,
+            };
+            let r = FE::from_bigint(&rx.mod_floor(q));
+            if r == FE::zero() {
+                continue;
+            }
+            let mut s = k.invert().unwrap() * (message + &(&r * secret));
+            if s == FE::zero() {
+                continue;
+            }
+
+            let mut recid = if rx >= *q { 2u8 } else { 0u8 };
+            if ry.is_odd() {
+                recid |= 1;
+            }
+            let s_bn = s.to_bigint();
+            if s_bn > half_q {
+                s = FE::from_bigint(&(q - &s_bn));
+                recid ^= 1;
+            }
+            return Self { s, r, recid };
+        }
+    }
+
+    pub fn to_hex(&self) -> SignatureX {
+        SignatureX {
+            s: self.s.to_bigint().to_hex(),
+            r: self.r.to_bigint().to_hex(),
+            recid: self.recid,
+        }
+    }
+
     pub fn verify(&self, pubkey: &GE, message: &FE) -> Result<(), MulEcdsaError> {
         let q = FE::group_order();
 
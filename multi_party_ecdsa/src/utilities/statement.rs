@@ -0,0 +1,75 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Parameter context absorbed by every CL proof transcript.
+//!
+//! A sigma protocol challenge used to hash only the statement and the
+//! commitments, so the same proof checked under another `CLGroup` with the
+//! same discriminant (`GROUP_1827` and `GROUP_UPDATE_1827`, say) was bound
+//! to nothing that told the two apart. `StatementContext` puts the
+//! parameter-set id, the generator `g` (`g^q` in the update groups) and
+//! `f = [(q^2, q)]` at the front of the transcript, under the label of the
+//! proof, and the provers add their public keys and statements after it.
+use crate::utilities::class_group::*;
+use crate::utilities::params_id::ParamsId;
+use crate::utilities::transcript::{Transcript, TranscriptWrite};
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use sha2::Digest;
+
+#[derive(Clone, Debug)]
+pub struct StatementContext {
+    pub params: ParamsId,
+    pub generator: GmpClassGroup,
+    pub f: GmpClassGroup,
+}
+
+impl StatementContext {
+    pub fn new(group: &CLGroup) -> Self {
+        Self {
+            params: group.params_id(),
+            generator: group.generator.clone(),
+            f: expo_f(&group.q, group.generator.discriminant(), &Mpz::one()),
+        }
+    }
+
+    /// A transcript under `label` that has already absorbed this context.
+    pub fn transcript(&self, label: &[u8]) -> Transcript {
+        Transcript::new(label).append(self)
+    }
+}
+
+impl TranscriptWrite for StatementContext {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        self.params.0[..].write_to(transcript);
+        self.generator.write_to(transcript);
+        self.f.write_to(transcript);
+    }
+}
+
+#[cfg(feature = "params-1827")]
+#[test]
+fn test_statement_context_separates_groups() {
+    use crate::utilities::cl_proof::CLProof;
+    use crate::FE;
+
+    let (_, pk) = GROUP_UPDATE_1827.keygen();
+    let (cipher, _) = CLGroup::encrypt(&GROUP_UPDATE_1827, &pk, &FE::random());
+    let t = GROUP_UPDATE_1827.generator.clone();
+    let under = |group: &CLGroup| CLProof::challenge(group, &pk, &t, &t, &cipher);
+    assert_eq!(under(&GROUP_UPDATE_1827), under(&GROUP_UPDATE_1827));
+    assert_ne!(under(&GROUP_UPDATE_1827), under(&GROUP_1827));
+}
@@ -0,0 +1,158 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! External time anchors for the audit trail.
+//!
+//! The timestamps inside audit records come from the local clock and are
+//! only as trustworthy as the host. A `TimestampAuthority` is a backend,
+//! an RFC 3161 TSA, a ledger or a notary service, that countersigns a
+//! digest together with its own notion of the time. An `AuditLog` with an
+//! authority attached submits every `SignatureProduced` record to it and
+//! appends the receipt as a `TimestampAnchored` record, so a third party
+//! can check when a signature existed at the latest without trusting the
+//! signers' clocks. Since each record hash covers the chain before it, the
+//! anchor also dates every earlier record.
+use crate::utilities::audit::{AuditEvent, AuditRecord};
+use crate::utilities::clock::{self, Clock};
+use crate::utilities::eckeypair::EcKeyPair;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::Signature;
+use crate::{FE, GE};
+use curv::arithmetic::traits::*;
+use curv::cryptographic_primitives::hashing::{Digest, DigestExt};
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+
+pub trait TimestampAuthority: Send + Sync {
+    /// Identifies the backend in anchor records.
+    fn name(&self) -> &str;
+    /// Have `digest` timestamped and return the backend's receipt.
+    fn timestamp(&self, digest: &[u8; 32]) -> Result<Vec<u8>, MulEcdsaError>;
+    /// Check that `receipt` timestamps `digest` and return the time it
+    /// attests, in Unix seconds.
+    fn verify(&self, digest: &[u8; 32], receipt: &[u8]) -> Result<i64, MulEcdsaError>;
+}
+
+/// What gets timestamped for `record`: its hash as 32 big endian bytes.
+pub fn anchor_digest(record: &AuditRecord) -> Result<[u8; 32], MulEcdsaError> {
+    let hash = BigInt::from_hex(&record.hash).map_err(|_| MulEcdsaError::FromHexFailed)?;
+    let bytes = hash.to_bytes();
+    if bytes.len() > 32 {
+        return Err(MulEcdsaError::VrfyAuditRecordFailed);
+    }
+    let mut digest = [0u8; 32];
+    digest[32 - bytes.len()..].copy_from_slice(&bytes);
+    Ok(digest)
+}
+
+/// Check the anchor of the record numbered `seq` in `records` and return
+/// the time it attests. The records themselves are checked with
+/// `verify_chain`.
+pub fn verify_anchor(
+    records: &[AuditRecord],
+    seq: u64,
+    authority: &dyn TimestampAuthority,
+) -> Result<i64, MulEcdsaError> {
+    let anchored = records
+        .iter()
+        .find(|record| record.seq == seq)
+        .ok_or(MulEcdsaError::VrfyTimestampFailed)?;
+    let receipt = records
+        .iter()
+        .find_map(|record| match &record.event {
+            AuditEvent::TimestampAnchored {
+                seq: anchored_seq,
+                hash,
+                authority: name,
+                receipt,
+            } if *anchored_seq == seq && *hash == anchored.hash && name == authority.name() => {
+                Some(receipt)
+            }
+            _ => None,
+        })
+        .ok_or(MulEcdsaError::VrfyTimestampFailed)?;
+    let receipt = hex::decode(receipt).map_err(|_| MulEcdsaError::FromHexFailed)?;
+    authority.verify(&anchor_digest(anchored)?, &receipt)
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocalReceipt {
+    time: i64,
+    signature: Signature,
+}
+
+/// An authority run in-house: signs the digest and the time of its own
+/// clock with an ECDSA key. Meant for an internal notary, whose key and
+/// clock are kept apart from the signing parties, and for tests.
+pub struct LocalTimestampAuthority {
+    name: String,
+    key: EcKeyPair,
+    clock: Arc<dyn Clock>,
+}
+
+impl LocalTimestampAuthority {
+    pub fn new(name: String, key: EcKeyPair) -> Self {
+        Self {
+            name,
+            key,
+            clock: clock::system(),
+        }
+    }
+
+    /// Attest the time of `clock` instead of the host's.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn public_key(&self) -> &GE {
+        self.key.get_public_key()
+    }
+
+    fn message(digest: &[u8; 32], time: i64) -> FE {
+        let hash = Sha256::new()
+            .chain(&digest[..])
+            .chain(&time.to_be_bytes())
+            .result_bigint();
+        FE::from_bigint(&hash)
+    }
+}
+
+impl TimestampAuthority for LocalTimestampAuthority {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn timestamp(&self, digest: &[u8; 32]) -> Result<Vec<u8>, MulEcdsaError> {
+        let time = self.clock.unix_secs();
+        let signature = Signature::sign(self.key.get_secret_key(), &Self::message(digest, time));
+        serde_json::to_vec(&LocalReceipt { time, signature })
+            .map_err(|_| MulEcdsaError::SerializeFailed)
+    }
+
+    fn verify(&self, digest: &[u8; 32], receipt: &[u8]) -> Result<i64, MulEcdsaError> {
+        let receipt: LocalReceipt =
+            serde_json::from_slice(receipt).map_err(|_| MulEcdsaError::VrfyTimestampFailed)?;
+        receipt
+            .signature
+            .verify(self.public_key(), &Self::message(digest, receipt.time))
+            .map_err(|_| MulEcdsaError::VrfyTimestampFailed)?;
+        Ok(receipt.time)
+    }
+}
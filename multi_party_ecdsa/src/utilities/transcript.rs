@@ -0,0 +1,200 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Canonical, streaming Fiat-Shamir transcripts.
+//!
+//! Class group elements of the 1827-bit group are several hundred bytes
+//! each and the old challenges round-tripped every one of them through
+//! `to_bytes`, a concatenation and two `BigInt` conversions. That encoding
+//! was also ambiguous: `a || b` carries no boundary and drops the sign of
+//! `b`. Here every integer is written as a sign byte, its magnitude length
+//! as a big-endian `u64` and the big-endian magnitude, straight into the
+//! hasher.
+use crate::utilities::class_group::{Ciphertext, PK};
+use crate::utilities::SECURITY_PARAMETER;
+use crate::{FE, GE};
+use classgroup::prelude::{GmpClassGroup, Mpz, Sign};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::digest::consts::U32;
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+
+/// Types with a canonical encoding that can be fed to a running hash.
+pub trait TranscriptWrite {
+    fn write_to<D: Digest>(&self, transcript: &mut D);
+}
+
+fn write_len<D: Digest>(transcript: &mut D, len: usize) {
+    transcript.update(&(len as u64).to_be_bytes());
+}
+
+fn write_signed<D: Digest>(transcript: &mut D, negative: bool, magnitude: &[u8]) {
+    transcript.update(&[negative as u8]);
+    write_len(transcript, magnitude.len());
+    transcript.update(magnitude);
+}
+
+impl TranscriptWrite for [u8] {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        write_len(transcript, self.len());
+        transcript.update(self);
+    }
+}
+
+impl TranscriptWrite for Mpz {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        let magnitude: Vec<u8> = self.into();
+        write_signed(transcript, self.sign() == Sign::Negative, &magnitude);
+    }
+}
+
+impl TranscriptWrite for BigInt {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        let negative = self < &BigInt::zero();
+        let magnitude = if negative {
+            (BigInt::zero() - self).to_bytes()
+        } else {
+            self.to_bytes()
+        };
+        write_signed(transcript, negative, &magnitude);
+    }
+}
+
+/// A reduced form is determined by `(a, b)` and the discriminant, which is
+/// fixed by the group, so `c` is not written.
+impl TranscriptWrite for GmpClassGroup {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        self.a.write_to(transcript);
+        self.b.write_to(transcript);
+    }
+}
+
+impl TranscriptWrite for PK {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        self.0.write_to(transcript);
+    }
+}
+
+impl TranscriptWrite for Ciphertext {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        let c = self.canonical();
+        c.c1.write_to(transcript);
+        c.c2.write_to(transcript);
+    }
+}
+
+impl TranscriptWrite for GE {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        let encoded = self.to_bytes(true);
+        let bytes: &[u8] = encoded.as_ref();
+        bytes.write_to(transcript);
+    }
+}
+
+impl TranscriptWrite for FE {
+    fn write_to<D: Digest>(&self, transcript: &mut D) {
+        self.to_bigint().write_to(transcript);
+    }
+}
+
+/// Names of the hash functions a transcript can run on, for recording the
+/// choice next to keys and parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProtocolHashId {
+    Sha256,
+    Sha3_256,
+    Poseidon,
+}
+
+/// A 256-bit hash function usable for Fiat-Shamir transcripts.
+pub trait ProtocolHash: Digest<OutputSize = U32> {
+    const ID: ProtocolHashId;
+}
+
+impl ProtocolHash for Sha256 {
+    const ID: ProtocolHashId = ProtocolHashId::Sha256;
+}
+
+impl ProtocolHash for Sha3_256 {
+    const ID: ProtocolHashId = ProtocolHashId::Sha3_256;
+}
+
+/// A transcript opened under a domain label, SHA-256 unless chosen
+/// otherwise with `with_hash`.
+pub struct Transcript<H: ProtocolHash = Sha256> {
+    hasher: H,
+}
+
+impl Transcript<Sha256> {
+    pub fn new(label: &[u8]) -> Self {
+        Self::with_hash(label)
+    }
+}
+
+impl<H: ProtocolHash> Transcript<H> {
+    pub fn with_hash(label: &[u8]) -> Self {
+        let mut hasher = H::new();
+        label.write_to(&mut hasher);
+        Self { hasher }
+    }
+
+    pub fn append<T: TranscriptWrite + ?Sized>(mut self, item: &T) -> Self {
+        item.write_to(&mut self.hasher);
+        self
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&self.hasher.finalize());
+        out
+    }
+
+    /// The first `SECURITY_PARAMETER` bits of the digest, as used for every
+    /// sigma protocol challenge in this crate.
+    pub fn challenge(self) -> BigInt {
+        BigInt::from_bytes(&self.finalize()[..SECURITY_PARAMETER / 8])
+    }
+}
+
+#[test]
+fn test_transcript_is_unambiguous() {
+    let split = |a: &[u8], b: &[u8]| Transcript::new(b"test").append(a).append(b).finalize();
+    assert_ne!(split(b"ab", b"c"), split(b"a", b"bc"));
+
+    let one = Mpz::from(1u64);
+    let minus_one = -Mpz::from(1u64);
+    let digest = |x: &Mpz| Transcript::new(b"test").append(x).finalize();
+    assert_ne!(digest(&one), digest(&minus_one));
+
+    let x = BigInt::from(12345u32);
+    let by_mpz = Transcript::new(b"test")
+        .append(&crate::utilities::class_group::bigint_to_mpz(&x))
+        .finalize();
+    assert_eq!(by_mpz, Transcript::new(b"test").append(&x).finalize());
+}
+
+#[test]
+fn test_transcript_hash_choice() {
+    let sha2 = Transcript::new(b"test").append(&b"x"[..]).finalize();
+    let sha3 = Transcript::<Sha3_256>::with_hash(b"test")
+        .append(&b"x"[..])
+        .finalize();
+    assert_ne!(sha2, sha3);
+    assert_eq!(<Sha3_256 as ProtocolHash>::ID, ProtocolHashId::Sha3_256);
+}
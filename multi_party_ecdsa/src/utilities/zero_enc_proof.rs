@@ -0,0 +1,176 @@
+/*
+    This file is part of OpenTSS.
+    Copyright (C) 2022 LatticeX Foundation.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! Proof that a CL ciphertext encrypts zero, i.e. that it is (g^r, h^r) for
+//! some r known to the prover. This is a plain equality-of-discrete-logs
+//! proof: one commitment pair and one response, against two commitments and
+//! two responses for `CLProof`. Typical uses are proving that a ciphertext
+//! was only re-randomized (the quotient of the two encrypts zero) and that
+//! blinding terms cancel out.
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::exp_pool::{self, Priority};
+use crate::utilities::statement::StatementContext;
+use crate::utilities::SECURITY_PARAMETER;
+use classgroup::prelude::{ClassGroup, GmpClassGroup, Mpz};
+use curv::arithmetic::traits::*;
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZeroEncState {
+    pub cipher: Ciphertext,
+    pub cl_pub_key: PK,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZeroEncWit {
+    pub r: SK,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZeroEncryptionProof {
+    pub t1: GmpClassGroup,
+    pub t2: GmpClassGroup,
+    pub u: Mpz,
+}
+
+impl ZeroEncState {
+    /// Statement that `rerandomized` is `original` times an encryption of
+    /// zero. The witness is the randomness returned by `CLGroup::rerandomize`.
+    pub fn from_rerandomization(
+        original: &Ciphertext,
+        rerandomized: &Ciphertext,
+        cl_pub_key: &PK,
+    ) -> Self {
+        let mut c1_inv = original.c1.clone();
+        c1_inv.inverse();
+        let mut c2_inv = original.c2.clone();
+        c2_inv.inverse();
+        Self {
+            cipher: Ciphertext {
+                c1: rerandomized.c1.clone() * c1_inv,
+                c2: rerandomized.c2.clone() * c2_inv,
+            },
+            cl_pub_key: cl_pub_key.clone(),
+        }
+    }
+}
+
+impl ZeroEncryptionProof {
+    pub fn prove(
+        group: &CLGroup,
+        witness: &ZeroEncWit,
+        statement: &ZeroEncState,
+    ) -> Result<Self, MulEcdsaError> {
+        let s = BigInt::sample_below(
+            &(&mpz_to_bigint(&group.stilde)
+                * BigInt::from(2u32).pow(40)
+                * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+                * BigInt::from(2u32).pow(40)),
+        );
+        let s_mpz = bigint_to_mpz(&s);
+        let mut powers = exp_pool::current().pow_all(
+            vec![
+                (group.generator.clone(), s_mpz.clone()),
+                (statement.cl_pub_key.0.clone(), s_mpz.clone()),
+            ],
+            Priority::Low,
+        )?;
+        let t2 = powers.pop().unwrap();
+        let t1 = powers.pop().unwrap();
+        let k = Self::challenge(group, statement, &t1, &t2);
+        let u = s_mpz + &bigint_to_mpz(&k) * witness.r.0.expose();
+        Ok(Self { t1, t2, u })
+    }
+
+    /// Compute the Fiat-Shamir challenge for the proof.
+    pub fn challenge(
+        group: &CLGroup,
+        statement: &ZeroEncState,
+        t1: &GmpClassGroup,
+        t2: &GmpClassGroup,
+    ) -> BigInt {
+        StatementContext::new(group)
+            .transcript(b"DMZ21-ZERO-ENC-PROOF")
+            .append(&statement.cipher)
+            .append(&statement.cl_pub_key)
+            .append(t1)
+            .append(t2)
+            .challenge()
+    }
+
+    pub fn verify(&self, group: &CLGroup, statement: &ZeroEncState) -> Result<(), MulEcdsaError> {
+        group.check_discriminants(&[
+            &statement.cipher.c1,
+            &statement.cipher.c2,
+            &statement.cl_pub_key.0,
+            &self.t1,
+            &self.t2,
+        ])?;
+        let k = Self::challenge(group, statement, &self.t1, &self.t2);
+
+        let sample_size = &mpz_to_bigint(&group.stilde)
+            * (BigInt::from(2u32).pow(40))
+            * BigInt::from(2u32).pow(SECURITY_PARAMETER as u32)
+            * (BigInt::from(2u32).pow(40) + BigInt::one());
+        if self.u > bigint_to_mpz(&sample_size) || self.u < Mpz::zero() {
+            return Err(MulEcdsaError::VrfyZeroEncProofFailed);
+        }
+
+        let k_mpz = bigint_to_mpz(&k);
+        let mut c1k = statement.cipher.c1.clone();
+        c1k.pow(k_mpz.clone());
+        let mut gu = group.generator.clone();
+        gu.pow(self.u.clone());
+        let mut c2k = statement.cipher.c2.clone();
+        c2k.pow(k_mpz);
+        let mut hu = statement.cl_pub_key.0.clone();
+        hu.pow(self.u.clone());
+
+        if self.t1.clone() * c1k == gu && self.t2.clone() * c2k == hu {
+            Ok(())
+        } else {
+            Err(MulEcdsaError::VrfyZeroEncProofFailed)
+        }
+    }
+}
+
+#[cfg(all(feature = "params-1827", feature = "params-3072"))]
+#[test]
+fn zero_enc_proof_test() {
+    use crate::FE;
+
+    let group = &GROUP_UPDATE_1827;
+    let (_sk, pk) = group.keygen();
+    let (original, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    let (rerandomized, r) = CLGroup::rerandomize(group, &pk, &original);
+    let statement = ZeroEncState::from_rerandomization(&original, &rerandomized, &pk);
+    let proof = ZeroEncryptionProof::prove(group, &ZeroEncWit { r }, &statement).unwrap();
+    proof.verify(group, &statement).unwrap();
+
+    let (other, _) = CLGroup::encrypt(group, &pk, &FE::random());
+    let forged = ZeroEncState::from_rerandomization(&other, &rerandomized, &pk);
+    assert!(proof.verify(group, &forged).is_err());
+
+    let mut mixed = statement.clone();
+    mixed.cipher.c1 = GROUP_3072.generator.clone();
+    assert_eq!(
+        proof.verify(group, &mixed),
+        Err(MulEcdsaError::DiscriminantMismatch)
+    );
+}